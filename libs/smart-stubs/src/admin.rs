@@ -0,0 +1,236 @@
+//! Admin routes shared by every service smart stub
+//! Scenario upload, inspection and clearing under `/stub/scenario`, fixture
+//! world export and import under `/stub/world`
+
+use crate::scenario::Scenario;
+use crate::stub::SharedStub;
+use crate::world::World;
+use axum::extract::{FromRef, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, MethodRouter};
+use axum::Json;
+use chrono::Utc;
+
+/// `/stub/scenario` route: `POST` uploads a YAML scenario, `GET` reports the
+/// cursor and `DELETE` stops the running scenario
+///
+/// The router state must expose the service's [`SharedStub`] through `FromRef`.
+pub fn scenario_route<S>() -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+    SharedStub: FromRef<S>,
+{
+    get(get_scenario)
+        .post(upload_scenario)
+        .delete(clear_scenario)
+}
+
+pub async fn upload_scenario(State(stub): State<SharedStub>, body: String) -> Response {
+    let started = match Scenario::from_yaml(&body) {
+        Ok(scenario) => stub.lock().await.start_scenario(scenario),
+        Err(err) => Err(err),
+    };
+
+    match started {
+        Ok(cursor) => (StatusCode::CREATED, Json(cursor)).into_response(),
+        Err(err) => error_response(
+            StatusCode::BAD_REQUEST,
+            "INVALID_SCENARIO",
+            &format!("Invalid scenario: {err:#}"),
+        ),
+    }
+}
+
+pub async fn get_scenario(State(stub): State<SharedStub>) -> Response {
+    match stub.lock().await.scenario_cursor() {
+        Some(cursor) => Json(cursor).into_response(),
+        None => error_response(
+            StatusCode::NOT_FOUND,
+            "SCENARIO_NOT_FOUND",
+            "No scenario is running",
+        ),
+    }
+}
+
+pub async fn clear_scenario(State(stub): State<SharedStub>) -> Response {
+    let cleared = stub.lock().await.clear_scenario();
+    Json(serde_json::json!({
+        "message": if cleared { "Scenario cleared" } else { "No scenario was running" },
+        "cleared": cleared,
+    }))
+    .into_response()
+}
+
+/// `/stub/world` route: `GET` exports the fixture world as a snapshot and
+/// `POST` replaces it with an uploaded one after checking its integrity
+///
+/// The router state must expose the service's [`SharedStub`] through `FromRef`.
+pub fn world_route<S>() -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+    SharedStub: FromRef<S>,
+{
+    get(export_world).post(import_world)
+}
+
+pub async fn export_world(State(stub): State<SharedStub>) -> Response {
+    let world = stub.lock().await.world();
+    match world.to_snapshot_json() {
+        Ok(json) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+        Err(err) => error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "SNAPSHOT_FAILED",
+            &format!("Failed to export world: {err:#}"),
+        ),
+    }
+}
+
+pub async fn import_world(State(stub): State<SharedStub>, body: String) -> Response {
+    let world = match World::from_snapshot_json(&body) {
+        Ok(world) => world,
+        Err(err) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "INVALID_WORLD_SNAPSHOT",
+                &format!("Invalid world snapshot: {err:#}"),
+            )
+        }
+    };
+
+    let summary = serde_json::json!({
+        "message": "World snapshot loaded",
+        "players": world.players.len(),
+        "nfts": world.nfts.len(),
+        "listings": world.listings.len(),
+        "events": world.events.len(),
+    });
+    stub.lock().await.replace_world(world);
+    (StatusCode::CREATED, Json(summary)).into_response()
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = serde_json::json!({
+        "error": message,
+        "code": code,
+        "timestamp": Utc::now(),
+        "request_id": uuid::Uuid::new_v4().to_string(),
+    });
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Dataset, StubConfiguration};
+    use crate::stub::ServiceStub;
+    use axum::body::Body;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn router() -> Router {
+        let stub =
+            ServiceStub::new(StubConfiguration::for_service("admin-test-stub", 9001)).into_shared();
+        Router::new()
+            .route("/stub/scenario", scenario_route())
+            .route("/stub/world", world_route())
+            .with_state(stub)
+    }
+
+    async fn send(router: &Router, method: &str, body: &str) -> (StatusCode, serde_json::Value) {
+        send_to(router, "/stub/scenario", method, body).await
+    }
+
+    async fn send_to(
+        router: &Router,
+        uri: &str,
+        method: &str,
+        body: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_scenario_upload_cursor_and_clear() {
+        let router = router();
+
+        let (status, _) = send(&router, "GET", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let yaml = "name: outage\nsteps:\n  - endpoint: CreateListing\n    calls: 3\n    fail: UNAVAILABLE\n";
+        let (status, cursor) = send(&router, "POST", yaml).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(cursor["name"], "outage");
+        assert_eq!(cursor["state"], "active");
+        assert_eq!(cursor["calls_remaining"], 3);
+
+        let (status, cursor) = send(&router, "GET", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(cursor["step_index"], 0);
+
+        let (status, cleared) = send(&router, "DELETE", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(cleared["cleared"], true);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_scenario_is_rejected() {
+        let (status, body) = send(&router(), "POST", "steps:\n  - fail: TEAPOT\n").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "INVALID_SCENARIO");
+    }
+
+    #[tokio::test]
+    async fn test_world_snapshot_import_and_export() {
+        let router = router();
+        let world = World::generate(Dataset::Minimal, 5);
+
+        let (status, summary) = send_to(
+            &router,
+            "/stub/world",
+            "POST",
+            &world.to_snapshot_json().unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(summary["players"], 10);
+
+        let (status, snapshot) = send_to(&router, "/stub/world", "GET", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(snapshot["format_version"], 1);
+        assert_eq!(
+            snapshot["world"]["players"][0]["player_id"],
+            world.players[0].player_id.as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_broken_world_snapshot_is_rejected() {
+        let mut world = World::generate(Dataset::Minimal, 5);
+        world.robots[0].owner_id = "ghost".to_string();
+
+        let (status, body) = send_to(
+            &router(),
+            "/stub/world",
+            "POST",
+            &world.to_snapshot_json().unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "INVALID_WORLD_SNAPSHOT");
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("unknown player ghost"));
+    }
+}
@@ -0,0 +1,383 @@
+//! Record/replay cassettes for smart stubs
+//! JSONL recordings of request/response exchanges served back deterministically
+
+use crate::config::{CassetteConfig, CassetteMode, ReplayOrder};
+use crate::stub::RequestContext;
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Http,
+    Grpc,
+}
+
+/// One recorded request/response exchange, stored as a single JSONL line
+///
+/// Bodies are base64 encoded so binary gRPC frames survive the round trip.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteEntry {
+    pub sequence: u64,
+    pub transport: Transport,
+    pub method: String,
+    /// Request path including the query string
+    pub path: String,
+    pub request_hash: String,
+    #[serde(with = "base64_bytes")]
+    pub request_body: Vec<u8>,
+    pub context: RequestContext,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "base64_bytes")]
+    pub body: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trailers: Vec<(String, String)>,
+    pub latency_ms: u64,
+}
+
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Stable hash identifying a request by method, path and body
+#[must_use]
+pub fn request_hash(method: &str, path: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.to_ascii_uppercase().as_bytes());
+    hasher.update([0]);
+    hasher.update(path.as_bytes());
+    hasher.update([0]);
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Header name/value pairs that can be stored in a cassette
+///
+/// Values that are not valid UTF-8 are skipped.
+pub fn header_pairs<'a>(
+    headers: impl Iterator<Item = (&'a str, &'a [u8])>,
+) -> Vec<(String, String)> {
+    headers
+        .filter_map(|(name, value)| {
+            std::str::from_utf8(value)
+                .ok()
+                .map(|value| (name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Active cassette for a stub, either recording or replaying
+#[derive(Debug)]
+pub enum Cassette {
+    Recorder(CassetteRecorder),
+    Player(CassettePlayer),
+}
+
+impl Cassette {
+    /// Open the cassette described by `config`; `None` when the mode is `Off`
+    ///
+    /// # Errors
+    /// Returns an error if the cassette file cannot be created or parsed
+    pub fn open(config: &CassetteConfig) -> Result<Option<Self>> {
+        match config.mode {
+            CassetteMode::Off => Ok(None),
+            CassetteMode::Record => Ok(Some(Self::Recorder(CassetteRecorder::create(
+                &config.path,
+            )?))),
+            CassetteMode::Replay => Ok(Some(Self::Player(CassettePlayer::load(
+                &config.path,
+                config.replay_order,
+            )?))),
+        }
+    }
+
+    #[must_use]
+    pub fn mode(&self) -> CassetteMode {
+        match self {
+            Self::Recorder(_) => CassetteMode::Record,
+            Self::Player(_) => CassetteMode::Replay,
+        }
+    }
+}
+
+/// Appends exchanges to a JSONL cassette file
+#[derive(Debug)]
+pub struct CassetteRecorder {
+    path: PathBuf,
+    state: Mutex<RecorderState>,
+}
+
+#[derive(Debug)]
+struct RecorderState {
+    file: File,
+    next_sequence: u64,
+}
+
+impl CassetteRecorder {
+    /// Create (or truncate) the cassette file at `path`
+    ///
+    /// # Errors
+    /// Returns an error if the file or its parent directory cannot be created
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating cassette directory {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("creating cassette {}", path.display()))?;
+
+        Ok(Self {
+            path,
+            state: Mutex::new(RecorderState {
+                file,
+                next_sequence: 0,
+            }),
+        })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append an exchange, assigning it the next sequence number
+    ///
+    /// # Errors
+    /// Returns an error if the entry cannot be written to the cassette file
+    pub fn record(&self, mut entry: CassetteEntry) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        entry.sequence = state.next_sequence;
+
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        state
+            .file
+            .write_all(&line)
+            .and_then(|()| state.file.flush())
+            .with_context(|| format!("writing cassette {}", self.path.display()))?;
+
+        state.next_sequence += 1;
+        Ok(entry.sequence)
+    }
+}
+
+/// Serves recorded exchanges back in order or by request hash
+#[derive(Debug)]
+pub struct CassettePlayer {
+    order: ReplayOrder,
+    state: Mutex<PlayerState>,
+}
+
+#[derive(Debug, Default)]
+struct PlayerState {
+    sequential: VecDeque<CassetteEntry>,
+    by_hash: HashMap<String, VecDeque<CassetteEntry>>,
+}
+
+impl CassettePlayer {
+    /// Load a cassette recorded by [`CassetteRecorder`]
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or a line is not a valid entry
+    pub fn load(path: impl AsRef<Path>, order: ReplayOrder) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("opening cassette {}", path.display()))?;
+
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.with_context(|| format!("reading cassette {}", path.display()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line).with_context(|| {
+                format!("parsing cassette {} line {}", path.display(), index + 1)
+            })?;
+            entries.push(entry);
+        }
+
+        Ok(Self::from_entries(entries, order))
+    }
+
+    #[must_use]
+    pub fn from_entries(mut entries: Vec<CassetteEntry>, order: ReplayOrder) -> Self {
+        entries.sort_by_key(|entry| entry.sequence);
+
+        let mut state = PlayerState::default();
+        match order {
+            ReplayOrder::Sequential => state.sequential = entries.into(),
+            ReplayOrder::RequestHash => {
+                for entry in entries {
+                    state
+                        .by_hash
+                        .entry(entry.request_hash.clone())
+                        .or_default()
+                        .push_back(entry);
+                }
+            }
+        }
+
+        Self {
+            order,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Next recorded exchange for a request with the given hash
+    ///
+    /// Sequential replay serves the next entry even when the hash differs,
+    /// logging a warning. Hash replay serves identical requests in recording
+    /// order and keeps repeating the last one once the others are used up.
+    pub fn next(&self, request_hash: &str) -> Option<CassetteEntry> {
+        let mut state = self.state.lock().unwrap();
+        match self.order {
+            ReplayOrder::Sequential => {
+                let entry = state.sequential.pop_front()?;
+                if entry.request_hash != request_hash {
+                    warn!(
+                        event_type = "cassette_out_of_order",
+                        sequence = entry.sequence,
+                        expected_path = %entry.path,
+                        "Replayed request differs from the recorded one"
+                    );
+                }
+                Some(entry)
+            }
+            ReplayOrder::RequestHash => {
+                let queue = state.by_hash.get_mut(request_hash)?;
+                if queue.len() > 1 {
+                    queue.pop_front()
+                } else {
+                    queue.front().cloned()
+                }
+            }
+        }
+    }
+
+    /// Number of entries not yet served
+    pub fn remaining(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.sequential.len() + state.by_hash.values().map(VecDeque::len).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn entry(path: &str, body: &[u8]) -> CassetteEntry {
+        CassetteEntry {
+            sequence: 0,
+            transport: Transport::Http,
+            method: "GET".to_string(),
+            path: path.to_string(),
+            request_hash: request_hash("GET", path, b""),
+            request_body: Vec::new(),
+            context: RequestContext {
+                request_id: uuid::Uuid::new_v4().to_string(),
+                trace_id: None,
+                timestamp: Utc::now(),
+                enable_crypto: false,
+            },
+            status: 200,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: body.to_vec(),
+            trailers: Vec::new(),
+            latency_ms: 3,
+        }
+    }
+
+    fn temp_cassette() -> PathBuf {
+        std::env::temp_dir().join(format!("cassette-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_request_hash_is_stable_and_body_sensitive() {
+        let hash = request_hash("get", "/health", b"");
+        assert_eq!(hash, request_hash("GET", "/health", b""));
+        assert_ne!(hash, request_hash("GET", "/health", b"x"));
+        assert_ne!(hash, request_hash("GET", "/healthz", b""));
+    }
+
+    #[test]
+    fn test_recorded_cassette_replays_in_order() {
+        let path = temp_cassette();
+        let recorder = CassetteRecorder::create(&path).unwrap();
+        assert_eq!(recorder.record(entry("/a", b"first")).unwrap(), 0);
+        assert_eq!(recorder.record(entry("/b", &[0, 159, 255])).unwrap(), 1);
+
+        let player = CassettePlayer::load(&path, ReplayOrder::Sequential).unwrap();
+        assert_eq!(player.remaining(), 2);
+        assert_eq!(player.next("unrelated").unwrap().body, b"first");
+        assert_eq!(player.next("unrelated").unwrap().body, vec![0, 159, 255]);
+        assert!(player.next("unrelated").is_none());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_hash_replay_matches_requests_and_repeats_last() {
+        let player = CassettePlayer::from_entries(
+            vec![entry("/a", b"a1"), entry("/b", b"b1"), entry("/a", b"a2")],
+            ReplayOrder::RequestHash,
+        );
+        let hash_a = request_hash("GET", "/a", b"");
+
+        assert_eq!(player.next(&hash_a).unwrap().body, b"a1");
+        assert_eq!(player.next(&hash_a).unwrap().body, b"a2");
+        assert_eq!(player.next(&hash_a).unwrap().body, b"a2");
+        assert_eq!(
+            player.next(&request_hash("GET", "/b", b"")).unwrap().body,
+            b"b1"
+        );
+        assert!(player.next(&request_hash("GET", "/c", b"")).is_none());
+    }
+
+    #[test]
+    fn test_open_respects_mode() {
+        let mut config = CassetteConfig {
+            path: temp_cassette().display().to_string(),
+            ..CassetteConfig::default()
+        };
+        assert!(Cassette::open(&config).unwrap().is_none());
+
+        config.mode = CassetteMode::Record;
+        let cassette = Cassette::open(&config).unwrap().unwrap();
+        assert_eq!(cassette.mode(), CassetteMode::Record);
+
+        config.mode = CassetteMode::Replay;
+        let cassette = Cassette::open(&config).unwrap().unwrap();
+        assert_eq!(cassette.mode(), CassetteMode::Replay);
+
+        std::fs::remove_file(&config.path).ok();
+        assert!(Cassette::open(&config).is_err());
+    }
+}
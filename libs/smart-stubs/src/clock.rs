@@ -0,0 +1,51 @@
+//! Sources of the current Unix time
+//! The wall clock for running stubs and a manual clock tests can fast-forward
+
+use chrono::Utc;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+/// Source of the current Unix time
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> i64;
+}
+
+/// Wall clock time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        Utc::now().timestamp()
+    }
+}
+
+/// Clock that only moves when told to; clones share the same time
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicI64>,
+}
+
+impl ManualClock {
+    #[must_use]
+    pub fn new(now: i64) -> Self {
+        Self {
+            now: Arc::new(AtomicI64::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: i64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, secs: i64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
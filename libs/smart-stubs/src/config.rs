@@ -0,0 +1,395 @@
+//! Stub configuration shared by every service smart stub
+//! Latency, error injection, dual-mode and dataset settings, plus the sections
+//! a service extends them with
+
+use anyhow::{Context as _, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StubConfiguration {
+    pub base: BaseConfig,
+    pub dual_mode: DualModeConfig,
+    pub latency: LatencyConfig,
+    pub errors: ErrorConfig,
+    pub data: DataConfig,
+    #[serde(default)]
+    pub cassette: CassetteConfig,
+    /// Top-level sections only one service reads, such as the marketplace
+    /// `fees` or the indexer `ingest`, kept as written; see
+    /// [`StubConfiguration::extension`]
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BaseConfig {
+    pub name: String,
+    pub version: String,
+    pub port: u16,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DualModeConfig {
+    pub enable_crypto: bool,
+    pub crypto_response_mode: CryptoResponseMode,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CryptoResponseMode {
+    Disabled,
+    Error,
+    Mock,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LatencyConfig {
+    pub min_response_time_ms: u64,
+    pub max_response_time_ms: u64,
+    pub distribution: LatencyDistribution,
+    pub network_condition: NetworkCondition,
+    /// Seed for reproducible latency and error injection sequences; random when unset
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// How long a request hangs when `ErrorConfig::timeout_rate` fires
+    #[serde(default = "default_timeout_hang_ms")]
+    pub timeout_hang_ms: u64,
+}
+
+fn default_timeout_hang_ms() -> u64 {
+    30_000
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LatencyDistribution {
+    Uniform,
+    Normal,
+    Exponential,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkCondition {
+    Good,
+    Poor,
+    Variable,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ErrorConfig {
+    pub error_rate: f64,
+    pub specific_errors: Vec<SpecificError>,
+    pub timeout_rate: f64,
+}
+
+/// Fault injection rule for a single endpoint
+///
+/// `endpoint` is a gRPC method (`CreateListing`), a `Service/Method` suffix,
+/// an HTTP route (`/api/marketplace/listings`), optionally prefixed with an
+/// HTTP method (`POST /api/marketplace/listings`) or ending in `*` to match a
+/// route prefix.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpecificError {
+    pub endpoint: String,
+    pub status_code: u16,
+    pub rate: f64,
+    #[serde(default)]
+    pub fault: FaultKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    /// Fail with `status_code` (mapped to the matching tonic `Code` for gRPC)
+    #[default]
+    Status,
+    /// Answer successfully with a body the client cannot decode
+    MalformedPayload,
+    /// Abort the response mid-stream so the client sees a broken connection
+    DropConnection,
+}
+
+impl SpecificError {
+    /// Whether this rule applies to a request with the given HTTP method and path
+    #[must_use]
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        endpoint_matches(&self.endpoint, method, path)
+    }
+}
+
+/// Match an endpoint pattern as described on [`SpecificError`]
+#[must_use]
+pub fn endpoint_matches(endpoint: &str, method: &str, path: &str) -> bool {
+    let pattern = endpoint.trim();
+    let pattern = match pattern.split_once(' ') {
+        Some((rule_method, rest)) => {
+            if !rule_method.eq_ignore_ascii_case(method) {
+                return false;
+            }
+            rest.trim()
+        }
+        None => pattern,
+    };
+
+    if let Some(prefix) = pattern.strip_suffix('*') {
+        return path.starts_with(prefix);
+    }
+
+    path == pattern
+        || path
+            .strip_suffix(pattern)
+            .is_some_and(|rest| rest.ends_with('/') || rest.ends_with('.'))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DataConfig {
+    pub dataset: Dataset,
+    /// Seed for the generated fixture world; services sharing it share IDs
+    #[serde(default = "default_dataset_seed")]
+    pub seed: u64,
+    pub persist_state: bool,
+    /// Duration such as `24h` after which stub state is cleared; empty disables resets
+    pub state_reset_interval: String,
+    #[serde(default)]
+    pub state_backend: StateBackendKind,
+    /// Defaults to `stub-state/<service name>.<json|redb>`
+    #[serde(default)]
+    pub state_path: Option<String>,
+    /// World snapshot loaded at startup instead of generating from the seed
+    #[serde(default)]
+    pub world_snapshot: Option<String>,
+}
+
+fn default_dataset_seed() -> u64 {
+    42
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateBackendKind {
+    /// JSON document on the local filesystem
+    #[default]
+    File,
+    /// Embedded redb database
+    Redb,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dataset {
+    Minimal,
+    Development,
+    StressTest,
+}
+
+/// Record/replay settings for JSONL request cassettes
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CassetteConfig {
+    pub mode: CassetteMode,
+    pub path: String,
+    pub replay_order: ReplayOrder,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMode {
+    /// Requests reach the handlers and nothing is recorded
+    #[default]
+    Off,
+    /// Every exchange is appended to the cassette, truncated on startup
+    Record,
+    /// Responses are served from the cassette without calling the handlers
+    Replay,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayOrder {
+    /// Serve recorded responses strictly in recording order
+    #[default]
+    Sequential,
+    /// Serve the response recorded for the same method, path and body
+    RequestHash,
+}
+
+impl Default for CassetteConfig {
+    fn default() -> Self {
+        Self {
+            mode: CassetteMode::Off,
+            path: "stub-cassette.jsonl".to_string(),
+            replay_order: ReplayOrder::Sequential,
+        }
+    }
+}
+
+impl StubConfiguration {
+    /// Default stub configuration for a named service listening on `port`
+    #[must_use]
+    pub fn for_service(name: &str, port: u16) -> Self {
+        Self {
+            base: BaseConfig {
+                name: name.to_string(),
+                port,
+                ..BaseConfig::default()
+            },
+            ..Self::default()
+        }
+    }
+
+    /// Add a service's own settings to the configuration, one section per field
+    ///
+    /// Adding them to the defaults lets `STUB__` overrides reach the sections.
+    ///
+    /// # Panics
+    /// Panics if `extension` does not serialize to a map of sections
+    #[must_use]
+    pub fn with_extension<T: Serialize>(mut self, extension: &T) -> Self {
+        let Ok(Value::Object(sections)) = serde_json::to_value(extension) else {
+            panic!("configuration extensions must serialize to a map of sections");
+        };
+        self.extensions.extend(sections);
+        self
+    }
+
+    /// A service's own settings, read from the sections named by `T`'s fields
+    ///
+    /// # Errors
+    /// Returns an error if the sections do not describe a `T`
+    pub fn extension<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_value(Value::Object(self.extensions.clone()))
+            .context("Invalid service configuration")
+    }
+}
+
+impl Default for BaseConfig {
+    fn default() -> Self {
+        Self {
+            name: "service-stub".to_string(),
+            version: "1.0.0".to_string(),
+            port: 8080,
+            enabled: true,
+        }
+    }
+}
+
+impl Default for StubConfiguration {
+    fn default() -> Self {
+        Self {
+            base: BaseConfig::default(),
+            dual_mode: DualModeConfig {
+                enable_crypto: std::env::var("ENABLE_CRYPTO")
+                    .unwrap_or_else(|_| "false".to_string())
+                    .parse()
+                    .unwrap_or(false),
+                crypto_response_mode: CryptoResponseMode::Disabled,
+            },
+            latency: LatencyConfig {
+                min_response_time_ms: 10,
+                max_response_time_ms: 500,
+                distribution: LatencyDistribution::Normal,
+                network_condition: NetworkCondition::Good,
+                seed: None,
+                timeout_hang_ms: default_timeout_hang_ms(),
+            },
+            errors: ErrorConfig {
+                error_rate: 0.05,
+                specific_errors: vec![],
+                timeout_rate: 0.01,
+            },
+            data: DataConfig {
+                dataset: Dataset::Development,
+                seed: default_dataset_seed(),
+                persist_state: false,
+                state_reset_interval: "24h".to_string(),
+                state_backend: StateBackendKind::File,
+                state_path: None,
+                world_snapshot: None,
+            },
+            cassette: CassetteConfig::default(),
+            extensions: Map::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_service_overrides_name_and_port() {
+        let config = StubConfiguration::for_service("indexer-service-stub", 8082);
+        assert_eq!(config.base.name, "indexer-service-stub");
+        assert_eq!(config.base.port, 8082);
+        assert_eq!(config.base.version, "1.0.0");
+        assert!(config.base.enabled);
+        assert_eq!(config.data.state_reset_interval, "24h");
+    }
+
+    #[test]
+    fn test_specific_error_matching() {
+        let rule = |endpoint: &str| SpecificError {
+            endpoint: endpoint.to_string(),
+            status_code: 503,
+            rate: 1.0,
+            fault: FaultKind::Status,
+        };
+        let grpc_path = "/bunkerverse.services.v1.MarketplaceService/CreateListing";
+
+        assert!(rule("CreateListing").matches("POST", grpc_path));
+        assert!(rule("MarketplaceService/CreateListing").matches("POST", grpc_path));
+        assert!(!rule("Listing").matches("POST", grpc_path));
+        assert!(rule("/api/marketplace/listings").matches("GET", "/api/marketplace/listings"));
+        assert!(rule("POST /api/marketplace/listings").matches("POST", "/api/marketplace/listings"));
+        assert!(!rule("POST /api/marketplace/listings").matches("GET", "/api/marketplace/listings"));
+        assert!(rule("/api/marketplace/nfts/*").matches("GET", "/api/marketplace/nfts/nft_001"));
+    }
+
+    #[test]
+    fn test_latency_config_defaults_new_fields() {
+        let latency: LatencyConfig = serde_json::from_str(
+            r#"{"min_response_time_ms":10,"max_response_time_ms":50,"distribution":"uniform","network_condition":"poor"}"#,
+        )
+        .unwrap();
+        assert_eq!(latency.seed, None);
+        assert_eq!(latency.timeout_hang_ms, 30_000);
+    }
+
+    #[test]
+    fn test_cassette_config_defaults_when_missing() {
+        let mut value = serde_json::to_value(StubConfiguration::default()).unwrap();
+        value.as_object_mut().unwrap().remove("cassette");
+
+        let config: StubConfiguration = serde_json::from_value(value).unwrap();
+        assert_eq!(config.cassette, CassetteConfig::default());
+
+        let cassette: CassetteConfig =
+            serde_json::from_str(r#"{"mode":"replay","replay_order":"request_hash"}"#).unwrap();
+        assert_eq!(cassette.mode, CassetteMode::Replay);
+        assert_eq!(cassette.replay_order, ReplayOrder::RequestHash);
+        assert_eq!(cassette.path, "stub-cassette.jsonl");
+    }
+
+    #[test]
+    fn test_specific_error_fault_defaults_to_status() {
+        let rule: SpecificError =
+            serde_json::from_str(r#"{"endpoint":"GetEvents","status_code":404,"rate":0.5}"#)
+                .unwrap();
+        assert_eq!(rule.fault, FaultKind::Status);
+    }
+
+    #[test]
+    fn test_configuration_serde_roundtrip() {
+        let config = StubConfiguration::for_service("identity-service-stub", 8083);
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains("\"distribution\":\"normal\""));
+
+        let parsed: StubConfiguration = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.base.name, config.base.name);
+        assert_eq!(parsed.base.port, config.base.port);
+    }
+}
@@ -0,0 +1,396 @@
+//! Layered stub configuration loading with hot reload
+//! Defaults, then a YAML/TOML/JSON file, then `STUB__` environment overrides
+
+use crate::config::StubConfiguration;
+use crate::stub::{SharedStub, SmartStub};
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Environment variable holding the path of the stub configuration file
+pub const CONFIG_PATH_ENV: &str = "STUB_CONFIG_PATH";
+
+/// Prefix for per-field overrides, e.g. `STUB__ERRORS__ERROR_RATE=0.2`
+pub const OVERRIDE_ENV_PREFIX: &str = "STUB__";
+
+/// How often the configuration file is checked for changes
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Check run on every resolved configuration, e.g. of a service's extension
+pub type ConfigValidator = fn(&StubConfiguration) -> Result<()>;
+
+/// Where a stub's configuration comes from
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    defaults: StubConfiguration,
+    path: Option<PathBuf>,
+    poll_interval: Duration,
+    validator: Option<ConfigValidator>,
+}
+
+impl ConfigSource {
+    #[must_use]
+    pub fn new(defaults: StubConfiguration, path: Option<PathBuf>) -> Self {
+        Self {
+            defaults,
+            path,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            validator: None,
+        }
+    }
+
+    /// Build a source whose file path is taken from `STUB_CONFIG_PATH`
+    #[must_use]
+    pub fn from_env(defaults: StubConfiguration) -> Self {
+        let path = std::env::var(CONFIG_PATH_ENV)
+            .ok()
+            .filter(|path| !path.trim().is_empty())
+            .map(PathBuf::from);
+        Self::new(defaults, path)
+    }
+
+    #[must_use]
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Reject configurations `validator` fails, at startup and on reload alike
+    #[must_use]
+    pub fn with_validator(mut self, validator: ConfigValidator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Resolve the configuration: defaults, file contents, then env overrides
+    /// # Errors
+    /// Returns an error if the file cannot be read or does not describe a valid configuration
+    pub fn load(&self) -> Result<StubConfiguration> {
+        let contents = match &self.path {
+            Some(path) => Some(
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read stub config {}", path.display()))?,
+            ),
+            None => None,
+        };
+        self.resolve(contents.as_deref())
+    }
+
+    fn resolve(&self, contents: Option<&str>) -> Result<StubConfiguration> {
+        let mut value = serde_json::to_value(&self.defaults)?;
+
+        if let (Some(path), Some(contents)) = (&self.path, contents) {
+            let file_value = parse_file(path, contents)?;
+            merge_values(&mut value, file_value);
+        }
+
+        apply_env_overrides(&mut value, std::env::vars());
+
+        let config: StubConfiguration =
+            serde_json::from_value(value).context("Invalid stub configuration")?;
+        if let Some(validator) = self.validator {
+            validator(&config).context("Invalid stub configuration")?;
+        }
+        Ok(config)
+    }
+
+    /// Watch the configuration file and push changes into the stub
+    ///
+    /// Returns `None` when no file is configured. Invalid files are logged and
+    /// ignored so the stub keeps running with its last good configuration.
+    pub fn spawn_watcher(&self, stub: SharedStub) -> Option<JoinHandle<()>> {
+        let path = self.path.clone()?;
+        let source = self.clone();
+        // Snapshot before spawning so edits made right after startup are not missed
+        let mut last_contents = std::fs::read_to_string(&path).ok();
+
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(source.poll_interval);
+
+            loop {
+                interval.tick().await;
+
+                let contents = match std::fs::read_to_string(&path) {
+                    Ok(contents) => contents,
+                    Err(err) => {
+                        warn!(path = %path.display(), error = %err, "Stub config file unreadable");
+                        continue;
+                    }
+                };
+                if last_contents.as_deref() == Some(contents.as_str()) {
+                    continue;
+                }
+                last_contents = Some(contents.clone());
+
+                let mut config = match source.resolve(Some(&contents)) {
+                    Ok(config) => config,
+                    Err(err) => {
+                        warn!(
+                            path = %path.display(),
+                            error = %err,
+                            "Ignoring invalid stub config change"
+                        );
+                        continue;
+                    }
+                };
+
+                let mut stub = stub.lock().await;
+                let running_port = stub.get_configuration().base.port;
+                if config.base.port != running_port {
+                    warn!(
+                        configured_port = config.base.port,
+                        running_port = running_port,
+                        "Port changes require a restart; keeping the running port"
+                    );
+                    config.base.port = running_port;
+                }
+
+                match stub.set_configuration(config) {
+                    Ok(()) => info!(
+                        path = %path.display(),
+                        event_type = "config_reloaded",
+                        "Stub configuration reloaded"
+                    ),
+                    Err(err) => warn!(error = %err, "Failed to apply stub configuration"),
+                }
+            }
+        }))
+    }
+}
+
+fn parse_file(path: &Path, contents: &str) -> Result<Value> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+
+    let value = match extension.as_str() {
+        "yaml" | "yml" => serde_yaml::from_str(contents)?,
+        "toml" => serde_json::to_value(toml::from_str::<toml::Value>(contents)?)?,
+        "json" => serde_json::from_str(contents)?,
+        other => return Err(anyhow!("Unsupported stub config format: '{other}'")),
+    };
+    Ok(value)
+}
+
+/// Recursively overlay `overlay` onto `base`; objects merge, everything else replaces
+fn merge_values(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Apply `ENABLE_CRYPTO` and `STUB__` overrides; keys naming no config field are logged and skipped
+fn apply_env_overrides(value: &mut Value, vars: impl IntoIterator<Item = (String, String)>) {
+    for (key, raw) in vars {
+        let (path, parsed): (Vec<String>, Value) = if key == "ENABLE_CRYPTO" {
+            // Legacy switch shared with the rest of the platform; invalid values keep the default
+            let Ok(enabled) = raw.parse::<bool>() else {
+                continue;
+            };
+            (
+                vec!["dual_mode".to_string(), "enable_crypto".to_string()],
+                Value::Bool(enabled),
+            )
+        } else if let Some(rest) = key.strip_prefix(OVERRIDE_ENV_PREFIX) {
+            // Numbers and booleans parse as JSON, anything else is taken as a string
+            (
+                rest.split("__").map(str::to_ascii_lowercase).collect(),
+                serde_json::from_str(&raw).unwrap_or(Value::String(raw)),
+            )
+        } else {
+            continue;
+        };
+
+        let target = path
+            .iter()
+            .try_fold(&mut *value, |target, segment| target.get_mut(segment));
+        match target {
+            Some(target) => *target = parsed,
+            None => warn!(key = %key, "Ignoring unknown stub config override"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Dataset, LatencyDistribution};
+    use crate::stub::ServiceStub;
+
+    fn temp_config_path(extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("stub-config-{}.{extension}", uuid::Uuid::new_v4()))
+    }
+
+    fn defaults() -> StubConfiguration {
+        StubConfiguration::for_service("config-test-stub", 9100)
+    }
+
+    #[test]
+    fn test_partial_yaml_merges_over_defaults() {
+        let path = temp_config_path("yaml");
+        let source = ConfigSource::new(defaults(), Some(path.clone()));
+        let yaml = "latency:\n  distribution: uniform\nerrors:\n  error_rate: 0.5\n";
+
+        let config = source.resolve(Some(yaml)).unwrap();
+        assert_eq!(config.base.name, "config-test-stub");
+        assert!(matches!(
+            config.latency.distribution,
+            LatencyDistribution::Uniform
+        ));
+        assert_eq!(config.latency.max_response_time_ms, 500);
+        assert!((config.errors.error_rate - 0.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_toml_file_is_supported() {
+        let path = temp_config_path("toml");
+        let source = ConfigSource::new(defaults(), Some(path));
+        let toml = "[data]\ndataset = \"minimal\"\npersist_state = true\n";
+
+        let config = source.resolve(Some(toml)).unwrap();
+        assert!(matches!(config.data.dataset, Dataset::Minimal));
+        assert!(config.data.persist_state);
+    }
+
+    #[test]
+    fn test_unknown_extension_is_rejected() {
+        let source = ConfigSource::new(defaults(), Some(PathBuf::from("stub.ini")));
+        assert!(source.resolve(Some("error_rate=1")).is_err());
+    }
+
+    #[test]
+    fn test_env_overrides_apply_by_path() {
+        let mut value = serde_json::to_value(defaults()).unwrap();
+        apply_env_overrides(
+            &mut value,
+            vec![
+                ("STUB__ERRORS__ERROR_RATE".to_string(), "0.25".to_string()),
+                ("STUB__DATA__DATASET".to_string(), "stresstest".to_string()),
+                (
+                    "STUB__DATA__STATE_PATH".to_string(),
+                    "/tmp/state.json".to_string(),
+                ),
+                ("ENABLE_CRYPTO".to_string(), "true".to_string()),
+                ("UNRELATED".to_string(), "ignored".to_string()),
+            ],
+        );
+
+        let config: StubConfiguration = serde_json::from_value(value).unwrap();
+        assert!((config.errors.error_rate - 0.25).abs() < f64::EPSILON);
+        assert!(matches!(config.data.dataset, Dataset::StressTest));
+        assert_eq!(config.data.state_path.as_deref(), Some("/tmp/state.json"));
+        assert!(config.dual_mode.enable_crypto);
+    }
+
+    #[test]
+    fn test_unknown_env_override_is_skipped() {
+        let mut value = serde_json::to_value(defaults()).unwrap();
+        apply_env_overrides(
+            &mut value,
+            vec![
+                ("STUB__LATENCY__TYPO".to_string(), "1".to_string()),
+                ("STUB__ERRORS__ERROR_RATE".to_string(), "0.25".to_string()),
+            ],
+        );
+
+        let config: StubConfiguration = serde_json::from_value(value).unwrap();
+        assert!((config.errors.error_rate - 0.25).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_extension_sections_merge_override_and_validate() {
+        #[derive(Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+        #[serde(default)]
+        struct Extension {
+            limits: Limits,
+        }
+        #[derive(Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+        #[serde(default)]
+        struct Limits {
+            max_items: u32,
+            label: String,
+        }
+
+        let path = temp_config_path("yaml");
+        let source =
+            ConfigSource::new(defaults().with_extension(&Extension::default()), Some(path))
+                .with_validator(|config| {
+                    let extension: Extension = config.extension()?;
+                    if extension.limits.max_items == 0 {
+                        return Err(anyhow!("max_items must be greater than zero"));
+                    }
+                    Ok(())
+                });
+        assert!(source.resolve(None).is_err());
+
+        let config = source
+            .resolve(Some(
+                "limits:
+  max_items: 5
+",
+            ))
+            .unwrap();
+        let extension: Extension = config.extension().unwrap();
+        assert_eq!(extension.limits.max_items, 5);
+
+        let mut value = serde_json::to_value(&config).unwrap();
+        apply_env_overrides(
+            &mut value,
+            vec![("STUB__LIMITS__LABEL".to_string(), "bulk".to_string())],
+        );
+        let config: StubConfiguration = serde_json::from_value(value).unwrap();
+        assert_eq!(
+            config.extension::<Extension>().unwrap().limits,
+            Limits {
+                max_items: 5,
+                label: "bulk".to_string(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watcher_applies_file_changes() {
+        let path = temp_config_path("yaml");
+        std::fs::write(&path, "errors:\n  error_rate: 0.0\n").unwrap();
+
+        let source = ConfigSource::new(defaults(), Some(path.clone()))
+            .with_poll_interval(Duration::from_millis(10));
+        let stub = ServiceStub::new(source.load().unwrap()).into_shared();
+        let watcher = source.spawn_watcher(stub.clone()).unwrap();
+
+        std::fs::write(&path, "errors:\n  error_rate: 1.0\nbase:\n  port: 1\n").unwrap();
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            if stub.lock().await.get_configuration().errors.error_rate > 0.5 {
+                break;
+            }
+        }
+
+        let config = stub.lock().await.get_configuration().clone();
+        watcher.abort();
+        let _ = std::fs::remove_file(&path);
+
+        assert!((config.errors.error_rate - 1.0).abs() < f64::EPSILON);
+        assert_eq!(config.base.port, 9100);
+    }
+}
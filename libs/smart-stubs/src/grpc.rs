@@ -0,0 +1,119 @@
+//! gRPC helpers for smart stub handlers
+//! Latency and error simulation shared by every tonic service stub
+
+use crate::stub::{RequestContext, SharedStub};
+use tonic::{Code, Status};
+
+/// Simulate latency and error injection for a single gRPC call
+///
+/// The stub lock is released while sleeping so concurrent calls are not
+/// serialized behind the simulated latency.
+/// # Errors
+/// Returns `Status::internal(error_message)` when an error is injected and
+/// `Status::deadline_exceeded` after a simulated timeout hang
+pub async fn simulate_call(
+    stub: &SharedStub,
+    context: &RequestContext,
+    method: &str,
+    error_message: &str,
+) -> Result<(), Status> {
+    let (latency, inject_error) = {
+        let stub = stub.lock().await;
+        (stub.sample_latency(), stub.should_inject_error_response())
+    };
+
+    tokio::time::sleep(latency.delay).await;
+
+    let stub = stub.lock().await;
+    let latency_ms = latency.delay.as_millis() as u64;
+    if latency.timed_out {
+        stub.log_response(context, method, latency_ms, 504, true);
+        return Err(Status::deadline_exceeded("Simulated request timeout"));
+    }
+    if inject_error {
+        stub.log_response(context, method, latency_ms, 500, true);
+        return Err(Status::internal(error_message));
+    }
+
+    stub.log_response(context, method, latency_ms, 200, false);
+    Ok(())
+}
+
+/// Map an HTTP-style status code from a fault rule onto the closest gRPC code
+#[must_use]
+pub fn code_for_http_status(status_code: u16) -> Code {
+    match status_code {
+        200..=299 => Code::Ok,
+        400 => Code::InvalidArgument,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::NotFound,
+        409 => Code::Aborted,
+        412 => Code::FailedPrecondition,
+        416 => Code::OutOfRange,
+        429 => Code::ResourceExhausted,
+        499 => Code::Cancelled,
+        501 => Code::Unimplemented,
+        502 | 503 => Code::Unavailable,
+        504 => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    }
+}
+
+/// HTTP-style status code for a gRPC code name such as `UNAVAILABLE`
+///
+/// Only names that map back to the same code through [`code_for_http_status`]
+/// are accepted.
+#[must_use]
+pub fn http_status_for_code_name(name: &str) -> Option<u16> {
+    let status = match name.trim().to_ascii_uppercase().as_str() {
+        "INVALID_ARGUMENT" => 400,
+        "UNAUTHENTICATED" => 401,
+        "PERMISSION_DENIED" => 403,
+        "NOT_FOUND" => 404,
+        "ABORTED" => 409,
+        "FAILED_PRECONDITION" => 412,
+        "OUT_OF_RANGE" => 416,
+        "RESOURCE_EXHAUSTED" => 429,
+        "CANCELLED" => 499,
+        "INTERNAL" => 500,
+        "UNIMPLEMENTED" => 501,
+        "UNAVAILABLE" => 503,
+        "DEADLINE_EXCEEDED" => 504,
+        _ => return None,
+    };
+    Some(status)
+}
+
+/// Build the `Status` returned for an injected fault
+#[must_use]
+pub fn status_for_http_status(status_code: u16, message: impl Into<String>) -> Status {
+    Status::new(code_for_http_status(status_code), message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_status_maps_to_grpc_code() {
+        assert_eq!(code_for_http_status(404), Code::NotFound);
+        assert_eq!(code_for_http_status(503), Code::Unavailable);
+        assert_eq!(code_for_http_status(429), Code::ResourceExhausted);
+        assert_eq!(code_for_http_status(504), Code::DeadlineExceeded);
+        assert_eq!(code_for_http_status(418), Code::Internal);
+    }
+
+    #[test]
+    fn test_code_names_roundtrip_through_http_status() {
+        for name in ["UNAVAILABLE", "not_found", "Deadline_Exceeded", "INTERNAL"] {
+            let status = http_status_for_code_name(name).unwrap();
+            let code = code_for_http_status(status);
+            assert_eq!(
+                format!("{code:?}").to_ascii_uppercase(),
+                name.replace('_', "").to_ascii_uppercase()
+            );
+        }
+        assert_eq!(http_status_for_code_name("TEAPOT"), None);
+    }
+}
@@ -0,0 +1,223 @@
+//! Latency model for smart stubs
+//! Bounded distribution sampling plus network condition jitter, tail spikes and hangs
+
+use crate::config::{LatencyConfig, LatencyDistribution, NetworkCondition};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use rand_distr::{Exp, Normal};
+use std::time::Duration;
+
+/// Resampling attempts before a bounded normal sample is clamped
+const MAX_NORMAL_RESAMPLES: usize = 8;
+
+/// One simulated response delay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    pub delay: Duration,
+    /// The delay is a tail spike well above the configured maximum
+    pub spiked: bool,
+    /// The request hangs for `timeout_hang_ms` to trigger client timeouts
+    pub timed_out: bool,
+}
+
+/// How a network condition distorts the base latency
+#[derive(Debug, Clone, Copy)]
+struct NetworkProfile {
+    multiplier: f64,
+    /// Symmetric jitter as a fraction of the base latency
+    jitter: f64,
+    spike_probability: f64,
+    spike_multiplier: (f64, f64),
+    timeouts: bool,
+}
+
+impl NetworkProfile {
+    fn for_condition(condition: &NetworkCondition) -> Self {
+        match condition {
+            NetworkCondition::Good => Self {
+                multiplier: 1.0,
+                jitter: 0.0,
+                spike_probability: 0.0,
+                spike_multiplier: (1.0, 1.0),
+                timeouts: false,
+            },
+            NetworkCondition::Poor => Self {
+                multiplier: 2.0,
+                jitter: 0.25,
+                spike_probability: 0.05,
+                spike_multiplier: (3.0, 10.0),
+                timeouts: true,
+            },
+            NetworkCondition::Variable => Self {
+                multiplier: 1.0,
+                jitter: 0.5,
+                spike_probability: 0.10,
+                spike_multiplier: (2.0, 8.0),
+                timeouts: true,
+            },
+        }
+    }
+}
+
+/// Samples response latencies from a `LatencyConfig`
+///
+/// With `LatencyConfig::seed` set the sequence of samples is reproducible.
+#[derive(Debug)]
+pub struct LatencyModel {
+    config: LatencyConfig,
+    timeout_rate: f64,
+    rng: StdRng,
+}
+
+impl LatencyModel {
+    pub fn new(config: &LatencyConfig, timeout_rate: f64) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            config: config.clone(),
+            timeout_rate,
+            rng,
+        }
+    }
+
+    /// Whether an event of `probability` happens, drawn from the same sequence as the samples
+    pub fn roll(&mut self, probability: f64) -> bool {
+        self.rng.gen::<f64>() < probability
+    }
+
+    pub fn sample(&mut self) -> LatencySample {
+        let profile = NetworkProfile::for_condition(&self.config.network_condition);
+
+        if profile.timeouts && self.rng.gen::<f64>() < self.timeout_rate {
+            return LatencySample {
+                delay: Duration::from_millis(self.config.timeout_hang_ms),
+                spiked: false,
+                timed_out: true,
+            };
+        }
+
+        let mut latency = self.sample_base() * profile.multiplier;
+
+        if profile.jitter > 0.0 {
+            latency *= 1.0 + self.rng.gen_range(-profile.jitter..=profile.jitter);
+        }
+
+        let spiked = self.rng.gen::<f64>() < profile.spike_probability;
+        if spiked {
+            let (low, high) = profile.spike_multiplier;
+            latency *= self.rng.gen_range(low..=high);
+        }
+
+        LatencySample {
+            delay: Duration::from_millis(latency.max(0.0).round() as u64),
+            spiked,
+            timed_out: false,
+        }
+    }
+
+    /// Sample in milliseconds from the configured distribution, within `[min, max]`
+    fn sample_base(&mut self) -> f64 {
+        let min = self.config.min_response_time_ms as f64;
+        let max = (self.config.max_response_time_ms as f64).max(min);
+        let span = max - min;
+        if span == 0.0 {
+            return min;
+        }
+
+        match self.config.distribution {
+            LatencyDistribution::Uniform => self.rng.gen_range(min..=max),
+            LatencyDistribution::Normal => {
+                // Six standard deviations across the range keeps ~99.7% of samples inside it
+                let normal = Normal::new(min + span / 2.0, span / 6.0)
+                    .expect("standard deviation is positive");
+                (0..MAX_NORMAL_RESAMPLES)
+                    .map(|_| normal.sample(&mut self.rng))
+                    .find(|sample| (min..=max).contains(sample))
+                    .unwrap_or_else(|| normal.sample(&mut self.rng).clamp(min, max))
+            }
+            LatencyDistribution::Exponential => {
+                // Most samples land near the minimum with a long tail towards the maximum
+                let exp = Exp::new(4.0 / span).expect("rate is positive");
+                (min + exp.sample(&mut self.rng)).min(max)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latency_config(
+        distribution: LatencyDistribution,
+        network_condition: NetworkCondition,
+    ) -> LatencyConfig {
+        LatencyConfig {
+            min_response_time_ms: 20,
+            max_response_time_ms: 200,
+            distribution,
+            network_condition,
+            seed: Some(42),
+            timeout_hang_ms: 30_000,
+        }
+    }
+
+    #[test]
+    fn test_good_network_stays_within_bounds() {
+        for distribution in [
+            LatencyDistribution::Uniform,
+            LatencyDistribution::Normal,
+            LatencyDistribution::Exponential,
+        ] {
+            let mut model =
+                LatencyModel::new(&latency_config(distribution, NetworkCondition::Good), 1.0);
+            for _ in 0..1_000 {
+                let sample = model.sample();
+                assert!(!sample.timed_out);
+                assert!((20..=200).contains(&sample.delay.as_millis()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_seeded_models_are_reproducible() {
+        let config = latency_config(LatencyDistribution::Normal, NetworkCondition::Variable);
+        let mut first = LatencyModel::new(&config, 0.1);
+        let mut second = LatencyModel::new(&config, 0.1);
+
+        for _ in 0..500 {
+            assert_eq!(first.sample(), second.sample());
+        }
+    }
+
+    #[test]
+    fn test_poor_network_hangs_at_timeout_rate() {
+        let config = latency_config(LatencyDistribution::Uniform, NetworkCondition::Poor);
+        let sample = LatencyModel::new(&config, 1.0).sample();
+
+        assert!(sample.timed_out);
+        assert_eq!(sample.delay, Duration::from_millis(30_000));
+    }
+
+    #[test]
+    fn test_variable_network_produces_tail_spikes() {
+        let config = latency_config(LatencyDistribution::Uniform, NetworkCondition::Variable);
+        let mut model = LatencyModel::new(&config, 0.0);
+        let samples: Vec<_> = (0..1_000).map(|_| model.sample()).collect();
+
+        assert!(samples.iter().any(|sample| sample.spiked));
+        assert!(samples.iter().any(|sample| sample.delay.as_millis() > 200));
+        assert!(samples.iter().all(|sample| !sample.timed_out));
+    }
+
+    #[test]
+    fn test_equal_bounds_return_fixed_latency() {
+        let mut config = latency_config(LatencyDistribution::Exponential, NetworkCondition::Good);
+        config.max_response_time_ms = config.min_response_time_ms;
+
+        let sample = LatencyModel::new(&config, 0.0).sample();
+        assert_eq!(sample.delay, Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,952 @@
+//! Tower layer that applies smart stub behaviour at the transport level
+//! Plugs into an axum `Router` (http 1.x) or a tonic `Server` (http 0.2)
+
+use crate::cassette::{header_pairs, request_hash, CassetteEntry, Transport};
+use crate::config::{CassetteMode, FaultKind};
+use crate::grpc::status_for_http_status;
+use crate::stub::{InjectedFault, RequestContext, SharedStub};
+use axum::body::{Bytes, HttpBody};
+use axum::response::IntoResponse;
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+
+/// Truncated JSON document served for `FaultKind::MalformedPayload` on HTTP routes
+const MALFORMED_JSON: &str = r#"{"data": [{"id": "stub-malformed", "value": "#;
+
+/// gRPC frame with a valid 5-byte prefix but an undecodable protobuf message
+const MALFORMED_GRPC_FRAME: [u8; 10] = [0, 0, 0, 0, 5, 0xff, 0xff, 0xff, 0xff, 0xff];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LayerMode {
+    /// Logging, latency, global error rate and endpoint fault rules
+    Full,
+    /// Only `specific_errors` rules; handlers simulate everything else
+    FaultRules,
+}
+
+/// Layer that injects stub behaviour in front of every route or RPC
+///
+/// [`StubLayer::new`] logs each request, simulates latency and injects errors,
+/// for services whose handlers do not call the stub hooks themselves.
+/// [`StubLayer::fault_rules`] only applies the per-endpoint `specific_errors`
+/// rules, for services whose handlers already simulate latency and errors.
+#[derive(Clone)]
+pub struct StubLayer {
+    stub: SharedStub,
+    mode: LayerMode,
+}
+
+impl StubLayer {
+    #[must_use]
+    pub fn new(stub: SharedStub) -> Self {
+        Self {
+            stub,
+            mode: LayerMode::Full,
+        }
+    }
+
+    #[must_use]
+    pub fn fault_rules(stub: SharedStub) -> Self {
+        Self {
+            stub,
+            mode: LayerMode::FaultRules,
+        }
+    }
+}
+
+impl<S> Layer<S> for StubLayer {
+    type Service = StubService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        StubService {
+            inner,
+            stub: self.stub.clone(),
+            mode: self.mode,
+        }
+    }
+}
+
+/// Service produced by [`StubLayer`]
+#[derive(Clone)]
+pub struct StubService<S> {
+    inner: S,
+    stub: SharedStub,
+    mode: LayerMode,
+}
+
+struct Admission {
+    context: RequestContext,
+    latency: Duration,
+    fault: Option<InjectedFault>,
+}
+
+impl Admission {
+    fn latency_ms(&self) -> u64 {
+        self.latency.as_millis() as u64
+    }
+}
+
+/// Decide what happens to a request; `None` means pass it through untouched
+async fn admit(
+    stub: &SharedStub,
+    mode: LayerMode,
+    endpoint: &str,
+    method: &str,
+) -> Option<Admission> {
+    let admission = {
+        let stub = stub.lock().await;
+        let fault = stub.select_endpoint_fault(method, endpoint);
+
+        let (latency, fault) = match mode {
+            LayerMode::Full => {
+                let latency = stub.sample_latency();
+                let simulated_status = if latency.timed_out {
+                    Some(504)
+                } else {
+                    stub.should_inject_error_response().then_some(500)
+                };
+                let fault = fault.or_else(|| {
+                    simulated_status.map(|status_code| InjectedFault {
+                        endpoint: endpoint.to_string(),
+                        status_code,
+                        kind: FaultKind::Status,
+                    })
+                });
+                (latency.delay, fault)
+            }
+            LayerMode::FaultRules => (Duration::ZERO, Some(fault?)),
+        };
+
+        let context = stub.create_context(None);
+        stub.log_request(&context, endpoint, method);
+        Admission {
+            context,
+            latency,
+            fault,
+        }
+    };
+
+    tokio::time::sleep(admission.latency).await;
+    Some(admission)
+}
+
+async fn finish(stub: &SharedStub, admission: &Admission, endpoint: &str, status: u16) {
+    let stub = stub.lock().await;
+    stub.log_response(
+        &admission.context,
+        endpoint,
+        admission.latency_ms(),
+        status,
+        admission.fault.is_some(),
+    );
+}
+
+fn http_fault_response(fault: &InjectedFault, request_id: &str) -> axum::response::Response {
+    use axum::body::{Body, Bytes};
+
+    match fault.kind {
+        FaultKind::Status => {
+            let status = http::StatusCode::from_u16(fault.status_code)
+                .unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR);
+            let body = serde_json::json!({
+                "error": "Simulated error",
+                "code": status
+                    .canonical_reason()
+                    .unwrap_or("INTERNAL_ERROR")
+                    .to_uppercase()
+                    .replace(' ', "_"),
+                "timestamp": Utc::now(),
+                "request_id": request_id,
+            });
+            (status, axum::Json(body)).into_response()
+        }
+        FaultKind::MalformedPayload => (
+            http::StatusCode::OK,
+            [(http::header::CONTENT_TYPE, "application/json")],
+            MALFORMED_JSON,
+        )
+            .into_response(),
+        FaultKind::DropConnection => {
+            let aborted = futures_util::stream::once(async {
+                Err::<Bytes, _>(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "simulated dropped connection",
+                ))
+            });
+            (http::StatusCode::OK, Body::from_stream(aborted)).into_response()
+        }
+    }
+}
+
+fn grpc_body(body: hyper014::Body) -> tonic::body::BoxBody {
+    use http_body04::Body as _;
+
+    body.map_err(|err| tonic::Status::from_error(Box::new(err)))
+        .boxed_unsync()
+}
+
+fn grpc_fault_response(fault: &InjectedFault) -> http02::Response<tonic::body::BoxBody> {
+    use hyper014::body::Bytes;
+
+    match fault.kind {
+        FaultKind::Status => status_for_http_status(
+            fault.status_code,
+            format!("Injected fault for {}", fault.endpoint),
+        )
+        .to_http(),
+        FaultKind::MalformedPayload => http02::Response::builder()
+            .header(http02::header::CONTENT_TYPE, "application/grpc")
+            .body(grpc_body(hyper014::Body::from(
+                MALFORMED_GRPC_FRAME.to_vec(),
+            )))
+            .expect("static gRPC response parts are valid"),
+        FaultKind::DropConnection => {
+            let aborted = futures_util::stream::once(async {
+                Err::<Bytes, _>(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "simulated dropped connection",
+                ))
+            });
+            http02::Response::builder()
+                .header(http02::header::CONTENT_TYPE, "application/grpc")
+                .body(grpc_body(hyper014::Body::wrap_stream(aborted)))
+                .expect("static gRPC response parts are valid")
+        }
+    }
+}
+
+/// Header carrying a caller trace ID into recorded request contexts
+const TRACE_ID_HEADER: &str = "x-trace-id";
+
+/// Method name prefix of the server-streaming RPCs (`SubscribeMarketEvents`, `SubscribeEvents`)
+const STREAMING_RPC_PREFIX: &str = "Subscribe";
+
+/// Server-streaming RPCs never end, so the cassette cannot buffer them
+fn is_streaming_rpc(path: &str) -> bool {
+    path.rsplit('/')
+        .next()
+        .is_some_and(|method| method.starts_with(STREAMING_RPC_PREFIX))
+}
+
+/// WebSocket upgrades and event streams stay open, so the cassette cannot buffer them
+fn is_streaming_http(headers: &http::HeaderMap) -> bool {
+    headers.contains_key(http::header::UPGRADE)
+        || headers
+            .get(http::header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+/// Create the context for a recorded or replayed exchange and log the request
+async fn begin_exchange(
+    stub: &SharedStub,
+    trace_id: Option<String>,
+    path: &str,
+    method: &str,
+) -> RequestContext {
+    let stub = stub.lock().await;
+    let context = stub.create_context(trace_id);
+    stub.log_request(&context, path, method);
+    context
+}
+
+fn path_and_query(uri: &http::Uri) -> String {
+    uri.path_and_query()
+        .map_or_else(|| uri.path().to_string(), ToString::to_string)
+}
+
+fn dropped_connection(endpoint: &str) -> InjectedFault {
+    InjectedFault {
+        endpoint: endpoint.to_string(),
+        status_code: 200,
+        kind: FaultKind::DropConnection,
+    }
+}
+
+async fn handle_http<S, B>(
+    mut inner: S,
+    stub: &SharedStub,
+    mode: LayerMode,
+    request: http::Request<B>,
+) -> Result<axum::response::Response, S::Error>
+where
+    S: Service<http::Request<B>, Response = axum::response::Response>,
+{
+    let endpoint = request.uri().path().to_string();
+    let method = request.method().to_string();
+    let Some(admission) = admit(stub, mode, &endpoint, &method).await else {
+        return inner.call(request).await;
+    };
+
+    if let Some(fault) = &admission.fault {
+        let response = http_fault_response(fault, &admission.context.request_id);
+        finish(stub, &admission, &endpoint, response.status().as_u16()).await;
+        return Ok(response);
+    }
+
+    let response = inner.call(request).await?;
+    finish(stub, &admission, &endpoint, response.status().as_u16()).await;
+    Ok(response)
+}
+
+async fn record_http<S, B>(
+    inner: S,
+    stub: &SharedStub,
+    mode: LayerMode,
+    request: http::Request<B>,
+) -> Result<axum::response::Response, S::Error>
+where
+    S: Service<http::Request<B>, Response = axum::response::Response>,
+    B: HttpBody<Data = Bytes> + From<Bytes> + Send + 'static,
+    B::Error: Into<axum::BoxError>,
+{
+    let (parts, body) = request.into_parts();
+    let request_body = axum::body::to_bytes(axum::body::Body::new(body), usize::MAX)
+        .await
+        .unwrap_or_default();
+    let path = path_and_query(&parts.uri);
+    let method = parts.method.to_string();
+    let trace_id = header_value(parts.headers.get(TRACE_ID_HEADER).map(|v| v.as_bytes()));
+    let context = begin_exchange(stub, trace_id, &path, &method).await;
+
+    let started = Instant::now();
+    let request = http::Request::from_parts(parts, B::from(request_body.clone()));
+    let response = handle_http(inner, stub, mode, request).await?;
+
+    let (parts, body) = response.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, usize::MAX).await else {
+        return Ok(http_fault_response(
+            &dropped_connection(&path),
+            &context.request_id,
+        ));
+    };
+
+    stub.lock().await.record_exchange(CassetteEntry {
+        sequence: 0,
+        transport: Transport::Http,
+        request_hash: request_hash(&method, &path, &request_body),
+        method,
+        path,
+        request_body: request_body.to_vec(),
+        context,
+        status: parts.status.as_u16(),
+        headers: header_pairs(
+            parts
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_bytes())),
+        ),
+        body: body.to_vec(),
+        trailers: Vec::new(),
+        latency_ms: started.elapsed().as_millis() as u64,
+    });
+
+    Ok(http::Response::from_parts(
+        parts,
+        axum::body::Body::from(body),
+    ))
+}
+
+async fn replay_http<B>(stub: &SharedStub, request: http::Request<B>) -> axum::response::Response
+where
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<axum::BoxError>,
+{
+    let (parts, body) = request.into_parts();
+    let request_body = axum::body::to_bytes(axum::body::Body::new(body), usize::MAX)
+        .await
+        .unwrap_or_default();
+    let path = path_and_query(&parts.uri);
+    let method = parts.method.to_string();
+    let trace_id = header_value(parts.headers.get(TRACE_ID_HEADER).map(|v| v.as_bytes()));
+    let context = begin_exchange(stub, trace_id, &path, &method).await;
+    let hash = request_hash(&method, &path, &request_body);
+
+    let stub = stub.lock().await;
+    let Some(entry) = stub.replay_exchange(&hash) else {
+        stub.log_response(&context, &path, 0, 404, true);
+        let body = serde_json::json!({
+            "error": "No recorded response for request",
+            "code": "CASSETTE_MISS",
+            "request_hash": hash,
+            "timestamp": Utc::now(),
+            "request_id": context.request_id,
+        });
+        return (http::StatusCode::NOT_FOUND, axum::Json(body)).into_response();
+    };
+    stub.log_response(&context, &path, 0, entry.status, false);
+
+    let mut response = http::Response::new(axum::body::Body::from(entry.body));
+    *response.status_mut() =
+        http::StatusCode::from_u16(entry.status).unwrap_or(http::StatusCode::OK);
+    for (name, value) in &entry.headers {
+        if name.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            http::HeaderName::from_bytes(name.as_bytes()),
+            http::HeaderValue::from_str(value),
+        ) {
+            response.headers_mut().append(name, value);
+        }
+    }
+    response
+}
+
+impl<S, B> Service<http::Request<B>> for StubService<S>
+where
+    S: Service<http::Request<B>, Response = axum::response::Response> + Clone + Send + 'static,
+    S::Future: Send,
+    B: HttpBody<Data = Bytes> + From<Bytes> + Send + 'static,
+    B::Error: Into<axum::BoxError>,
+{
+    type Response = axum::response::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        // Take the service that was driven to readiness and leave a clone behind
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        let stub = self.stub.clone();
+        let mode = self.mode;
+
+        Box::pin(async move {
+            // Streams pass through live in either cassette mode
+            if is_streaming_http(request.headers()) {
+                return handle_http(inner, &stub, mode, request).await;
+            }
+            let cassette = stub.lock().await.cassette();
+            match cassette.map(|cassette| cassette.mode()) {
+                Some(CassetteMode::Record) => record_http(inner, &stub, mode, request).await,
+                Some(CassetteMode::Replay) => Ok(replay_http(&stub, request).await),
+                _ => handle_http(inner, &stub, mode, request).await,
+            }
+        })
+    }
+}
+
+/// gRPC body serving a recorded message and its trailers
+struct ReplayBody {
+    data: Option<Bytes>,
+    trailers: Option<http02::HeaderMap>,
+}
+
+impl http_body04::Body for ReplayBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.data.take().map(Ok))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http02::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+}
+
+fn grpc_header_map(pairs: &[(String, String)]) -> http02::HeaderMap {
+    let mut headers = http02::HeaderMap::new();
+    for (name, value) in pairs {
+        if name.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            http02::HeaderName::from_bytes(name.as_bytes()),
+            http02::HeaderValue::from_str(value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers
+}
+
+fn grpc_header_pairs(headers: &http02::HeaderMap) -> Vec<(String, String)> {
+    header_pairs(
+        headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_bytes())),
+    )
+}
+
+fn header_value(value: Option<&[u8]>) -> Option<String> {
+    value
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .map(ToString::to_string)
+}
+
+/// Buffer a gRPC response body, keeping the trailers that carry `grpc-status`
+async fn collect_grpc_body(
+    mut body: tonic::body::BoxBody,
+) -> Result<(Vec<u8>, Option<http02::HeaderMap>), tonic::Status> {
+    use http_body04::Body as _;
+
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk?);
+    }
+    let trailers = body.trailers().await?;
+    Ok((data, trailers))
+}
+
+async fn handle_grpc<S, B>(
+    mut inner: S,
+    stub: &SharedStub,
+    mode: LayerMode,
+    request: http02::Request<B>,
+) -> Result<http02::Response<tonic::body::BoxBody>, S::Error>
+where
+    S: Service<http02::Request<B>, Response = http02::Response<tonic::body::BoxBody>>,
+{
+    let endpoint = request.uri().path().to_string();
+    let method = request.method().to_string();
+    let Some(admission) = admit(stub, mode, &endpoint, &method).await else {
+        return inner.call(request).await;
+    };
+
+    if let Some(fault) = &admission.fault {
+        finish(stub, &admission, &endpoint, fault.status_code).await;
+        return Ok(grpc_fault_response(fault));
+    }
+
+    let response = inner.call(request).await?;
+    finish(stub, &admission, &endpoint, 200).await;
+    Ok(response)
+}
+
+async fn record_grpc<S, B>(
+    inner: S,
+    stub: &SharedStub,
+    mode: LayerMode,
+    request: http02::Request<B>,
+) -> Result<http02::Response<tonic::body::BoxBody>, S::Error>
+where
+    S: Service<http02::Request<B>, Response = http02::Response<tonic::body::BoxBody>>,
+    B: http_body04::Body<Data = Bytes> + From<Bytes> + Send + 'static,
+{
+    let (parts, body) = request.into_parts();
+    let request_body = hyper014::body::to_bytes(body).await.unwrap_or_default();
+    let path = parts.uri.path().to_string();
+    let method = parts.method.to_string();
+    let trace_id = header_value(parts.headers.get(TRACE_ID_HEADER).map(|v| v.as_bytes()));
+    let context = begin_exchange(stub, trace_id, &path, &method).await;
+
+    let started = Instant::now();
+    let request = http02::Request::from_parts(parts, B::from(request_body.clone()));
+    let response = handle_grpc(inner, stub, mode, request).await?;
+
+    let (parts, body) = response.into_parts();
+    let Ok((body, trailers)) = collect_grpc_body(body).await else {
+        return Ok(grpc_fault_response(&dropped_connection(&path)));
+    };
+
+    stub.lock().await.record_exchange(CassetteEntry {
+        sequence: 0,
+        transport: Transport::Grpc,
+        request_hash: request_hash(&method, &path, &request_body),
+        method,
+        path,
+        request_body: request_body.to_vec(),
+        context,
+        status: parts.status.as_u16(),
+        headers: grpc_header_pairs(&parts.headers),
+        body: body.clone(),
+        trailers: trailers.as_ref().map(grpc_header_pairs).unwrap_or_default(),
+        latency_ms: started.elapsed().as_millis() as u64,
+    });
+
+    let body = ReplayBody {
+        data: (!body.is_empty()).then(|| Bytes::from(body)),
+        trailers,
+    };
+    Ok(http02::Response::from_parts(
+        parts,
+        http_body04::Body::boxed_unsync(body),
+    ))
+}
+
+async fn replay_grpc<B>(
+    stub: &SharedStub,
+    request: http02::Request<B>,
+) -> http02::Response<tonic::body::BoxBody>
+where
+    B: http_body04::Body<Data = Bytes> + Send + 'static,
+{
+    let (parts, body) = request.into_parts();
+    let request_body = hyper014::body::to_bytes(body).await.unwrap_or_default();
+    let path = parts.uri.path().to_string();
+    let method = parts.method.to_string();
+    let trace_id = header_value(parts.headers.get(TRACE_ID_HEADER).map(|v| v.as_bytes()));
+    let context = begin_exchange(stub, trace_id, &path, &method).await;
+    let hash = request_hash(&method, &path, &request_body);
+
+    let stub = stub.lock().await;
+    let Some(entry) = stub.replay_exchange(&hash) else {
+        stub.log_response(&context, &path, 0, 404, true);
+        return tonic::Status::not_found(format!("No recorded response for {path}")).to_http();
+    };
+    stub.log_response(&context, &path, 0, entry.status, false);
+
+    let trailers = (!entry.trailers.is_empty()).then(|| grpc_header_map(&entry.trailers));
+    let body = ReplayBody {
+        data: (!entry.body.is_empty()).then(|| Bytes::from(entry.body)),
+        trailers,
+    };
+    let mut response = http02::Response::new(http_body04::Body::boxed_unsync(body));
+    *response.status_mut() =
+        http02::StatusCode::from_u16(entry.status).unwrap_or(http02::StatusCode::OK);
+    *response.headers_mut() = grpc_header_map(&entry.headers);
+    response
+}
+
+impl<S, B> Service<http02::Request<B>> for StubService<S>
+where
+    S: Service<http02::Request<B>, Response = http02::Response<tonic::body::BoxBody>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+    B: http_body04::Body<Data = Bytes> + From<Bytes> + Send + 'static,
+{
+    type Response = http02::Response<tonic::body::BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http02::Request<B>) -> Self::Future {
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        let stub = self.stub.clone();
+        let mode = self.mode;
+
+        Box::pin(async move {
+            // Streams pass through live in either cassette mode
+            if is_streaming_rpc(request.uri().path()) {
+                return handle_grpc(inner, &stub, mode, request).await;
+            }
+            let cassette = stub.lock().await.cassette();
+            match cassette.map(|cassette| cassette.mode()) {
+                Some(CassetteMode::Record) => record_grpc(inner, &stub, mode, request).await,
+                Some(CassetteMode::Replay) => Ok(replay_grpc(&stub, request).await),
+                _ => handle_grpc(inner, &stub, mode, request).await,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{SpecificError, StubConfiguration};
+    use crate::stub::ServiceStub;
+    use crate::stub::SmartStub;
+    use axum::body::Body;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tower::{service_fn, ServiceExt};
+
+    fn shared_stub(error_rate: f64, specific_errors: Vec<SpecificError>) -> SharedStub {
+        let mut config = StubConfiguration::for_service("layer-test-stub", 9000);
+        config.errors.error_rate = error_rate;
+        config.errors.specific_errors = specific_errors;
+        config.latency.min_response_time_ms = 0;
+        config.latency.max_response_time_ms = 0;
+        config.latency.distribution = crate::config::LatencyDistribution::Uniform;
+        ServiceStub::new(config).into_shared()
+    }
+
+    fn rule(endpoint: &str, status_code: u16, fault: FaultKind) -> SpecificError {
+        SpecificError {
+            endpoint: endpoint.to_string(),
+            status_code,
+            rate: 1.0,
+            fault,
+        }
+    }
+
+    fn ok_service() -> impl Service<
+        http::Request<Body>,
+        Response = axum::response::Response,
+        Error = Infallible,
+        Future = impl Send,
+    > + Clone {
+        service_fn(|_request: http::Request<Body>| async {
+            Ok::<_, Infallible>(http::StatusCode::OK.into_response())
+        })
+    }
+
+    fn ok_grpc_service() -> impl Service<
+        http02::Request<hyper014::Body>,
+        Response = http02::Response<tonic::body::BoxBody>,
+        Error = Infallible,
+        Future = impl Send,
+    > + Clone {
+        service_fn(|_request: http02::Request<hyper014::Body>| async {
+            Ok::<_, Infallible>(http02::Response::new(tonic::body::empty_body()))
+        })
+    }
+
+    fn grpc_request(method: &str) -> http02::Request<hyper014::Body> {
+        http02::Request::post(format!("/bunkerverse.services.v1.IndexerService/{method}"))
+            .body(hyper014::Body::empty())
+            .unwrap()
+    }
+
+    /// Echoes the request body and a per-service call counter
+    fn counting_service(
+        calls: Arc<AtomicUsize>,
+    ) -> impl Service<
+        http::Request<Body>,
+        Response = axum::response::Response,
+        Error = Infallible,
+        Future = impl Send,
+    > + Clone {
+        service_fn(move |request: http::Request<Body>| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                let body = axum::body::to_bytes(request.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let echo = format!("{call}:{}", String::from_utf8_lossy(&body));
+                Ok::<_, Infallible>((http::StatusCode::CREATED, echo).into_response())
+            }
+        })
+    }
+
+    fn cassette_stub(mode: CassetteMode, path: &std::path::Path) -> SharedStub {
+        let stub = shared_stub(0.0, vec![]);
+        let mut config = stub.try_lock().unwrap().get_configuration().clone();
+        config.cassette.mode = mode;
+        config.cassette.path = path.display().to_string();
+        config.cassette.replay_order = crate::config::ReplayOrder::RequestHash;
+        stub.try_lock().unwrap().set_configuration(config).unwrap();
+        stub
+    }
+
+    async fn body_text(response: axum::response::Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_http_layer_passes_through_without_errors() {
+        let service = StubLayer::new(shared_stub(0.0, vec![])).layer(ok_service());
+        let request = http::Request::get("/health").body(Body::empty()).unwrap();
+
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_http_layer_injects_errors() {
+        let service = StubLayer::new(shared_stub(1.0, vec![])).layer(ok_service());
+        let request = http::Request::get("/health").body(Body::empty()).unwrap();
+
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_fault_rules_ignore_global_error_rate() {
+        let stub = shared_stub(1.0, vec![rule("/api/other", 503, FaultKind::Status)]);
+        let service = StubLayer::fault_rules(stub).layer(ok_service());
+        let request = http::Request::get("/health").body(Body::empty()).unwrap();
+
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_http_rule_uses_configured_status() {
+        let stub = shared_stub(0.0, vec![rule("GET /health", 429, FaultKind::Status)]);
+        let service = StubLayer::fault_rules(stub).layer(ok_service());
+        let request = http::Request::get("/health").body(Body::empty()).unwrap();
+
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_http_malformed_payload_is_not_json() {
+        let stub = shared_stub(0.0, vec![rule("/health", 200, FaultKind::MalformedPayload)]);
+        let service = StubLayer::fault_rules(stub).layer(ok_service());
+        let request = http::Request::get("/health").body(Body::empty()).unwrap();
+
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(serde_json::from_slice::<serde_json::Value>(&body).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_drop_connection_aborts_body() {
+        let stub = shared_stub(0.0, vec![rule("/health", 200, FaultKind::DropConnection)]);
+        let service = StubLayer::fault_rules(stub).layer(ok_service());
+        let request = http::Request::get("/health").body(Body::empty()).unwrap();
+
+        let response = service.oneshot(request).await.unwrap();
+        assert!(axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_grpc_layer_injects_internal_status() {
+        let service = StubLayer::new(shared_stub(1.0, vec![])).layer(ok_grpc_service());
+
+        let response = service.oneshot(grpc_request("GetEvents")).await.unwrap();
+        let status = tonic::Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), tonic::Code::Internal);
+    }
+
+    #[tokio::test]
+    async fn test_grpc_rule_maps_status_code() {
+        let stub = shared_stub(0.0, vec![rule("GetEvents", 503, FaultKind::Status)]);
+        let service = StubLayer::fault_rules(stub);
+
+        let response = service
+            .clone()
+            .layer(ok_grpc_service())
+            .oneshot(grpc_request("GetEvents"))
+            .await
+            .unwrap();
+        let status = tonic::Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+
+        let response = service
+            .layer(ok_grpc_service())
+            .oneshot(grpc_request("Health"))
+            .await
+            .unwrap();
+        assert!(tonic::Status::from_header_map(response.headers()).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_http_exchanges_replay_from_cassette() {
+        let path = std::env::temp_dir().join(format!("layer-{}.jsonl", uuid::Uuid::new_v4()));
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let recorder = StubLayer::fault_rules(cassette_stub(CassetteMode::Record, &path))
+            .layer(counting_service(calls.clone()));
+        for body in ["a", "b"] {
+            let request = http::Request::post("/api/echo?x=1")
+                .body(Body::from(body))
+                .unwrap();
+            let response = recorder.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), http::StatusCode::CREATED);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let replayer = StubLayer::fault_rules(cassette_stub(CassetteMode::Replay, &path))
+            .layer(counting_service(calls.clone()));
+        let request = http::Request::post("/api/echo?x=1")
+            .body(Body::from("b"))
+            .unwrap();
+        let response = replayer.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::CREATED);
+        assert_eq!(body_text(response).await, "1:b");
+
+        let request = http::Request::post("/api/echo")
+            .body(Body::from("c"))
+            .unwrap();
+        let response = replayer.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_grpc_status_trailers_survive_replay() {
+        let path = std::env::temp_dir().join(format!("layer-{}.jsonl", uuid::Uuid::new_v4()));
+        let failing = service_fn(|_request: http02::Request<hyper014::Body>| async {
+            let mut trailers = http02::HeaderMap::new();
+            trailers.insert("grpc-status", http02::HeaderValue::from_static("5"));
+            let body = ReplayBody {
+                data: Some(Bytes::from_static(&[0, 0, 0, 0, 0])),
+                trailers: Some(trailers),
+            };
+            Ok::<_, Infallible>(http02::Response::new(http_body04::Body::boxed_unsync(body)))
+        });
+
+        StubLayer::fault_rules(cassette_stub(CassetteMode::Record, &path))
+            .layer(failing)
+            .oneshot(grpc_request("GetEvents"))
+            .await
+            .unwrap();
+
+        let response = StubLayer::fault_rules(cassette_stub(CassetteMode::Replay, &path))
+            .layer(ok_grpc_service())
+            .oneshot(grpc_request("GetEvents"))
+            .await
+            .unwrap();
+        let (body, trailers) = collect_grpc_body(response.into_body()).await.unwrap();
+        assert_eq!(body, vec![0, 0, 0, 0, 0]);
+        let status = tonic::Status::from_header_map(&trailers.unwrap()).unwrap();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_grpc_subscription_streams_while_recording() {
+        use futures_util::StreamExt;
+        use http_body04::Body as _;
+
+        let path = std::env::temp_dir().join(format!("layer-{}.jsonl", uuid::Uuid::new_v4()));
+        let subscription = service_fn(|_request: http02::Request<hyper014::Body>| async {
+            let frames = futures_util::stream::once(async {
+                Ok::<_, std::io::Error>(Bytes::from_static(&[0, 0, 0, 0, 0]))
+            })
+            .chain(futures_util::stream::pending());
+            let body = grpc_body(hyper014::Body::wrap_stream(frames));
+            Ok::<_, Infallible>(http02::Response::new(body))
+        });
+
+        let first_frame = async {
+            let response = StubLayer::fault_rules(cassette_stub(CassetteMode::Record, &path))
+                .layer(subscription)
+                .oneshot(grpc_request("SubscribeEvents"))
+                .await
+                .unwrap();
+            response.into_body().data().await
+        };
+        let frame = tokio::time::timeout(Duration::from_secs(1), first_frame)
+            .await
+            .expect("first frame arrives while the stream stays open")
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.as_ref(), &[0, 0, 0, 0, 0]);
+        assert!(std::fs::read_to_string(&path)
+            .unwrap_or_default()
+            .is_empty());
+
+        std::fs::remove_file(path).ok();
+    }
+}
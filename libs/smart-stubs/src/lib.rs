@@ -0,0 +1,32 @@
+//! BUNKERVERSE Platform - Smart Stub Framework
+//! Shared configuration, latency/error simulation, request logging,
+//! transport layers and the fixture world used by every service smart stub
+
+pub mod admin;
+pub mod cassette;
+pub mod clock;
+pub mod config;
+pub mod config_source;
+pub mod grpc;
+pub mod latency;
+pub mod layer;
+pub mod scenario;
+pub mod state;
+pub mod stub;
+pub mod world;
+
+pub use cassette::{Cassette, CassetteEntry, CassettePlayer, CassetteRecorder};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use config::*;
+pub use config_source::ConfigSource;
+pub use latency::{LatencyModel, LatencySample};
+pub use layer::{StubLayer, StubService};
+pub use scenario::{Scenario, ScenarioCursor, ScenarioStep};
+pub use state::{
+    spawn_state_reset, FileStateBackend, PersistedState, RedbStateBackend, StateBackend,
+};
+pub use stub::*;
+pub use world::{paginate, World, WorldSize};
+
+/// Version information for the smart stub framework
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,428 @@
+//! Scenario scripting for smart stubs
+//! Ordered YAML steps that inject endpoint faults and change health over time
+
+use crate::config::{endpoint_matches, FaultKind};
+use crate::grpc::http_status_for_code_name;
+use crate::stub::{HealthStatus, InjectedFault};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Scripted chaos scenario uploaded through `/stub/scenario`
+///
+/// ```yaml
+/// name: listing-outage
+/// steps:
+///   - endpoint: CreateListing
+///     calls: 3
+///     fail: UNAVAILABLE
+///   - after: 10s
+///     health: degraded
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    pub steps: Vec<ScenarioStep>,
+}
+
+/// One scenario step
+///
+/// A step becomes active `after` the previous one completes. On activation it
+/// applies `health`; with an `endpoint` it then stays active for the next
+/// `calls` matching requests, failing them when `fail` is set.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ScenarioStep {
+    /// Delay such as `10s` or `2m` before the step activates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// Endpoint pattern, matched like `SpecificError::endpoint`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Matching calls covered by the step, defaults to 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calls: Option<u32>,
+    /// gRPC code name (`UNAVAILABLE`) or HTTP status (`503`) for failed calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fail: Option<String>,
+    #[serde(default)]
+    pub fault: FaultKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthStatus>,
+}
+
+impl Scenario {
+    /// Parse a scenario from YAML (JSON is accepted as a YAML subset)
+    ///
+    /// # Errors
+    /// Returns an error if the document does not parse or a step is invalid
+    pub fn from_yaml(contents: &str) -> Result<Self> {
+        let scenario: Self = serde_yaml::from_str(contents)?;
+        scenario.compile()?;
+        Ok(scenario)
+    }
+
+    fn compile(&self) -> Result<Vec<CompiledStep>> {
+        if self.steps.is_empty() {
+            bail!("scenario has no steps");
+        }
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                step.compile()
+                    .map_err(|err| anyhow!("step {}: {err}", index + 1))
+            })
+            .collect()
+    }
+}
+
+impl ScenarioStep {
+    fn compile(&self) -> Result<CompiledStep> {
+        let after = self
+            .after
+            .as_deref()
+            .map(|after| {
+                common_rust::time::parse_duration(after)
+                    .map_err(|err| anyhow!("invalid delay '{after}': {err}"))
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        let status_code = self
+            .fail
+            .as_deref()
+            .map(|fail| {
+                fail.trim()
+                    .parse::<u16>()
+                    .ok()
+                    .or_else(|| http_status_for_code_name(fail))
+                    .ok_or_else(|| anyhow!("unknown failure '{fail}'"))
+            })
+            .transpose()?;
+
+        if self.endpoint.is_none() {
+            if self.calls.is_some() || self.fail.is_some() {
+                bail!("'calls' and 'fail' require an 'endpoint'");
+            }
+            if self.health.is_none() && self.after.is_none() {
+                bail!("step needs an 'endpoint', 'health' or 'after'");
+            }
+        }
+        if self.calls == Some(0) {
+            bail!("'calls' must be at least 1");
+        }
+
+        Ok(CompiledStep {
+            after,
+            calls: self.endpoint.as_ref().map(|_| self.calls.unwrap_or(1)),
+            status_code,
+        })
+    }
+
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(after) = &self.after {
+            parts.push(format!("after {after}"));
+        }
+        if let Some(health) = &self.health {
+            parts.push(format!("health becomes {}", health.as_str()));
+        }
+        if let Some(endpoint) = &self.endpoint {
+            let calls = self.calls.unwrap_or(1);
+            match &self.fail {
+                Some(fail) => {
+                    parts.push(format!("next {calls} calls to {endpoint} fail with {fail}"))
+                }
+                None => parts.push(format!("next {calls} calls to {endpoint} succeed")),
+            }
+        }
+        parts.join(", ")
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CompiledStep {
+    after: Duration,
+    /// Matching calls to wait for; `None` completes on activation
+    calls: Option<u32>,
+    status_code: Option<u16>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepState {
+    /// Waiting for the step's `after` delay
+    Waiting,
+    /// Counting matching calls
+    Active,
+    /// Every step has completed
+    Finished,
+}
+
+/// Position of a running scenario, reported by `GET /stub/scenario`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioCursor {
+    pub name: String,
+    pub started_at: DateTime<Utc>,
+    /// Zero-based index of the current step; equals `total_steps` when finished
+    pub step_index: usize,
+    pub total_steps: usize,
+    pub state: StepState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calls_remaining: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activates_in_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthStatus>,
+}
+
+/// A scenario being executed against a stub
+#[derive(Debug)]
+pub struct ScenarioRun {
+    scenario: Scenario,
+    steps: Vec<CompiledStep>,
+    started_at: DateTime<Utc>,
+    cursor: usize,
+    /// When the current step became current, the base for its `after` delay
+    step_started: Instant,
+    activated: bool,
+    calls_seen: u32,
+    health: Option<HealthStatus>,
+}
+
+impl ScenarioRun {
+    /// Start running a scenario now
+    ///
+    /// # Errors
+    /// Returns an error if a step is invalid
+    pub fn start(scenario: Scenario) -> Result<Self> {
+        Self::start_at(scenario, Instant::now())
+    }
+
+    fn start_at(scenario: Scenario, now: Instant) -> Result<Self> {
+        let steps = scenario.compile()?;
+        let mut run = Self {
+            scenario,
+            steps,
+            started_at: Utc::now(),
+            cursor: 0,
+            step_started: now,
+            activated: false,
+            calls_seen: 0,
+            health: None,
+        };
+        run.advance(now);
+        Ok(run)
+    }
+
+    /// Health set by the most recent activated `health` step
+    pub fn health(&mut self) -> Option<HealthStatus> {
+        self.health_at(Instant::now())
+    }
+
+    fn health_at(&mut self, now: Instant) -> Option<HealthStatus> {
+        self.advance(now);
+        self.health.clone()
+    }
+
+    /// Count a request against the active step, returning the fault to inject
+    pub fn on_call(&mut self, method: &str, path: &str) -> Option<InjectedFault> {
+        self.on_call_at(method, path, Instant::now())
+    }
+
+    fn on_call_at(&mut self, method: &str, path: &str, now: Instant) -> Option<InjectedFault> {
+        self.advance(now);
+        if !self.activated || self.cursor >= self.steps.len() {
+            return None;
+        }
+
+        let step = self.steps[self.cursor];
+        let definition = &self.scenario.steps[self.cursor];
+        let endpoint = definition.endpoint.as_deref()?;
+        if !endpoint_matches(endpoint, method, path) {
+            return None;
+        }
+
+        let fault = step.status_code.map(|status_code| InjectedFault {
+            endpoint: path.to_string(),
+            status_code,
+            kind: definition.fault,
+        });
+
+        self.calls_seen += 1;
+        if step.calls.is_some_and(|calls| self.calls_seen >= calls) {
+            self.complete_step(now);
+            self.advance(now);
+        }
+        fault
+    }
+
+    pub fn cursor(&mut self) -> ScenarioCursor {
+        self.cursor_at(Instant::now())
+    }
+
+    fn cursor_at(&mut self, now: Instant) -> ScenarioCursor {
+        self.advance(now);
+        let current = self.steps.get(self.cursor).copied();
+
+        let state = match current {
+            None => StepState::Finished,
+            Some(_) if self.activated => StepState::Active,
+            Some(_) => StepState::Waiting,
+        };
+        let activates_in_ms = current.filter(|_| !self.activated).map(|step| {
+            (self.step_started + step.after)
+                .saturating_duration_since(now)
+                .as_millis() as u64
+        });
+
+        ScenarioCursor {
+            name: self.scenario.name.clone(),
+            started_at: self.started_at,
+            step_index: self.cursor,
+            total_steps: self.steps.len(),
+            state,
+            description: self
+                .scenario
+                .steps
+                .get(self.cursor)
+                .map(ScenarioStep::describe),
+            calls_remaining: current
+                .and_then(|step| step.calls)
+                .map(|calls| calls - self.calls_seen),
+            activates_in_ms,
+            health: self.health.clone(),
+        }
+    }
+
+    /// Activate and complete steps whose delays have elapsed
+    fn advance(&mut self, now: Instant) {
+        while let Some(step) = self.steps.get(self.cursor).copied() {
+            if self.activated {
+                return;
+            }
+
+            let activation = self.step_started + step.after;
+            if now < activation {
+                return;
+            }
+
+            self.activated = true;
+            if let Some(health) = &self.scenario.steps[self.cursor].health {
+                self.health = Some(health.clone());
+            }
+            if step.calls.is_some() {
+                return;
+            }
+            // Timed steps hand over at their activation time, not when observed
+            self.complete_step(activation);
+        }
+    }
+
+    fn complete_step(&mut self, now: Instant) {
+        self.cursor += 1;
+        self.step_started = now;
+        self.activated = false;
+        self.calls_seen = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRPC_PATH: &str = "/bunkerverse.services.v1.MarketplaceService/CreateListing";
+
+    fn run(yaml: &str, now: Instant) -> ScenarioRun {
+        ScenarioRun::start_at(Scenario::from_yaml(yaml).unwrap(), now).unwrap()
+    }
+
+    #[test]
+    fn test_next_calls_fail_then_succeed() {
+        let now = Instant::now();
+        let mut run = run(
+            "steps:\n  - endpoint: CreateListing\n    calls: 3\n    fail: UNAVAILABLE\n",
+            now,
+        );
+
+        assert!(run.on_call_at("POST", "/other", now).is_none());
+        for _ in 0..3 {
+            let fault = run.on_call_at("POST", GRPC_PATH, now).unwrap();
+            assert_eq!(fault.status_code, 503);
+        }
+        assert!(run.on_call_at("POST", GRPC_PATH, now).is_none());
+        assert_eq!(run.cursor_at(now).state, StepState::Finished);
+    }
+
+    #[test]
+    fn test_health_changes_after_delay() {
+        let now = Instant::now();
+        let mut run = run("steps:\n  - after: 10s\n    health: degraded\n", now);
+
+        assert_eq!(run.health_at(now + Duration::from_secs(9)), None);
+        let cursor = run.cursor_at(now + Duration::from_secs(9));
+        assert_eq!(cursor.state, StepState::Waiting);
+        assert_eq!(cursor.activates_in_ms, Some(1_000));
+
+        assert_eq!(
+            run.health_at(now + Duration::from_secs(10)),
+            Some(HealthStatus::Degraded)
+        );
+        assert_eq!(
+            run.cursor_at(now + Duration::from_secs(10)).state,
+            StepState::Finished
+        );
+    }
+
+    #[test]
+    fn test_cursor_tracks_active_step() {
+        let now = Instant::now();
+        let mut run = run(
+            r#"
+name: outage
+steps:
+  - endpoint: CreateListing
+    calls: 2
+    fail: "503"
+  - after: 5s
+    health: unhealthy
+  - endpoint: "GET /health"
+"#,
+            now,
+        );
+
+        let cursor = run.cursor_at(now);
+        assert_eq!(cursor.name, "outage");
+        assert_eq!((cursor.step_index, cursor.total_steps), (0, 3));
+        assert_eq!(cursor.calls_remaining, Some(2));
+        assert_eq!(
+            cursor.description.as_deref(),
+            Some("next 2 calls to CreateListing fail with 503")
+        );
+
+        run.on_call_at("POST", GRPC_PATH, now);
+        run.on_call_at("POST", GRPC_PATH, now);
+        let cursor = run.cursor_at(now + Duration::from_secs(1));
+        assert_eq!((cursor.step_index, cursor.state), (1, StepState::Waiting));
+
+        let later = now + Duration::from_secs(6);
+        let cursor = run.cursor_at(later);
+        assert_eq!((cursor.step_index, cursor.state), (2, StepState::Active));
+        assert_eq!(cursor.health, Some(HealthStatus::Unhealthy));
+        assert!(run.on_call_at("GET", "/health", later).is_none());
+        assert_eq!(run.cursor_at(later).state, StepState::Finished);
+    }
+
+    #[test]
+    fn test_invalid_steps_are_rejected() {
+        assert!(Scenario::from_yaml("steps: []").is_err());
+        assert!(Scenario::from_yaml("steps:\n  - fail: UNAVAILABLE\n").is_err());
+        assert!(Scenario::from_yaml("steps:\n  - endpoint: X\n    fail: TEAPOT\n").is_err());
+        assert!(Scenario::from_yaml("steps:\n  - after: soon\n").is_err());
+        assert!(Scenario::from_yaml("steps:\n  - endpoint: X\n    calls: 0\n").is_err());
+    }
+}
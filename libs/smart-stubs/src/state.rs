@@ -0,0 +1,291 @@
+//! Persistent stub state
+//! Pluggable `StateManager` backends and the periodic state reset task
+
+use crate::config::{DataConfig, StateBackendKind};
+use crate::stub::SharedStub;
+use anyhow::{anyhow, Context as _, Result};
+use chrono::{DateTime, Utc};
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Longest wait between checks for an elapsed `state_reset_interval`
+pub const STATE_RESET_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Directory used when `DataConfig::state_path` is not set
+const DEFAULT_STATE_DIR: &str = "stub-state";
+
+const ENTRIES_TABLE: TableDefinition<&str, &str> = TableDefinition::new("stub_state");
+const META_TABLE: TableDefinition<&str, &str> = TableDefinition::new("stub_state_meta");
+const LAST_RESET_KEY: &str = "last_reset";
+
+/// Stub state as stored by a [`StateBackend`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedState {
+    pub last_reset: DateTime<Utc>,
+    pub entries: HashMap<String, String>,
+}
+
+impl PersistedState {
+    #[must_use]
+    pub fn empty(last_reset: DateTime<Utc>) -> Self {
+        Self {
+            last_reset,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+/// Storage for `StateManager` state that survives restarts
+pub trait StateBackend: Send + Sync + fmt::Debug {
+    /// Load the stored state, `None` if nothing has been saved yet
+    ///
+    /// # Errors
+    /// Returns an error if the stored state cannot be read
+    fn load(&self) -> Result<Option<PersistedState>>;
+
+    /// Replace the stored state
+    ///
+    /// # Errors
+    /// Returns an error if the state cannot be written
+    fn save(&self, state: &PersistedState) -> Result<()>;
+}
+
+/// Backend for a service's `DataConfig`, `None` when `persist_state` is off
+///
+/// # Errors
+/// Returns an error if the backend storage cannot be opened
+pub fn backend_for(
+    config: &DataConfig,
+    service_name: &str,
+) -> Result<Option<Box<dyn StateBackend>>> {
+    if !config.persist_state {
+        return Ok(None);
+    }
+
+    let extension = match config.state_backend {
+        StateBackendKind::File => "json",
+        StateBackendKind::Redb => "redb",
+    };
+    let path = config.state_path.as_ref().map_or_else(
+        || PathBuf::from(DEFAULT_STATE_DIR).join(format!("{service_name}.{extension}")),
+        PathBuf::from,
+    );
+
+    let backend: Box<dyn StateBackend> = match config.state_backend {
+        StateBackendKind::File => Box::new(FileStateBackend::new(path)),
+        StateBackendKind::Redb => Box::new(RedbStateBackend::open(path)?),
+    };
+    Ok(Some(backend))
+}
+
+/// Parse `DataConfig::state_reset_interval`; an empty string disables resets
+///
+/// # Errors
+/// Returns an error if the interval is not a valid duration such as `24h`
+pub fn parse_reset_interval(interval: &str) -> Result<Option<Duration>> {
+    if interval.trim().is_empty() {
+        return Ok(None);
+    }
+    let duration = common_rust::time::parse_duration(interval)
+        .map_err(|err| anyhow!("invalid state_reset_interval '{interval}': {err}"))?;
+    Ok((!duration.is_zero()).then_some(duration))
+}
+
+fn ensure_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating state directory {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+/// JSON document on the local filesystem, replaced atomically on every save
+#[derive(Debug)]
+pub struct FileStateBackend {
+    path: PathBuf,
+}
+
+impl FileStateBackend {
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl StateBackend for FileStateBackend {
+    fn load(&self) -> Result<Option<PersistedState>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map(Some)
+                .with_context(|| format!("parsing state file {}", self.path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => {
+                Err(err).with_context(|| format!("reading state file {}", self.path.display()))
+            }
+        }
+    }
+
+    fn save(&self, state: &PersistedState) -> Result<()> {
+        ensure_parent_dir(&self.path)?;
+        let temp_path = self.path.with_extension("tmp");
+        std::fs::write(&temp_path, serde_json::to_vec_pretty(state)?)
+            .with_context(|| format!("writing state file {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, &self.path)
+            .with_context(|| format!("replacing state file {}", self.path.display()))
+    }
+}
+
+/// Embedded redb database holding one row per state key
+pub struct RedbStateBackend {
+    path: PathBuf,
+    db: Database,
+}
+
+impl fmt::Debug for RedbStateBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedbStateBackend")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RedbStateBackend {
+    /// Open or create the database at `path`
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be created or opened
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        ensure_parent_dir(&path)?;
+        let db = Database::create(&path)
+            .with_context(|| format!("opening state database {}", path.display()))?;
+        Ok(Self { path, db })
+    }
+}
+
+impl StateBackend for RedbStateBackend {
+    fn load(&self) -> Result<Option<PersistedState>> {
+        let txn = self.db.begin_read()?;
+        let meta = match txn.open_table(META_TABLE) {
+            Ok(meta) => meta,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let Some(last_reset) = meta.get(LAST_RESET_KEY)? else {
+            return Ok(None);
+        };
+        let last_reset = DateTime::parse_from_rfc3339(last_reset.value())
+            .context("parsing stored last_reset")?
+            .with_timezone(&Utc);
+
+        let mut entries = HashMap::new();
+        let table = txn.open_table(ENTRIES_TABLE)?;
+        for row in table.iter()? {
+            let (key, value) = row?;
+            entries.insert(key.value().to_string(), value.value().to_string());
+        }
+
+        Ok(Some(PersistedState {
+            last_reset,
+            entries,
+        }))
+    }
+
+    fn save(&self, state: &PersistedState) -> Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            txn.delete_table(ENTRIES_TABLE)?;
+            let mut table = txn.open_table(ENTRIES_TABLE)?;
+            for (key, value) in &state.entries {
+                table.insert(key.as_str(), value.as_str())?;
+            }
+            let mut meta = txn.open_table(META_TABLE)?;
+            meta.insert(LAST_RESET_KEY, state.last_reset.to_rfc3339().as_str())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+/// Periodically reset stub state once `state_reset_interval` has elapsed
+///
+/// The interval is re-read on every check so configuration reloads apply.
+pub fn spawn_state_reset(stub: SharedStub) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let wait = {
+                let stub = stub.lock().await;
+                stub.reset_state_if_due(Utc::now());
+                stub.state_reset_interval()
+                    .map_or(STATE_RESET_CHECK_INTERVAL, |interval| {
+                        interval.min(STATE_RESET_CHECK_INTERVAL)
+                    })
+            };
+            tokio::time::sleep(wait).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(extension: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("stub-state-{}", uuid::Uuid::new_v4()))
+            .join(format!("state.{extension}"))
+    }
+
+    fn sample_state() -> PersistedState {
+        let mut state = PersistedState::empty(Utc::now());
+        state
+            .entries
+            .insert("listing:1".to_string(), r#"{"price":"10"}"#.to_string());
+        state
+    }
+
+    fn assert_roundtrip(backend: &dyn StateBackend) {
+        assert_eq!(backend.load().unwrap(), None);
+
+        let state = sample_state();
+        backend.save(&state).unwrap();
+        assert_eq!(backend.load().unwrap(), Some(state.clone()));
+
+        let emptied = PersistedState::empty(state.last_reset);
+        backend.save(&emptied).unwrap();
+        assert_eq!(backend.load().unwrap(), Some(emptied));
+    }
+
+    #[test]
+    fn test_file_backend_roundtrip() {
+        let path = temp_path("json");
+        assert_roundtrip(&FileStateBackend::new(&path));
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_redb_backend_roundtrip() {
+        let path = temp_path("redb");
+        assert_roundtrip(&RedbStateBackend::open(&path).unwrap());
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_reset_interval_parsing() {
+        assert_eq!(
+            parse_reset_interval("24h").unwrap(),
+            Some(Duration::from_secs(86_400))
+        );
+        assert_eq!(parse_reset_interval("").unwrap(), None);
+        assert_eq!(parse_reset_interval("0s").unwrap(), None);
+        assert!(parse_reset_interval("daily").is_err());
+    }
+}
@@ -0,0 +1,1094 @@
+//! Core smart stub traits and the shared `ServiceStub` implementation
+//! Latency simulation, error injection, request logging and stub state
+
+use crate::cassette::{Cassette, CassetteEntry};
+use crate::config::{CassetteConfig, CryptoResponseMode, DataConfig, FaultKind, StubConfiguration};
+use crate::latency::{LatencyModel, LatencySample};
+use crate::scenario::{Scenario, ScenarioCursor, ScenarioRun};
+use crate::state::{backend_for, parse_reset_interval, PersistedState, StateBackend};
+use crate::world::World;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use parking_lot::lock_api::{ArcMutexGuard, ArcRwLockReadGuard};
+use parking_lot::{RawMutex, RawRwLock, RwLock};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Stub handle shared between the HTTP and gRPC servers of a service
+pub type SharedStub = Arc<tokio::sync::Mutex<ServiceStub>>;
+
+/// Read access to the fixture world, shared with other readers
+///
+/// Not `Send`, so it cannot be held across an `.await` in a served request.
+pub type WorldGuard = ArcRwLockReadGuard<RawRwLock, World>;
+
+/// Service state derived from the fixture world and kept beside it
+///
+/// Built from the world the first time a service asks for it and dropped
+/// whenever the world is regenerated or replaced, so it always describes the
+/// current world. A stub keeps one state type at a time.
+pub trait WorldState: Any + Send {
+    /// State describing `world` as it stands
+    fn from_world(world: &World) -> Self;
+}
+
+type ServiceState = Option<Box<dyn Any + Send>>;
+
+/// Fixture world and the service state derived from it, replaced together
+#[derive(Debug)]
+struct WorldCell {
+    world: Arc<RwLock<World>>,
+    /// Locked after `world`, never before
+    state: Arc<parking_lot::Mutex<ServiceState>>,
+}
+
+impl WorldCell {
+    fn new(world: World) -> Self {
+        Self {
+            world: Arc::new(RwLock::new(world)),
+            state: Arc::default(),
+        }
+    }
+}
+
+type SharedWorld = Arc<WorldCell>;
+
+/// Keep `S` in the state slot, building it from `world` unless it holds one already
+fn ensure_state<S: WorldState>(slot: &mut ServiceState, world: &World) {
+    if !slot.as_ref().is_some_and(|state| state.is::<S>()) {
+        *slot = Some(Box::new(S::from_world(world)));
+    }
+}
+
+/// Read access to the fixture world together with a service's state `S`
+///
+/// Dereferences to the world. Holds the state until dropped, so like
+/// [`WorldGuard`] it must not be held across an `.await`.
+pub struct WorldStateGuard<S> {
+    world: WorldGuard,
+    state: ArcMutexGuard<RawMutex, ServiceState>,
+    kind: PhantomData<S>,
+}
+
+impl<S: WorldState> WorldStateGuard<S> {
+    /// Service state describing the guarded world
+    #[must_use]
+    pub fn state(&self) -> &S {
+        self.state
+            .as_ref()
+            .and_then(|state| state.downcast_ref())
+            .expect("state kind is checked when the guard is taken")
+    }
+}
+
+impl<S> Deref for WorldStateGuard<S> {
+    type Target = World;
+
+    fn deref(&self) -> &World {
+        &self.world
+    }
+}
+
+const DEFAULT_CRYPTO_UNAVAILABLE_MESSAGE: &str = "Cryptocurrency features are not available";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub version: String,
+    pub status: String,
+    pub uptime: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HealthStatus {
+    #[serde(alias = "healthy")]
+    Healthy,
+    #[serde(alias = "degraded")]
+    Degraded,
+    #[serde(alias = "unhealthy")]
+    Unhealthy,
+}
+
+impl HealthStatus {
+    /// Status string reported by the HTTP and gRPC health endpoints
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Healthy => "HEALTHY",
+            Self::Degraded => "DEGRADED",
+            Self::Unhealthy => "UNHEALTHY",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestContext {
+    pub request_id: String,
+    pub trace_id: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub enable_crypto: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StubResponse<T> {
+    pub data: Option<T>,
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub injected_error: bool,
+}
+
+/// Fault chosen for a request by a matching `SpecificError` rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InjectedFault {
+    pub endpoint: String,
+    pub status_code: u16,
+    pub kind: FaultKind,
+}
+
+pub trait SmartStub {
+    fn get_service_info(&self) -> ServiceInfo;
+    fn health_check(&self) -> HealthStatus;
+    fn reset_state(&mut self) -> Result<()>;
+    fn get_configuration(&self) -> &StubConfiguration;
+    fn set_configuration(&mut self, config: StubConfiguration) -> Result<()>;
+}
+
+pub trait ResponseGenerator<TRequest, TResponse> {
+    fn generate_response(
+        &self,
+        request: TRequest,
+        context: RequestContext,
+    ) -> StubResponse<TResponse>;
+    fn should_inject_error(&self, request: &TRequest, context: &RequestContext) -> bool;
+    fn calculate_latency(&self, request: &TRequest, context: &RequestContext) -> Duration;
+}
+
+pub trait StateManager {
+    fn get_state<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Option<T>;
+    fn set_state<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()>;
+    fn clear_state(&mut self, key: &str) -> Result<()>;
+    fn reset_all_state(&mut self) -> Result<()>;
+}
+
+/// Smart stub core used by every service stub
+#[derive(Debug)]
+pub struct ServiceStub {
+    config: StubConfiguration,
+    state: Arc<Mutex<PersistedState>>,
+    state_backend: Option<Arc<dyn StateBackend>>,
+    start_time: DateTime<Utc>,
+    crypto_unavailable_message: String,
+    latency_model: Mutex<LatencyModel>,
+    cassette: Option<Arc<Cassette>>,
+    scenario: Mutex<Option<ScenarioRun>>,
+    world: Mutex<Option<SharedWorld>>,
+    /// Revision of the fixture world, bumped whenever it is updated or replaced
+    world_updates: watch::Sender<u64>,
+}
+
+impl ServiceStub {
+    pub fn new(config: StubConfiguration) -> Self {
+        let latency_model = Mutex::new(LatencyModel::new(
+            &config.latency,
+            config.errors.timeout_rate,
+        ));
+        let cassette = open_cassette(&config.base.name, &config.cassette);
+        let state_backend = open_state_backend(&config);
+        let state = state_backend
+            .as_deref()
+            .and_then(|backend| load_state(&config.base.name, backend))
+            .unwrap_or_else(|| PersistedState::empty(Utc::now()));
+        warn_on_invalid_reset_interval(&config);
+
+        let world = load_world_snapshot(&config.base.name, &config.data);
+        let (world_updates, _) = watch::channel(0);
+        let stub = Self {
+            config,
+            state: Arc::new(Mutex::new(state)),
+            state_backend,
+            start_time: Utc::now(),
+            crypto_unavailable_message: DEFAULT_CRYPTO_UNAVAILABLE_MESSAGE.to_string(),
+            latency_model,
+            cassette,
+            scenario: Mutex::new(None),
+            world: Mutex::new(world),
+            world_updates,
+        };
+        stub.reset_state_if_due(Utc::now());
+        stub
+    }
+
+    /// Message returned by `check_crypto_features` in `CryptoResponseMode::Error`
+    #[must_use]
+    pub fn with_crypto_unavailable_message(mut self, message: &str) -> Self {
+        self.crypto_unavailable_message = message.to_string();
+        self
+    }
+
+    /// Wrap the stub in a handle that can be shared across servers and layers
+    #[must_use]
+    pub fn into_shared(self) -> SharedStub {
+        Arc::new(tokio::sync::Mutex::new(self))
+    }
+
+    pub fn create_context(&self, trace_id: Option<String>) -> RequestContext {
+        RequestContext {
+            request_id: Uuid::new_v4().to_string(),
+            trace_id,
+            timestamp: Utc::now(),
+            enable_crypto: self.config.dual_mode.enable_crypto,
+        }
+    }
+
+    /// Sample the next simulated latency, including tail spikes and timeout hangs
+    pub fn sample_latency(&self) -> LatencySample {
+        self.latency_model.lock().unwrap().sample()
+    }
+
+    pub fn calculate_response_latency(&self) -> Duration {
+        self.sample_latency().delay
+    }
+
+    /// Roll `error_rate`; seeded like the latency samples, so seeded runs repeat
+    pub fn should_inject_error_response(&self) -> bool {
+        self.latency_model
+            .lock()
+            .unwrap()
+            .roll(self.config.errors.error_rate)
+    }
+
+    /// Fault for a request from the running scenario or the `specific_errors` rules
+    pub fn select_endpoint_fault(&self, method: &str, path: &str) -> Option<InjectedFault> {
+        if let Some(fault) = self
+            .scenario
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|run| run.on_call(method, path))
+        {
+            return Some(fault);
+        }
+
+        let mut latency_model = self.latency_model.lock().unwrap();
+        self.config
+            .errors
+            .specific_errors
+            .iter()
+            .filter(|rule| rule.matches(method, path))
+            .find(|rule| latency_model.roll(rule.rate))
+            .map(|rule| InjectedFault {
+                endpoint: rule.endpoint.clone(),
+                status_code: rule.status_code,
+                kind: rule.fault,
+            })
+    }
+
+    pub fn log_request(&self, context: &RequestContext, endpoint: &str, method: &str) {
+        info!(
+            timestamp = %context.timestamp,
+            stub_name = %self.config.base.name,
+            stub_version = %self.config.base.version,
+            event_type = "request_received",
+            endpoint = endpoint,
+            method = method,
+            request_id = %context.request_id,
+            enable_crypto = context.enable_crypto,
+            "Request received"
+        );
+    }
+
+    pub fn log_response(
+        &self,
+        context: &RequestContext,
+        endpoint: &str,
+        latency_ms: u64,
+        status: u16,
+        error_injected: bool,
+    ) {
+        info!(
+            timestamp = %Utc::now(),
+            stub_name = %self.config.base.name,
+            stub_version = %self.config.base.version,
+            event_type = "response_sent",
+            endpoint = endpoint,
+            request_id = %context.request_id,
+            enable_crypto = context.enable_crypto,
+            simulated_latency_ms = latency_ms,
+            response_status = status,
+            error_injected = error_injected,
+            "Response sent"
+        );
+    }
+
+    /// Replace any running scenario with `scenario`, starting it now
+    ///
+    /// # Errors
+    /// Returns an error if a scenario step is invalid
+    pub fn start_scenario(&self, scenario: Scenario) -> Result<ScenarioCursor> {
+        let mut run = ScenarioRun::start(scenario)?;
+        let cursor = run.cursor();
+        info!(
+            stub_name = %self.config.base.name,
+            event_type = "scenario_started",
+            scenario = %cursor.name,
+            steps = cursor.total_steps,
+            "Scenario started"
+        );
+        *self.scenario.lock().unwrap() = Some(run);
+        Ok(cursor)
+    }
+
+    pub fn scenario_cursor(&self) -> Option<ScenarioCursor> {
+        self.scenario
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(ScenarioRun::cursor)
+    }
+
+    /// Stop the running scenario, returning whether one was running
+    pub fn clear_scenario(&self) -> bool {
+        let cleared = self.scenario.lock().unwrap().take().is_some();
+        if cleared {
+            info!(
+                stub_name = %self.config.base.name,
+                event_type = "scenario_cleared",
+                "Scenario cleared"
+            );
+        }
+        cleared
+    }
+
+    /// Parsed `state_reset_interval`, `None` when disabled or invalid
+    pub fn state_reset_interval(&self) -> Option<Duration> {
+        parse_reset_interval(&self.config.data.state_reset_interval)
+            .ok()
+            .flatten()
+    }
+
+    /// Clear state if `state_reset_interval` has elapsed since the last reset
+    pub fn reset_state_if_due(&self, now: DateTime<Utc>) -> bool {
+        let Some(interval) = self.state_reset_interval() else {
+            return false;
+        };
+        let mut state = self.state.lock().unwrap();
+        let elapsed = now
+            .signed_duration_since(state.last_reset)
+            .to_std()
+            .unwrap_or_default();
+        if elapsed < interval {
+            return false;
+        }
+
+        *state = PersistedState::empty(now);
+        self.persist(&state);
+        info!(
+            stub_name = %self.config.base.name,
+            event_type = "state_reset",
+            reason = "interval",
+            interval = %self.config.data.state_reset_interval,
+            "State reset completed"
+        );
+        true
+    }
+
+    fn persist(&self, state: &PersistedState) {
+        let Some(backend) = &self.state_backend else {
+            return;
+        };
+        if let Err(err) = backend.save(state) {
+            warn!(
+                stub_name = %self.config.base.name,
+                event_type = "state_persist_failed",
+                error = %err,
+                "Failed to persist stub state"
+            );
+        }
+    }
+
+    /// Fixture world for the configured dataset and seed
+    ///
+    /// Comes from the configured `world_snapshot` when one loaded at startup,
+    /// otherwise generated on first use and again after the dataset or seed changes.
+    /// Updates wait until the returned guard is dropped, so drop it before
+    /// calling [`ServiceStub::update_world`].
+    pub fn world(&self) -> WorldGuard {
+        self.shared_world().world.read_arc()
+    }
+
+    /// Fixture world together with the service state `S` derived from it
+    ///
+    /// Builds the state on first use. Drop the guard before calling
+    /// [`ServiceStub::update_world_with`].
+    pub fn world_with<S: WorldState>(&self) -> WorldStateGuard<S> {
+        let cell = self.shared_world();
+        let world = cell.world.read_arc();
+        let mut state = cell.state.lock_arc();
+        ensure_state::<S>(&mut state, &world);
+        WorldStateGuard {
+            world,
+            state,
+            kind: PhantomData,
+        }
+    }
+
+    /// Mutate the fixture world in place once current readers are done with it
+    ///
+    /// Services keeping a [`WorldState`] change the world through
+    /// [`ServiceStub::update_world_with`] instead, so the state stays in step.
+    pub fn update_world<R>(&self, update: impl FnOnce(&mut World) -> R) -> R {
+        let cell = self.shared_world();
+        let mut world = cell.world.write();
+        let result = update(&mut world);
+        drop(world);
+        self.announce_world_update();
+        result
+    }
+
+    /// Mutate the fixture world together with the service state `S` derived from it
+    pub fn update_world_with<S: WorldState, R>(
+        &self,
+        update: impl FnOnce(&mut World, &mut S) -> R,
+    ) -> R {
+        let cell = self.shared_world();
+        let mut world = cell.world.write();
+        let mut slot = cell.state.lock();
+        let mut state = match slot.take().map(|state| state.downcast::<S>()) {
+            Some(Ok(state)) => state,
+            _ => Box::new(S::from_world(&world)),
+        };
+        let result = update(&mut world, &mut state);
+        *slot = Some(state);
+        drop(slot);
+        drop(world);
+        self.announce_world_update();
+        result
+    }
+
+    fn announce_world_update(&self) {
+        self.world_updates
+            .send_modify(|revision| *revision = revision.wrapping_add(1));
+    }
+
+    fn shared_world(&self) -> SharedWorld {
+        let mut world = self.world.lock().unwrap();
+        Arc::clone(
+            world.get_or_insert_with(|| generate_world(&self.config.base.name, &self.config.data)),
+        )
+    }
+
+    /// Notified whenever the fixture world is updated or replaced
+    pub fn subscribe_world_updates(&self) -> watch::Receiver<u64> {
+        self.world_updates.subscribe()
+    }
+
+    /// Replace the fixture world with a validated snapshot
+    pub fn replace_world(&self, world: World) {
+        info!(
+            stub_name = %self.config.base.name,
+            event_type = "world_replaced",
+            players = world.players.len(),
+            nfts = world.nfts.len(),
+            listings = world.listings.len(),
+            events = world.events.len(),
+            "Fixture world replaced from snapshot"
+        );
+        *self.world.lock().unwrap() = Some(Arc::new(WorldCell::new(world)));
+        self.announce_world_update();
+    }
+
+    /// Cassette the transport layer records to or replays from, if any
+    pub fn cassette(&self) -> Option<Arc<Cassette>> {
+        self.cassette.clone()
+    }
+
+    /// Log a completed exchange and append it to the recording cassette
+    pub fn record_exchange(&self, entry: CassetteEntry) {
+        self.log_response(
+            &entry.context,
+            &entry.path,
+            entry.latency_ms,
+            entry.status,
+            false,
+        );
+
+        let Some(Cassette::Recorder(recorder)) = self.cassette.as_deref() else {
+            return;
+        };
+        if let Err(err) = recorder.record(entry) {
+            warn!(
+                stub_name = %self.config.base.name,
+                event_type = "cassette_write_failed",
+                error = %err,
+                "Failed to record exchange"
+            );
+        }
+    }
+
+    /// Recorded exchange to replay for a request, if the stub is replaying
+    pub fn replay_exchange(&self, request_hash: &str) -> Option<CassetteEntry> {
+        match self.cassette.as_deref() {
+            Some(Cassette::Player(player)) => player.next(request_hash),
+            _ => None,
+        }
+    }
+
+    pub fn check_crypto_features(&self, context: &RequestContext) -> Result<(), String> {
+        if !context.enable_crypto {
+            match self.config.dual_mode.crypto_response_mode {
+                CryptoResponseMode::Disabled => Err("FEATURE_NOT_ENABLED".to_string()),
+                CryptoResponseMode::Error => Err(self.crypto_unavailable_message.clone()),
+                CryptoResponseMode::Mock => Ok(()),
+            }
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Open the configured state backend, logging instead of failing stub construction
+fn open_state_backend(config: &StubConfiguration) -> Option<Arc<dyn StateBackend>> {
+    match backend_for(&config.data, &config.base.name) {
+        Ok(backend) => backend.map(Arc::from),
+        Err(err) => {
+            error!(
+                stub_name = %config.base.name,
+                event_type = "state_backend_failed",
+                error = %err,
+                "Failed to open state backend; state will not be persisted"
+            );
+            None
+        }
+    }
+}
+
+fn load_state(stub_name: &str, backend: &dyn StateBackend) -> Option<PersistedState> {
+    match backend.load() {
+        Ok(state) => {
+            if let Some(state) = &state {
+                info!(
+                    stub_name = %stub_name,
+                    event_type = "state_restored",
+                    entries = state.entries.len(),
+                    last_reset = %state.last_reset,
+                    "Persisted state restored"
+                );
+            }
+            state
+        }
+        Err(err) => {
+            error!(
+                stub_name = %stub_name,
+                event_type = "state_load_failed",
+                error = %err,
+                "Failed to load persisted state; starting empty"
+            );
+            None
+        }
+    }
+}
+
+fn warn_on_invalid_reset_interval(config: &StubConfiguration) {
+    if let Err(err) = parse_reset_interval(&config.data.state_reset_interval) {
+        warn!(
+            stub_name = %config.base.name,
+            event_type = "invalid_state_reset_interval",
+            error = %err,
+            "State will not be reset automatically"
+        );
+    }
+}
+
+fn generate_world(stub_name: &str, config: &DataConfig) -> SharedWorld {
+    let started = std::time::Instant::now();
+    let world = World::generate(config.dataset.clone(), config.seed);
+    info!(
+        stub_name = %stub_name,
+        event_type = "world_generated",
+        dataset = ?config.dataset,
+        seed = config.seed,
+        players = world.players.len(),
+        nfts = world.nfts.len(),
+        listings = world.listings.len(),
+        events = world.events.len(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Fixture world generated"
+    );
+    Arc::new(WorldCell::new(world))
+}
+
+/// Load the configured world snapshot, logging instead of failing stub
+/// construction; a rejected snapshot falls back to the generated world
+fn load_world_snapshot(stub_name: &str, config: &DataConfig) -> Option<SharedWorld> {
+    let path = config.world_snapshot.as_deref()?;
+    match World::load_snapshot(path) {
+        Ok(world) => {
+            info!(
+                stub_name = %stub_name,
+                event_type = "world_snapshot_loaded",
+                path = %path,
+                players = world.players.len(),
+                nfts = world.nfts.len(),
+                listings = world.listings.len(),
+                events = world.events.len(),
+                "Fixture world loaded from snapshot"
+            );
+            Some(Arc::new(WorldCell::new(world)))
+        }
+        Err(err) => {
+            error!(
+                stub_name = %stub_name,
+                event_type = "world_snapshot_rejected",
+                path = %path,
+                error = %format_args!("{err:#}"),
+                "Failed to load world snapshot, generating from seed instead"
+            );
+            None
+        }
+    }
+}
+
+/// Open the configured cassette, logging instead of failing stub construction
+fn open_cassette(stub_name: &str, config: &CassetteConfig) -> Option<Arc<Cassette>> {
+    match Cassette::open(config) {
+        Ok(cassette) => {
+            if let Some(cassette) = &cassette {
+                info!(
+                    stub_name = %stub_name,
+                    event_type = "cassette_opened",
+                    mode = ?cassette.mode(),
+                    path = %config.path,
+                    "Cassette opened"
+                );
+            }
+            cassette.map(Arc::new)
+        }
+        Err(err) => {
+            error!(
+                stub_name = %stub_name,
+                event_type = "cassette_open_failed",
+                path = %config.path,
+                error = %err,
+                "Failed to open cassette; requests will reach the handlers"
+            );
+            None
+        }
+    }
+}
+
+impl SmartStub for ServiceStub {
+    fn get_service_info(&self) -> ServiceInfo {
+        ServiceInfo {
+            name: self.config.base.name.clone(),
+            version: self.config.base.version.clone(),
+            status: if self.config.base.enabled {
+                "active"
+            } else {
+                "inactive"
+            }
+            .to_string(),
+            uptime: Utc::now()
+                .signed_duration_since(self.start_time)
+                .to_std()
+                .unwrap_or_default(),
+        }
+    }
+
+    fn health_check(&self) -> HealthStatus {
+        if !self.config.base.enabled {
+            return HealthStatus::Unhealthy;
+        }
+        self.scenario
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(ScenarioRun::health)
+            .unwrap_or(HealthStatus::Healthy)
+    }
+
+    fn reset_state(&mut self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        *state = PersistedState::empty(Utc::now());
+        self.persist(&state);
+        info!(
+            stub_name = %self.config.base.name,
+            event_type = "state_reset",
+            "State reset completed"
+        );
+        Ok(())
+    }
+
+    fn get_configuration(&self) -> &StubConfiguration {
+        &self.config
+    }
+
+    fn set_configuration(&mut self, config: StubConfiguration) -> Result<()> {
+        let old_crypto = self.config.dual_mode.enable_crypto;
+        let new_crypto = config.dual_mode.enable_crypto;
+
+        // Rebuilding the model restarts a seeded latency sequence from the top
+        *self.latency_model.lock().unwrap() =
+            LatencyModel::new(&config.latency, config.errors.timeout_rate);
+        if config.cassette != self.config.cassette {
+            self.cassette = open_cassette(&config.base.name, &config.cassette);
+        }
+        let data_changed = config.data != self.config.data;
+        let world_changed = (
+            &config.data.dataset,
+            config.data.seed,
+            &config.data.world_snapshot,
+        ) != (
+            &self.config.data.dataset,
+            self.config.data.seed,
+            &self.config.data.world_snapshot,
+        );
+        self.config = config;
+        if world_changed {
+            *self.world.lock().unwrap() =
+                load_world_snapshot(&self.config.base.name, &self.config.data);
+            self.announce_world_update();
+        }
+        if data_changed {
+            warn_on_invalid_reset_interval(&self.config);
+            self.state_backend = open_state_backend(&self.config);
+            // Carry the in-memory state over to the newly configured backend
+            self.persist(&self.state.lock().unwrap());
+        }
+
+        if old_crypto != new_crypto {
+            info!(
+                stub_name = %self.config.base.name,
+                event_type = "mode_switched",
+                enable_crypto = new_crypto,
+                "Dual-mode configuration changed"
+            );
+        }
+
+        info!(
+            stub_name = %self.config.base.name,
+            event_type = "config_updated",
+            "Configuration updated"
+        );
+
+        Ok(())
+    }
+}
+
+impl StateManager for ServiceStub {
+    fn get_state<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Option<T> {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .get(key)
+            .and_then(|v| serde_json::from_str(v).ok())
+    }
+
+    fn set_state<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let json = serde_json::to_string(value)?;
+        state.entries.insert(key.to_string(), json);
+        self.persist(&state);
+
+        info!(
+            stub_name = %self.config.base.name,
+            event_type = "state_updated",
+            key = key,
+            "State updated"
+        );
+
+        Ok(())
+    }
+
+    fn clear_state(&mut self, key: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.entries.remove(key).is_some() {
+            self.persist(&state);
+        }
+        Ok(())
+    }
+
+    fn reset_all_state(&mut self) -> Result<()> {
+        self.reset_state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Dataset;
+
+    fn test_config(error_rate: f64) -> StubConfiguration {
+        let mut config = StubConfiguration::for_service("test-service-stub", 9000);
+        config.errors.error_rate = error_rate;
+        config.dual_mode.enable_crypto = false;
+        config
+    }
+
+    #[test]
+    fn test_error_injection_respects_rate_bounds() {
+        assert!(!ServiceStub::new(test_config(0.0)).should_inject_error_response());
+        assert!(ServiceStub::new(test_config(1.0)).should_inject_error_response());
+    }
+
+    #[test]
+    fn test_endpoint_fault_only_fires_for_matching_rules() {
+        let mut config = test_config(0.0);
+        config.errors.specific_errors = vec![
+            crate::config::SpecificError {
+                endpoint: "GetEvents".to_string(),
+                status_code: 404,
+                rate: 0.0,
+                fault: FaultKind::Status,
+            },
+            crate::config::SpecificError {
+                endpoint: "GetEvents".to_string(),
+                status_code: 503,
+                rate: 1.0,
+                fault: FaultKind::DropConnection,
+            },
+        ];
+        let stub = ServiceStub::new(config);
+
+        let fault = stub
+            .select_endpoint_fault("POST", "/bunkerverse.services.v1.IndexerService/GetEvents")
+            .unwrap();
+        assert_eq!(fault.status_code, 503);
+        assert_eq!(fault.kind, FaultKind::DropConnection);
+        assert!(stub
+            .select_endpoint_fault("POST", "/bunkerverse.services.v1.IndexerService/Health")
+            .is_none());
+    }
+
+    #[test]
+    fn test_latency_stays_within_configured_bounds() {
+        let mut config = test_config(0.0);
+        config.latency.distribution = crate::config::LatencyDistribution::Uniform;
+        config.latency.min_response_time_ms = 5;
+        config.latency.max_response_time_ms = 10;
+        let stub = ServiceStub::new(config);
+
+        for _ in 0..100 {
+            let latency = stub.calculate_response_latency().as_millis();
+            assert!((5..=10).contains(&latency));
+        }
+    }
+
+    #[test]
+    fn test_seeded_latency_restarts_on_reconfigure() {
+        let mut config = test_config(0.0);
+        config.latency.seed = Some(7);
+        let mut stub = ServiceStub::new(config.clone());
+
+        let first: Vec<_> = (0..20).map(|_| stub.calculate_response_latency()).collect();
+        stub.set_configuration(config).unwrap();
+        let second: Vec<_> = (0..20).map(|_| stub.calculate_response_latency()).collect();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_seeded_error_injection_is_reproducible() {
+        let mut config = test_config(0.5);
+        config.latency.seed = Some(7);
+        let rolls = || {
+            let stub = ServiceStub::new(config.clone());
+            (0..50)
+                .map(|_| stub.should_inject_error_response())
+                .collect::<Vec<_>>()
+        };
+
+        let first = rolls();
+        assert_eq!(first, rolls());
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+
+    #[test]
+    fn test_scenario_drives_faults_and_health() {
+        let stub = ServiceStub::new(test_config(0.0));
+        let scenario = Scenario::from_yaml(
+            "steps:\n  - health: degraded\n    endpoint: GetEvents\n    fail: NOT_FOUND\n",
+        )
+        .unwrap();
+        stub.start_scenario(scenario).unwrap();
+
+        assert_eq!(stub.health_check(), HealthStatus::Degraded);
+        let fault = stub.select_endpoint_fault("POST", "/x.IndexerService/GetEvents");
+        assert_eq!(fault.map(|fault| fault.status_code), Some(404));
+        assert!(stub
+            .select_endpoint_fault("POST", "/x.IndexerService/GetEvents")
+            .is_none());
+
+        assert!(stub.clear_scenario());
+        assert!(stub.scenario_cursor().is_none());
+        assert_eq!(stub.health_check(), HealthStatus::Healthy);
+    }
+
+    #[test]
+    fn test_crypto_check_uses_service_message() {
+        let mut config = test_config(0.0);
+        config.dual_mode.crypto_response_mode = CryptoResponseMode::Error;
+        let stub = ServiceStub::new(config).with_crypto_unavailable_message("No crypto here");
+        let context = stub.create_context(None);
+
+        assert_eq!(
+            stub.check_crypto_features(&context),
+            Err("No crypto here".to_string())
+        );
+    }
+
+    #[test]
+    fn test_state_roundtrip_and_reset() {
+        let mut stub = ServiceStub::new(test_config(0.0));
+        stub.set_state("player", &42u32).unwrap();
+        assert_eq!(stub.get_state::<u32>("player"), Some(42));
+
+        stub.reset_all_state().unwrap();
+        assert_eq!(stub.get_state::<u32>("player"), None);
+    }
+
+    #[test]
+    fn test_persisted_state_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("stub-state-{}", Uuid::new_v4()));
+        let mut config = test_config(0.0);
+        config.data.persist_state = true;
+        config.data.state_path = Some(dir.join("stub.json").display().to_string());
+
+        let mut stub = ServiceStub::new(config.clone());
+        stub.set_state("listing", &"nft_001").unwrap();
+        drop(stub);
+
+        let restarted = ServiceStub::new(config);
+        assert_eq!(
+            restarted.get_state::<String>("listing").as_deref(),
+            Some("nft_001")
+        );
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_state_resets_after_interval() {
+        let mut config = test_config(0.0);
+        config.data.state_reset_interval = "1h".to_string();
+        let mut stub = ServiceStub::new(config);
+        stub.set_state("player", &1u32).unwrap();
+
+        let now = Utc::now();
+        assert!(!stub.reset_state_if_due(now + chrono::Duration::minutes(59)));
+        assert_eq!(stub.get_state::<u32>("player"), Some(1));
+
+        assert!(stub.reset_state_if_due(now + chrono::Duration::minutes(61)));
+        assert_eq!(stub.get_state::<u32>("player"), None);
+    }
+
+    #[test]
+    fn test_world_follows_dataset_and_seed() {
+        let mut config = test_config(0.0);
+        config.data.dataset = Dataset::Minimal;
+        let mut stub = ServiceStub::new(config.clone());
+        let world = stub.world();
+        assert_eq!(world.players.len(), 10);
+        assert!(Arc::ptr_eq(
+            WorldGuard::rwlock(&world),
+            WorldGuard::rwlock(&stub.world())
+        ));
+
+        config.data.seed += 1;
+        stub.set_configuration(config).unwrap();
+        assert_ne!(
+            stub.world().players[0].player_id,
+            world.players[0].player_id
+        );
+    }
+
+    #[test]
+    fn test_world_updates_in_place() {
+        let mut config = test_config(0.0);
+        config.data.dataset = Dataset::Minimal;
+        let stub = ServiceStub::new(config);
+        let world = stub.world();
+        let shared = Arc::clone(WorldGuard::rwlock(&world));
+        let player_id = world.players[0].player_id.clone();
+        drop(world);
+
+        stub.update_world(|world| {
+            world.player_mut(&player_id).unwrap().bunker_tag = "renamed".to_string();
+        });
+        let world = stub.world();
+        assert!(Arc::ptr_eq(&shared, WorldGuard::rwlock(&world)));
+        assert_eq!(world.player(&player_id).unwrap().bunker_tag, "renamed");
+    }
+
+    /// Players named "renamed", counted when the world is loaded and kept up to date
+    #[derive(Debug)]
+    struct RenamedPlayers(usize);
+
+    impl WorldState for RenamedPlayers {
+        fn from_world(world: &World) -> Self {
+            Self(
+                world
+                    .players
+                    .iter()
+                    .filter(|player| player.bunker_tag == "renamed")
+                    .count(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_world_state_follows_its_world() {
+        let mut config = test_config(0.0);
+        config.data.dataset = Dataset::Minimal;
+        let mut stub = ServiceStub::new(config.clone());
+        let player_id = stub.world().players[0].player_id.clone();
+        assert_eq!(stub.world_with::<RenamedPlayers>().state().0, 0);
+        let mut updates = stub.subscribe_world_updates();
+
+        stub.update_world_with(|world, renamed: &mut RenamedPlayers| {
+            world.player_mut(&player_id).unwrap().bunker_tag = "renamed".to_string();
+            renamed.0 += 1;
+        });
+        assert!(updates.has_changed().unwrap());
+        updates.mark_unchanged();
+        let world = stub.world_with::<RenamedPlayers>();
+        assert_eq!(world.player(&player_id).unwrap().bunker_tag, "renamed");
+        assert_eq!(world.state().0, 1);
+        drop(world);
+
+        // A new world starts over with state built from it
+        config.data.seed += 1;
+        stub.set_configuration(config).unwrap();
+        assert_eq!(stub.world_with::<RenamedPlayers>().state().0, 0);
+        assert!(updates.has_changed().unwrap());
+    }
+
+    #[test]
+    fn test_world_snapshot_loads_at_startup() {
+        let dir = std::env::temp_dir().join(format!("stub-world-{}", Uuid::new_v4()));
+        let path = dir.join("world.json");
+        World::generate(Dataset::Minimal, 99)
+            .save_snapshot(&path)
+            .unwrap();
+
+        let mut config = test_config(0.0);
+        config.data.dataset = Dataset::Minimal;
+        config.data.world_snapshot = Some(path.to_string_lossy().into_owned());
+        let stub = ServiceStub::new(config.clone());
+        assert_eq!(stub.world().seed, 99);
+
+        // A snapshot with dangling references is rejected in favour of the seed
+        let mut broken = World::generate(Dataset::Minimal, 99);
+        broken.missions[0].player_id = "ghost".to_string();
+        broken.save_snapshot(&path).unwrap();
+        let stub = ServiceStub::new(config.clone());
+        assert_eq!(stub.world().seed, config.data.seed);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,2597 @@
+//! Seeded fixture world shared by every service smart stub
+//! Players, robots, NFTs, listings, missions and chain events sized per `Dataset`
+
+use crate::config::Dataset;
+use anyhow::{bail, Context as _, Result};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::ops::Range;
+use std::path::Path;
+
+/// Unix timestamp of the first generated block (2025-01-01T00:00:00Z)
+pub const GENESIS_TIMESTAMP: i64 = 1_735_689_600;
+
+/// Seconds between generated L3 blocks
+pub const BLOCK_TIME_SECS: i64 = 12;
+
+/// One NTC expressed in wei
+pub const NTC_WEI: u64 = 1_000_000_000_000_000_000;
+
+/// Version stamped on every generated event and NFT
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Largest page served by [`paginate`], matching `PaginationProto`
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// Version of the JSON world snapshot document
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// Integrity violations listed in a rejected snapshot's error
+const REPORTED_VIOLATIONS: usize = 10;
+
+const DEFAULT_PAGE_SIZE: u32 = 20;
+/// Credits charged per milli-NTC of a listing price when crypto is off
+const CREDITS_PER_MILLI_NTC_DIVISOR: u64 = 5;
+const MAX_EVENTS_PER_BLOCK: u64 = 4;
+const MAX_STAT: u32 = 1000;
+
+/// Listings stay open for years so fixtures do not age out of the market
+const LISTING_DURATION_DAYS: Range<i64> = 1095..1825;
+
+const EQUIPMENT_SLOTS: [ItemType; 4] = [
+    ItemType::Head,
+    ItemType::Torso,
+    ItemType::Gear,
+    ItemType::Accessory,
+];
+
+const TAG_PREFIXES: &[&str] = &[
+    "Rust", "Iron", "Neon", "Ghost", "Cinder", "Static", "Chrome", "Ash", "Volt", "Hollow", "Echo",
+    "Scrap", "Null", "Feral", "Quartz", "Vapor",
+];
+
+const TAG_SUFFIXES: &[&str] = &[
+    "Viper", "Warden", "Fox", "Hound", "Drifter", "Raven", "Golem", "Mantis", "Specter", "Jackal",
+    "Nomad", "Sentry", "Wraith", "Lynx", "Bolt", "Reaver",
+];
+
+const CONSTRUCT_ORIGINS: &[&str] = &[
+    "Factory Alpha",
+    "Factory Beta",
+    "Scrapyard Delta",
+    "Nexus Forge",
+    "Outer Rim Foundry",
+    "Deepcore Vault",
+];
+
+const MISSION_TYPES: &[&str] = &["daily", "weekly", "story", "achievement"];
+
+const MISSION_NAMES: &[&str] = &[
+    "Salvage Run",
+    "Perimeter Sweep",
+    "Signal Recovery",
+    "Vault Breach",
+    "Convoy Escort",
+    "Reactor Reboot",
+    "Wasteland Survey",
+    "Data Heist",
+];
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Entity counts generated for a [`Dataset`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldSize {
+    pub players: usize,
+    /// Item NFTs minted per player on top of their Bunkerguard robot
+    pub items_per_player: usize,
+    /// Upper bound on open listings; only tradable, unequipped items are listed
+    pub listings: usize,
+    pub missions_per_player: usize,
+}
+
+impl WorldSize {
+    #[must_use]
+    pub const fn for_dataset(dataset: &Dataset) -> Self {
+        match dataset {
+            Dataset::Minimal => Self {
+                players: 10,
+                items_per_player: 4,
+                listings: 8,
+                missions_per_player: 2,
+            },
+            Dataset::Development => Self {
+                players: 1_000,
+                items_per_player: 5,
+                listings: 1_000,
+                missions_per_player: 3,
+            },
+            Dataset::StressTest => Self {
+                players: 100_000,
+                items_per_player: 3,
+                listings: 50_000,
+                missions_per_player: 1,
+            },
+        }
+    }
+}
+
+/// Mirrors `BunkerClassProto`; discriminants match the proto values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum BunkerClass {
+    Explorer = 1,
+    Pathfinder = 2,
+    Cybermancer = 3,
+    Vanguard = 4,
+    Enforcer = 5,
+    Scavenger = 6,
+    Stalker = 7,
+    Disruptor = 8,
+    Codebreaker = 9,
+    Overlord = 10,
+    Breacher = 11,
+    Reclaimer = 12,
+}
+
+impl BunkerClass {
+    pub const ALL: [Self; 12] = [
+        Self::Explorer,
+        Self::Pathfinder,
+        Self::Cybermancer,
+        Self::Vanguard,
+        Self::Enforcer,
+        Self::Scavenger,
+        Self::Stalker,
+        Self::Disruptor,
+        Self::Codebreaker,
+        Self::Overlord,
+        Self::Breacher,
+        Self::Reclaimer,
+    ];
+
+    #[must_use]
+    pub fn from_proto(value: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|class| *class as i32 == value)
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Explorer => "explorer",
+            Self::Pathfinder => "pathfinder",
+            Self::Cybermancer => "cybermancer",
+            Self::Vanguard => "vanguard",
+            Self::Enforcer => "enforcer",
+            Self::Scavenger => "scavenger",
+            Self::Stalker => "stalker",
+            Self::Disruptor => "disruptor",
+            Self::Codebreaker => "codebreaker",
+            Self::Overlord => "overlord",
+            Self::Breacher => "breacher",
+            Self::Reclaimer => "reclaimer",
+        }
+    }
+}
+
+/// Mirrors `ClassAffiliationProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum ClassAffiliation {
+    Loyal = 1,
+    Corrupt = 2,
+    Neutral = 3,
+}
+
+impl ClassAffiliation {
+    #[must_use]
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            1 => Some(Self::Loyal),
+            2 => Some(Self::Corrupt),
+            3 => Some(Self::Neutral),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Loyal => "loyal",
+            Self::Corrupt => "corrupt",
+            Self::Neutral => "neutral",
+        }
+    }
+}
+
+/// Mirrors `ItemRarityProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum ItemRarity {
+    Standard = 1,
+    Optimized = 2,
+    Advanced = 3,
+    Supreme = 4,
+    Echelon = 5,
+    Eternal = 6,
+}
+
+impl ItemRarity {
+    #[must_use]
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            1 => Some(Self::Standard),
+            2 => Some(Self::Optimized),
+            3 => Some(Self::Advanced),
+            4 => Some(Self::Supreme),
+            5 => Some(Self::Echelon),
+            6 => Some(Self::Eternal),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Optimized => "optimized",
+            Self::Advanced => "advanced",
+            Self::Supreme => "supreme",
+            Self::Echelon => "echelon",
+            Self::Eternal => "eternal",
+        }
+    }
+}
+
+/// Mirrors `ItemConditionProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum ItemCondition {
+    Prime = 1,
+    New = 2,
+    Used = 3,
+    Broken = 4,
+}
+
+impl ItemCondition {
+    #[must_use]
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            1 => Some(Self::Prime),
+            2 => Some(Self::New),
+            3 => Some(Self::Used),
+            4 => Some(Self::Broken),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Prime => "prime",
+            Self::New => "new",
+            Self::Used => "used",
+            Self::Broken => "broken",
+        }
+    }
+}
+
+/// Mirrors `ItemTypeProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum ItemType {
+    Head = 1,
+    Torso = 2,
+    Gear = 3,
+    Accessory = 4,
+    Perk = 5,
+    Badge = 6,
+    BunkerguardRobot = 7,
+    RobotItem = 8,
+    CosmeticSkin = 9,
+}
+
+impl ItemType {
+    /// Types minted as player items, i.e. everything but the robot itself
+    pub const ITEMS: [Self; 8] = [
+        Self::Head,
+        Self::Torso,
+        Self::Gear,
+        Self::Accessory,
+        Self::Perk,
+        Self::Badge,
+        Self::RobotItem,
+        Self::CosmeticSkin,
+    ];
+
+    #[must_use]
+    pub fn from_proto(value: i32) -> Option<Self> {
+        if value == Self::BunkerguardRobot as i32 {
+            return Some(Self::BunkerguardRobot);
+        }
+        Self::ITEMS
+            .into_iter()
+            .find(|item_type| *item_type as i32 == value)
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Head => "Head",
+            Self::Torso => "Torso",
+            Self::Gear => "Gear",
+            Self::Accessory => "Accessory",
+            Self::Perk => "Perk",
+            Self::Badge => "Badge",
+            Self::BunkerguardRobot => "BunkerguardRobot",
+            Self::RobotItem => "RobotItem",
+            Self::CosmeticSkin => "CosmeticSkin",
+        }
+    }
+
+    /// Robot equipment slot name for equippable types
+    #[must_use]
+    pub fn equipment_slot(&self) -> Option<&'static str> {
+        match self {
+            Self::Head => Some("head"),
+            Self::Torso => Some("torso"),
+            Self::Gear => Some("gear"),
+            Self::Accessory => Some("accessory"),
+            _ => None,
+        }
+    }
+}
+
+/// Mirrors `MarketStatusProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum MarketStatus {
+    NotListed = 1,
+    ListedForSale = 2,
+    ListedForAuction = 3,
+    Sold = 4,
+    Cancelled = 5,
+    /// Listed, with a purchase waiting for its transaction to settle
+    Locked = 6,
+}
+
+impl MarketStatus {
+    /// Status for a `MarketStatusProto` value, `None` if unspecified or unknown
+    #[must_use]
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            1 => Some(Self::NotListed),
+            2 => Some(Self::ListedForSale),
+            3 => Some(Self::ListedForAuction),
+            4 => Some(Self::Sold),
+            5 => Some(Self::Cancelled),
+            6 => Some(Self::Locked),
+            _ => None,
+        }
+    }
+}
+
+/// Twelve sub-stats of `CoreStatsProto`; category averages are derived
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreStats {
+    pub damage: u32,
+    pub accuracy: u32,
+    pub critical_chance: u32,
+    pub armor_piercing: u32,
+    pub speed: u32,
+    pub agility: u32,
+    pub stealth: u32,
+    pub evasion: u32,
+    pub health: u32,
+    pub shield: u32,
+    pub detection: u32,
+    pub range: u32,
+}
+
+impl CoreStats {
+    #[must_use]
+    pub fn combat_average(&self) -> u32 {
+        (self.damage + self.accuracy + self.critical_chance + self.armor_piercing) / 4
+    }
+
+    #[must_use]
+    pub fn mobility_average(&self) -> u32 {
+        (self.speed + self.agility + self.stealth + self.evasion) / 4
+    }
+
+    #[must_use]
+    pub fn survivability_average(&self) -> u32 {
+        (self.health + self.shield) / 2
+    }
+
+    #[must_use]
+    pub fn sensors_average(&self) -> u32 {
+        (self.detection + self.range) / 2
+    }
+
+    /// Sum of two stat blocks, each sub-stat capped at 1000
+    #[must_use]
+    pub fn combined(&self, other: &Self) -> Self {
+        let mut combined = *self;
+        for (stat, boost) in combined.stats_mut().into_iter().zip(other.stats()) {
+            *stat = (*stat + boost).min(MAX_STAT);
+        }
+        combined
+    }
+
+    fn stats(&self) -> [u32; 12] {
+        [
+            self.damage,
+            self.accuracy,
+            self.critical_chance,
+            self.armor_piercing,
+            self.speed,
+            self.agility,
+            self.stealth,
+            self.evasion,
+            self.health,
+            self.shield,
+            self.detection,
+            self.range,
+        ]
+    }
+
+    fn stats_mut(&mut self) -> [&mut u32; 12] {
+        [
+            &mut self.damage,
+            &mut self.accuracy,
+            &mut self.critical_chance,
+            &mut self.armor_piercing,
+            &mut self.speed,
+            &mut self.agility,
+            &mut self.stealth,
+            &mut self.evasion,
+            &mut self.health,
+            &mut self.shield,
+            &mut self.detection,
+            &mut self.range,
+        ]
+    }
+}
+
+/// L3 addresses of the contracts that emit the world's events
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contracts {
+    pub registry: String,
+    pub robots: String,
+    pub items: String,
+    pub marketplace: String,
+    pub missions: String,
+    pub staking: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Player {
+    pub player_id: String,
+    pub bunker_tag: String,
+    pub email: String,
+    pub display_name: String,
+    pub l3_wallet_address: String,
+    pub l2_wallet_address: String,
+    pub l1_wallet_address: String,
+    pub registered_at: i64,
+    pub xp: u64,
+    pub ntc_balance_wei: u64,
+    pub credits_balance: u64,
+    pub staking: Option<Staking>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Staking {
+    pub staked_ntc_wei: u64,
+    pub rewards_earned_ntc_wei: u64,
+    pub duration_days: u32,
+    pub apy_basis_points: u32,
+    pub started_at: i64,
+    pub last_reward_claim_at: i64,
+    pub tier: u32,
+    pub auto_compound: bool,
+}
+
+/// Bunkerguard robot state; `robot_id` is the robot's NFT ID
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Robot {
+    pub robot_id: String,
+    pub owner_id: String,
+    pub level: u32,
+    pub class: BunkerClass,
+    pub affiliation: ClassAffiliation,
+    pub base_stats: CoreStats,
+    /// Base stats plus the boosts of every equipped item
+    pub final_stats: CoreStats,
+    /// Equipment slot name to item NFT ID
+    pub equipped: BTreeMap<String, String>,
+    pub total_xp: u64,
+    pub last_active_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Nft {
+    pub nft_id: String,
+    pub token_id: u64,
+    pub contract_address: String,
+    pub item_type: ItemType,
+    pub rarity: ItemRarity,
+    pub condition: ItemCondition,
+    pub class_affinities: Vec<BunkerClass>,
+    pub affiliation: ClassAffiliation,
+    pub stat_boosts: CoreStats,
+    pub construct_origin: String,
+    pub metadata_cid: String,
+    pub owner_id: String,
+    /// Player the NFT was minted to, owed royalties when others sell it
+    #[serde(default)]
+    pub creator_id: Option<String>,
+    pub is_soulbound: bool,
+    /// "mint", "mission_reward" or "purchase"
+    pub mint_reason: String,
+    pub minted_at: i64,
+    pub mint_block: u64,
+    pub mint_tx_hash: String,
+    /// When the NFT last changed hands; `None` while still with its minter
+    #[serde(default)]
+    pub acquired_at: Option<i64>,
+}
+
+impl Nft {
+    /// Display name used in metadata and search
+    #[must_use]
+    pub fn name(&self) -> String {
+        if self.item_type == ItemType::BunkerguardRobot {
+            format!("Bunkerguard #{}", self.token_id)
+        } else {
+            format!(
+                "{} {} #{}",
+                self.construct_origin,
+                self.item_type.as_str(),
+                self.token_id
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Listing {
+    pub listing_id: String,
+    pub nft_id: String,
+    pub seller_id: String,
+    pub listing_type: MarketStatus,
+    /// Asking price, or the opening bid of an auction
+    pub price_ntc_wei: u64,
+    /// Price used when crypto features are disabled, and the only price of
+    /// a credits listing
+    pub price_credits: u64,
+    /// Fixed when the listing is created; a credits listing has no NTC price
+    #[serde(default)]
+    pub currency: Currency,
+    /// Lowest winning bid of an auction, 0 for none
+    #[serde(default)]
+    pub reserve_price_ntc_wei: u64,
+    pub created_at: i64,
+    /// Auctions move this out when a late bid triggers anti-sniping
+    pub expires_at: i64,
+    pub view_count: u32,
+    pub favorite_count: u32,
+    pub tx_hash: String,
+}
+
+impl Listing {
+    /// Asking price in the listing's currency: wei for NTC, whole credits otherwise
+    #[must_use]
+    pub fn price(&self) -> u64 {
+        match self.currency {
+            Currency::Ntc => self.price_ntc_wei,
+            Currency::Credits => self.price_credits,
+        }
+    }
+}
+
+/// Mirrors `BidStatusProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum BidStatus {
+    /// Current highest bid; the amount is held from the bidder's balance
+    Active = 1,
+    /// Overtaken by a higher bid and refunded
+    Outbid = 2,
+    /// Won the auction at settlement
+    Accepted = 3,
+    /// Highest bid that missed the reserve, refunded at settlement
+    Rejected = 4,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bid {
+    pub bid_id: String,
+    pub listing_id: String,
+    pub bidder_id: String,
+    pub amount_ntc_wei: u64,
+    pub placed_at: i64,
+    pub status: BidStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mission {
+    pub mission_id: String,
+    pub player_id: String,
+    pub name: String,
+    /// "daily", "weekly", "story" or "achievement"
+    pub mission_type: String,
+    pub xp_reward: u64,
+    pub ntc_reward_wei: u64,
+    pub credits_reward: u64,
+    pub completed_at: i64,
+    pub tx_hash: String,
+}
+
+/// Currency a price is quoted in; mirrors `MarketCurrencyProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum Currency {
+    /// On-chain NTC, amounts in wei
+    #[default]
+    Ntc = 1,
+    /// Off-chain credits
+    Credits = 2,
+}
+
+impl Currency {
+    #[must_use]
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            1 => Some(Self::Ntc),
+            2 => Some(Self::Credits),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ntc => "NTC",
+            Self::Credits => "CREDITS",
+        }
+    }
+}
+
+/// Where the price of a sale goes
+///
+/// Amounts are in the sale currency: wei for NTC, whole credits otherwise.
+/// `marketplace_fee + royalty + seller_proceeds` always equals the price.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FeeBreakdown {
+    /// Platform fee rate after overrides, before the staking discount
+    pub platform_fee_basis_points: u32,
+    /// Platform fee before the staking discount
+    pub platform_fee: u64,
+    pub seller_staking_tier: u32,
+    pub staking_discount: u64,
+    /// Platform fee actually taken
+    pub marketplace_fee: u64,
+    pub royalty_basis_points: u32,
+    pub royalty: u64,
+    /// `None` when no royalty is due
+    pub royalty_recipient_id: Option<String>,
+    pub seller_proceeds: u64,
+}
+
+/// Completed marketplace trade
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sale {
+    /// Listing or accepted offer the trade settled
+    pub listing_id: String,
+    pub nft_id: String,
+    pub seller_id: String,
+    pub buyer_id: String,
+    pub price_ntc_wei: u64,
+    pub marketplace_fee_wei: u64,
+    pub seller_proceeds_wei: u64,
+    /// How the price was split between the platform, creator and seller
+    #[serde(default)]
+    pub fees: FeeBreakdown,
+    pub sold_at: i64,
+    pub tx_hash: String,
+}
+
+/// Mirrors `CreditTransactionTypeProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum CreditTransactionType {
+    /// Paid for a credits listing
+    Spend = 2,
+    /// Seller proceeds or creator royalty from a credits sale
+    Sale = 6,
+}
+
+/// One movement on a player's off-chain credits balance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreditLedgerEntry {
+    pub transaction_id: String,
+    pub player_id: String,
+    pub transaction_type: CreditTransactionType,
+    /// Positive when credited, negative when debited
+    pub credit_amount: i64,
+    pub description: String,
+    /// Listing the movement settled
+    pub reference_id: String,
+    pub timestamp: i64,
+}
+
+/// Event emitted on the simulated L3 chain, mirroring `CanonicalEventProto`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainEvent {
+    pub event_id: String,
+    pub block_number: u64,
+    pub log_index: u64,
+    pub contract_address: String,
+    pub transaction_hash: String,
+    pub block_timestamp: i64,
+    pub payload: EventPayload,
+}
+
+/// Event payloads reference world entities by ID; the full entity is looked
+/// up when an event is rendered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventPayload {
+    UserRegistered {
+        player_id: String,
+    },
+    NftMinted {
+        nft_id: String,
+        player_id: String,
+        /// Set for mints ingested from the chain that have no fixture NFT
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<MintedToken>,
+    },
+    ItemEquipped {
+        player_id: String,
+        robot_id: String,
+        nft_id: String,
+        slot: String,
+    },
+    NtcStakingInitiated {
+        player_id: String,
+        #[serde(default)]
+        staked_amount_wei: u64,
+        #[serde(default)]
+        duration_days: u32,
+    },
+    /// Carries the listing terms so the event outlives the listing
+    NftMarketListed {
+        listing_id: String,
+        player_id: String,
+        nft_id: String,
+        price_ntc_wei: u64,
+        listing_type: MarketStatus,
+        expires_at: i64,
+    },
+    /// `player_id` is the buyer; the sale itself is kept in [`World::sales`]
+    NftMarketSold {
+        nft_id: String,
+        player_id: String,
+        seller_id: String,
+        price_ntc_wei: u64,
+        marketplace_fee_wei: u64,
+        /// Creator royalty; the seller received the rest after the fee
+        #[serde(default)]
+        royalty_wei: u64,
+    },
+    MissionCompleted {
+        mission_id: String,
+        player_id: String,
+    },
+    /// XP credited to a player, and to their robot when `robot_id` is set
+    XpAwarded {
+        player_id: String,
+        #[serde(default)]
+        robot_id: String,
+        xp_amount: u64,
+        /// "mission", "combat" or "daily_bonus"
+        xp_source: String,
+    },
+    /// Robot progression; totals are absolute so the event stands on its own
+    RobotStatsUpdated {
+        player_id: String,
+        robot_id: String,
+        old_level: u32,
+        new_level: u32,
+        xp_gained: u64,
+        total_xp: u64,
+        /// "mission_completion" or "combat_victory"
+        update_reason: String,
+    },
+    /// Movement of NTC between wallets; an empty side is a mint or a burn
+    NtcTransfer {
+        from_player_id: String,
+        to_player_id: String,
+        /// Chain amounts pass 2^64 wei above about 18.4 NTC
+        #[serde(with = "decimal_u128")]
+        amount_wei: u128,
+        /// "transfer", "mint" or "burn"
+        transfer_type: String,
+    },
+    /// Change of NFT owner on chain; an empty side is a mint or a burn
+    NftTransferred {
+        nft_id: String,
+        from_player_id: String,
+        to_player_id: String,
+        /// "transfer", "mint" or "burn"
+        transfer_type: String,
+    },
+}
+
+/// `u128` as a decimal string, which internally tagged enums can buffer;
+/// JSON numbers from before amounts were widened still load
+mod decimal_u128 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Decimal {
+        Text(String),
+        Number(u64),
+    }
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        match Decimal::deserialize(deserializer)? {
+            Decimal::Text(text) => text.parse().map_err(serde::de::Error::custom),
+            Decimal::Number(number) => Ok(u128::from(number)),
+        }
+    }
+}
+
+/// Token of an ingested mint, carried on the event for lack of a fixture NFT
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintedToken {
+    pub token_id: u64,
+    pub token_uri: String,
+}
+
+impl EventPayload {
+    /// Event type name used by indexer filters and statistics
+    #[must_use]
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            Self::UserRegistered { .. } => "UserRegistered",
+            Self::NftMinted { .. } => "NftMinted",
+            Self::ItemEquipped { .. } => "ItemEquipped",
+            Self::NtcStakingInitiated { .. } => "NtcStakingInitiated",
+            Self::NftMarketListed { .. } => "NftMarketListed",
+            Self::NftMarketSold { .. } => "NftMarketSold",
+            Self::MissionCompleted { .. } => "MissionCompleted",
+            Self::XpAwarded { .. } => "XpAwarded",
+            Self::RobotStatsUpdated { .. } => "RobotStatsUpdated",
+            Self::NtcTransfer { .. } => "NtcTransfer",
+            Self::NftTransferred { .. } => "NftTransferred",
+        }
+    }
+
+    /// Player that caused the event
+    #[must_use]
+    pub fn player_id(&self) -> &str {
+        match self {
+            Self::UserRegistered { player_id }
+            | Self::NftMinted { player_id, .. }
+            | Self::ItemEquipped { player_id, .. }
+            | Self::NtcStakingInitiated { player_id, .. }
+            | Self::NftMarketListed { player_id, .. }
+            | Self::NftMarketSold { player_id, .. }
+            | Self::MissionCompleted { player_id, .. }
+            | Self::XpAwarded { player_id, .. }
+            | Self::RobotStatsUpdated { player_id, .. } => player_id,
+            // Mints have no sender, so the recipient caused them as far as filters go
+            Self::NtcTransfer {
+                from_player_id,
+                to_player_id,
+                ..
+            }
+            | Self::NftTransferred {
+                from_player_id,
+                to_player_id,
+                ..
+            } => {
+                if from_player_id.is_empty() {
+                    to_player_id
+                } else {
+                    from_player_id
+                }
+            }
+        }
+    }
+
+    /// Every player the event concerns, the causing player first
+    #[must_use]
+    pub fn participants(&self) -> Vec<&str> {
+        match self {
+            Self::NftMarketSold {
+                player_id,
+                seller_id,
+                ..
+            } => vec![player_id, seller_id],
+            Self::NtcTransfer {
+                from_player_id,
+                to_player_id,
+                ..
+            }
+            | Self::NftTransferred {
+                from_player_id,
+                to_player_id,
+                ..
+            } => [from_player_id, to_player_id]
+                .into_iter()
+                .filter(|player_id| !player_id.is_empty())
+                .map(String::as_str)
+                .collect(),
+            _ => vec![self.player_id()],
+        }
+    }
+}
+
+/// Events of a chain-ordered slice in the inclusive block range
+#[must_use]
+pub fn events_in_blocks(events: &[ChainEvent], start_block: u64, end_block: u64) -> &[ChainEvent] {
+    let start = events.partition_point(|event| event.block_number < start_block);
+    let end = events.partition_point(|event| event.block_number <= end_block);
+    &events[start..end.max(start)]
+}
+
+/// Slice of a collection selected by [`paginate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    /// 1-based page number
+    pub page: u32,
+    pub page_size: u32,
+    pub total_items: u64,
+    pub total_pages: u32,
+}
+
+/// Select a 1-based page of `items`; zero values fall back to page 1 and 20 items
+pub fn paginate<T>(items: &[T], page: u32, page_size: u32) -> (&[T], Page) {
+    let page = page.max(1);
+    let page_size = if page_size == 0 {
+        DEFAULT_PAGE_SIZE
+    } else {
+        page_size.min(MAX_PAGE_SIZE)
+    };
+    let start = (page as usize - 1)
+        .saturating_mul(page_size as usize)
+        .min(items.len());
+    let end = start.saturating_add(page_size as usize).min(items.len());
+    let total_pages = u32::try_from(items.len().div_ceil(page_size as usize)).unwrap_or(u32::MAX);
+
+    (
+        &items[start..end],
+        Page {
+            page,
+            page_size,
+            total_items: items.len() as u64,
+            total_pages,
+        },
+    )
+}
+
+/// NFTs that trade as interchangeable for pricing purposes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Template {
+    pub item_type: ItemType,
+    pub rarity: ItemRarity,
+}
+
+impl Template {
+    #[must_use]
+    pub fn of(nft: &Nft) -> Self {
+        Self {
+            item_type: nft.item_type,
+            rarity: nft.rarity,
+        }
+    }
+}
+
+/// Order of NFTs in [`World::nfts_owned_by`]
+fn mint_order(nft: &Nft) -> (i64, u64) {
+    (nft.minted_at, nft.token_id)
+}
+
+#[derive(Debug, Clone, Default)]
+struct WorldIndex {
+    players: HashMap<String, usize>,
+    /// Keyed by lowercased L3 wallet address
+    players_by_wallet: HashMap<String, usize>,
+    robots_by_owner: HashMap<String, usize>,
+    nfts: HashMap<String, usize>,
+    /// Keyed by lowercased contract address and token ID
+    nfts_by_token: HashMap<(String, u64), usize>,
+    nfts_by_owner: HashMap<String, Vec<usize>>,
+    listings: HashMap<String, usize>,
+    listings_by_nft: HashMap<String, usize>,
+    bids_by_listing: HashMap<String, Vec<usize>>,
+    sales_by_template: HashMap<Template, Vec<usize>>,
+    missions: HashMap<String, usize>,
+    events_by_player: HashMap<String, Vec<usize>>,
+}
+
+/// Consistent set of fixtures every stub draws from
+///
+/// Generation is deterministic for a given dataset and seed, so services
+/// started with the same `DataConfig` agree on every ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct World {
+    pub dataset: Dataset,
+    pub seed: u64,
+    pub contracts: Contracts,
+    pub players: Vec<Player>,
+    pub robots: Vec<Robot>,
+    pub nfts: Vec<Nft>,
+    /// NFTs destroyed since the world was generated, in burn order
+    #[serde(default)]
+    pub burned_nft_ids: Vec<String>,
+    pub listings: Vec<Listing>,
+    pub missions: Vec<Mission>,
+    /// Trades settled since the world was generated, oldest first
+    #[serde(default)]
+    pub sales: Vec<Sale>,
+    /// Auction bids in the order they were placed
+    #[serde(default)]
+    pub bids: Vec<Bid>,
+    /// Payment ledger of off-chain credits movements, oldest first
+    #[serde(default)]
+    pub credit_ledger: Vec<CreditLedgerEntry>,
+    /// Ordered by block number and log index
+    pub events: Vec<ChainEvent>,
+    #[serde(skip)]
+    index: WorldIndex,
+}
+
+impl World {
+    /// Generate the world for `dataset` from `seed`
+    #[must_use]
+    pub fn generate(dataset: Dataset, seed: u64) -> Self {
+        let size = WorldSize::for_dataset(&dataset);
+        Generator::new(seed, size).run(dataset, seed)
+    }
+
+    /// Rebuild the lookup tables after the entity vectors change
+    pub fn reindex(&mut self) {
+        let mut index = WorldIndex::default();
+        for (position, player) in self.players.iter().enumerate() {
+            index.players.insert(player.player_id.clone(), position);
+            index
+                .players_by_wallet
+                .insert(player.l3_wallet_address.to_ascii_lowercase(), position);
+        }
+        for (position, robot) in self.robots.iter().enumerate() {
+            index
+                .robots_by_owner
+                .insert(robot.owner_id.clone(), position);
+        }
+        for (position, nft) in self.nfts.iter().enumerate() {
+            index.nfts.insert(nft.nft_id.clone(), position);
+            index.nfts_by_token.insert(
+                (nft.contract_address.to_ascii_lowercase(), nft.token_id),
+                position,
+            );
+            index
+                .nfts_by_owner
+                .entry(nft.owner_id.clone())
+                .or_default()
+                .push(position);
+        }
+        for owned in index.nfts_by_owner.values_mut() {
+            owned.sort_by_key(|&position| mint_order(&self.nfts[position]));
+        }
+        for (position, mission) in self.missions.iter().enumerate() {
+            index.missions.insert(mission.mission_id.clone(), position);
+        }
+        for (position, bid) in self.bids.iter().enumerate() {
+            index
+                .bids_by_listing
+                .entry(bid.listing_id.clone())
+                .or_default()
+                .push(position);
+        }
+        for (position, sale) in self.sales.iter().enumerate() {
+            if let Some(&nft) = index.nfts.get(&sale.nft_id) {
+                index
+                    .sales_by_template
+                    .entry(Template::of(&self.nfts[nft]))
+                    .or_default()
+                    .push(position);
+            }
+        }
+        for (position, event) in self.events.iter().enumerate() {
+            for player_id in event.payload.participants() {
+                index
+                    .events_by_player
+                    .entry(player_id.to_string())
+                    .or_default()
+                    .push(position);
+            }
+        }
+        self.index = index;
+        self.reindex_listings();
+    }
+
+    fn reindex_listings(&mut self) {
+        self.index.listings.clear();
+        self.index.listings_by_nft.clear();
+        for (position, listing) in self.listings.iter().enumerate() {
+            self.index
+                .listings
+                .insert(listing.listing_id.clone(), position);
+            self.index
+                .listings_by_nft
+                .insert(listing.nft_id.clone(), position);
+        }
+    }
+
+    /// Mutable access to a player; IDs must not be changed through it
+    pub fn player_mut(&mut self, player_id: &str) -> Option<&mut Player> {
+        self.index
+            .players
+            .get(player_id)
+            .map(|&position| &mut self.players[position])
+    }
+
+    /// Mutable access to a listing; IDs must not be changed through it
+    pub fn listing_mut(&mut self, listing_id: &str) -> Option<&mut Listing> {
+        self.index
+            .listings
+            .get(listing_id)
+            .map(|&position| &mut self.listings[position])
+    }
+
+    /// Open a listing, keeping the lookup tables current
+    pub fn insert_listing(&mut self, listing: Listing) {
+        let position = self.listings.len();
+        self.index
+            .listings
+            .insert(listing.listing_id.clone(), position);
+        self.index
+            .listings_by_nft
+            .insert(listing.nft_id.clone(), position);
+        self.listings.push(listing);
+    }
+
+    /// Close a listing; the rest of the order book keeps its creation order
+    pub fn remove_listing(&mut self, listing_id: &str) -> Option<Listing> {
+        let position = self.index.listings.remove(listing_id)?;
+        let listing = self.listings.remove(position);
+        self.index.listings_by_nft.remove(&listing.nft_id);
+        // Every later listing moved down a place
+        for (moved_to, moved) in self.listings.iter().enumerate().skip(position) {
+            self.index
+                .listings
+                .insert(moved.listing_id.clone(), moved_to);
+            self.index
+                .listings_by_nft
+                .insert(moved.nft_id.clone(), moved_to);
+        }
+        Some(listing)
+    }
+
+    /// Hand an NFT to `new_owner_id` at `timestamp`
+    ///
+    /// Returns `false` if the NFT does not exist.
+    pub fn transfer_nft(&mut self, nft_id: &str, new_owner_id: &str, timestamp: i64) -> bool {
+        let Some(&position) = self.index.nfts.get(nft_id) else {
+            return false;
+        };
+        let nft = &mut self.nfts[position];
+        let previous_owner = std::mem::replace(&mut nft.owner_id, new_owner_id.to_string());
+        nft.acquired_at = Some(timestamp);
+
+        if let Some(owned) = self.index.nfts_by_owner.get_mut(&previous_owner) {
+            owned.retain(|&owned_position| owned_position != position);
+        }
+        let minted = mint_order(&self.nfts[position]);
+        let owned = self
+            .index
+            .nfts_by_owner
+            .entry(new_owner_id.to_string())
+            .or_default();
+        let insert_at = owned
+            .partition_point(|&owned_position| mint_order(&self.nfts[owned_position]) < minted);
+        owned.insert(insert_at, position);
+        true
+    }
+
+    /// Destroy an NFT, keeping the rest in mint order
+    ///
+    /// Its ID is remembered in `burned_nft_ids`, so sales and events that
+    /// mention it stay valid.
+    ///
+    /// Returns `None` if the NFT does not exist, is a Bunkerguard robot, is
+    /// equipped or is listed.
+    pub fn burn_nft(&mut self, nft_id: &str) -> Option<Nft> {
+        let position = *self.index.nfts.get(nft_id)?;
+        if self.listing_for_nft(nft_id).is_some()
+            || self.is_equipped(&self.nfts[position])
+            || self.robots.iter().any(|robot| robot.robot_id == nft_id)
+        {
+            return None;
+        }
+        self.index.nfts.remove(nft_id);
+        let nft = self.nfts.remove(position);
+        self.burned_nft_ids.push(nft.nft_id.clone());
+        self.index
+            .nfts_by_token
+            .remove(&(nft.contract_address.to_ascii_lowercase(), nft.token_id));
+        if let Some(owned) = self.index.nfts_by_owner.get_mut(&nft.owner_id) {
+            owned.retain(|&owned_position| owned_position != position);
+        }
+        // Like a reindex, which only files sales under NFTs that still exist
+        if let Some(sales) = self.index.sales_by_template.get_mut(&Template::of(&nft)) {
+            sales.retain(|&sale| self.sales[sale].nft_id != nft.nft_id);
+        }
+
+        // Every later NFT moved down a place
+        for (moved_to, moved) in self.nfts.iter().enumerate().skip(position) {
+            self.index.nfts.insert(moved.nft_id.clone(), moved_to);
+            self.index.nfts_by_token.insert(
+                (moved.contract_address.to_ascii_lowercase(), moved.token_id),
+                moved_to,
+            );
+        }
+        for owned in self.index.nfts_by_owner.values_mut() {
+            for owned_position in owned.iter_mut().filter(|owned| **owned > position) {
+                *owned_position -= 1;
+            }
+        }
+        Some(nft)
+    }
+
+    /// Append an event in a new block after the chain head
+    ///
+    /// The block timestamp never runs backwards, even if `timestamp` does.
+    pub fn append_event(
+        &mut self,
+        contract_address: String,
+        transaction_hash: String,
+        timestamp: i64,
+        payload: EventPayload,
+    ) -> &ChainEvent {
+        let block_timestamp = self
+            .events
+            .last()
+            .map_or(timestamp, |last| timestamp.max(last.block_timestamp));
+        let position = self.events.len();
+        let block_number = self.latest_block() + 1;
+        for player_id in payload.participants() {
+            self.index
+                .events_by_player
+                .entry(player_id.to_string())
+                .or_default()
+                .push(position);
+        }
+        self.events.push(ChainEvent {
+            event_id: uuid::Uuid::new_v4().to_string(),
+            block_number,
+            log_index: 0,
+            contract_address,
+            transaction_hash,
+            block_timestamp,
+            payload,
+        });
+        &self.events[position]
+    }
+
+    /// Bids on a listing in the order they were placed
+    pub fn bids_for_listing<'a>(&'a self, listing_id: &str) -> impl Iterator<Item = &'a Bid> + 'a {
+        self.index
+            .bids_by_listing
+            .get(listing_id)
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.bids[position])
+    }
+
+    /// Sales of NFTs of one template, oldest first
+    pub fn sales_for_template(&self, template: Template) -> impl Iterator<Item = &Sale> {
+        self.index
+            .sales_by_template
+            .get(&template)
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.sales[position])
+    }
+
+    /// Sales of one NFT, oldest first
+    pub fn sales_for_nft<'a>(&'a self, nft_id: &'a str) -> impl Iterator<Item = &'a Sale> + 'a {
+        self.sales.iter().filter(move |sale| sale.nft_id == nft_id)
+    }
+
+    /// Payment ledger entries of one player, oldest first
+    pub fn credit_history<'a>(
+        &'a self,
+        player_id: &'a str,
+    ) -> impl Iterator<Item = &'a CreditLedgerEntry> + 'a {
+        self.credit_ledger
+            .iter()
+            .filter(move |entry| entry.player_id == player_id)
+    }
+
+    /// Record a settled trade, keeping the lookup tables current
+    pub fn insert_sale(&mut self, sale: Sale) {
+        if let Some(nft) = self.nft(&sale.nft_id) {
+            let template = Template::of(nft);
+            self.index
+                .sales_by_template
+                .entry(template)
+                .or_default()
+                .push(self.sales.len());
+        }
+        self.sales.push(sale);
+    }
+
+    /// Record a bid, keeping the lookup tables current
+    pub fn insert_bid(&mut self, bid: Bid) {
+        self.index
+            .bids_by_listing
+            .entry(bid.listing_id.clone())
+            .or_default()
+            .push(self.bids.len());
+        self.bids.push(bid);
+    }
+
+    /// Mutable access to a bid; IDs must not be changed through it
+    pub fn bid_mut(&mut self, bid_id: &str) -> Option<&mut Bid> {
+        self.bids.iter_mut().rev().find(|bid| bid.bid_id == bid_id)
+    }
+
+    /// Events emitted by a transaction
+    pub fn events_for_transaction<'a>(
+        &'a self,
+        transaction_hash: &'a str,
+    ) -> impl Iterator<Item = &'a ChainEvent> + 'a {
+        self.events
+            .iter()
+            .filter(move |event| event.transaction_hash == transaction_hash)
+    }
+
+    #[must_use]
+    pub fn player(&self, player_id: &str) -> Option<&Player> {
+        self.index
+            .players
+            .get(player_id)
+            .map(|&position| &self.players[position])
+    }
+
+    /// Player whose L3 wallet is `address`, in any letter case
+    #[must_use]
+    pub fn player_by_wallet(&self, address: &str) -> Option<&Player> {
+        self.index
+            .players_by_wallet
+            .get(&address.to_ascii_lowercase())
+            .map(|&position| &self.players[position])
+    }
+
+    /// Player chosen deterministically from an opaque key such as an OAuth code
+    ///
+    /// The same key always maps to the same player, so repeated logins agree.
+    #[must_use]
+    pub fn player_for_key(&self, key: &str) -> Option<&Player> {
+        if self.players.is_empty() {
+            return None;
+        }
+        // FNV-1a keeps the mapping stable across builds and platforms
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        self.players
+            .get((hash % self.players.len() as u64) as usize)
+    }
+
+    #[must_use]
+    pub fn robot_for(&self, player_id: &str) -> Option<&Robot> {
+        self.index
+            .robots_by_owner
+            .get(player_id)
+            .map(|&position| &self.robots[position])
+    }
+
+    #[must_use]
+    pub fn nft(&self, nft_id: &str) -> Option<&Nft> {
+        self.index
+            .nfts
+            .get(nft_id)
+            .map(|&position| &self.nfts[position])
+    }
+
+    /// NFT minted as `token_id` on `contract_address`, in any letter case
+    #[must_use]
+    pub fn nft_by_token(&self, contract_address: &str, token_id: u64) -> Option<&Nft> {
+        self.index
+            .nfts_by_token
+            .get(&(contract_address.to_ascii_lowercase(), token_id))
+            .map(|&position| &self.nfts[position])
+    }
+
+    /// NFTs owned by `player_id`, in mint order
+    pub fn nfts_owned_by<'a>(&'a self, player_id: &str) -> impl Iterator<Item = &'a Nft> + 'a {
+        self.index
+            .nfts_by_owner
+            .get(player_id)
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.nfts[position])
+    }
+
+    #[must_use]
+    pub fn listing(&self, listing_id: &str) -> Option<&Listing> {
+        self.index
+            .listings
+            .get(listing_id)
+            .map(|&position| &self.listings[position])
+    }
+
+    /// Open listing for `nft_id`, if any
+    #[must_use]
+    pub fn listing_for_nft(&self, nft_id: &str) -> Option<&Listing> {
+        self.index
+            .listings_by_nft
+            .get(nft_id)
+            .map(|&position| &self.listings[position])
+    }
+
+    #[must_use]
+    pub fn mission(&self, mission_id: &str) -> Option<&Mission> {
+        self.index
+            .missions
+            .get(mission_id)
+            .map(|&position| &self.missions[position])
+    }
+
+    /// Market status of `nft_id` derived from its open listing
+    #[must_use]
+    pub fn market_status(&self, nft_id: &str) -> MarketStatus {
+        self.listing_for_nft(nft_id)
+            .map_or(MarketStatus::NotListed, |listing| listing.listing_type)
+    }
+
+    /// Whether `nft_id` is equipped on its owner's robot
+    #[must_use]
+    pub fn is_equipped(&self, nft: &Nft) -> bool {
+        self.robot_for(&nft.owner_id)
+            .is_some_and(|robot| robot.equipped.values().any(|id| *id == nft.nft_id))
+    }
+
+    /// Events involving `player_id`, in chain order
+    pub fn events_for_player<'a>(
+        &'a self,
+        player_id: &str,
+    ) -> impl Iterator<Item = &'a ChainEvent> + 'a {
+        self.index
+            .events_by_player
+            .get(player_id)
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.events[position])
+    }
+
+    /// Events in the inclusive block range, in chain order
+    #[must_use]
+    pub fn events_in_blocks(&self, start_block: u64, end_block: u64) -> &[ChainEvent] {
+        events_in_blocks(&self.events, start_block, end_block)
+    }
+
+    /// Highest block number with an event, 0 for an empty world
+    #[must_use]
+    pub fn latest_block(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.block_number)
+    }
+
+    /// Parse a JSON snapshot, rejecting it unless [`World::validate`] passes
+    pub fn from_snapshot_json(json: &str) -> Result<Self> {
+        let snapshot: Snapshot<World> =
+            serde_json::from_str(json).context("parsing world snapshot")?;
+        snapshot.into_world()
+    }
+
+    /// Load a snapshot written by [`World::save_snapshot`]
+    pub fn load_snapshot(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = fs::File::open(path)
+            .with_context(|| format!("opening world snapshot {}", path.display()))?;
+        let snapshot: Snapshot<World> = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("parsing world snapshot {}", path.display()))?;
+        snapshot
+            .into_world()
+            .with_context(|| format!("loading world snapshot {}", path.display()))
+    }
+
+    /// Serialize the world as a versioned JSON snapshot document
+    pub fn to_snapshot_json(&self) -> Result<String> {
+        serde_json::to_string(&Snapshot::of(self)).context("serializing world snapshot")
+    }
+
+    /// Write the world to `path` as a snapshot other stubs can load
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("creating snapshot directory {}", parent.display()))?;
+        }
+        let file = fs::File::create(path)
+            .with_context(|| format!("creating world snapshot {}", path.display()))?;
+        serde_json::to_writer(BufWriter::new(file), &Snapshot::of(self))
+            .with_context(|| format!("writing world snapshot {}", path.display()))
+    }
+
+    /// Check referential integrity between players, robots, NFTs, listings,
+    /// missions and events
+    ///
+    /// Expects the lookup tables to be current; see [`World::reindex`].
+    pub fn validate(&self) -> Result<()> {
+        let violations = self.integrity_violations();
+        if violations.is_empty() {
+            return Ok(());
+        }
+        let reported: Vec<_> = violations
+            .iter()
+            .take(REPORTED_VIOLATIONS)
+            .cloned()
+            .collect();
+        bail!(
+            "{} integrity violation(s): {}",
+            violations.len(),
+            reported.join("; ")
+        )
+    }
+
+    /// Every broken reference or duplicate ID in the world
+    #[must_use]
+    pub fn integrity_violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        duplicate_ids(
+            "player",
+            self.players.iter().map(|player| &player.player_id),
+            &mut violations,
+        );
+        duplicate_ids(
+            "nft",
+            self.nfts.iter().map(|nft| &nft.nft_id),
+            &mut violations,
+        );
+        duplicate_ids(
+            "listing",
+            self.listings.iter().map(|listing| &listing.listing_id),
+            &mut violations,
+        );
+        duplicate_ids(
+            "mission",
+            self.missions.iter().map(|mission| &mission.mission_id),
+            &mut violations,
+        );
+        duplicate_ids(
+            "event",
+            self.events.iter().map(|event| &event.event_id),
+            &mut violations,
+        );
+        duplicate_ids(
+            "robot owner",
+            self.robots.iter().map(|robot| &robot.owner_id),
+            &mut violations,
+        );
+
+        for nft in &self.nfts {
+            if self.player(&nft.owner_id).is_none() {
+                violations.push(format!(
+                    "nft {} is owned by unknown player {}",
+                    nft.nft_id, nft.owner_id
+                ));
+            }
+            if let Some(creator_id) = &nft.creator_id {
+                if self.player(creator_id).is_none() {
+                    violations.push(format!(
+                        "nft {} was created by unknown player {}",
+                        nft.nft_id, creator_id
+                    ));
+                }
+            }
+        }
+
+        for robot in &self.robots {
+            if self.player(&robot.owner_id).is_none() {
+                violations.push(format!(
+                    "robot {} is owned by unknown player {}",
+                    robot.robot_id, robot.owner_id
+                ));
+            }
+            match self.nft(&robot.robot_id) {
+                Some(nft) if nft.item_type != ItemType::BunkerguardRobot => {
+                    violations.push(format!(
+                        "robot {} is minted as a {}",
+                        robot.robot_id,
+                        nft.item_type.as_str()
+                    ))
+                }
+                Some(nft) if nft.owner_id != robot.owner_id => violations.push(format!(
+                    "robot {} nft is owned by {} not {}",
+                    robot.robot_id, nft.owner_id, robot.owner_id
+                )),
+                Some(_) => {}
+                None => violations.push(format!("robot {} has no nft", robot.robot_id)),
+            }
+            for (slot, nft_id) in &robot.equipped {
+                match self.nft(nft_id) {
+                    Some(nft) if nft.owner_id != robot.owner_id => violations.push(format!(
+                        "robot {} equips {} owned by {}",
+                        robot.robot_id, nft_id, nft.owner_id
+                    )),
+                    Some(nft) if nft.item_type.equipment_slot() != Some(slot.as_str()) => {
+                        violations.push(format!(
+                            "robot {} equips {} {} in slot {}",
+                            robot.robot_id,
+                            nft.item_type.as_str(),
+                            nft_id,
+                            slot
+                        ));
+                    }
+                    Some(_) => {}
+                    None => violations.push(format!(
+                        "robot {} equips unknown nft {}",
+                        robot.robot_id, nft_id
+                    )),
+                }
+            }
+        }
+
+        let mut listed_nfts = HashSet::new();
+        for listing in &self.listings {
+            if !listed_nfts.insert(&listing.nft_id) {
+                violations.push(format!("nft {} is listed more than once", listing.nft_id));
+            }
+            match self.nft(&listing.nft_id) {
+                Some(nft) if nft.owner_id != listing.seller_id => violations.push(format!(
+                    "listing {} seller {} does not own nft {}",
+                    listing.listing_id, listing.seller_id, listing.nft_id
+                )),
+                Some(nft) if nft.is_soulbound => violations.push(format!(
+                    "listing {} sells soulbound nft {}",
+                    listing.listing_id, listing.nft_id
+                )),
+                Some(nft) if self.is_equipped(nft) => violations.push(format!(
+                    "listing {} sells equipped nft {}",
+                    listing.listing_id, listing.nft_id
+                )),
+                Some(_) => {}
+                None => violations.push(format!(
+                    "listing {} references unknown nft {}",
+                    listing.listing_id, listing.nft_id
+                )),
+            }
+            // A credits listing never carries an NTC price or takes NTC bids
+            if listing.currency == Currency::Credits
+                && (listing.price_ntc_wei != 0
+                    || listing.listing_type != MarketStatus::ListedForSale)
+            {
+                violations.push(format!(
+                    "credits listing {} mixes in NTC pricing",
+                    listing.listing_id
+                ));
+            }
+        }
+
+        for mission in &self.missions {
+            if self.player(&mission.player_id).is_none() {
+                violations.push(format!(
+                    "mission {} belongs to unknown player {}",
+                    mission.mission_id, mission.player_id
+                ));
+            }
+        }
+
+        for bid in &self.bids {
+            if self.player(&bid.bidder_id).is_none() {
+                violations.push(format!(
+                    "bid {} placed by unknown player {}",
+                    bid.bid_id, bid.bidder_id
+                ));
+            }
+            if bid.status == BidStatus::Active && self.listing(&bid.listing_id).is_none() {
+                violations.push(format!(
+                    "active bid {} is on closed listing {}",
+                    bid.bid_id, bid.listing_id
+                ));
+            }
+        }
+
+        for entry in &self.credit_ledger {
+            if self.player(&entry.player_id).is_none() {
+                violations.push(format!(
+                    "credit ledger entry {} is for unknown player {}",
+                    entry.transaction_id, entry.player_id
+                ));
+            }
+        }
+
+        for sale in &self.sales {
+            if !self.is_known_nft(&sale.nft_id) {
+                violations.push(format!(
+                    "sale {} references unknown nft {}",
+                    sale.listing_id, sale.nft_id
+                ));
+            }
+            for player_id in [&sale.seller_id, &sale.buyer_id] {
+                if self.player(player_id).is_none() {
+                    violations.push(format!(
+                        "sale {} references unknown player {}",
+                        sale.listing_id, player_id
+                    ));
+                }
+            }
+        }
+
+        for pair in self.events.windows(2) {
+            if (pair[0].block_number, pair[0].log_index)
+                >= (pair[1].block_number, pair[1].log_index)
+            {
+                violations.push(format!(
+                    "event {} is out of chain order after {}",
+                    pair[1].event_id, pair[0].event_id
+                ));
+            }
+        }
+        for event in &self.events {
+            self.event_violations(event, &mut violations);
+        }
+
+        violations
+    }
+
+    /// Whether `nft_id` exists or was burned; history may mention either
+    fn is_known_nft(&self, nft_id: &str) -> bool {
+        self.nft(nft_id).is_some() || self.burned_nft_ids.iter().any(|burned| burned == nft_id)
+    }
+
+    fn event_violations(&self, event: &ChainEvent, violations: &mut Vec<String>) {
+        let mut missing = |kind: &str, id: &str| {
+            violations.push(format!(
+                "event {} references unknown {} {}",
+                event.event_id, kind, id
+            ));
+        };
+
+        for player_id in event.payload.participants() {
+            if self.player(player_id).is_none() {
+                missing("player", player_id);
+            }
+        }
+        match &event.payload {
+            EventPayload::UserRegistered { .. }
+            | EventPayload::NtcStakingInitiated { .. }
+            | EventPayload::NtcTransfer { .. } => {}
+            EventPayload::XpAwarded { robot_id, .. } => {
+                if !robot_id.is_empty() && self.nft(robot_id).is_none() {
+                    missing("robot", robot_id);
+                }
+            }
+            EventPayload::RobotStatsUpdated { robot_id, .. } => {
+                if self.nft(robot_id).is_none() {
+                    missing("robot", robot_id);
+                }
+            }
+            EventPayload::NftMinted { nft_id, .. }
+            | EventPayload::NftMarketListed { nft_id, .. }
+            | EventPayload::NftMarketSold { nft_id, .. }
+            | EventPayload::NftTransferred { nft_id, .. } => {
+                if !self.is_known_nft(nft_id) {
+                    missing("nft", nft_id);
+                }
+            }
+            EventPayload::ItemEquipped {
+                robot_id, nft_id, ..
+            } => {
+                if self.nft(robot_id).is_none() {
+                    missing("robot", robot_id);
+                }
+                if !self.is_known_nft(nft_id) {
+                    missing("nft", nft_id);
+                }
+            }
+            EventPayload::MissionCompleted { mission_id, .. } => {
+                if self.mission(mission_id).is_none() {
+                    missing("mission", mission_id);
+                }
+            }
+        }
+    }
+}
+
+/// Versioned envelope around a serialized [`World`]
+#[derive(Serialize, Deserialize)]
+struct Snapshot<W> {
+    format_version: u32,
+    world: W,
+}
+
+impl<'a> Snapshot<&'a World> {
+    fn of(world: &'a World) -> Self {
+        Self {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            world,
+        }
+    }
+}
+
+impl Snapshot<World> {
+    fn into_world(self) -> Result<World> {
+        if self.format_version != SNAPSHOT_FORMAT_VERSION {
+            bail!(
+                "unsupported world snapshot format version {} (expected {})",
+                self.format_version,
+                SNAPSHOT_FORMAT_VERSION
+            );
+        }
+        let mut world = self.world;
+        world.reindex();
+        world.validate()?;
+        Ok(world)
+    }
+}
+
+fn duplicate_ids<'a>(
+    kind: &str,
+    ids: impl Iterator<Item = &'a String>,
+    violations: &mut Vec<String>,
+) {
+    let mut seen = HashSet::new();
+    for id in ids {
+        if !seen.insert(id) {
+            violations.push(format!("duplicate {kind} id {id}"));
+        }
+    }
+}
+
+/// Hands out block numbers, log indexes and timestamps in chain order
+struct Chain {
+    block_number: u64,
+    log_index: u64,
+    events_in_block: u64,
+}
+
+impl Chain {
+    fn new() -> Self {
+        Self {
+            block_number: 1,
+            log_index: 0,
+            events_in_block: 1,
+        }
+    }
+
+    fn block_timestamp(&self) -> i64 {
+        GENESIS_TIMESTAMP + (self.block_number as i64 - 1) * BLOCK_TIME_SECS
+    }
+
+    /// Reserve the next log position, moving to a new block when the current one is full
+    fn next_slot(&mut self, rng: &mut StdRng) -> (u64, u64, i64) {
+        if self.log_index >= self.events_in_block {
+            self.block_number += rng.gen_range(1..=3);
+            self.log_index = 0;
+            self.events_in_block = rng.gen_range(1..=MAX_EVENTS_PER_BLOCK);
+        }
+        let slot = (self.block_number, self.log_index, self.block_timestamp());
+        self.log_index += 1;
+        slot
+    }
+}
+
+struct Generator {
+    rng: StdRng,
+    size: WorldSize,
+    contracts: Contracts,
+    chain: Chain,
+    next_token_id: u64,
+}
+
+impl Generator {
+    fn new(seed: u64, size: WorldSize) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let contracts = Contracts {
+            registry: address(&mut rng),
+            robots: address(&mut rng),
+            items: address(&mut rng),
+            marketplace: address(&mut rng),
+            missions: address(&mut rng),
+            staking: address(&mut rng),
+        };
+        Self {
+            rng,
+            size,
+            contracts,
+            chain: Chain::new(),
+            next_token_id: 1,
+        }
+    }
+
+    fn run(mut self, dataset: Dataset, seed: u64) -> World {
+        let mut world = World {
+            dataset,
+            seed,
+            contracts: self.contracts.clone(),
+            players: Vec::with_capacity(self.size.players),
+            robots: Vec::with_capacity(self.size.players),
+            nfts: Vec::with_capacity(self.size.players * (self.size.items_per_player + 1)),
+            burned_nft_ids: Vec::new(),
+            listings: Vec::with_capacity(self.size.listings),
+            missions: Vec::with_capacity(self.size.players * self.size.missions_per_player),
+            sales: Vec::new(),
+            bids: Vec::new(),
+            credit_ledger: Vec::new(),
+            events: Vec::new(),
+            index: WorldIndex::default(),
+        };
+
+        for index in 0..self.size.players {
+            self.generate_player(&mut world, index);
+        }
+        self.generate_missions(&mut world);
+        self.generate_listings(&mut world);
+
+        world.reindex();
+        world
+    }
+
+    fn generate_player(&mut self, world: &mut World, index: usize) {
+        let player_id = self.uuid();
+        let prefix = TAG_PREFIXES[self.rng.gen_range(0..TAG_PREFIXES.len())];
+        let suffix = TAG_SUFFIXES[self.rng.gen_range(0..TAG_SUFFIXES.len())];
+        let bunker_tag = format!("{prefix}{suffix}{index}");
+
+        let registered = self.emit(
+            world,
+            self.contracts.registry.clone(),
+            EventPayload::UserRegistered {
+                player_id: player_id.clone(),
+            },
+        );
+
+        // Robot NFT first, then the player's items
+        let robot_nft = self.mint(world, &player_id, ItemType::BunkerguardRobot);
+        let mut owned_items = Vec::with_capacity(self.size.items_per_player);
+        for _ in 0..self.size.items_per_player {
+            let item_type = ItemType::ITEMS[self.rng.gen_range(0..ItemType::ITEMS.len())];
+            owned_items.push(self.mint(world, &player_id, item_type));
+        }
+
+        let level = self.rng.gen_range(1..=50);
+        let base_stats = self.robot_stats(level);
+        let mut robot = Robot {
+            robot_id: world.nfts[robot_nft].nft_id.clone(),
+            owner_id: player_id.clone(),
+            level,
+            class: world.nfts[robot_nft].class_affinities[0],
+            affiliation: world.nfts[robot_nft].affiliation,
+            base_stats,
+            final_stats: base_stats,
+            equipped: BTreeMap::new(),
+            total_xp: u64::from(level) * 5_000 + self.rng.gen_range(0..5_000),
+            last_active_at: registered,
+        };
+
+        // Equip the first owned item for each slot
+        for slot_type in EQUIPMENT_SLOTS {
+            let Some(&item) = owned_items
+                .iter()
+                .find(|&&item| world.nfts[item].item_type == slot_type)
+            else {
+                continue;
+            };
+            let slot = slot_type.equipment_slot().unwrap_or_default().to_string();
+            let nft_id = world.nfts[item].nft_id.clone();
+            robot.final_stats = robot.final_stats.combined(&world.nfts[item].stat_boosts);
+            robot.equipped.insert(slot.clone(), nft_id.clone());
+            robot.last_active_at = self.emit(
+                world,
+                self.contracts.robots.clone(),
+                EventPayload::ItemEquipped {
+                    player_id: player_id.clone(),
+                    robot_id: robot.robot_id.clone(),
+                    nft_id,
+                    slot,
+                },
+            );
+        }
+
+        let staking = if self.rng.gen_bool(0.3) {
+            let started_at = self.emit(
+                world,
+                self.contracts.staking.clone(),
+                EventPayload::NtcStakingInitiated {
+                    player_id: player_id.clone(),
+                    staked_amount_wei: 0,
+                    duration_days: 0,
+                },
+            );
+            let tier = self.rng.gen_range(1..=5);
+            let staking = Staking {
+                staked_ntc_wei: self.rng.gen_range(100..=5_000) * (NTC_WEI / 1_000),
+                rewards_earned_ntc_wei: self.rng.gen_range(0..=500) * (NTC_WEI / 1_000),
+                duration_days: [30, 90, 180, 365][self.rng.gen_range(0..4)],
+                apy_basis_points: 400 + tier * 150,
+                started_at,
+                last_reward_claim_at: started_at + self.rng.gen_range(1..=30) * 86_400,
+                tier,
+                auto_compound: self.rng.gen_bool(0.5),
+            };
+            // Terms are drawn after the event so seeded worlds keep their values
+            if let Some(EventPayload::NtcStakingInitiated {
+                staked_amount_wei,
+                duration_days,
+                ..
+            }) = world.events.last_mut().map(|event| &mut event.payload)
+            {
+                *staked_amount_wei = staking.staked_ntc_wei;
+                *duration_days = staking.duration_days;
+            }
+            Some(staking)
+        } else {
+            None
+        };
+
+        world.players.push(Player {
+            email: format!("{}@players.bunkerverse.test", bunker_tag.to_lowercase()),
+            display_name: format!("{prefix} {suffix}"),
+            player_id,
+            bunker_tag,
+            l3_wallet_address: self.address(),
+            l2_wallet_address: self.address(),
+            l1_wallet_address: self.address(),
+            registered_at: registered,
+            xp: robot.total_xp,
+            ntc_balance_wei: self.rng.gen_range(0..=15_000) * (NTC_WEI / 1_000),
+            credits_balance: self.rng.gen_range(0..=50_000),
+            staking,
+        });
+        world.robots.push(robot);
+    }
+
+    fn generate_missions(&mut self, world: &mut World) {
+        for player in 0..world.players.len() {
+            for _ in 0..self.size.missions_per_player {
+                let mission_id = self.uuid();
+                let player_id = world.players[player].player_id.clone();
+                let mission_type = MISSION_TYPES[self.rng.gen_range(0..MISSION_TYPES.len())];
+                let name = MISSION_NAMES[self.rng.gen_range(0..MISSION_NAMES.len())];
+                let xp_reward = self.rng.gen_range(100..=2_500);
+                let completed_at = self.emit(
+                    world,
+                    self.contracts.missions.clone(),
+                    EventPayload::MissionCompleted {
+                        mission_id: mission_id.clone(),
+                        player_id: player_id.clone(),
+                    },
+                );
+                world.players[player].xp += xp_reward;
+                world.missions.push(Mission {
+                    mission_id,
+                    player_id,
+                    name: name.to_string(),
+                    mission_type: mission_type.to_string(),
+                    xp_reward,
+                    ntc_reward_wei: if self.rng.gen_bool(0.25) {
+                        self.rng.gen_range(1..=500) * (NTC_WEI / 1_000)
+                    } else {
+                        0
+                    },
+                    credits_reward: self.rng.gen_range(10..=500),
+                    completed_at,
+                    tx_hash: world
+                        .events
+                        .last()
+                        .map_or_else(String::new, |event| event.transaction_hash.clone()),
+                });
+            }
+        }
+    }
+
+    fn generate_listings(&mut self, world: &mut World) {
+        let equipped: HashSet<&str> = world
+            .robots
+            .iter()
+            .flat_map(|robot| robot.equipped.values().map(String::as_str))
+            .collect();
+        let mut candidates: Vec<usize> = world
+            .nfts
+            .iter()
+            .enumerate()
+            .filter(|(_, nft)| {
+                !nft.is_soulbound
+                    && nft.item_type != ItemType::BunkerguardRobot
+                    && !equipped.contains(nft.nft_id.as_str())
+            })
+            .map(|(position, _)| position)
+            .collect();
+        candidates.shuffle(&mut self.rng);
+        candidates.truncate(self.size.listings);
+        // Keep listings in mint order so their events read chronologically
+        candidates.sort_unstable();
+
+        for position in candidates {
+            let listing_id = self.uuid();
+            let nft_id = world.nfts[position].nft_id.clone();
+            let seller_id = world.nfts[position].owner_id.clone();
+            let rarity = world.nfts[position].rarity as u64;
+            // Prices scale with rarity, roughly 0.02 - 2.3 NTC in milli-NTC steps
+            let price_milli_ntc = self.rng.gen_range(10..=250) * rarity * rarity / 4 + 10;
+            let price_ntc_wei = price_milli_ntc * (NTC_WEI / 1_000);
+            let listing_type = if self.rng.gen_bool(0.1) {
+                MarketStatus::ListedForAuction
+            } else {
+                MarketStatus::ListedForSale
+            };
+            let duration_secs = self.rng.gen_range(LISTING_DURATION_DAYS) * 86_400;
+            let created_at =
+                self.emit_with(world, self.contracts.marketplace.clone(), |created_at| {
+                    EventPayload::NftMarketListed {
+                        listing_id: listing_id.clone(),
+                        player_id: seller_id.clone(),
+                        nft_id: nft_id.clone(),
+                        price_ntc_wei,
+                        listing_type,
+                        expires_at: created_at + duration_secs,
+                    }
+                });
+            world.listings.push(Listing {
+                listing_id,
+                nft_id,
+                seller_id,
+                listing_type,
+                price_ntc_wei,
+                price_credits: credits_for_price(price_ntc_wei),
+                currency: Currency::Ntc,
+                reserve_price_ntc_wei: 0,
+                created_at,
+                expires_at: created_at + duration_secs,
+                view_count: self.rng.gen_range(0..=500),
+                favorite_count: self.rng.gen_range(0..=40),
+                tx_hash: world
+                    .events
+                    .last()
+                    .map_or_else(String::new, |event| event.transaction_hash.clone()),
+            });
+        }
+    }
+
+    /// Mint an NFT to `owner_id`, returning its position in `world.nfts`
+    fn mint(&mut self, world: &mut World, owner_id: &str, item_type: ItemType) -> usize {
+        let nft_id = self.uuid();
+        let rarity = self.rarity();
+        let affinity_count = if item_type == ItemType::BunkerguardRobot {
+            1
+        } else {
+            self.rng.gen_range(1..=2)
+        };
+        let class_affinities = BunkerClass::ALL
+            .choose_multiple(&mut self.rng, affinity_count)
+            .copied()
+            .collect();
+        let affiliation = [
+            ClassAffiliation::Loyal,
+            ClassAffiliation::Corrupt,
+            ClassAffiliation::Neutral,
+        ][self.rng.gen_range(0..3)];
+        let condition = match self.rng.gen_range(0..100) {
+            0..=14 => ItemCondition::Prime,
+            15..=64 => ItemCondition::New,
+            65..=94 => ItemCondition::Used,
+            _ => ItemCondition::Broken,
+        };
+        let contract_address = if item_type == ItemType::BunkerguardRobot {
+            self.contracts.robots.clone()
+        } else {
+            self.contracts.items.clone()
+        };
+        let mint_reason = if item_type == ItemType::BunkerguardRobot {
+            "mint"
+        } else {
+            ["mint", "mission_reward", "purchase"][self.rng.gen_range(0..3)]
+        };
+
+        let minted_at = self.emit(
+            world,
+            contract_address.clone(),
+            EventPayload::NftMinted {
+                nft_id: nft_id.clone(),
+                player_id: owner_id.to_string(),
+                token: None,
+            },
+        );
+        let (mint_block, mint_tx_hash) = world
+            .events
+            .last()
+            .map(|event| (event.block_number, event.transaction_hash.clone()))
+            .unwrap_or_default();
+
+        let token_id = self.next_token_id;
+        self.next_token_id += 1;
+        world.nfts.push(Nft {
+            nft_id,
+            token_id,
+            contract_address,
+            item_type,
+            rarity,
+            condition,
+            class_affinities,
+            affiliation,
+            stat_boosts: self.item_stats(rarity),
+            construct_origin: CONSTRUCT_ORIGINS[self.rng.gen_range(0..CONSTRUCT_ORIGINS.len())]
+                .to_string(),
+            metadata_cid: self.cid(),
+            owner_id: owner_id.to_string(),
+            creator_id: Some(owner_id.to_string()),
+            is_soulbound: item_type == ItemType::Badge,
+            mint_reason: mint_reason.to_string(),
+            minted_at,
+            mint_block,
+            mint_tx_hash,
+            acquired_at: None,
+        });
+        world.nfts.len() - 1
+    }
+
+    /// Append an event in its own transaction, returning its block timestamp
+    fn emit(&mut self, world: &mut World, contract_address: String, payload: EventPayload) -> i64 {
+        self.emit_with(world, contract_address, |_| payload)
+    }
+
+    /// Emit an event whose payload depends on its block timestamp
+    fn emit_with(
+        &mut self,
+        world: &mut World,
+        contract_address: String,
+        payload: impl FnOnce(i64) -> EventPayload,
+    ) -> i64 {
+        let (block_number, log_index, block_timestamp) = self.chain.next_slot(&mut self.rng);
+        let payload = payload(block_timestamp);
+        let event_id = self.uuid();
+        let transaction_hash = self.hash();
+        world.events.push(ChainEvent {
+            event_id,
+            block_number,
+            log_index,
+            contract_address,
+            transaction_hash,
+            block_timestamp,
+            payload,
+        });
+        block_timestamp
+    }
+
+    fn rarity(&mut self) -> ItemRarity {
+        match self.rng.gen_range(0..1000) {
+            0..=449 => ItemRarity::Standard,
+            450..=729 => ItemRarity::Optimized,
+            730..=889 => ItemRarity::Advanced,
+            890..=964 => ItemRarity::Supreme,
+            965..=994 => ItemRarity::Echelon,
+            _ => ItemRarity::Eternal,
+        }
+    }
+
+    /// Two to four boosted sub-stats scaled by rarity
+    fn item_stats(&mut self, rarity: ItemRarity) -> CoreStats {
+        let tier = rarity as u32;
+        let mut stats = CoreStats::default();
+        let boosted = self.rng.gen_range(2..=4);
+        let mut slots = stats.stats_mut();
+        slots.shuffle(&mut self.rng);
+        for stat in slots.into_iter().take(boosted) {
+            *stat = self.rng.gen_range(5 * tier..=25 * tier);
+        }
+        stats
+    }
+
+    fn robot_stats(&mut self, level: u32) -> CoreStats {
+        let mut stats = CoreStats::default();
+        for stat in stats.stats_mut() {
+            *stat = (self.rng.gen_range(20..=80) + level * 2).min(MAX_STAT);
+        }
+        stats
+    }
+
+    fn uuid(&mut self) -> String {
+        uuid::Builder::from_random_bytes(self.rng.gen())
+            .into_uuid()
+            .to_string()
+    }
+
+    fn address(&mut self) -> String {
+        address(&mut self.rng)
+    }
+
+    /// `0x`-prefixed 32 byte transaction hash
+    fn hash(&mut self) -> String {
+        random_hash(&mut self.rng)
+    }
+
+    /// CIDv0-shaped IPFS pointer: `Qm` followed by 44 base58 characters
+    fn cid(&mut self) -> String {
+        let mut cid = String::with_capacity(46);
+        cid.push_str("Qm");
+        for _ in 0..44 {
+            cid.push(BASE58_ALPHABET[self.rng.gen_range(0..BASE58_ALPHABET.len())] as char);
+        }
+        cid
+    }
+}
+
+/// `0x`-prefixed 20 byte address
+fn address(rng: &mut StdRng) -> String {
+    let bytes: [u8; 20] = rng.gen();
+    format!("0x{}", hex(&bytes))
+}
+
+/// Credits-mode price equivalent of an NTC price
+#[must_use]
+pub fn credits_for_price(price_ntc_wei: u64) -> u64 {
+    price_ntc_wei / (NTC_WEI / 1_000) / CREDITS_PER_MILLI_NTC_DIVISOR + 1
+}
+
+/// Random `0x`-prefixed 32 byte hash, shaped like an L3 transaction hash
+pub fn random_hash(rng: &mut impl Rng) -> String {
+    let bytes: [u8; 32] = rng.gen();
+    format!("0x{}", hex(&bytes))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write as _;
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut out, byte| {
+            let _ = write!(out, "{byte:02x}");
+            out
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generation_is_deterministic_per_seed() {
+        let first = World::generate(Dataset::Minimal, 7);
+        let second = World::generate(Dataset::Minimal, 7);
+        let other = World::generate(Dataset::Minimal, 8);
+
+        assert_eq!(first.players, second.players);
+        assert_eq!(first.nfts, second.nfts);
+        assert_eq!(first.events, second.events);
+        assert_ne!(first.players[0].player_id, other.players[0].player_id);
+    }
+
+    #[test]
+    fn test_dataset_sizes() {
+        let minimal = World::generate(Dataset::Minimal, 1);
+        assert_eq!(minimal.players.len(), 10);
+        assert_eq!(minimal.robots.len(), 10);
+        assert_eq!(minimal.nfts.len(), 50);
+        assert_eq!(minimal.missions.len(), 20);
+        assert!(!minimal.listings.is_empty() && minimal.listings.len() <= 8);
+
+        let development = World::generate(Dataset::Development, 1);
+        assert_eq!(development.players.len(), 1_000);
+        assert_eq!(development.nfts.len(), 6_000);
+        assert_eq!(development.listings.len(), 1_000);
+    }
+
+    #[test]
+    fn test_entities_reference_each_other() {
+        let world = World::generate(Dataset::Development, 42);
+
+        for robot in &world.robots {
+            let robot_nft = world.nft(&robot.robot_id).unwrap();
+            assert_eq!(robot_nft.item_type, ItemType::BunkerguardRobot);
+            assert_eq!(robot_nft.owner_id, robot.owner_id);
+            for nft_id in robot.equipped.values() {
+                assert_eq!(world.nft(nft_id).unwrap().owner_id, robot.owner_id);
+            }
+        }
+        for listing in &world.listings {
+            let nft = world.nft(&listing.nft_id).unwrap();
+            assert_eq!(nft.owner_id, listing.seller_id);
+            assert!(!nft.is_soulbound && !world.is_equipped(nft));
+            assert!(listing.expires_at > listing.created_at);
+        }
+        for event in &world.events {
+            assert!(world.player(event.payload.player_id()).is_some());
+        }
+        for mission in &world.missions {
+            assert!(world.player(&mission.player_id).is_some());
+        }
+
+        let event_ids: HashSet<_> = world.events.iter().map(|e| &e.event_id).collect();
+        assert_eq!(event_ids.len(), world.events.len());
+        assert!(world
+            .events
+            .windows(2)
+            .all(|pair| (pair[0].block_number, pair[0].log_index)
+                < (pair[1].block_number, pair[1].log_index)));
+    }
+
+    #[test]
+    fn test_lookups_and_pagination() {
+        let world = World::generate(Dataset::Minimal, 3);
+        let player = &world.players[4];
+
+        assert_eq!(
+            world.player_for_key("oauth-code"),
+            world.player_for_key("oauth-code")
+        );
+        assert_eq!(world.nfts_owned_by(&player.player_id).count(), 5);
+        let wallet = player.l3_wallet_address.to_ascii_uppercase();
+        assert_eq!(world.player_by_wallet(&wallet), Some(player));
+        let nft = &world.nfts[7];
+        assert_eq!(
+            world.nft_by_token(&nft.contract_address.to_ascii_uppercase(), nft.token_id),
+            Some(nft)
+        );
+        assert!(world.events_for_player(&player.player_id).all(|event| event
+            .payload
+            .participants()
+            .contains(&player.player_id.as_str())));
+
+        let latest = world.latest_block();
+        assert_eq!(world.events_in_blocks(0, latest).len(), world.events.len());
+        assert!(world.events_in_blocks(latest + 1, latest + 10).is_empty());
+
+        let (items, page) = paginate(&world.nfts, 3, 20);
+        assert_eq!(items.len(), 10);
+        assert_eq!(page.total_items, 50);
+        assert_eq!(page.total_pages, 3);
+        let (items, page) = paginate(&world.nfts, 0, 0);
+        assert_eq!((items.len(), page.page, page.page_size), (20, 1, 20));
+    }
+
+    #[test]
+    fn test_removals_keep_the_lookups_of_a_full_reindex() {
+        let mut world = World::generate(Dataset::Minimal, 3);
+        let listing_id = world.listings[0].listing_id.clone();
+        let closed = world.remove_listing(&listing_id).unwrap();
+        let burned_id = world
+            .nfts
+            .iter()
+            .rev()
+            .skip(1)
+            .find(|nft| {
+                world.listing_for_nft(&nft.nft_id).is_none()
+                    && !world.is_equipped(nft)
+                    && world
+                        .robots
+                        .iter()
+                        .all(|robot| robot.robot_id != nft.nft_id)
+            })
+            .unwrap()
+            .nft_id
+            .clone();
+        let listing_order: Vec<String> = world
+            .listings
+            .iter()
+            .map(|listing| listing.listing_id.clone())
+            .filter(|id| *id != listing_id)
+            .collect();
+        let nft_order: Vec<String> = world
+            .nfts
+            .iter()
+            .map(|nft| nft.nft_id.clone())
+            .filter(|id| *id != burned_id)
+            .collect();
+        let burned = world.burn_nft(&burned_id).unwrap();
+
+        // Pages over the survivors keep their creation order
+        assert!(world
+            .listings
+            .iter()
+            .map(|listing| &listing.listing_id)
+            .eq(&listing_order));
+        assert!(world.nfts.iter().map(|nft| &nft.nft_id).eq(&nft_order));
+        // History that mentions the burned NFT still round-trips through a snapshot
+        assert_eq!(world.burned_nft_ids, [burned_id.clone()]);
+        World::from_snapshot_json(&world.to_snapshot_json().unwrap()).unwrap();
+
+        let mut reindexed = world.clone();
+        reindexed.reindex();
+        assert!(world.listing(&listing_id).is_none());
+        assert!(world.listing_for_nft(&closed.nft_id).is_none());
+        assert!(world.nft(&burned_id).is_none());
+        assert!(world
+            .nft_by_token(&burned.contract_address, burned.token_id)
+            .is_none());
+        for listing in &world.listings {
+            assert_eq!(world.listing(&listing.listing_id), Some(listing));
+            assert_eq!(world.listing_for_nft(&listing.nft_id), Some(listing));
+        }
+        for nft in &world.nfts {
+            assert_eq!(world.nft(&nft.nft_id), Some(nft));
+            assert_eq!(
+                world.nft_by_token(&nft.contract_address, nft.token_id),
+                Some(nft)
+            );
+        }
+        for player in &world.players {
+            assert!(world
+                .nfts_owned_by(&player.player_id)
+                .eq(reindexed.nfts_owned_by(&player.player_id)));
+        }
+        let template = Template::of(&burned);
+        assert!(world
+            .sales_for_template(template)
+            .eq(reindexed.sales_for_template(template)));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let world = World::generate(Dataset::Minimal, 11);
+        let dir = std::env::temp_dir().join(format!("world-snapshot-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("world.json");
+
+        world.save_snapshot(&path).unwrap();
+        let loaded = World::load_snapshot(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.players, world.players);
+        assert_eq!(loaded.events, world.events);
+        let player_id = &world.players[2].player_id;
+        assert_eq!(
+            loaded.nfts_owned_by(player_id).count(),
+            world.nfts_owned_by(player_id).count()
+        );
+        assert!(loaded.robot_for(player_id).is_some());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_broken_references() {
+        let mut world = World::generate(Dataset::Minimal, 11);
+        assert!(world.validate().is_ok());
+
+        world.listings[0].seller_id = "ghost".to_string();
+        world.missions[0].player_id = "ghost".to_string();
+        world.nfts.push(world.nfts[0].clone());
+        let json = world.to_snapshot_json().unwrap();
+
+        let err = World::from_snapshot_json(&json).unwrap_err().to_string();
+        assert!(err.starts_with("3 integrity violation(s)"), "{err}");
+        assert!(err.contains("does not own nft"));
+        assert!(err.contains("mission"));
+        assert!(err.contains("duplicate nft id"));
+    }
+
+    #[test]
+    fn test_snapshot_rejects_unknown_format_version() {
+        let world = World::generate(Dataset::Minimal, 11);
+        let json = world.to_snapshot_json().unwrap().replacen(
+            "\"format_version\":1",
+            "\"format_version\":99",
+            1,
+        );
+
+        let err = World::from_snapshot_json(&json).unwrap_err().to_string();
+        assert!(err.contains("format version 99"));
+    }
+}
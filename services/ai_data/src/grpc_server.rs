@@ -1,8 +1,7 @@
-use crate::config::StubConfiguration;
-use crate::stub::{AiDataStub, RequestContext, SmartStub};
+use crate::stub::{RequestContext, SharedStub, SmartStub};
 use anyhow::Result;
 use chrono::Utc;
-use std::{collections::HashMap, sync::Arc};
+use std::collections::HashMap;
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
@@ -101,24 +100,16 @@ use bunkerverse::services::v1::{
 };
 
 pub struct AiDataGrpcService {
-    stub: Arc<tokio::sync::Mutex<AiDataStub>>,
+    stub: SharedStub,
 }
 
 impl AiDataGrpcService {
-    pub fn new(config: StubConfiguration) -> Self {
-        Self {
-            stub: Arc::new(tokio::sync::Mutex::new(AiDataStub::new(config))),
-        }
+    pub fn new(stub: SharedStub) -> Self {
+        Self { stub }
     }
 
     async fn create_context(&self, trace_id: Option<String>) -> RequestContext {
-        let stub = self.stub.lock().await;
-        RequestContext {
-            request_id: Uuid::new_v4().to_string(),
-            trace_id,
-            timestamp: Utc::now(),
-            enable_crypto: stub.get_configuration().dual_mode.enable_crypto,
-        }
+        self.stub.lock().await.create_context(trace_id)
     }
 
     async fn simulate_latency_and_errors(
@@ -126,23 +117,13 @@ impl AiDataGrpcService {
         context: &RequestContext,
         method: &str,
     ) -> Result<(), Status> {
-        let stub = self.stub.lock().await;
-
-        // Simulate latency
-        let latency = stub.calculate_response_latency();
-        tokio::time::sleep(tokio::time::Duration::from_millis(
-            latency.as_millis() as u64
-        ))
-        .await;
-
-        // Check for error injection
-        if stub.should_inject_error_response() {
-            stub.log_response(context, method, latency.as_millis() as u64, 500, true);
-            return Err(Status::internal("Simulated AI Data service gRPC error"));
-        }
-
-        stub.log_response(context, method, latency.as_millis() as u64, 200, false);
-        Ok(())
+        smart_stubs::grpc::simulate_call(
+            &self.stub,
+            context,
+            method,
+            "Simulated AI Data service gRPC error",
+        )
+        .await
     }
 }
 
@@ -1113,13 +1094,14 @@ impl AiDataService for AiDataGrpcService {
         let _req = request.into_inner();
         let context = self.create_context(None).await;
 
-        {
+        let health = {
             let stub = self.stub.lock().await;
             stub.log_request(&context, "Health", "gRPC");
-        }
+            stub.health_check()
+        };
 
         let response = HealthResponse {
-            status: "HEALTHY".to_string(),
+            status: health.as_str().to_string(),
             version: "0.1.0".to_string(),
             timestamp: Utc::now().timestamp(),
             details: HashMap::new(),
@@ -4,7 +4,7 @@ mod stub;
 
 use anyhow::Result;
 use axum::{
-    extract::{Query, State},
+    extract::{FromRef, Query, State},
     http::StatusCode,
     response::Json,
     routing::{get, post},
@@ -16,8 +16,9 @@ use grpc_server::{
     bunkerverse::services::v1::ai_data_service_server::AiDataServiceServer, AiDataGrpcService,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use stub::{AiDataStub, RequestContext, SmartStub};
+use smart_stubs::{admin, StubLayer};
+use std::{collections::HashMap, net::SocketAddr};
+use stub::{RequestContext, SharedStub, SmartStub};
 use tokio::signal;
 use tonic::transport::Server;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
@@ -132,24 +133,22 @@ pub struct ErrorResponse {
 // Application State
 #[derive(Clone)]
 pub struct AppState {
-    pub stub: Arc<tokio::sync::Mutex<AiDataStub>>,
+    pub stub: SharedStub,
 }
 
 impl AppState {
-    pub fn new(config: StubConfiguration) -> Self {
-        Self {
-            stub: Arc::new(tokio::sync::Mutex::new(AiDataStub::new(config))),
-        }
+    pub fn new(stub: SharedStub) -> Self {
+        Self { stub }
     }
 
     pub async fn create_context(&self, trace_id: Option<String>) -> RequestContext {
-        let stub = self.stub.lock().await;
-        RequestContext {
-            request_id: Uuid::new_v4().to_string(),
-            trace_id,
-            timestamp: Utc::now(),
-            enable_crypto: stub.get_configuration().dual_mode.enable_crypto,
-        }
+        self.stub.lock().await.create_context(trace_id)
+    }
+}
+
+impl FromRef<AppState> for SharedStub {
+    fn from_ref(state: &AppState) -> Self {
+        state.stub.clone()
     }
 }
 
@@ -383,10 +382,15 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer().json())
         .init();
 
-    let config = StubConfiguration::default();
+    let config_source = config::config_source();
+    let config = config_source.load()?;
     let http_addr: SocketAddr = format!("0.0.0.0:{}", config.base.port).parse()?;
     let grpc_addr: SocketAddr = format!("0.0.0.0:{}", config.base.port + 1000).parse()?; // gRPC on port 9084
-    let state = AppState::new(config.clone());
+    let stub = stub::new_shared_stub(config.clone());
+    let state = AppState::new(stub.clone());
+    // Hot reload latency, error and dataset settings when the config file changes
+    config_source.spawn_watcher(stub.clone());
+    smart_stubs::spawn_state_reset(stub.clone());
 
     info!(
         service_name = %config.base.name,
@@ -394,6 +398,7 @@ async fn main() -> Result<()> {
         http_address = %http_addr,
         grpc_address = %grpc_addr,
         enable_crypto = config.dual_mode.enable_crypto,
+        config_path = ?config_source.path(),
         "Starting AI Data Service Smart Stub with HTTP and gRPC servers"
     );
 
@@ -402,12 +407,15 @@ async fn main() -> Result<()> {
         .route("/health", get(health_check))
         .route("/stub/config", get(get_stub_config))
         .route("/stub/reset", post(reset_stub_state))
+        .route("/stub/scenario", admin::scenario_route())
+        .route("/stub/world", admin::world_route())
         // AI Data API endpoints
         .route("/api/ai-data/datasets", get(get_datasets))
         .route("/api/ai-data/models", get(get_models))
         .route("/api/ai-data/models/train", post(train_model))
         .route("/api/ai-data/inference", post(inference))
         // Middleware
+        .layer(StubLayer::fault_rules(stub.clone()))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
         .with_state(state);
@@ -415,7 +423,7 @@ async fn main() -> Result<()> {
     let http_listener = tokio::net::TcpListener::bind(http_addr).await?;
 
     // gRPC Server
-    let grpc_service = AiDataGrpcService::new(config.clone());
+    let grpc_service = AiDataGrpcService::new(stub.clone());
 
     info!("HTTP server ready and listening on {}", http_addr);
     info!("gRPC server ready and listening on {}", grpc_addr);
@@ -424,6 +432,7 @@ async fn main() -> Result<()> {
     let http_server = axum::serve(http_listener, app).with_graceful_shutdown(shutdown_signal());
 
     let grpc_server = Server::builder()
+        .layer(StubLayer::fault_rules(stub))
         .add_service(AiDataServiceServer::new(grpc_service))
         .serve_with_shutdown(grpc_addr, shutdown_signal());
 
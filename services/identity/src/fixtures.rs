@@ -0,0 +1,38 @@
+//! Identity views of the shared fixture world
+//! Mock tokens carry the player ID so later calls resolve to the same player
+
+use crate::grpc_server::bunkerverse::services::v1::UserProfileProto;
+use smart_stubs::world::{Player, World};
+
+const MOCK_JWT_HEADER: &str = "eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9";
+
+/// Mock JWT whose payload segment is the player ID
+pub fn mock_jwt(player_id: &str, kind: &str) -> String {
+    format!("{MOCK_JWT_HEADER}.{player_id}.mock_{kind}")
+}
+
+/// Player a token was issued to; tokens not issued by the stub map to a
+/// player derived from the token itself
+pub fn player_for_token<'a>(world: &'a World, token: &str) -> Option<&'a Player> {
+    token
+        .split('.')
+        .nth(1)
+        .and_then(|player_id| world.player(player_id))
+        .or_else(|| world.player_for_key(token))
+}
+
+pub fn user_profile(player: &Player, last_login_at: i64) -> UserProfileProto {
+    UserProfileProto {
+        player_id: player.player_id.clone(),
+        bunker_tag: player.bunker_tag.clone(),
+        email: player.email.clone(),
+        display_name: player.display_name.clone(),
+        avatar_url: format!(
+            "https://avatars.bunkerverse.test/{}.png",
+            player.bunker_tag.to_lowercase()
+        ),
+        roles: vec!["user".to_string()],
+        created_at: player.registered_at,
+        last_login_at,
+    }
+}
@@ -1,8 +1,9 @@
-use crate::config::StubConfiguration;
-use crate::stub::{IdentityStub, RequestContext, SmartStub};
+use crate::fixtures;
+use crate::stub::{RequestContext, SharedStub, SmartStub};
 use anyhow::Result;
 use chrono::Utc;
-use std::{collections::HashMap, sync::Arc};
+use smart_stubs::stub::WorldGuard;
+use std::collections::HashMap;
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
@@ -30,29 +31,21 @@ use bunkerverse::services::v1::{
     GetSessionInfoRequest, GetSessionInfoResponse, GetSessionInfoSuccess, HealthRequest,
     HealthResponse, InitiateZkLoginRequest, InitiateZkLoginResponse, InitiateZkLoginSuccess,
     RefreshTokenRequest, RefreshTokenResponse, RefreshTokenSuccess, RevokeTokenRequest,
-    RevokeTokenResponse, RevokeTokenSuccess, UserProfileProto, ValidateTokenRequest,
-    ValidateTokenResponse, ValidateTokenSuccess,
+    RevokeTokenResponse, RevokeTokenSuccess, ValidateTokenRequest, ValidateTokenResponse,
+    ValidateTokenSuccess,
 };
 
 pub struct IdentityGrpcService {
-    stub: Arc<tokio::sync::Mutex<IdentityStub>>,
+    stub: SharedStub,
 }
 
 impl IdentityGrpcService {
-    pub fn new(config: StubConfiguration) -> Self {
-        Self {
-            stub: Arc::new(tokio::sync::Mutex::new(IdentityStub::new(config))),
-        }
+    pub fn new(stub: SharedStub) -> Self {
+        Self { stub }
     }
 
     async fn create_context(&self, trace_id: Option<String>) -> RequestContext {
-        let stub = self.stub.lock().await;
-        RequestContext {
-            request_id: Uuid::new_v4().to_string(),
-            trace_id,
-            timestamp: Utc::now(),
-            enable_crypto: stub.get_configuration().dual_mode.enable_crypto,
-        }
+        self.stub.lock().await.create_context(trace_id)
     }
 
     async fn simulate_latency_and_errors(
@@ -60,23 +53,17 @@ impl IdentityGrpcService {
         context: &RequestContext,
         method: &str,
     ) -> Result<(), Status> {
-        let stub = self.stub.lock().await;
-
-        // Simulate latency
-        let latency = stub.calculate_response_latency();
-        tokio::time::sleep(tokio::time::Duration::from_millis(
-            latency.as_millis() as u64
-        ))
-        .await;
-
-        // Check for error injection
-        if stub.should_inject_error_response() {
-            stub.log_response(context, method, latency.as_millis() as u64, 500, true);
-            return Err(Status::internal("Simulated identity service gRPC error"));
-        }
+        smart_stubs::grpc::simulate_call(
+            &self.stub,
+            context,
+            method,
+            "Simulated identity service gRPC error",
+        )
+        .await
+    }
 
-        stub.log_response(context, method, latency.as_millis() as u64, 200, false);
-        Ok(())
+    async fn world(&self) -> WorldGuard {
+        self.stub.lock().await.world()
     }
 }
 
@@ -129,22 +116,17 @@ impl identity_service_server::IdentityService for IdentityGrpcService {
         self.simulate_latency_and_errors(&context, "CompleteZkLogin")
             .await?;
 
-        let user_profile = UserProfileProto {
-            player_id: Uuid::new_v4().to_string(),
-            bunker_tag: "MockPlayer".to_string(),
-            email: "mock.player@example.com".to_string(),
-            display_name: "Mock Player".to_string(),
-            avatar_url: "https://example.com/avatar.png".to_string(),
-            roles: vec!["user".to_string()],
-            created_at: Utc::now().timestamp(),
-            last_login_at: Utc::now().timestamp(),
-        };
+        // The same authorization code always logs in the same world player
+        let world = self.world().await;
+        let player = world
+            .player_for_key(&req.authorization_code)
+            .ok_or_else(|| Status::unavailable("Fixture world has no players"))?;
 
         let login_success = CompleteZkLoginSuccess {
-            jwt_token: "eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9.mock_jwt_token".to_string(),
-            refresh_token: "mock_refresh_token_".to_string() + &Uuid::new_v4().to_string(),
+            jwt_token: fixtures::mock_jwt(&player.player_id, "jwt_token"),
+            refresh_token: fixtures::mock_jwt(&player.player_id, "refresh_token"),
             expires_at: (Utc::now() + chrono::Duration::hours(24)).timestamp(),
-            user_profile: Some(user_profile),
+            user_profile: Some(fixtures::user_profile(player, Utc::now().timestamp())),
             is_new_user: false,
         };
 
@@ -170,9 +152,13 @@ impl identity_service_server::IdentityService for IdentityGrpcService {
         self.simulate_latency_and_errors(&context, "RefreshToken")
             .await?;
 
+        let world = self.world().await;
+        let player = fixtures::player_for_token(&world, &req.refresh_token)
+            .ok_or_else(|| Status::unauthenticated("Unknown refresh token"))?;
+
         let refresh_success = RefreshTokenSuccess {
-            jwt_token: "eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9.refreshed_mock_jwt_token".to_string(),
-            refresh_token: "new_mock_refresh_token_".to_string() + &Uuid::new_v4().to_string(),
+            jwt_token: fixtures::mock_jwt(&player.player_id, "refreshed_jwt_token"),
+            refresh_token: fixtures::mock_jwt(&player.player_id, "refresh_token"),
             expires_at: (Utc::now() + chrono::Duration::hours(24)).timestamp(),
         };
 
@@ -199,15 +185,17 @@ impl identity_service_server::IdentityService for IdentityGrpcService {
             .await?;
 
         // Simple validation - check if token is not empty
-        let is_valid = !req.jwt_token.is_empty();
+        let world = self.world().await;
+        let player = (!req.jwt_token.is_empty())
+            .then(|| fixtures::player_for_token(&world, &req.jwt_token))
+            .flatten();
+        let is_valid = player.is_some();
 
         let validate_success = ValidateTokenSuccess {
             is_valid,
-            player_id: if is_valid {
-                "player_123".to_string()
-            } else {
-                "".to_string()
-            },
+            player_id: player
+                .map(|player| player.player_id.clone())
+                .unwrap_or_default(),
             permissions: if is_valid {
                 vec![
                     "read:profile".to_string(),
@@ -298,9 +286,15 @@ impl identity_service_server::IdentityService for IdentityGrpcService {
         self.simulate_latency_and_errors(&context, "GetSessionInfo")
             .await?;
 
+        let world = self.world().await;
+        let player_id = world
+            .player_for_key(&req.session_id)
+            .map(|player| player.player_id.clone())
+            .unwrap_or_default();
+
         let session_info = GetSessionInfoSuccess {
             session_id: req.session_id,
-            player_id: "player_123".to_string(),
+            player_id,
             device_id: "device_456".to_string(),
             created_at: (Utc::now() - chrono::Duration::hours(2)).timestamp(),
             last_active_at: (Utc::now() - chrono::Duration::minutes(5)).timestamp(),
@@ -346,13 +340,14 @@ impl identity_service_server::IdentityService for IdentityGrpcService {
         let _req = request.into_inner();
         let context = self.create_context(None).await;
 
-        {
+        let health = {
             let stub = self.stub.lock().await;
             stub.log_request(&context, "Health", "gRPC");
-        }
+            stub.health_check()
+        };
 
         let response = HealthResponse {
-            status: "HEALTHY".to_string(),
+            status: health.as_str().to_string(),
             version: "0.1.0".to_string(),
             timestamp: Utc::now().timestamp(),
             details: HashMap::new(),
@@ -1,10 +1,11 @@
 mod config;
+mod fixtures;
 mod grpc_server;
 mod stub;
 
 use anyhow::Result;
 use axum::{
-    extract::{Path, Query, State},
+    extract::{FromRef, Path, Query, State},
     http::StatusCode,
     response::Json,
     routing::{get, post},
@@ -16,8 +17,10 @@ use grpc_server::{
     bunkerverse::services::v1::identity_service_server::IdentityServiceServer, IdentityGrpcService,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use stub::{IdentityStub, RequestContext, SmartStub};
+use smart_stubs::world::{paginate, Player};
+use smart_stubs::{admin, StubLayer};
+use std::{collections::HashMap, net::SocketAddr};
+use stub::{RequestContext, SharedStub, SmartStub};
 use tokio::signal;
 use tonic::transport::Server;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
@@ -136,27 +139,49 @@ pub struct ErrorResponse {
 // Application State
 #[derive(Clone)]
 pub struct AppState {
-    pub stub: Arc<tokio::sync::Mutex<IdentityStub>>,
+    pub stub: SharedStub,
 }
 
 impl AppState {
-    pub fn new(config: StubConfiguration) -> Self {
-        Self {
-            stub: Arc::new(tokio::sync::Mutex::new(IdentityStub::new(config))),
-        }
+    pub fn new(stub: SharedStub) -> Self {
+        Self { stub }
     }
 
     pub async fn create_context(&self, trace_id: Option<String>) -> RequestContext {
-        let stub = self.stub.lock().await;
-        RequestContext {
-            request_id: Uuid::new_v4().to_string(),
-            trace_id,
-            timestamp: Utc::now(),
-            enable_crypto: stub.get_configuration().dual_mode.enable_crypto,
-        }
+        self.stub.lock().await.create_context(trace_id)
     }
 }
 
+impl FromRef<AppState> for SharedStub {
+    fn from_ref(state: &AppState) -> Self {
+        state.stub.clone()
+    }
+}
+
+fn user_for(player: &Player, enable_crypto: bool) -> User {
+    User {
+        user_id: player.player_id.clone(),
+        username: player.bunker_tag.clone(),
+        email: player.email.clone(),
+        wallet_address: enable_crypto.then(|| player.l3_wallet_address.clone()),
+        roles: vec!["user".to_string()],
+        permissions: vec!["read:profile".to_string()],
+        is_active: true,
+        created_at: DateTime::from_timestamp(player.registered_at, 0).unwrap_or_default(),
+        last_login: None,
+    }
+}
+
+fn not_found(message: &str, request_id: String) -> (StatusCode, Json<ErrorResponse>) {
+    let error = ErrorResponse {
+        error: message.to_string(),
+        code: "NOT_FOUND".to_string(),
+        timestamp: Utc::now(),
+        request_id,
+    };
+    (StatusCode::NOT_FOUND, Json(error))
+}
+
 // Handler Functions
 pub async fn health_check(
     State(state): State<AppState>,
@@ -238,28 +263,23 @@ pub async fn login(
         return Err((StatusCode::UNAUTHORIZED, Json(error)));
     }
 
-    // Generate mock user based on dual-mode configuration
-    let user = User {
-        user_id: Uuid::new_v4().to_string(),
-        username: request.username.clone(),
-        email: format!("{}@example.com", request.username),
-        wallet_address: if context.enable_crypto {
-            request
-                .wallet_address
-                .or_else(|| Some("0x1234567890abcdef".to_string()))
-        } else {
-            None
-        },
-        roles: vec!["user".to_string()],
-        permissions: vec!["read:profile".to_string(), "update:profile".to_string()],
-        is_active: true,
-        created_at: Utc::now() - chrono::Duration::days(30),
-        last_login: Some(Utc::now()),
+    // Usernames resolve to a world player by bunker tag, or deterministically otherwise
+    let world = stub.world();
+    let Some(player) = world
+        .players
+        .iter()
+        .find(|player| player.bunker_tag.eq_ignore_ascii_case(&request.username))
+        .or_else(|| world.player_for_key(&request.username))
+    else {
+        return Err(not_found("No players in fixture world", context.request_id));
     };
+    let mut user = user_for(player, context.enable_crypto);
+    user.permissions.push("update:profile".to_string());
+    user.last_login = Some(Utc::now());
 
     let response = LoginResponse {
-        access_token: "eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9.mock_access_token".to_string(),
-        refresh_token: "mock_refresh_token_1234567890".to_string(),
+        access_token: fixtures::mock_jwt(&player.player_id, "access_token"),
+        refresh_token: fixtures::mock_jwt(&player.player_id, "refresh_token"),
         token_type: "Bearer".to_string(),
         expires_in: 3600,
         user,
@@ -340,9 +360,14 @@ pub async fn validate_token(
     ))
     .await;
 
+    let world = stub.world();
+    let player = (!request.access_token.is_empty())
+        .then(|| fixtures::player_for_token(&world, &request.access_token))
+        .flatten();
+
     let response = TokenValidationResponse {
-        valid: !request.access_token.is_empty(),
-        user_id: Some("user_12345".to_string()),
+        valid: player.is_some(),
+        user_id: player.map(|player| player.player_id.clone()),
         expires_at: Some(Utc::now() + chrono::Duration::hours(1)),
         scopes: vec!["read".to_string(), "write".to_string()],
     };
@@ -373,30 +398,21 @@ pub async fn get_users(
     ))
     .await;
 
-    let users = vec![User {
-        user_id: "user_001".to_string(),
-        username: "alice".to_string(),
-        email: "alice@example.com".to_string(),
-        wallet_address: if context.enable_crypto {
-            Some("0xalice123456789".to_string())
-        } else {
-            None
-        },
-        roles: vec!["user".to_string()],
-        permissions: vec!["read:profile".to_string()],
-        is_active: true,
-        created_at: Utc::now() - chrono::Duration::days(30),
-        last_login: Some(Utc::now() - chrono::Duration::hours(2)),
-    }];
-
-    let page = pagination.page.unwrap_or(1);
-    let limit = pagination.limit.unwrap_or(10).min(100);
+    let world = stub.world();
+    let (players, page_info) = paginate(
+        &world.players,
+        pagination.page.unwrap_or(1),
+        pagination.limit.unwrap_or(10),
+    );
 
     let response = UsersResponse {
-        users,
-        total_count: 1,
-        page,
-        limit,
+        users: players
+            .iter()
+            .map(|player| user_for(player, context.enable_crypto))
+            .collect(),
+        total_count: page_info.total_items as u32,
+        page: page_info.page,
+        limit: page_info.page_size,
     };
 
     stub.log_response(
@@ -425,21 +441,18 @@ pub async fn get_user_details(
     ))
     .await;
 
-    let user = User {
-        user_id: user_id.clone(),
-        username: "mock_user".to_string(),
-        email: "mock_user@example.com".to_string(),
-        wallet_address: if context.enable_crypto {
-            Some("0xmockuser123456789".to_string())
-        } else {
-            None
-        },
-        roles: vec!["user".to_string()],
-        permissions: vec!["read:profile".to_string()],
-        is_active: true,
-        created_at: Utc::now() - chrono::Duration::days(10),
-        last_login: Some(Utc::now() - chrono::Duration::hours(1)),
+    let world = stub.world();
+    let Some(player) = world.player(&user_id) else {
+        stub.log_response(
+            &context,
+            &format!("/api/identity/users/{}", user_id),
+            latency.as_millis() as u64,
+            404,
+            false,
+        );
+        return Err(not_found("User not found", context.request_id));
     };
+    let user = user_for(player, context.enable_crypto);
 
     stub.log_response(
         &context,
@@ -459,10 +472,15 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer().json())
         .init();
 
-    let config = StubConfiguration::default();
+    let config_source = config::config_source();
+    let config = config_source.load()?;
     let http_addr: SocketAddr = format!("0.0.0.0:{}", config.base.port).parse()?;
     let grpc_addr: SocketAddr = format!("0.0.0.0:{}", config.base.port + 1000).parse()?; // gRPC on port 9083
-    let state = AppState::new(config.clone());
+    let stub = stub::new_shared_stub(config.clone());
+    let state = AppState::new(stub.clone());
+    // Hot reload latency, error and dataset settings when the config file changes
+    config_source.spawn_watcher(stub.clone());
+    smart_stubs::spawn_state_reset(stub.clone());
 
     info!(
         service_name = %config.base.name,
@@ -470,6 +488,7 @@ async fn main() -> Result<()> {
         http_address = %http_addr,
         grpc_address = %grpc_addr,
         enable_crypto = config.dual_mode.enable_crypto,
+        config_path = ?config_source.path(),
         "Starting Identity Service Smart Stub with HTTP and gRPC servers"
     );
 
@@ -478,6 +497,8 @@ async fn main() -> Result<()> {
         .route("/health", get(health_check))
         .route("/stub/config", get(get_stub_config))
         .route("/stub/reset", post(reset_stub_state))
+        .route("/stub/scenario", admin::scenario_route())
+        .route("/stub/world", admin::world_route())
         // Authentication endpoints
         .route("/api/identity/auth/login", post(login))
         .route("/api/identity/auth/register", post(register))
@@ -486,6 +507,7 @@ async fn main() -> Result<()> {
         .route("/api/identity/users", get(get_users))
         .route("/api/identity/users/:user_id", get(get_user_details))
         // Middleware
+        .layer(StubLayer::fault_rules(stub.clone()))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
         .with_state(state);
@@ -493,7 +515,7 @@ async fn main() -> Result<()> {
     let http_listener = tokio::net::TcpListener::bind(http_addr).await?;
 
     // gRPC Server
-    let grpc_service = IdentityGrpcService::new(config.clone());
+    let grpc_service = IdentityGrpcService::new(stub.clone());
 
     info!("HTTP server ready and listening on {}", http_addr);
     info!("gRPC server ready and listening on {}", grpc_addr);
@@ -502,6 +524,7 @@ async fn main() -> Result<()> {
     let http_server = axum::serve(http_listener, app).with_graceful_shutdown(shutdown_signal());
 
     let grpc_server = Server::builder()
+        .layer(StubLayer::fault_rules(stub))
         .add_service(IdentityServiceServer::new(grpc_service))
         .serve_with_shutdown(grpc_addr, shutdown_signal());
 
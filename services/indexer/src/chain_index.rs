@@ -0,0 +1,977 @@
+//! Durable index of L3 blocks, transactions and the canonical events decoded
+//! from their logs, filled by the ingestion worker in [`crate::ingest`]
+
+use crate::chain_store::{ChainIndexMeta, ChainIndexStore};
+use crate::projection::{PlayerProjections, SNAPSHOT_CAPACITY, SNAPSHOT_INTERVAL};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use smart_stubs::world::{events_in_blocks, ChainEvent};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Indexing errors kept for status reports; older ones are dropped
+pub const RECENT_ERRORS_CAPACITY: usize = 20;
+
+/// Retractions kept for clients catching up; older ones are dropped
+pub const RETRACTIONS_CAPACITY: usize = 1_000;
+
+/// Index shared by the ingestion worker and the indexer RPCs
+pub type SharedChainIndex = Arc<RwLock<ChainIndex>>;
+
+/// Header of an indexed block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedBlock {
+    pub number: u64,
+    pub hash: String,
+    pub parent_hash: String,
+    pub timestamp: i64,
+    pub transaction_count: u32,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    pub size: u64,
+    /// Logs that matched no canonical event
+    pub undecoded_logs: u64,
+}
+
+/// Transaction of an indexed block with the outcome from its receipt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedTransaction {
+    pub hash: String,
+    pub block_number: u64,
+    pub from_address: String,
+    /// Empty for contract creations
+    pub to_address: String,
+    /// Decimal wei
+    pub value: String,
+    pub gas_price: u64,
+    pub gas_used: u64,
+    pub succeeded: bool,
+    pub timestamp: i64,
+}
+
+/// Everything ingested from one block, appended to the index as a unit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IngestedBlock {
+    pub block: IndexedBlock,
+    pub transactions: Vec<IndexedTransaction>,
+    /// In log order
+    pub events: Vec<ChainEvent>,
+}
+
+/// Event dropped from the index because its block left the canonical chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetractedEvent {
+    /// Starts at 1 and increases by one per retraction
+    pub sequence: u64,
+    pub event: ChainEvent,
+    /// Hash of the orphaned block the event was in
+    pub block_hash: String,
+    pub retracted_at: i64,
+}
+
+/// Lifecycle of a [`ReindexJob`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReindexStatus {
+    Running,
+    Completed,
+    Cancelled,
+}
+
+/// Backfill that decodes already indexed blocks again, for example after a
+/// decoder fix
+///
+/// The job is saved with the index, so each checkpoint covers exactly the
+/// blocks that were replaced before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReindexJob {
+    pub id: String,
+    pub status: ReindexStatus,
+    pub start_block: u64,
+    /// Indexed head when the job started; later blocks come from the live tail
+    pub target_block: u64,
+    /// Next block to reindex
+    pub next_block: u64,
+    /// Contracts whose events are rebuilt, empty for all
+    pub contract_addresses: Vec<String>,
+    /// Keep indexed events and only add the ones that were missing
+    pub preserve_existing: bool,
+    /// Events the job wrote into the index
+    pub events_reindexed: u64,
+    pub started_at: i64,
+    /// When the job was last started or resumed, and the block it resumed at
+    pub resumed_at: i64,
+    pub resumed_from: u64,
+    /// Set once the job completes or is cancelled
+    pub finished_at: Option<i64>,
+    /// Why the last batch failed; the batch is retried
+    pub last_error: Option<String>,
+}
+
+impl ReindexJob {
+    /// Last block reindexed, `None` before the first batch
+    #[must_use]
+    pub fn current_block(&self) -> Option<u64> {
+        self.next_block
+            .checked_sub(1)
+            .filter(|block| *block >= self.start_block)
+    }
+
+    #[must_use]
+    pub fn blocks_remaining(&self) -> u64 {
+        (self.target_block + 1).saturating_sub(self.next_block)
+    }
+
+    /// Blocks reindexed per second since the job was last resumed
+    #[must_use]
+    pub fn blocks_per_second(&self, now: i64) -> f64 {
+        let elapsed = (self.finished_at.unwrap_or(now) - self.resumed_at).max(1);
+        (self.next_block - self.resumed_from) as f64 / elapsed as f64
+    }
+
+    /// When the job should reach its target at the current rate
+    ///
+    /// `None` once finished or before any progress has been measured.
+    #[must_use]
+    pub fn estimated_completion(&self, now: i64) -> Option<i64> {
+        if self.status != ReindexStatus::Running {
+            return None;
+        }
+        let rate = self.blocks_per_second(now);
+        (rate > 0.0).then(|| now + (self.blocks_remaining() as f64 / rate).ceil() as i64)
+    }
+
+    fn in_scope(&self, contract_address: &str) -> bool {
+        self.contract_addresses.is_empty()
+            || self
+                .contract_addresses
+                .iter()
+                .any(|address| address.eq_ignore_ascii_case(contract_address))
+    }
+}
+
+/// Contiguous run of indexed blocks and what they contained
+///
+/// Blocks are appended in order and each must extend the previous one, so
+/// the index always describes a single branch of the chain. When the node
+/// switches branches the index is rolled back to the fork point and the
+/// events it drops are journaled as retractions.
+///
+/// Player projections follow the blocks. They are not stored but folded again
+/// when the index is loaded, and periodic snapshots bound how much has to be
+/// refolded after a rollback or reindex.
+#[derive(Debug, Clone, Default)]
+pub struct ChainIndex {
+    blocks: BTreeMap<u64, IndexedBlock>,
+    transactions: Vec<IndexedTransaction>,
+    /// Shared with readers and copied only when appended to while read
+    events: Arc<Vec<ChainEvent>>,
+    /// Head block the node reported on the last poll
+    chain_head: u64,
+    started_at: i64,
+    last_synced_at: i64,
+    recent_errors: VecDeque<String>,
+    retractions: VecDeque<RetractedEvent>,
+    last_retraction: u64,
+    /// Branch switches seen since the index was created
+    reorgs: u64,
+    /// Running reindex job, or the last one to finish
+    reindex_job: Option<ReindexJob>,
+    /// Shared with readers like `events`, as of the head block
+    projections: Arc<PlayerProjections>,
+    /// Every `SNAPSHOT_INTERVAL` blocks, oldest first
+    projection_snapshots: VecDeque<Arc<PlayerProjections>>,
+}
+
+impl ChainIndex {
+    #[must_use]
+    pub fn new(now: i64) -> Self {
+        Self {
+            started_at: now,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn into_shared(self) -> SharedChainIndex {
+        Arc::new(RwLock::new(self))
+    }
+
+    /// Load the index kept in `store`, `None` if nothing was stored
+    ///
+    /// # Errors
+    /// Returns an error if the stored index cannot be read or parsed
+    pub fn load(store: &dyn ChainIndexStore) -> Result<Option<Self>> {
+        let Some(stored) = store.load()? else {
+            return Ok(None);
+        };
+        let meta = stored.meta;
+        let mut index = Self {
+            chain_head: meta.chain_head,
+            started_at: meta.started_at,
+            last_synced_at: meta.last_synced_at,
+            recent_errors: meta.recent_errors,
+            retractions: stored.retractions.into(),
+            last_retraction: meta.last_retraction,
+            reorgs: meta.reorgs,
+            reindex_job: meta.reindex_job,
+            ..Self::default()
+        };
+        let events = Arc::make_mut(&mut index.events);
+        for (number, ingested) in stored.blocks {
+            index.transactions.extend(ingested.transactions);
+            events.extend(ingested.events);
+            index.blocks.insert(number, ingested.block);
+        }
+        index.refold_projections(0);
+        Ok(Some(index))
+    }
+
+    /// Everything but the blocks and retractions, as stored
+    #[must_use]
+    pub fn meta(&self) -> ChainIndexMeta {
+        ChainIndexMeta {
+            chain_head: self.chain_head,
+            started_at: self.started_at,
+            last_synced_at: self.last_synced_at,
+            recent_errors: self.recent_errors.clone(),
+            last_retraction: self.last_retraction,
+            reorgs: self.reorgs,
+            reindex_job: self.reindex_job.clone(),
+        }
+    }
+
+    /// Indexed blocks in the inclusive range with their transactions and events
+    #[must_use]
+    pub fn ingested_blocks(&self, start_block: u64, end_block: u64) -> Vec<IngestedBlock> {
+        self.blocks
+            .range(start_block..=end_block)
+            .map(|(&number, block)| {
+                let start = self
+                    .transactions
+                    .partition_point(|transaction| transaction.block_number < number);
+                let end = self
+                    .transactions
+                    .partition_point(|transaction| transaction.block_number <= number);
+                IngestedBlock {
+                    block: block.clone(),
+                    transactions: self.transactions[start..end].to_vec(),
+                    events: self.events_in_blocks(number, number).to_vec(),
+                }
+            })
+            .collect()
+    }
+
+    /// Highest indexed block
+    #[must_use]
+    pub fn head(&self) -> Option<&IndexedBlock> {
+        self.blocks.values().next_back()
+    }
+
+    /// Block to ingest next; `start_block` while the index is empty
+    #[must_use]
+    pub fn next_block(&self, start_block: u64) -> u64 {
+        self.head().map_or(start_block, |head| head.number + 1)
+    }
+
+    #[must_use]
+    pub fn block(&self, number: u64) -> Option<&IndexedBlock> {
+        self.blocks.get(&number)
+    }
+
+    /// Indexed blocks in chain order
+    pub fn blocks(&self) -> impl DoubleEndedIterator<Item = &IndexedBlock> {
+        self.blocks.values()
+    }
+
+    /// Indexed transactions in chain order
+    #[must_use]
+    pub fn transactions(&self) -> &[IndexedTransaction] {
+        &self.transactions
+    }
+
+    /// Indexed events in chain order
+    #[must_use]
+    pub fn events(&self) -> Arc<Vec<ChainEvent>> {
+        Arc::clone(&self.events)
+    }
+
+    /// Player projections as of the head block
+    #[must_use]
+    pub fn projections(&self) -> Arc<PlayerProjections> {
+        Arc::clone(&self.projections)
+    }
+
+    #[must_use]
+    pub fn chain_head(&self) -> u64 {
+        self.chain_head
+    }
+
+    /// Blocks the node has that are not indexed yet
+    #[must_use]
+    pub fn blocks_behind(&self) -> u64 {
+        let indexed = self.head().map_or(0, |head| head.number);
+        self.chain_head.saturating_sub(indexed)
+    }
+
+    #[must_use]
+    pub fn started_at(&self) -> i64 {
+        self.started_at
+    }
+
+    /// When the index last caught up with a poll, 0 before the first
+    #[must_use]
+    pub fn last_synced_at(&self) -> i64 {
+        self.last_synced_at
+    }
+
+    #[must_use]
+    pub fn undecoded_logs(&self) -> u64 {
+        self.blocks.values().map(|block| block.undecoded_logs).sum()
+    }
+
+    #[must_use]
+    pub fn reorgs(&self) -> u64 {
+        self.reorgs
+    }
+
+    /// Highest block at least `confirmations` blocks below the node's head
+    ///
+    /// Events up to it are final; later ones may still be retracted. `None`
+    /// while no indexed block is that deep.
+    #[must_use]
+    pub fn finalized_block(&self, confirmations: u64) -> Option<u64> {
+        let head = self.head()?.number;
+        let first = self.blocks.keys().next().copied()?;
+        let finalized = self.chain_head.max(head).checked_sub(confirmations)?;
+        (finalized >= first).then_some(finalized.min(head))
+    }
+
+    /// Sequence number of the newest retraction, 0 before the first
+    #[must_use]
+    pub fn last_retraction(&self) -> u64 {
+        self.last_retraction
+    }
+
+    /// Retained retractions with a sequence number above `after`, oldest first
+    ///
+    /// Sequence numbers are contiguous, so a gap after `after` means some were
+    /// dropped and the client should refetch the affected blocks.
+    pub fn retractions_since(&self, after: u64) -> impl Iterator<Item = &RetractedEvent> {
+        self.retractions
+            .iter()
+            .filter(move |retraction| retraction.sequence > after)
+    }
+
+    #[must_use]
+    pub fn reindex_job(&self) -> Option<&ReindexJob> {
+        self.reindex_job.as_ref()
+    }
+
+    /// Recent indexing errors, oldest first
+    pub fn recent_errors(&self) -> impl Iterator<Item = &String> {
+        self.recent_errors.iter()
+    }
+
+    /// Append the next block
+    ///
+    /// # Errors
+    /// Returns an error, leaving the index unchanged, unless the block directly
+    /// follows the head and names it as its parent
+    pub fn append(&mut self, ingested: IngestedBlock) -> Result<()> {
+        let block = &ingested.block;
+        if let Some(head) = self.head() {
+            if block.number != head.number + 1 {
+                bail!(
+                    "block {} does not follow indexed head {}",
+                    block.number,
+                    head.number
+                );
+            }
+            if block.parent_hash != head.hash {
+                bail!(
+                    "block {} has parent {} but indexed block {} is {}",
+                    block.number,
+                    block.parent_hash,
+                    head.number,
+                    head.hash
+                );
+            }
+        }
+
+        let number = block.number;
+        let first_event = self.events.len();
+        self.transactions.extend(ingested.transactions);
+        Arc::make_mut(&mut self.events).extend(ingested.events);
+        Arc::make_mut(&mut self.projections).apply_block(number, &self.events[first_event..]);
+        self.blocks.insert(number, ingested.block);
+        self.snapshot_projections();
+        Ok(())
+    }
+
+    /// Drop `fork_block` and every block after it, journaling their events
+    ///
+    /// Returns the retractions, newest event first so consumers can undo
+    /// them in order.
+    pub fn roll_back_to(&mut self, fork_block: u64, now: i64) -> Vec<RetractedEvent> {
+        let orphaned = self.blocks.split_off(&fork_block);
+        if orphaned.is_empty() {
+            return vec![];
+        }
+        self.reorgs += 1;
+
+        let kept = self
+            .transactions
+            .partition_point(|transaction| transaction.block_number < fork_block);
+        self.transactions.truncate(kept);
+        let kept = self
+            .events
+            .partition_point(|event| event.block_number < fork_block);
+        let dropped = Arc::make_mut(&mut self.events).split_off(kept);
+
+        let mut retracted = Vec::with_capacity(dropped.len());
+        for event in dropped.into_iter().rev() {
+            self.last_retraction += 1;
+            let retraction = RetractedEvent {
+                sequence: self.last_retraction,
+                block_hash: orphaned
+                    .get(&event.block_number)
+                    .map(|block| block.hash.clone())
+                    .unwrap_or_default(),
+                event,
+                retracted_at: now,
+            };
+            if self.retractions.len() == RETRACTIONS_CAPACITY {
+                self.retractions.pop_front();
+            }
+            self.retractions.push_back(retraction.clone());
+            retracted.push(retraction);
+        }
+        self.refold_projections(fork_block);
+        retracted
+    }
+
+    /// Start reindexing from `start_block` up to the current head
+    ///
+    /// # Errors
+    /// Returns an error if another job is running or `start_block` is not indexed
+    pub fn start_reindex(
+        &mut self,
+        id: String,
+        start_block: u64,
+        contract_addresses: Vec<String>,
+        preserve_existing: bool,
+        now: i64,
+    ) -> Result<&ReindexJob> {
+        if let Some(job) = self
+            .reindex_job
+            .as_ref()
+            .filter(|job| job.status == ReindexStatus::Running)
+        {
+            bail!("reindex job {} is still running", job.id);
+        }
+        let (Some(first), Some(head)) = (self.blocks.keys().next(), self.head()) else {
+            bail!("nothing is indexed yet");
+        };
+        if !(*first..=head.number).contains(&start_block) {
+            bail!(
+                "block {start_block} is outside the indexed blocks {first} to {}",
+                head.number
+            );
+        }
+
+        Ok(self.reindex_job.insert(ReindexJob {
+            id,
+            status: ReindexStatus::Running,
+            start_block,
+            target_block: head.number,
+            next_block: start_block,
+            contract_addresses,
+            preserve_existing,
+            events_reindexed: 0,
+            started_at: now,
+            resumed_at: now,
+            resumed_from: start_block,
+            finished_at: None,
+            last_error: None,
+        }))
+    }
+
+    /// Restart the rate measurement of a job loaded from a checkpoint
+    ///
+    /// Returns the job if it still has blocks to reindex.
+    pub fn resume_reindex(&mut self, now: i64) -> Option<&ReindexJob> {
+        let job = self
+            .reindex_job
+            .as_mut()
+            .filter(|job| job.status == ReindexStatus::Running)?;
+        job.resumed_at = now;
+        job.resumed_from = job.next_block;
+        Some(job)
+    }
+
+    /// Stop the running job `id`; blocks it already replaced stay replaced
+    ///
+    /// # Errors
+    /// Returns an error if `id` is not the running job
+    pub fn cancel_reindex(&mut self, id: &str, now: i64) -> Result<&ReindexJob> {
+        let Some(job) = self.reindex_job.as_mut().filter(|job| job.id == id) else {
+            bail!("reindex job {id} not found");
+        };
+        if job.status != ReindexStatus::Running {
+            bail!("reindex job {id} is no longer running");
+        }
+        job.status = ReindexStatus::Cancelled;
+        job.finished_at = Some(now);
+        Ok(job)
+    }
+
+    /// Replace indexed blocks with the copies job `id` decoded again and
+    /// checkpoint it after `through`
+    ///
+    /// Blocks that left the chain since they were fetched are skipped; the
+    /// live tail indexes their replacements. Returns false, changing
+    /// nothing, if `id` is no longer running.
+    pub fn apply_reindex(
+        &mut self,
+        id: &str,
+        reindexed: Vec<IngestedBlock>,
+        through: u64,
+        now: i64,
+    ) -> bool {
+        let Some(job) = self
+            .reindex_job
+            .as_mut()
+            .filter(|job| job.id == id && job.status == ReindexStatus::Running)
+        else {
+            return false;
+        };
+        let full_rebuild = job.contract_addresses.is_empty() && !job.preserve_existing;
+        let mut first_replaced = None;
+
+        for ingested in reindexed {
+            let number = ingested.block.number;
+            let Some(block) = self
+                .blocks
+                .get_mut(&number)
+                .filter(|block| block.hash == ingested.block.hash)
+            else {
+                continue;
+            };
+            if full_rebuild {
+                block.undecoded_logs = ingested.block.undecoded_logs;
+            }
+            first_replaced.get_or_insert(number);
+
+            let start = self
+                .transactions
+                .partition_point(|transaction| transaction.block_number < number);
+            let end = self
+                .transactions
+                .partition_point(|transaction| transaction.block_number <= number);
+            self.transactions.splice(start..end, ingested.transactions);
+
+            let events = Arc::make_mut(&mut self.events);
+            let start = events.partition_point(|event| event.block_number < number);
+            let end = events.partition_point(|event| event.block_number <= number);
+            let existing = &events[start..end];
+            let added: Vec<ChainEvent> = ingested
+                .events
+                .into_iter()
+                .filter(|event| {
+                    !job.preserve_existing
+                        || !existing
+                            .iter()
+                            .any(|indexed| indexed.event_id == event.event_id)
+                })
+                .collect();
+            job.events_reindexed += added.len() as u64;
+            let mut merged: Vec<ChainEvent> = existing
+                .iter()
+                .filter(|event| job.preserve_existing || !job.in_scope(&event.contract_address))
+                .cloned()
+                .chain(added)
+                .collect();
+            merged.sort_by_key(|event| event.log_index);
+            events.splice(start..end, merged);
+        }
+
+        job.next_block = job.next_block.max(through + 1);
+        job.last_error = None;
+        if job.next_block > job.target_block {
+            job.status = ReindexStatus::Completed;
+            job.finished_at = Some(now);
+        }
+        if let Some(first_replaced) = first_replaced {
+            self.refold_projections(first_replaced);
+        }
+        true
+    }
+
+    /// Note why a batch of job `id` failed
+    pub fn record_reindex_error(&mut self, id: &str, error: impl Into<String>) {
+        if let Some(job) = self.reindex_job.as_mut().filter(|job| job.id == id) {
+            job.last_error = Some(error.into());
+        }
+    }
+
+    /// Note the node's head after a poll that indexed everything it fetched
+    pub fn record_sync(&mut self, chain_head: u64, now: i64) {
+        self.chain_head = chain_head;
+        self.last_synced_at = now;
+    }
+
+    pub fn record_error(&mut self, error: impl Into<String>) {
+        if self.recent_errors.len() == RECENT_ERRORS_CAPACITY {
+            self.recent_errors.pop_front();
+        }
+        self.recent_errors.push_back(error.into());
+    }
+
+    /// Events in the inclusive block range, in chain order
+    #[must_use]
+    pub fn events_in_blocks(&self, start_block: u64, end_block: u64) -> &[ChainEvent] {
+        events_in_blocks(&self.events, start_block, end_block)
+    }
+
+    /// Keep the projections if they are `SNAPSHOT_INTERVAL` blocks past the last snapshot
+    fn snapshot_projections(&mut self) {
+        let Some(height) = self.projections.block_height() else {
+            return;
+        };
+        let due = match self
+            .projection_snapshots
+            .back()
+            .and_then(|snapshot| snapshot.block_height())
+        {
+            Some(last) => height >= last + SNAPSHOT_INTERVAL,
+            None => true,
+        };
+        if due {
+            if self.projection_snapshots.len() == SNAPSHOT_CAPACITY {
+                self.projection_snapshots.pop_front();
+            }
+            self.projection_snapshots
+                .push_back(Arc::clone(&self.projections));
+        }
+    }
+
+    /// Rebuild the projections after events from `from_block` on changed
+    ///
+    /// Starts from the newest snapshot below `from_block`, or from nothing if
+    /// none is left, and folds the indexed blocks after it.
+    fn refold_projections(&mut self, from_block: u64) {
+        while self
+            .projection_snapshots
+            .back()
+            .is_some_and(|snapshot| snapshot.block_height() >= Some(from_block))
+        {
+            self.projection_snapshots.pop_back();
+        }
+        self.projections = self
+            .projection_snapshots
+            .back()
+            .map_or_else(Arc::default, Arc::clone);
+
+        let resume = self
+            .projections
+            .block_height()
+            .map_or(0, |height| height + 1);
+        let numbers: Vec<u64> = self
+            .blocks
+            .range(resume..)
+            .map(|(number, _)| *number)
+            .collect();
+        for number in numbers {
+            let events = events_in_blocks(&self.events, number, number);
+            Arc::make_mut(&mut self.projections).apply_block(number, events);
+            self.snapshot_projections();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_store::{ChainIndexBatch, FileChainStore};
+    use smart_stubs::world::EventPayload;
+
+    const NOW: i64 = 1_800_000_000;
+
+    fn block(number: u64, parent_hash: &str) -> IngestedBlock {
+        IngestedBlock {
+            block: IndexedBlock {
+                number,
+                hash: format!("0x{number:064x}"),
+                parent_hash: parent_hash.to_string(),
+                timestamp: NOW + number as i64,
+                transaction_count: 1,
+                gas_used: 21_000,
+                gas_limit: 30_000_000,
+                size: 600,
+                undecoded_logs: 1,
+            },
+            transactions: vec![],
+            events: vec![ChainEvent {
+                event_id: uuid::Uuid::new_v4().to_string(),
+                block_number: number,
+                log_index: 0,
+                contract_address: "0xtoken".to_string(),
+                transaction_hash: format!("0xtx{number}"),
+                block_timestamp: NOW + number as i64,
+                payload: EventPayload::NtcTransfer {
+                    from_player_id: String::new(),
+                    to_player_id: "0xabc".to_string(),
+                    amount_wei: 1,
+                    transfer_type: "mint".to_string(),
+                },
+            }],
+        }
+    }
+
+    #[test]
+    fn test_blocks_must_extend_the_indexed_head() {
+        let mut index = ChainIndex::new(NOW);
+        assert_eq!(index.next_block(5), 5);
+        index.append(block(5, "0xgenesis")).unwrap();
+        let head_hash = index.head().unwrap().hash.clone();
+
+        assert!(index.append(block(7, &head_hash)).is_err());
+        assert!(index.append(block(6, "0xother")).is_err());
+        index.append(block(6, &head_hash)).unwrap();
+
+        assert_eq!(index.next_block(5), 7);
+        assert_eq!(index.events().len(), 2);
+        assert_eq!(index.events_in_blocks(6, 10).len(), 1);
+        assert_eq!(index.undecoded_logs(), 2);
+
+        index.record_sync(9, NOW + 10);
+        assert_eq!(index.blocks_behind(), 3);
+    }
+
+    #[test]
+    fn test_readers_keep_their_snapshot_while_blocks_are_appended() {
+        let mut index = ChainIndex::new(NOW);
+        index.append(block(1, "0xgenesis")).unwrap();
+        let snapshot = index.events();
+        let head_hash = index.head().unwrap().hash.clone();
+        index.append(block(2, &head_hash)).unwrap();
+
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(index.events().len(), 2);
+    }
+
+    #[test]
+    fn test_roll_back_retracts_orphaned_events_newest_first() {
+        let mut index = ChainIndex::new(NOW);
+        let mut parent = "0xgenesis".to_string();
+        for number in 1..=4 {
+            index.append(block(number, &parent)).unwrap();
+            parent = index.head().unwrap().hash.clone();
+        }
+        index.record_sync(4, NOW);
+        assert_eq!(index.finalized_block(2), Some(2));
+        assert_eq!(index.finalized_block(4), None);
+
+        let retracted = index.roll_back_to(3, NOW + 1);
+        assert_eq!(
+            retracted
+                .iter()
+                .map(|retraction| (retraction.sequence, retraction.event.block_number))
+                .collect::<Vec<_>>(),
+            [(1, 4), (2, 3)]
+        );
+        assert_eq!(retracted[0].block_hash, format!("0x{:064x}", 4));
+        assert_eq!(index.head().unwrap().number, 2);
+        assert_eq!(index.events().len(), 2);
+        assert_eq!(index.undecoded_logs(), 2);
+        assert_eq!(index.reorgs(), 1);
+        let projections = index.projections();
+        assert_eq!(projections.block_height(), Some(2));
+        assert_eq!(projections.player("0xabc").unwrap().ntc_balance_wei, 2);
+        assert_eq!(index.retractions_since(1).count(), 1);
+        assert_eq!(index.last_retraction(), 2);
+
+        // Nothing past the head is a no-op
+        assert!(index.roll_back_to(7, NOW + 2).is_empty());
+        assert_eq!(index.reorgs(), 1);
+    }
+
+    fn indexed(blocks: u64) -> ChainIndex {
+        let mut index = ChainIndex::new(NOW);
+        let mut parent = "0xgenesis".to_string();
+        for number in 1..=blocks {
+            index.append(block(number, &parent)).unwrap();
+            parent = index.head().unwrap().hash.clone();
+        }
+        index
+    }
+
+    fn decoded_again(number: u64, contracts: &[&str]) -> IngestedBlock {
+        let mut reindexed = block(number, "");
+        let event = reindexed.events.pop().unwrap();
+        reindexed.block.undecoded_logs = 0;
+        reindexed.events = contracts
+            .iter()
+            .enumerate()
+            .map(|(log_index, contract)| ChainEvent {
+                event_id: format!("{number}:{log_index}"),
+                log_index: log_index as u64,
+                contract_address: contract.to_string(),
+                ..event.clone()
+            })
+            .collect();
+        reindexed
+    }
+
+    #[test]
+    fn test_reindex_replaces_blocks_until_the_target() {
+        let mut index = indexed(3);
+        assert!(index
+            .start_reindex("job".to_string(), 0, vec![], false, NOW)
+            .is_err());
+        let job = index
+            .start_reindex("job".to_string(), 2, vec![], false, NOW)
+            .unwrap();
+        assert_eq!((job.target_block, job.current_block()), (3, None));
+        assert!(index
+            .start_reindex("other".to_string(), 1, vec![], false, NOW)
+            .is_err());
+
+        let blocks = vec![decoded_again(2, &["0xtoken", "0xnft"])];
+        assert!(index.apply_reindex("job", blocks, 2, NOW + 10));
+        let job = index.reindex_job().unwrap();
+        assert_eq!(
+            (job.status, job.current_block()),
+            (ReindexStatus::Running, Some(2))
+        );
+        assert_eq!(job.blocks_per_second(NOW + 10), 0.1);
+        assert_eq!(job.estimated_completion(NOW + 10), Some(NOW + 20));
+        let events = index.events_in_blocks(2, 2);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].contract_address, "0xnft");
+        assert_eq!(index.undecoded_logs(), 2);
+
+        // Block 3 was orphaned after it was fetched, so it is left to the live tail
+        let mut orphaned = decoded_again(3, &[]);
+        orphaned.block.hash = "0xorphaned".to_string();
+        assert!(index.apply_reindex("job", vec![orphaned], 3, NOW + 20));
+        assert_eq!(index.events_in_blocks(3, 3).len(), 1);
+        let job = index.reindex_job().unwrap();
+        assert_eq!(
+            (job.status, job.finished_at),
+            (ReindexStatus::Completed, Some(NOW + 20))
+        );
+        assert_eq!(job.events_reindexed, 2);
+        assert_eq!(job.estimated_completion(NOW + 20), None);
+        assert!(!index.apply_reindex("job", vec![], 3, NOW + 30));
+    }
+
+    #[test]
+    fn test_scoped_reindex_keeps_other_contracts_and_can_be_cancelled() {
+        let mut index = indexed(3);
+        index
+            .start_reindex("job".to_string(), 1, vec!["0xNFT".to_string()], true, NOW)
+            .unwrap();
+
+        // The indexed event is kept and the missing one is added once
+        let blocks = vec![decoded_again(1, &["0xnft"]), decoded_again(2, &["0xnft"])];
+        assert!(index.apply_reindex("job", blocks.clone(), 2, NOW));
+        assert!(index.apply_reindex("job", blocks, 2, NOW));
+        assert_eq!(index.events_in_blocks(1, 1).len(), 2);
+        assert_eq!(index.reindex_job().unwrap().events_reindexed, 2);
+
+        assert!(index.cancel_reindex("other", NOW).is_err());
+        index.cancel_reindex("job", NOW + 5).unwrap();
+        assert!(index.cancel_reindex("job", NOW + 5).is_err());
+        assert!(!index.apply_reindex("job", vec![decoded_again(3, &["0xnft"])], 3, NOW));
+        assert_eq!(index.events_in_blocks(3, 3).len(), 1);
+        assert!(index.resume_reindex(NOW + 6).is_none());
+        assert_eq!(
+            index.reindex_job().unwrap().status,
+            ReindexStatus::Cancelled
+        );
+    }
+
+    #[test]
+    fn test_projections_refold_from_the_snapshot_below_a_change() {
+        let mut index = indexed(2 * SNAPSHOT_INTERVAL + 50);
+        let balance = |index: &ChainIndex| {
+            index
+                .projections()
+                .player("0xabc")
+                .map_or(0, |player| player.ntc_balance_wei)
+        };
+        let blocks = |count: u64| u128::from(count);
+        assert_eq!(balance(&index), blocks(2 * SNAPSHOT_INTERVAL + 50));
+        assert_eq!(index.projection_snapshots.len(), 3);
+
+        index.roll_back_to(SNAPSHOT_INTERVAL + 20, NOW);
+        assert_eq!(balance(&index), blocks(SNAPSHOT_INTERVAL + 19));
+        assert_eq!(index.projection_snapshots.len(), 2);
+        assert_eq!(
+            index.projections().block_height(),
+            Some(SNAPSHOT_INTERVAL + 19)
+        );
+
+        // Reindexing a block without its transfer drops it from the balance
+        index
+            .start_reindex("job".to_string(), 5, vec![], false, NOW)
+            .unwrap();
+        assert!(index.apply_reindex("job", vec![decoded_again(5, &[])], 5, NOW));
+        assert_eq!(balance(&index), blocks(SNAPSHOT_INTERVAL + 18));
+    }
+
+    #[test]
+    fn test_index_survives_a_store_and_load() {
+        let path = std::env::temp_dir()
+            .join(format!("chain-index-{}", uuid::Uuid::new_v4()))
+            .join("index.jsonl");
+        let store = FileChainStore::new(&path);
+        assert!(ChainIndex::load(&store).unwrap().is_none());
+
+        let mut index = ChainIndex::new(NOW);
+        let first = block(1, "0xgenesis");
+        index.append(first.clone()).unwrap();
+        let second = block(2, &first.block.hash);
+        index.append(second.clone()).unwrap();
+        assert_eq!(index.ingested_blocks(1, 2), [first, second]);
+        store
+            .write(&ChainIndexBatch {
+                blocks: index.ingested_blocks(1, 2),
+                meta: index.meta(),
+                ..ChainIndexBatch::default()
+            })
+            .unwrap();
+
+        let retractions = index.roll_back_to(2, NOW);
+        index.record_error("node unreachable");
+        index
+            .start_reindex("job".to_string(), 1, vec![], false, NOW)
+            .unwrap();
+        store
+            .write(&ChainIndexBatch {
+                truncate_from: Some(2),
+                retractions,
+                meta: index.meta(),
+                ..ChainIndexBatch::default()
+            })
+            .unwrap();
+
+        let loaded = ChainIndex::load(&store).unwrap().unwrap();
+        assert_eq!(loaded.head(), index.head());
+        assert_eq!(loaded.events(), index.events());
+        assert_eq!(loaded.started_at(), NOW);
+        assert_eq!(loaded.recent_errors().count(), 1);
+        assert_eq!(loaded.reorgs(), 1);
+        assert_eq!(loaded.last_retraction(), 1);
+        assert_eq!(
+            loaded.retractions_since(0).collect::<Vec<_>>(),
+            index.retractions_since(0).collect::<Vec<_>>()
+        );
+        assert_eq!(loaded.reindex_job(), index.reindex_job());
+        assert_eq!(loaded.projections(), index.projections());
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+}
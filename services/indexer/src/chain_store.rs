@@ -0,0 +1,526 @@
+//! Incremental storage for the chain index
+//! Each indexed block is stored as its own row next to a small metadata row,
+//! so a poll writes the blocks it indexed rather than the whole index
+
+use crate::chain_index::{IngestedBlock, ReindexJob, RetractedEvent, RETRACTIONS_CAPACITY};
+use crate::config::IngestConfig;
+use anyhow::{bail, Context as _, Result};
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use smart_stubs::StateBackendKind;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{BufRead, BufReader, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex, PoisonError};
+use tokio::sync::Mutex;
+
+const BLOCKS_TABLE: TableDefinition<u64, &str> = TableDefinition::new("chain_blocks");
+const RETRACTIONS_TABLE: TableDefinition<u64, &str> = TableDefinition::new("chain_retractions");
+const META_TABLE: TableDefinition<&str, &str> = TableDefinition::new("chain_meta");
+const META_KEY: &str = "meta";
+
+/// Index state other than its blocks and retractions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainIndexMeta {
+    pub chain_head: u64,
+    pub started_at: i64,
+    pub last_synced_at: i64,
+    pub recent_errors: VecDeque<String>,
+    pub last_retraction: u64,
+    pub reorgs: u64,
+    pub reindex_job: Option<ReindexJob>,
+}
+
+/// Changes to write after the index was updated
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChainIndexBatch {
+    /// Stored blocks from this one on left the chain
+    pub truncate_from: Option<u64>,
+    /// Blocks indexed or decoded again, replacing stored copies
+    pub blocks: Vec<IngestedBlock>,
+    /// Retractions journaled by this change
+    pub retractions: Vec<RetractedEvent>,
+    pub meta: ChainIndexMeta,
+}
+
+/// Everything a [`ChainIndexStore`] holds
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoredChainIndex {
+    pub meta: ChainIndexMeta,
+    pub blocks: BTreeMap<u64, IngestedBlock>,
+    /// The newest `RETRACTIONS_CAPACITY`, oldest first
+    pub retractions: Vec<RetractedEvent>,
+}
+
+impl StoredChainIndex {
+    /// Drop retractions past the capacity and trust the newest stored one
+    /// over a metadata row written before it
+    fn settle(&mut self) {
+        let excess = self.retractions.len().saturating_sub(RETRACTIONS_CAPACITY);
+        self.retractions.drain(..excess);
+        if let Some(newest) = self.retractions.last() {
+            self.meta.last_retraction = self.meta.last_retraction.max(newest.sequence);
+        }
+    }
+}
+
+/// Storage for a [`ChainIndex`] that survives restarts
+pub trait ChainIndexStore: Send + Sync + fmt::Debug {
+    /// Load the stored index, `None` if nothing has been written yet
+    ///
+    /// # Errors
+    /// Returns an error if the stored index cannot be read or parsed
+    fn load(&self) -> Result<Option<StoredChainIndex>>;
+
+    /// Apply one batch of changes
+    ///
+    /// # Errors
+    /// Returns an error if the batch cannot be written
+    fn write(&self, batch: &ChainIndexBatch) -> Result<()>;
+}
+
+/// Storage for the chain index of `service_name` as `config` selects
+///
+/// # Errors
+/// Returns an error if the backend storage cannot be opened
+pub fn open_store(config: &IngestConfig, service_name: &str) -> Result<Arc<dyn ChainIndexStore>> {
+    let path = config.index_path(service_name);
+    Ok(match config.index_backend {
+        StateBackendKind::File => Arc::new(FileChainStore::new(path)),
+        StateBackendKind::Redb => Arc::new(RedbChainStore::open(path)?),
+    })
+}
+
+/// Batches waiting for a [`ChainIndexStore`], written in the order queued
+///
+/// Batches are queued while the index is locked, so their order matches the
+/// index's, and written after it is released.
+#[derive(Debug)]
+pub struct ChainIndexWriter {
+    store: Arc<dyn ChainIndexStore>,
+    pending: StdMutex<VecDeque<ChainIndexBatch>>,
+    /// Held while writing so batches from concurrent flushes stay in order
+    writing: Mutex<()>,
+}
+
+impl ChainIndexWriter {
+    #[must_use]
+    pub fn new(store: Arc<dyn ChainIndexStore>) -> Self {
+        Self {
+            store,
+            pending: StdMutex::default(),
+            writing: Mutex::new(()),
+        }
+    }
+
+    pub fn queue(&self, batch: ChainIndexBatch) {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_back(batch);
+    }
+
+    /// Write every queued batch
+    ///
+    /// # Errors
+    /// Returns an error if a batch cannot be written; it stays queued with
+    /// those after it and the next flush retries them.
+    pub async fn flush(&self) -> Result<()> {
+        let _writing = self.writing.lock().await;
+        let mut batches: VecDeque<ChainIndexBatch> =
+            std::mem::take(&mut *self.pending.lock().unwrap_or_else(PoisonError::into_inner));
+        if batches.is_empty() {
+            return Ok(());
+        }
+        let store = Arc::clone(&self.store);
+        let (mut unwritten, result) = tokio::task::spawn_blocking(move || {
+            while let Some(batch) = batches.front() {
+                if let Err(err) = store.write(batch) {
+                    return (batches, Err(err));
+                }
+                batches.pop_front();
+            }
+            (batches, Ok(()))
+        })
+        .await?;
+
+        if !unwritten.is_empty() {
+            let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+            unwritten.append(&mut pending);
+            *pending = unwritten;
+        }
+        result
+    }
+}
+
+/// Line of a [`FileChainStore`] journal
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JournalEntry {
+    Truncate(u64),
+    Block(IngestedBlock),
+    Retraction(RetractedEvent),
+}
+
+/// Append-only JSON lines journal of blocks, with the metadata in a file
+/// beside it that is replaced on every write
+///
+/// Blocks that were rolled back or decoded again leave superseded lines
+/// behind; loading compacts the journal down to the live rows.
+#[derive(Debug)]
+pub struct FileChainStore {
+    path: PathBuf,
+}
+
+impl FileChainStore {
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn meta_path(&self) -> PathBuf {
+        self.path.with_extension("meta.json")
+    }
+
+    fn append(&self, entries: &[JournalEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry)?;
+            lines.push(b'\n');
+        }
+        let mut journal = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("opening chain journal {}", self.path.display()))?;
+        journal
+            .write_all(&lines)
+            .with_context(|| format!("appending to chain journal {}", self.path.display()))
+    }
+
+    /// Rewrite the journal as one line per live block and retraction
+    fn compact(&self, stored: &StoredChainIndex) -> Result<()> {
+        let temp_path = self.path.with_extension("compact");
+        let mut lines = Vec::new();
+        for block in stored.blocks.values() {
+            serde_json::to_writer(&mut lines, &JournalEntry::Block(block.clone()))?;
+            lines.push(b'\n');
+        }
+        for retraction in &stored.retractions {
+            serde_json::to_writer(&mut lines, &JournalEntry::Retraction(retraction.clone()))?;
+            lines.push(b'\n');
+        }
+        std::fs::write(&temp_path, lines)
+            .with_context(|| format!("writing chain journal {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, &self.path)
+            .with_context(|| format!("replacing chain journal {}", self.path.display()))
+    }
+}
+
+fn ensure_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating index directory {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+impl ChainIndexStore for FileChainStore {
+    fn load(&self) -> Result<Option<StoredChainIndex>> {
+        let meta = match std::fs::read_to_string(self.meta_path()) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("parsing {}", self.meta_path().display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("reading {}", self.meta_path().display()))
+            }
+        };
+        let mut stored = StoredChainIndex {
+            meta,
+            ..StoredChainIndex::default()
+        };
+
+        let journal = match std::fs::File::open(&self.path) {
+            Ok(journal) => journal,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Some(stored)),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("reading chain journal {}", self.path.display()))
+            }
+        };
+        let mut lines = BufReader::new(journal).lines().peekable();
+        let mut line_count = 0;
+        while let Some(line) = lines.next() {
+            let line = line?;
+            line_count += 1;
+            let entry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                // A crash can cut the last append short; that write never completed
+                Err(_) if lines.peek().is_none() => break,
+                Err(err) => bail!(
+                    "parsing line {line_count} of chain journal {}: {err}",
+                    self.path.display()
+                ),
+            };
+            match entry {
+                JournalEntry::Truncate(fork_block) => {
+                    stored.blocks.split_off(&fork_block);
+                }
+                JournalEntry::Block(block) => {
+                    stored.blocks.insert(block.block.number, block);
+                }
+                JournalEntry::Retraction(retraction) => stored.retractions.push(retraction),
+            }
+        }
+        stored.settle();
+
+        if line_count > stored.blocks.len() + stored.retractions.len() {
+            self.compact(&stored)?;
+        }
+        Ok(Some(stored))
+    }
+
+    fn write(&self, batch: &ChainIndexBatch) -> Result<()> {
+        ensure_parent_dir(&self.path)?;
+        let entries: Vec<JournalEntry> = batch
+            .truncate_from
+            .map(JournalEntry::Truncate)
+            .into_iter()
+            .chain(batch.blocks.iter().cloned().map(JournalEntry::Block))
+            .chain(
+                batch
+                    .retractions
+                    .iter()
+                    .cloned()
+                    .map(JournalEntry::Retraction),
+            )
+            .collect();
+        self.append(&entries)?;
+
+        let meta_path = self.meta_path();
+        let temp_path = meta_path.with_extension("tmp");
+        std::fs::write(&temp_path, serde_json::to_vec(&batch.meta)?)
+            .with_context(|| format!("writing {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, &meta_path)
+            .with_context(|| format!("replacing {}", meta_path.display()))
+    }
+}
+
+/// Embedded redb database with a row per block and per retraction
+pub struct RedbChainStore {
+    path: PathBuf,
+    db: Database,
+}
+
+impl fmt::Debug for RedbChainStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedbChainStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RedbChainStore {
+    /// Open or create the database at `path`
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be created or opened
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        ensure_parent_dir(&path)?;
+        let db = Database::create(&path)
+            .with_context(|| format!("opening index database {}", path.display()))?;
+        Ok(Self { path, db })
+    }
+}
+
+impl ChainIndexStore for RedbChainStore {
+    fn load(&self) -> Result<Option<StoredChainIndex>> {
+        let txn = self.db.begin_read()?;
+        let meta = match txn.open_table(META_TABLE) {
+            Ok(meta) => meta,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let Some(meta) = meta.get(META_KEY)? else {
+            return Ok(None);
+        };
+        let mut stored = StoredChainIndex {
+            meta: serde_json::from_str(meta.value()).context("parsing stored index metadata")?,
+            ..StoredChainIndex::default()
+        };
+
+        for row in txn.open_table(BLOCKS_TABLE)?.iter()? {
+            let (number, block) = row?;
+            let block = serde_json::from_str(block.value())
+                .with_context(|| format!("parsing stored block {}", number.value()))?;
+            stored.blocks.insert(number.value(), block);
+        }
+        for row in txn.open_table(RETRACTIONS_TABLE)?.iter()? {
+            let (sequence, retraction) = row?;
+            stored.retractions.push(
+                serde_json::from_str(retraction.value())
+                    .with_context(|| format!("parsing stored retraction {}", sequence.value()))?,
+            );
+        }
+        stored.settle();
+        Ok(Some(stored))
+    }
+
+    fn write(&self, batch: &ChainIndexBatch) -> Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut blocks = txn.open_table(BLOCKS_TABLE)?;
+            if let Some(fork_block) = batch.truncate_from {
+                blocks.retain_in(fork_block.., |_, _| false)?;
+            }
+            for block in &batch.blocks {
+                blocks.insert(block.block.number, serde_json::to_string(block)?.as_str())?;
+            }
+
+            let mut retractions = txn.open_table(RETRACTIONS_TABLE)?;
+            for retraction in &batch.retractions {
+                retractions.insert(
+                    retraction.sequence,
+                    serde_json::to_string(retraction)?.as_str(),
+                )?;
+            }
+            let kept_from = batch
+                .meta
+                .last_retraction
+                .saturating_sub(RETRACTIONS_CAPACITY as u64 - 1);
+            retractions.retain_in(..kept_from, |_, _| false)?;
+
+            let mut meta = txn.open_table(META_TABLE)?;
+            meta.insert(META_KEY, serde_json::to_string(&batch.meta)?.as_str())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_index::IndexedBlock;
+
+    const NOW: i64 = 1_800_000_000;
+
+    fn temp_path(extension: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("chain-store-{}", uuid::Uuid::new_v4()))
+            .join(format!("index.{extension}"))
+    }
+
+    fn block(number: u64, undecoded_logs: u64) -> IngestedBlock {
+        IngestedBlock {
+            block: IndexedBlock {
+                number,
+                hash: format!("0x{number:064x}"),
+                parent_hash: String::new(),
+                timestamp: NOW + number as i64,
+                transaction_count: 0,
+                gas_used: 0,
+                gas_limit: 30_000_000,
+                size: 600,
+                undecoded_logs,
+            },
+            transactions: vec![],
+            events: vec![],
+        }
+    }
+
+    fn meta(chain_head: u64) -> ChainIndexMeta {
+        ChainIndexMeta {
+            chain_head,
+            started_at: NOW,
+            ..ChainIndexMeta::default()
+        }
+    }
+
+    fn assert_batches_apply(store: &dyn ChainIndexStore) {
+        assert_eq!(store.load().unwrap(), None);
+
+        let batches = [
+            ChainIndexBatch {
+                blocks: (1..=4).map(|number| block(number, 0)).collect(),
+                meta: meta(4),
+                ..ChainIndexBatch::default()
+            },
+            // Blocks 3 onwards left the chain and block 2 was decoded again
+            ChainIndexBatch {
+                truncate_from: Some(3),
+                blocks: vec![block(2, 1), block(3, 0)],
+                meta: meta(5),
+                ..ChainIndexBatch::default()
+            },
+        ];
+        let mut expected = StoredChainIndex::default();
+        for batch in batches {
+            store.write(&batch).unwrap();
+            if let Some(fork_block) = batch.truncate_from {
+                expected.blocks.split_off(&fork_block);
+            }
+            for block in batch.blocks {
+                expected.blocks.insert(block.block.number, block);
+            }
+            expected.meta = batch.meta;
+        }
+
+        let loaded = store.load().unwrap().unwrap();
+        assert_eq!(loaded, expected);
+        assert_eq!(loaded.blocks.keys().copied().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(loaded.blocks[&2].block.undecoded_logs, 1);
+        assert_eq!(loaded.meta.chain_head, 5);
+        // Loading again reads the same rows, compacted or not
+        assert_eq!(store.load().unwrap().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_file_store_applies_batches_in_order() {
+        let path = temp_path("jsonl");
+        let store = FileChainStore::new(&path);
+        assert_batches_apply(&store);
+
+        // The superseded lines were compacted away on load
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, 3);
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_redb_store_applies_batches_in_order() {
+        let path = temp_path("redb");
+        assert_batches_apply(&RedbChainStore::open(&path).unwrap());
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_file_store_ignores_a_cut_off_last_line() {
+        let path = temp_path("jsonl");
+        let store = FileChainStore::new(&path);
+        store
+            .write(&ChainIndexBatch {
+                blocks: vec![block(1, 0)],
+                meta: meta(1),
+                ..ChainIndexBatch::default()
+            })
+            .unwrap();
+        let mut journal = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        journal.write_all(br#"{"block":{"block":"#).unwrap();
+
+        let loaded = store.load().unwrap().unwrap();
+        assert_eq!(loaded.blocks.len(), 1);
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+}
@@ -1,121 +1,217 @@
+//! Indexer service stub configuration
+//! The shared stub settings, extended with chain ingestion settings
+
+use anyhow::{anyhow, bail, Result};
 use serde::{Deserialize, Serialize};
+use smart_stubs::StateBackendKind;
+use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct StubConfiguration {
-    pub base: BaseConfig,
-    pub dual_mode: DualModeConfig,
-    pub latency: LatencyConfig,
-    pub errors: ErrorConfig,
-    pub data: DataConfig,
-}
+pub use smart_stubs::{ConfigSource, StubConfiguration};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct BaseConfig {
-    pub name: String,
-    pub version: String,
-    pub port: u16,
-    pub enabled: bool,
+/// Configuration source for the indexer service stub
+///
+/// Starts from the service defaults, then reads the file named by
+/// `STUB_CONFIG_PATH` (YAML, TOML or JSON) and `STUB__` env overrides.
+pub fn config_source() -> ConfigSource {
+    ConfigSource::from_env(defaults())
+        .with_validator(|config| IndexerConfig::parse(config)?.validate())
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct DualModeConfig {
-    pub enable_crypto: bool,
-    pub crypto_response_mode: CryptoResponseMode,
+fn defaults() -> StubConfiguration {
+    StubConfiguration::for_service("indexer-service-stub", 8082)
+        .with_extension(&IndexerConfig::default())
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum CryptoResponseMode {
-    Disabled,
-    Error,
-    Mock,
+/// Sections the indexer adds to the shared stub configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IndexerConfig {
+    /// Indexing of a JSON-RPC L3 node
+    pub ingest: IngestConfig,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct LatencyConfig {
-    pub min_response_time_ms: u64,
-    pub max_response_time_ms: u64,
-    pub distribution: LatencyDistribution,
-    pub network_condition: NetworkCondition,
-}
+impl IndexerConfig {
+    /// Indexer sections of `config`
+    ///
+    /// # Errors
+    /// Returns an error if the `ingest` section does not parse
+    pub fn parse(config: &StubConfiguration) -> Result<Self> {
+        config.extension()
+    }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum LatencyDistribution {
-    Uniform,
-    Normal,
-    Exponential,
+    /// Check the ingestion settings when ingestion is enabled
+    ///
+    /// # Errors
+    /// Returns an error if enabled ingestion is unusable
+    pub fn validate(&self) -> Result<()> {
+        if self.ingest.enabled {
+            self.ingest.validate()?;
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum NetworkCondition {
-    Good,
-    Poor,
-    Variable,
-}
+/// Directory used when `IngestConfig::index_path` is not set
+const DEFAULT_INDEX_DIR: &str = "stub-state";
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct ErrorConfig {
-    pub error_rate: f64,
-    pub specific_errors: Vec<SpecificError>,
-    pub timeout_rate: f64,
+/// Chain ingestion settings
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IngestConfig {
+    /// Serve indexed chain data instead of the fixture world's events
+    pub enabled: bool,
+    /// JSON-RPC endpoint of the L3 node
+    pub rpc_url: String,
+    /// Duration such as `2s` between polls once caught up with the node
+    pub poll_interval: String,
+    /// First block indexed into an empty index
+    pub start_block: u64,
+    /// Contracts whose logs are indexed, the NTC token and `BunkerverseNFT`
+    pub contract_addresses: Vec<String>,
+    /// Most blocks indexed per poll, so a long catch-up is saved as it goes
+    pub max_blocks_per_poll: u64,
+    /// Blocks that must follow an event's block before the event is final
+    pub confirmations: u64,
+    pub index_backend: StateBackendKind,
+    /// Defaults to `stub-state/<service name>-chain-index.<jsonl|redb>`
+    pub index_path: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct SpecificError {
-    pub endpoint: String,
-    pub status_code: u16,
-    pub rate: f64,
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rpc_url: "http://127.0.0.1:8545".to_string(),
+            poll_interval: "2s".to_string(),
+            start_block: 0,
+            contract_addresses: vec![],
+            max_blocks_per_poll: 100,
+            confirmations: 6,
+            index_backend: StateBackendKind::File,
+            index_path: None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct DataConfig {
-    pub dataset: Dataset,
-    pub persist_state: bool,
-    pub state_reset_interval: String,
-}
+impl IngestConfig {
+    /// Check the endpoint, contracts, poll interval and batch size
+    ///
+    /// # Errors
+    /// Returns an error if `rpc_url` is not an HTTP URL, `contract_addresses`
+    /// is empty or holds something other than an address, `poll_interval`
+    /// does not parse or is zero, or `max_blocks_per_poll` is zero
+    pub fn validate(&self) -> Result<()> {
+        let uri: http02::Uri = self
+            .rpc_url
+            .parse()
+            .map_err(|err| anyhow!("invalid rpc_url '{}': {err}", self.rpc_url))?;
+        if uri.scheme_str() != Some("http") {
+            bail!("rpc_url '{}' must be an http:// URL", self.rpc_url);
+        }
+        // Any contract emitting `Transfer` would otherwise count as NTC
+        if self.contract_addresses.is_empty() {
+            bail!("contract_addresses must list the NTC token and NFT contracts");
+        }
+        if let Some(address) = self.contract_addresses.iter().find(|address| {
+            let digits = address
+                .strip_prefix("0x")
+                .or_else(|| address.strip_prefix("0X"));
+            !digits.is_some_and(|digits| {
+                digits.len() == 40 && digits.bytes().all(|digit| digit.is_ascii_hexdigit())
+            })
+        }) {
+            bail!("invalid contract address '{address}'");
+        }
+        if self.poll_interval().is_zero() {
+            bail!("invalid poll_interval '{}'", self.poll_interval);
+        }
+        if self.max_blocks_per_poll == 0 {
+            bail!("max_blocks_per_poll must be greater than zero");
+        }
+        Ok(())
+    }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum Dataset {
-    Minimal,
-    Development,
-    StressTest,
-}
+    /// Parsed `poll_interval`, zero if invalid
+    #[must_use]
+    pub fn poll_interval(&self) -> Duration {
+        common_rust::time::parse_duration(&self.poll_interval).unwrap_or_default()
+    }
 
-impl Default for StubConfiguration {
-    fn default() -> Self {
-        Self {
-            base: BaseConfig {
-                name: "indexer-service-stub".to_string(),
-                version: "1.0.0".to_string(),
-                port: 8082,
-                enabled: true,
+    #[must_use]
+    pub fn index_path(&self, service_name: &str) -> PathBuf {
+        let extension = match self.index_backend {
+            StateBackendKind::File => "jsonl",
+            StateBackendKind::Redb => "redb",
+        };
+        self.index_path.as_ref().map_or_else(
+            || {
+                PathBuf::from(DEFAULT_INDEX_DIR)
+                    .join(format!("{service_name}-chain-index.{extension}"))
             },
-            dual_mode: DualModeConfig {
-                enable_crypto: std::env::var("ENABLE_CRYPTO")
-                    .unwrap_or_else(|_| "false".to_string())
-                    .parse()
-                    .unwrap_or(false),
-                crypto_response_mode: CryptoResponseMode::Disabled,
+            PathBuf::from,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ingestion of two valid contracts
+    fn ingest_config() -> IngestConfig {
+        IngestConfig {
+            enabled: true,
+            contract_addresses: vec![
+                "0x5fbdb2315678afecb367f032d93f642f64180aa3".to_string(),
+                "0xe7f1725e7734ce288f8367e1bb143e90bb3f0512".to_string(),
+            ],
+            ..IngestConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_config_rejects_unusable_settings() {
+        assert!(ingest_config().validate().is_ok());
+        for config in [
+            IngestConfig::default(),
+            IngestConfig {
+                contract_addresses: vec!["ntc".to_string()],
+                ..ingest_config()
             },
-            latency: LatencyConfig {
-                min_response_time_ms: 10,
-                max_response_time_ms: 500,
-                distribution: LatencyDistribution::Normal,
-                network_condition: NetworkCondition::Good,
+            IngestConfig {
+                rpc_url: "ws://127.0.0.1:8545".to_string(),
+                ..ingest_config()
             },
-            errors: ErrorConfig {
-                error_rate: 0.05,
-                specific_errors: vec![],
-                timeout_rate: 0.01,
+            IngestConfig {
+                poll_interval: "soon".to_string(),
+                ..ingest_config()
             },
-            data: DataConfig {
-                dataset: Dataset::Development,
-                persist_state: false,
-                state_reset_interval: "24h".to_string(),
+            IngestConfig {
+                max_blocks_per_poll: 0,
+                ..ingest_config()
             },
+        ] {
+            assert!(config.validate().is_err());
         }
+        assert_eq!(
+            IngestConfig::default().index_path("indexer-service-stub"),
+            PathBuf::from("stub-state/indexer-service-stub-chain-index.jsonl")
+        );
+    }
+
+    #[test]
+    fn test_ingest_section_is_read_from_the_stub_configuration() {
+        let mut value = serde_json::to_value(defaults()).unwrap();
+        value["ingest"] = serde_json::to_value(ingest_config()).unwrap();
+        let config: StubConfiguration = serde_json::from_value(value).unwrap();
+
+        let indexer = IndexerConfig::parse(&config).unwrap();
+        assert_eq!(indexer.ingest, ingest_config());
+        assert!(indexer.validate().is_ok());
+        assert!(IndexerConfig::parse(&defaults())
+            .unwrap()
+            .validate()
+            .is_ok());
     }
 }
@@ -0,0 +1,446 @@
+//! Live view of the chain index for event subscribers
+//! Subscribers resume from the position of the last event they handled, so
+//! delivery is at least once across reconnects, and events retracted by a
+//! reorganization are delivered again as retractions
+
+use crate::chain_index::{ChainIndex, RetractedEvent, SharedChainIndex};
+use smart_stubs::world::ChainEvent;
+use std::collections::VecDeque;
+use tokio::sync::watch;
+
+/// Events a subscription reads from the index at a time
+///
+/// The next batch is only read once the subscriber has taken the previous
+/// one, so a slow consumer holds at most this many events in memory and
+/// falls behind on the index rather than losing events.
+pub const EVENT_SUBSCRIPTION_BUFFER: usize = 256;
+
+/// Position of an event in the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventCursor {
+    pub block_number: u64,
+    pub log_index: u64,
+}
+
+impl EventCursor {
+    #[must_use]
+    pub fn of(event: &ChainEvent) -> Self {
+        Self {
+            block_number: event.block_number,
+            log_index: event.log_index,
+        }
+    }
+
+    /// Resume point that replays `block_number` and every block after it
+    #[must_use]
+    pub fn before_block(block_number: u64) -> Option<Self> {
+        block_number.checked_sub(1).map(|block_number| Self {
+            block_number,
+            log_index: u64::MAX,
+        })
+    }
+}
+
+/// Which events a subscriber wants; empty lists match everything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFeedFilter {
+    pub event_types: Vec<String>,
+    /// Any participant of the event, such as the buyer or seller of a sale
+    pub player_ids: Vec<String>,
+    /// Compared ignoring case
+    pub contract_addresses: Vec<String>,
+}
+
+impl EventFeedFilter {
+    #[must_use]
+    pub fn matches(&self, event: &ChainEvent) -> bool {
+        (self.event_types.is_empty()
+            || self
+                .event_types
+                .iter()
+                .any(|event_type| event_type == event.payload.event_type()))
+            && (self.contract_addresses.is_empty()
+                || self
+                    .contract_addresses
+                    .iter()
+                    .any(|address| address.eq_ignore_ascii_case(&event.contract_address)))
+            && (self.player_ids.is_empty()
+                || event
+                    .payload
+                    .participants()
+                    .into_iter()
+                    .any(|player_id| self.player_ids.iter().any(|wanted| wanted == player_id)))
+    }
+}
+
+/// What a subscriber is told about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeliveredEvent {
+    Indexed(ChainEvent),
+    /// An event delivered earlier left the canonical chain
+    Retracted(RetractedEvent),
+}
+
+/// One event or retraction and where to resume once it is handled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventDelivery {
+    pub event: DeliveredEvent,
+    /// Events at or before it are handled; `None` replays from the first
+    ///
+    /// A retraction moves it back before the retracted block so the events
+    /// that replace it are delivered.
+    pub resume_after: Option<EventCursor>,
+    /// Sequence number of the last retraction accounted for
+    pub last_retraction: u64,
+}
+
+/// Live view of the indexed events for one subscriber
+///
+/// Replays the indexed events after the resume point, then waits for new
+/// ones. Events a reindex adds behind the resume point are not replayed;
+/// subscribe again from the reindexed block to pick them up.
+#[derive(Debug)]
+pub struct EventSubscription {
+    index: SharedChainIndex,
+    changes: watch::Receiver<()>,
+    filter: EventFeedFilter,
+    /// Only deliver blocks this many confirmations deep
+    confirmations: Option<u64>,
+    resume_after: Option<EventCursor>,
+    last_retraction: u64,
+    pending: VecDeque<EventDelivery>,
+}
+
+impl EventSubscription {
+    /// Subscribe to events after `resume_after` and retractions after `last_retraction`
+    ///
+    /// `changes` is notified whenever the index changes. A subscriber that
+    /// does not know its last retraction passes 0 and may be told about
+    /// retractions it has already handled.
+    #[must_use]
+    pub fn open(
+        index: SharedChainIndex,
+        changes: watch::Receiver<()>,
+        filter: EventFeedFilter,
+        resume_after: Option<EventCursor>,
+        last_retraction: u64,
+    ) -> Self {
+        Self {
+            index,
+            changes,
+            filter,
+            confirmations: None,
+            resume_after,
+            last_retraction,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Hold back events until their block is `confirmations` deep
+    #[must_use]
+    pub fn finalized_only(mut self, confirmations: u64) -> Self {
+        self.confirmations = Some(confirmations);
+        self
+    }
+
+    /// Next matching event or retraction, waiting for one if none is pending
+    ///
+    /// Returns `None` once the ingester is dropped.
+    pub async fn next(&mut self) -> Option<EventDelivery> {
+        loop {
+            if let Some(delivery) = self.pending.pop_front() {
+                return Some(delivery);
+            }
+            // Mark the current value seen before reading so no change slips between
+            self.changes.borrow_and_update();
+            let index = self.index.clone();
+            self.fill(&*index.read().await);
+            if self.pending.is_empty() {
+                self.changes.changed().await.ok()?;
+            }
+        }
+    }
+
+    /// Queue the retractions and up to `EVENT_SUBSCRIPTION_BUFFER` events the
+    /// subscriber has not seen yet
+    fn fill(&mut self, index: &ChainIndex) {
+        // Retractions first, so the resume point is moved back before reading
+        for retraction in index.retractions_since(self.last_retraction) {
+            self.last_retraction = retraction.sequence;
+            let position = EventCursor::of(&retraction.event);
+            if self.resume_after.is_some_and(|cursor| position <= cursor) {
+                self.resume_after =
+                    EventCursor::before_block(retraction.event.block_number).min(self.resume_after);
+                if self.filter.matches(&retraction.event) {
+                    self.pending.push_back(EventDelivery {
+                        event: DeliveredEvent::Retracted(retraction.clone()),
+                        resume_after: self.resume_after,
+                        last_retraction: self.last_retraction,
+                    });
+                }
+            }
+        }
+
+        let last_block = match self.confirmations {
+            Some(confirmations) => match index.finalized_block(confirmations) {
+                Some(finalized) => finalized,
+                None => return,
+            },
+            None => u64::MAX,
+        };
+        let events = index.events();
+        let first = match self.resume_after {
+            Some(cursor) => events.partition_point(|event| EventCursor::of(event) <= cursor),
+            None => 0,
+        };
+        let mut queued = 0;
+        for event in events[first..]
+            .iter()
+            .take_while(|event| event.block_number <= last_block)
+        {
+            self.resume_after = Some(EventCursor::of(event));
+            if self.filter.matches(event) {
+                self.pending.push_back(EventDelivery {
+                    event: DeliveredEvent::Indexed(event.clone()),
+                    resume_after: self.resume_after,
+                    last_retraction: self.last_retraction,
+                });
+                queued += 1;
+                if queued == EVENT_SUBSCRIPTION_BUFFER {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_index::{IndexedBlock, IngestedBlock};
+    use smart_stubs::world::EventPayload;
+
+    const NOW: i64 = 1_800_000_000;
+
+    fn transfer(number: u64, log_index: u64, contract_address: &str, to: &str) -> ChainEvent {
+        ChainEvent {
+            event_id: uuid::Uuid::new_v4().to_string(),
+            block_number: number,
+            log_index,
+            contract_address: contract_address.to_string(),
+            transaction_hash: format!("0xtx{number}"),
+            block_timestamp: NOW + number as i64,
+            payload: EventPayload::NtcTransfer {
+                from_player_id: String::new(),
+                to_player_id: to.to_string(),
+                amount_wei: 1,
+                transfer_type: "mint".to_string(),
+            },
+        }
+    }
+
+    /// Append a block with `events` on top of the index head
+    fn append(index: &mut ChainIndex, branch: &str, events: Vec<ChainEvent>) {
+        let number = index.next_block(1);
+        let parent_hash = index
+            .head()
+            .map_or_else(|| "0xgenesis".to_string(), |head| head.hash.clone());
+        index
+            .append(IngestedBlock {
+                block: IndexedBlock {
+                    number,
+                    hash: format!("0x{branch}{number}"),
+                    parent_hash,
+                    timestamp: NOW + number as i64,
+                    transaction_count: 1,
+                    gas_used: 21_000,
+                    gas_limit: 30_000_000,
+                    size: 600,
+                    undecoded_logs: 0,
+                },
+                transactions: vec![],
+                events,
+            })
+            .unwrap();
+    }
+
+    fn subscribe(
+        index: ChainIndex,
+        filter: EventFeedFilter,
+        resume_after: Option<EventCursor>,
+    ) -> (EventSubscription, watch::Sender<()>) {
+        let (changes, receiver) = watch::channel(());
+        let subscription =
+            EventSubscription::open(index.into_shared(), receiver, filter, resume_after, 0);
+        (subscription, changes)
+    }
+
+    fn positions(deliveries: &VecDeque<EventDelivery>) -> Vec<(u64, u64)> {
+        deliveries
+            .iter()
+            .map(|delivery| match &delivery.event {
+                DeliveredEvent::Indexed(event) => (event.block_number, event.log_index),
+                DeliveredEvent::Retracted(retraction) => {
+                    (retraction.event.block_number, retraction.event.log_index)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_matches_event_type_player_and_contract() {
+        let event = transfer(1, 0, "0xToken", "alice");
+
+        assert!(EventFeedFilter::default().matches(&event));
+        assert!(EventFeedFilter {
+            event_types: vec!["NtcTransfer".to_string()],
+            player_ids: vec!["alice".to_string()],
+            contract_addresses: vec!["0xtoken".to_string()],
+        }
+        .matches(&event));
+        assert!(!EventFeedFilter {
+            event_types: vec!["NftMinted".to_string()],
+            ..EventFeedFilter::default()
+        }
+        .matches(&event));
+        assert!(!EventFeedFilter {
+            player_ids: vec!["bob".to_string()],
+            ..EventFeedFilter::default()
+        }
+        .matches(&event));
+    }
+
+    #[test]
+    fn test_subscription_resumes_after_the_cursor_one_buffer_at_a_time() {
+        let mut index = ChainIndex::new(NOW);
+        append(
+            &mut index,
+            "a",
+            vec![
+                transfer(1, 0, "0xtoken", "alice"),
+                transfer(1, 1, "0xnft", "alice"),
+            ],
+        );
+        append(
+            &mut index,
+            "a",
+            (0..EVENT_SUBSCRIPTION_BUFFER as u64 + 10)
+                .map(|log_index| transfer(2, log_index, "0xtoken", "bob"))
+                .collect(),
+        );
+        let filter = EventFeedFilter {
+            contract_addresses: vec!["0xtoken".to_string()],
+            ..EventFeedFilter::default()
+        };
+        let (mut subscription, _changes) = subscribe(
+            index,
+            filter,
+            Some(EventCursor {
+                block_number: 1,
+                log_index: 0,
+            }),
+        );
+
+        let index = subscription.index.clone();
+        subscription.fill(&index.try_read().unwrap());
+        assert_eq!(subscription.pending.len(), EVENT_SUBSCRIPTION_BUFFER);
+        assert_eq!(positions(&subscription.pending)[0], (2, 0));
+        let last = subscription.pending.back().unwrap().resume_after.unwrap();
+        assert_eq!(last.log_index, EVENT_SUBSCRIPTION_BUFFER as u64 - 1);
+
+        subscription.pending.clear();
+        subscription.fill(&index.try_read().unwrap());
+        assert_eq!(subscription.pending.len(), 10);
+    }
+
+    #[test]
+    fn test_reorganized_events_are_retracted_and_replaced() {
+        let mut index = ChainIndex::new(NOW);
+        for number in 1..=3 {
+            append(
+                &mut index,
+                "a",
+                vec![transfer(number, 0, "0xtoken", "alice")],
+            );
+        }
+        let (mut subscription, _changes) = subscribe(index, EventFeedFilter::default(), None);
+        let index = subscription.index.clone();
+        subscription.fill(&index.try_read().unwrap());
+        assert_eq!(positions(&subscription.pending), [(1, 0), (2, 0), (3, 0)]);
+        // The subscriber has taken all three when the node switches branches
+        subscription.pending.clear();
+
+        {
+            let mut index = index.try_write().unwrap();
+            index.roll_back_to(2, NOW);
+            append(&mut index, "b", vec![transfer(2, 0, "0xtoken", "bob")]);
+        }
+        subscription.fill(&index.try_read().unwrap());
+
+        let deliveries: Vec<_> = subscription.pending.drain(..).collect();
+        let retracted: Vec<_> = deliveries
+            .iter()
+            .filter_map(|delivery| match &delivery.event {
+                DeliveredEvent::Retracted(retraction) => Some(retraction.event.block_number),
+                DeliveredEvent::Indexed(_) => None,
+            })
+            .collect();
+        assert_eq!(retracted, [3, 2]);
+        assert_eq!(deliveries[1].resume_after, EventCursor::before_block(2));
+        assert_eq!(deliveries[1].last_retraction, 2);
+        let DeliveredEvent::Indexed(replacement) = &deliveries[2].event else {
+            panic!("the new branch follows the retractions");
+        };
+        assert_eq!(replacement.payload.player_id(), "bob");
+        assert_eq!(deliveries.len(), 3);
+    }
+
+    #[test]
+    fn test_finalized_only_holds_back_unconfirmed_blocks() {
+        let mut index = ChainIndex::new(NOW);
+        for number in 1..=4 {
+            append(
+                &mut index,
+                "a",
+                vec![transfer(number, 0, "0xtoken", "alice")],
+            );
+        }
+        index.record_sync(4, NOW);
+        let (subscription, _changes) = subscribe(index, EventFeedFilter::default(), None);
+        let mut subscription = subscription.finalized_only(2);
+
+        let index = subscription.index.clone();
+        subscription.fill(&index.try_read().unwrap());
+        assert_eq!(positions(&subscription.pending), [(1, 0), (2, 0)]);
+    }
+
+    #[tokio::test]
+    async fn test_subscription_waits_for_new_blocks() {
+        let mut index = ChainIndex::new(NOW);
+        append(&mut index, "a", vec![transfer(1, 0, "0xtoken", "alice")]);
+        let (mut subscription, changes) = subscribe(index, EventFeedFilter::default(), None);
+        let index = subscription.index.clone();
+        assert!(subscription.next().await.is_some());
+
+        let waiting = tokio::spawn(async move { subscription.next().await });
+        tokio::task::yield_now().await;
+        append(
+            &mut *index.write().await,
+            "a",
+            vec![transfer(2, 0, "0xtoken", "bob")],
+        );
+        changes.send_replace(());
+        let delivery = tokio::time::timeout(std::time::Duration::from_secs(1), waiting)
+            .await
+            .expect("subscriber is woken by the new block")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            delivery.resume_after,
+            Some(EventCursor {
+                block_number: 2,
+                log_index: 0,
+            })
+        );
+    }
+}
@@ -0,0 +1,487 @@
+//! Conversions from the shared fixture world into indexer protos
+
+use crate::grpc_server::bunkerverse::core::v1::{
+    canonical_event_proto::Payload, ActiveBunkerguardDataProto, AgentChainStateProto,
+    BalancesProto, CanonicalEventProto, CoreStatsProto, CryptoAddressesProto,
+    ItemEquippedPayloadProto, MissionCompletedPayloadProto, NftDetailsProto, NftIdentifierProto,
+    NftMarketListedPayloadProto, NftMarketSoldPayloadProto, NftMintedPayloadProto,
+    NftMutableStateProto, NftTransferredPayloadProto, NtcStakingDetailsProto,
+    NtcStakingInitiatedPayloadProto, NtcTransferPayloadProto, RobotStatsUpdatedPayloadProto,
+    UserRegisteredPayloadProto, XpAwardedPayloadProto,
+};
+use crate::projection::PlayerProjection;
+use smart_stubs::world::{ChainEvent, CoreStats, EventPayload, Nft, Player, World, SCHEMA_VERSION};
+use std::collections::{BTreeMap, HashMap};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+pub fn core_stats(stats: &CoreStats) -> CoreStatsProto {
+    CoreStatsProto {
+        damage: stats.damage,
+        accuracy: stats.accuracy,
+        critical_chance: stats.critical_chance,
+        armor_piercing: stats.armor_piercing,
+        speed: stats.speed,
+        agility: stats.agility,
+        stealth: stats.stealth,
+        evasion: stats.evasion,
+        health: stats.health,
+        shield: stats.shield,
+        detection: stats.detection,
+        range: stats.range,
+        combat_average: stats.combat_average(),
+        mobility_average: stats.mobility_average(),
+        survivability_average: stats.survivability_average(),
+        sensors_average: stats.sensors_average(),
+    }
+}
+
+pub fn nft_details(nft: &Nft) -> NftDetailsProto {
+    NftDetailsProto {
+        identifier: Some(NftIdentifierProto {
+            nft_id: nft.nft_id.clone(),
+            token_id: nft.token_id,
+            contract_address: nft.contract_address.clone(),
+        }),
+        item_type: nft.item_type as i32,
+        item_rarity: nft.rarity as i32,
+        base_stat_boosts: Some(core_stats(&nft.stat_boosts)),
+        class_affinities: nft
+            .class_affinities
+            .iter()
+            .map(|class| *class as i32)
+            .collect(),
+        trait_affiliation: nft.affiliation as i32,
+        construct_origin: nft.construct_origin.clone(),
+        metadata_pointer_uri: nft.metadata_cid.clone(),
+        schema_version: SCHEMA_VERSION,
+        created_timestamp: nft.minted_at,
+    }
+}
+
+pub fn nft_state(world: &World, nft: &Nft) -> NftMutableStateProto {
+    let listing = world.listing_for_nft(&nft.nft_id);
+    NftMutableStateProto {
+        current_owner_id: nft.owner_id.clone(),
+        current_condition: nft.condition as i32,
+        is_soulbound: nft.is_soulbound,
+        market_status: world.market_status(&nft.nft_id) as i32,
+        market_price_ntc: listing.map_or(0, |listing| listing.price_ntc_wei),
+        last_updated_timestamp: listing.map_or(nft.minted_at, |listing| listing.created_at),
+    }
+}
+
+/// Render a world or ingested event with its full payload
+pub fn canonical_event(world: &World, event: &ChainEvent) -> CanonicalEventProto {
+    let timestamp = event.block_timestamp;
+    let tx_hash = event.transaction_hash.clone();
+    let payload = match &event.payload {
+        EventPayload::UserRegistered { player_id } => world.player(player_id).map(|player| {
+            Payload::UserRegistered(UserRegisteredPayloadProto {
+                player_id: player.player_id.clone(),
+                l3_wallet_address: player.l3_wallet_address.clone(),
+                bunker_tag: player.bunker_tag.clone(),
+                registration_timestamp: timestamp,
+                registration_tx_hash: tx_hash,
+                schema_version: SCHEMA_VERSION,
+            })
+        }),
+        EventPayload::NftMinted {
+            nft_id,
+            player_id,
+            token,
+        } => {
+            let minted = match (world.nft(nft_id), token) {
+                (Some(nft), _) => Some((nft_details(nft), nft.mint_reason.clone())),
+                // Ingested mints of tokens the fixture world does not know
+                (None, Some(token)) => Some((
+                    NftDetailsProto {
+                        identifier: Some(NftIdentifierProto {
+                            nft_id: nft_id.clone(),
+                            token_id: token.token_id,
+                            contract_address: event.contract_address.clone(),
+                        }),
+                        metadata_pointer_uri: token.token_uri.clone(),
+                        schema_version: SCHEMA_VERSION,
+                        created_timestamp: timestamp,
+                        ..NftDetailsProto::default()
+                    },
+                    "mint".to_string(),
+                )),
+                (None, None) => None,
+            };
+            minted.map(|(details, mint_reason)| {
+                Payload::NftMinted(NftMintedPayloadProto {
+                    nft_details: Some(details),
+                    minted_to_player_id: player_id.clone(),
+                    mint_transaction_hash: tx_hash,
+                    mint_reason,
+                    mint_timestamp: timestamp,
+                    schema_version: SCHEMA_VERSION,
+                })
+            })
+        }
+        EventPayload::ItemEquipped {
+            player_id,
+            robot_id,
+            nft_id,
+            slot,
+        } => Some(Payload::ItemEquipped(ItemEquippedPayloadProto {
+            player_id: player_id.clone(),
+            robot_id: robot_id.clone(),
+            item_nft_id: nft_id.clone(),
+            equipment_slot: slot.clone(),
+            previous_item_nft_id: String::new(),
+            new_total_stats: world
+                .robot_for(player_id)
+                .map(|robot| core_stats(&robot.final_stats)),
+            equipped_timestamp: timestamp,
+            schema_version: SCHEMA_VERSION,
+        })),
+        EventPayload::NtcStakingInitiated {
+            player_id,
+            staked_amount_wei,
+            duration_days,
+        } => {
+            // Ingested stakes carry their own terms but no APY
+            let staking = world
+                .player(player_id)
+                .and_then(|player| player.staking.as_ref());
+            let duration_days = staking.map_or(*duration_days, |staking| staking.duration_days);
+            Some(Payload::NtcStakingInitiated(
+                NtcStakingInitiatedPayloadProto {
+                    player_id: player_id.clone(),
+                    staked_amount_wei: staking
+                        .map_or(*staked_amount_wei, |staking| staking.staked_ntc_wei),
+                    staking_duration_days: duration_days,
+                    expected_apy_basis_points: staking
+                        .map_or(0, |staking| staking.apy_basis_points),
+                    stake_start_timestamp: timestamp,
+                    stake_end_timestamp: timestamp + i64::from(duration_days) * SECONDS_PER_DAY,
+                    staking_tx_hash: tx_hash,
+                    schema_version: SCHEMA_VERSION,
+                },
+            ))
+        }
+        EventPayload::NftMarketListed {
+            player_id,
+            nft_id,
+            price_ntc_wei,
+            listing_type,
+            expires_at,
+            ..
+        } => Some(Payload::NftMarketListed(NftMarketListedPayloadProto {
+            seller_player_id: player_id.clone(),
+            nft_id: nft_id.clone(),
+            listing_price_wei: *price_ntc_wei,
+            listing_type: *listing_type as i32,
+            listing_expiry_timestamp: *expires_at,
+            listing_timestamp: timestamp,
+            listing_tx_hash: tx_hash,
+            schema_version: SCHEMA_VERSION,
+        })),
+        EventPayload::NftMarketSold {
+            nft_id,
+            player_id,
+            seller_id,
+            price_ntc_wei,
+            marketplace_fee_wei,
+            royalty_wei,
+        } => Some(Payload::NftMarketSold(NftMarketSoldPayloadProto {
+            seller_player_id: seller_id.clone(),
+            buyer_player_id: player_id.clone(),
+            nft_id: nft_id.clone(),
+            sale_price_wei: *price_ntc_wei,
+            marketplace_fee_wei: *marketplace_fee_wei,
+            seller_proceeds_wei: price_ntc_wei - marketplace_fee_wei - royalty_wei,
+            royalty_wei: *royalty_wei,
+            sale_timestamp: timestamp,
+            sale_tx_hash: tx_hash,
+            schema_version: SCHEMA_VERSION,
+        })),
+        EventPayload::MissionCompleted { mission_id, .. } => {
+            world.mission(mission_id).map(|mission| {
+                Payload::MissionCompleted(MissionCompletedPayloadProto {
+                    player_id: mission.player_id.clone(),
+                    mission_id: mission.mission_id.clone(),
+                    mission_type: mission.mission_type.clone(),
+                    xp_reward: mission.xp_reward,
+                    ntc_reward_wei: mission.ntc_reward_wei,
+                    nft_rewards: vec![],
+                    completion_timestamp: mission.completed_at,
+                    completion_tx_hash: tx_hash,
+                    schema_version: SCHEMA_VERSION,
+                })
+            })
+        }
+        EventPayload::XpAwarded {
+            player_id,
+            robot_id,
+            xp_amount,
+            xp_source,
+        } => Some(Payload::XpAwarded(XpAwardedPayloadProto {
+            player_id: player_id.clone(),
+            robot_id: robot_id.clone(),
+            xp_amount: *xp_amount,
+            xp_source: xp_source.clone(),
+            // Running totals belong to the projections, not single events
+            player_total_xp: 0,
+            robot_total_xp: 0,
+            award_timestamp: timestamp,
+            award_tx_hash: tx_hash,
+            schema_version: SCHEMA_VERSION,
+        })),
+        EventPayload::RobotStatsUpdated {
+            player_id,
+            robot_id,
+            old_level,
+            new_level,
+            xp_gained,
+            total_xp,
+            update_reason,
+        } => {
+            let robot = world
+                .robot_for(player_id)
+                .filter(|robot| &robot.robot_id == robot_id);
+            Some(Payload::RobotStatsUpdated(RobotStatsUpdatedPayloadProto {
+                player_id: player_id.clone(),
+                robot_id: robot_id.clone(),
+                old_level: *old_level,
+                new_level: *new_level,
+                xp_gained: *xp_gained,
+                total_xp: *total_xp,
+                updated_base_stats: robot.map(|robot| core_stats(&robot.base_stats)),
+                updated_final_stats: robot.map(|robot| core_stats(&robot.final_stats)),
+                update_reason: update_reason.clone(),
+                update_timestamp: timestamp,
+                schema_version: SCHEMA_VERSION,
+            }))
+        }
+        EventPayload::NtcTransfer {
+            from_player_id,
+            to_player_id,
+            amount_wei,
+            transfer_type,
+        } => Some(Payload::NtcTransfer(NtcTransferPayloadProto {
+            from_player_id: from_player_id.clone(),
+            to_player_id: to_player_id.clone(),
+            amount_wei: saturating_u64(*amount_wei),
+            transfer_type: transfer_type.clone(),
+            transaction_hash: tx_hash,
+            transfer_timestamp: timestamp,
+            schema_version: SCHEMA_VERSION,
+            amount_wei_decimal: amount_wei.to_string(),
+        })),
+        EventPayload::NftTransferred {
+            nft_id,
+            from_player_id,
+            to_player_id,
+            transfer_type,
+        } => Some(Payload::NftTransferred(NftTransferredPayloadProto {
+            nft_id: nft_id.clone(),
+            from_player_id: from_player_id.clone(),
+            to_player_id: to_player_id.clone(),
+            transfer_type: transfer_type.clone(),
+            transaction_hash: tx_hash,
+            transfer_timestamp: timestamp,
+            schema_version: SCHEMA_VERSION,
+        })),
+    };
+
+    CanonicalEventProto {
+        event_id: event.event_id.clone(),
+        block_number: event.block_number,
+        log_index: event.log_index,
+        contract_address: event.contract_address.clone(),
+        transaction_hash: event.transaction_hash.clone(),
+        block_timestamp: event.block_timestamp,
+        payload,
+        schema_version: SCHEMA_VERSION,
+    }
+}
+
+/// Event counts keyed by event type name
+pub fn event_type_counts<'a>(
+    events: impl IntoIterator<Item = &'a ChainEvent>,
+) -> HashMap<String, u64> {
+    let mut counts = HashMap::new();
+    for event in events {
+        *counts
+            .entry(event.payload.event_type().to_string())
+            .or_default() += 1;
+    }
+    counts
+}
+
+/// Event counts keyed by emitting contract address
+pub fn contract_event_counts<'a>(
+    events: impl IntoIterator<Item = &'a ChainEvent>,
+) -> HashMap<String, u64> {
+    let mut counts = HashMap::new();
+    for event in events {
+        *counts.entry(event.contract_address.clone()).or_default() += 1;
+    }
+    counts
+}
+
+/// Chain amounts can pass 2^64 wei while the proto fields are `uint64`
+fn saturating_u64(wei: u128) -> u64 {
+    u64::try_from(wei).unwrap_or(u64::MAX)
+}
+
+/// NTC as reported to clients: wei with crypto on, milli-NTC without
+fn ntc_balance(wei: u128, enable_crypto: bool) -> u64 {
+    if enable_crypto {
+        saturating_u64(wei)
+    } else {
+        saturating_u64(wei / u128::from(smart_stubs::world::NTC_WEI / 1_000))
+    }
+}
+
+/// Chain state folded from the player's world entities
+pub fn chain_state(world: &World, player: &Player, enable_crypto: bool) -> AgentChainStateProto {
+    let robot = world.robot_for(&player.player_id);
+
+    let mut owned_by_type: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for nft in world.nfts_owned_by(&player.player_id) {
+        owned_by_type
+            .entry(nft.item_type.as_str())
+            .or_default()
+            .push(&nft.nft_id);
+    }
+
+    let last_updated = world
+        .events_for_player(&player.player_id)
+        .last()
+        .map_or(player.registered_at, |event| event.block_timestamp);
+
+    AgentChainStateProto {
+        player_id: player.player_id.clone(),
+        balances: Some(BalancesProto {
+            xp: player.xp,
+            ntc_balance: ntc_balance(player.ntc_balance_wei.into(), enable_crypto),
+            credits_balance: player.credits_balance,
+        }),
+        active_bunkerguard: robot.map(|robot| ActiveBunkerguardDataProto {
+            robot_id: Some(robot.robot_id.clone()),
+            level: robot.level,
+            current_class: Some(robot.class as i32),
+            current_affiliation: robot.affiliation as i32,
+            final_stats: Some(core_stats(&robot.final_stats)),
+            equipped_items: robot
+                .equipped
+                .iter()
+                .map(|(slot, nft_id)| (slot.clone(), nft_id.clone()))
+                .collect(),
+            total_xp: robot.total_xp,
+            last_active_timestamp: robot.last_active_at,
+        }),
+        owned_nft_ids_by_type: owned_by_type
+            .into_iter()
+            .map(|(item_type, ids)| (item_type.to_string(), ids.join(",")))
+            .collect(),
+        ntc_staking: player
+            .staking
+            .as_ref()
+            .filter(|_| enable_crypto)
+            .map(|staking| NtcStakingDetailsProto {
+                total_staked_ntc: staking.staked_ntc_wei,
+                rewards_earned_ntc: staking.rewards_earned_ntc_wei,
+                stake_start_timestamp: staking.started_at,
+                last_reward_claim_timestamp: staking.last_reward_claim_at,
+                staking_tier: staking.tier,
+                auto_compound: staking.auto_compound,
+            }),
+        crypto_addresses: enable_crypto.then(|| CryptoAddressesProto {
+            l3_wallet_address: player.l3_wallet_address.clone(),
+            l2_wallet_address: player.l2_wallet_address.clone(),
+            l1_wallet_address: player.l1_wallet_address.clone(),
+            addresses_updated_timestamp: player.registered_at,
+        }),
+        schema_version: SCHEMA_VERSION,
+        last_updated_timestamp: last_updated,
+    }
+}
+
+/// Chain state of a player projected from indexed events
+///
+/// The world fills in what events do not carry: robot class and stats, NFT
+/// types, staking tier, credits and wallet addresses. Players only known by
+/// wallet get their address as `player_id`.
+pub fn projected_chain_state(
+    world: &World,
+    player_id: &str,
+    projection: &PlayerProjection,
+    enable_crypto: bool,
+) -> AgentChainStateProto {
+    let player = world.player(player_id);
+
+    let mut owned_by_type: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for nft_id in &projection.owned_nft_ids {
+        let item_type = world
+            .nft(nft_id)
+            .map_or("Unknown", |nft| nft.item_type.as_str());
+        owned_by_type.entry(item_type).or_default().push(nft_id);
+    }
+
+    AgentChainStateProto {
+        player_id: player_id.to_string(),
+        balances: Some(BalancesProto {
+            xp: projection.xp,
+            ntc_balance: ntc_balance(projection.ntc_balance_wei, enable_crypto),
+            credits_balance: player.map_or(0, |player| player.credits_balance),
+        }),
+        active_bunkerguard: projection.robot.as_ref().map(|robot| {
+            let fixture = world
+                .robot_for(player_id)
+                .filter(|fixture| fixture.robot_id == robot.robot_id);
+            ActiveBunkerguardDataProto {
+                robot_id: Some(robot.robot_id.clone()),
+                level: robot.level,
+                current_class: fixture.map(|fixture| fixture.class as i32),
+                current_affiliation: fixture.map_or(0, |fixture| fixture.affiliation as i32),
+                final_stats: fixture.map(|fixture| core_stats(&fixture.final_stats)),
+                equipped_items: robot
+                    .equipped
+                    .iter()
+                    .map(|(slot, nft_id)| (slot.clone(), nft_id.clone()))
+                    .collect(),
+                total_xp: robot.total_xp,
+                last_active_timestamp: robot.last_active_at,
+            }
+        }),
+        owned_nft_ids_by_type: owned_by_type
+            .into_iter()
+            .map(|(item_type, ids)| (item_type.to_string(), ids.join(",")))
+            .collect(),
+        ntc_staking: projection
+            .staking
+            .as_ref()
+            .filter(|_| enable_crypto)
+            .map(|staking| {
+                let fixture = player.and_then(|player| player.staking.as_ref());
+                NtcStakingDetailsProto {
+                    total_staked_ntc: staking.staked_ntc_wei,
+                    rewards_earned_ntc: 0,
+                    stake_start_timestamp: staking.started_at,
+                    last_reward_claim_timestamp: 0,
+                    staking_tier: fixture.map_or(0, |fixture| fixture.tier),
+                    auto_compound: fixture.is_some_and(|fixture| fixture.auto_compound),
+                }
+            }),
+        crypto_addresses: enable_crypto.then(|| match player {
+            Some(player) => CryptoAddressesProto {
+                l3_wallet_address: player.l3_wallet_address.clone(),
+                l2_wallet_address: player.l2_wallet_address.clone(),
+                l1_wallet_address: player.l1_wallet_address.clone(),
+                addresses_updated_timestamp: player.registered_at,
+            },
+            None => CryptoAddressesProto {
+                l3_wallet_address: player_id.to_string(),
+                ..CryptoAddressesProto::default()
+            },
+        }),
+        schema_version: SCHEMA_VERSION,
+        last_updated_timestamp: projection.last_updated_at,
+    }
+}
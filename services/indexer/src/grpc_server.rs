@@ -1,8 +1,16 @@
-use crate::config::StubConfiguration;
-use crate::stub::{IndexerStub, RequestContext, SmartStub};
+use crate::event_feed::{DeliveredEvent, EventCursor, EventDelivery, EventFeedFilter};
+use crate::fixtures;
+use crate::ingestion::{self, Ingestion};
+use crate::stub::{RequestContext, SharedStub, SmartStub};
 use anyhow::Result;
 use chrono::Utc;
-use std::{collections::HashMap, sync::Arc};
+use futures_util::Stream;
+use smart_stubs::stub::WorldGuard;
+use smart_stubs::world::{events_in_blocks, paginate, ChainEvent, Page, World, GENESIS_TIMESTAMP};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::Arc;
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
@@ -22,25 +30,159 @@ pub mod bunkerverse {
 
 use bunkerverse::services::v1::*;
 
+/// Players listed in `EventTypeStatsProto::top_players`
+const TOP_PLAYERS: usize = 10;
+
+/// Cap on events returned by `GetEventsByBlock`, which has no pagination
+const MAX_BLOCK_RANGE_EVENTS: usize = 1_000;
+
+fn pagination_proto(page: Page) -> bunkerverse::core::v1::PaginationProto {
+    bunkerverse::core::v1::PaginationProto {
+        page: page.page,
+        page_size: page.page_size,
+        total_items: page.total_items,
+        total_pages: page.total_pages,
+    }
+}
+
+/// Apply `EventFiltersProto` to world events and order them by `EventSortProto`
+///
+/// Zero block and timestamp bounds are treated as unbounded. `finalized_only`
+/// keeps events at or below `finalized_block`, and none when nothing is final yet.
+fn select_events<'a>(
+    events: impl Iterator<Item = &'a ChainEvent>,
+    filters: Option<&EventFiltersProto>,
+    sort: Option<EventSortProto>,
+    finalized_block: Option<u64>,
+) -> Vec<&'a ChainEvent> {
+    let default_filters = EventFiltersProto::default();
+    let filters = filters.unwrap_or(&default_filters);
+    let any_of = |values: &[String], value: &str| {
+        values.is_empty() || values.iter().any(|candidate| candidate == value)
+    };
+
+    let mut selected: Vec<&ChainEvent> = events
+        .filter(|event| {
+            any_of(&filters.event_types, event.payload.event_type())
+                && any_of(&filters.contract_addresses, &event.contract_address)
+                && (filters.player_ids.is_empty()
+                    || event
+                        .payload
+                        .participants()
+                        .into_iter()
+                        .any(|player_id| any_of(&filters.player_ids, player_id)))
+                && any_of(&filters.transaction_hashes, &event.transaction_hash)
+                && (filters.start_block == 0 || event.block_number >= filters.start_block)
+                && (filters.end_block == 0 || event.block_number <= filters.end_block)
+                && (filters.start_timestamp == 0
+                    || event.block_timestamp >= filters.start_timestamp)
+                && (filters.end_timestamp == 0 || event.block_timestamp <= filters.end_timestamp)
+                && (!filters.finalized_only
+                    || finalized_block.is_some_and(|finalized| event.block_number <= finalized))
+        })
+        .collect();
+
+    // World events are already in chain order, so descending sorts are reversals
+    let sort_field = sort.map_or(event_sort_proto::SortField::Unspecified, |sort| {
+        sort.sort_field()
+    });
+    match sort_field {
+        event_sort_proto::SortField::BlockNumberDesc
+        | event_sort_proto::SortField::TimestampDesc => selected.reverse(),
+        event_sort_proto::SortField::LogIndexAsc => {
+            selected.sort_by_key(|event| event.log_index);
+        }
+        event_sort_proto::SortField::LogIndexDesc => {
+            selected.sort_by_key(|event| std::cmp::Reverse(event.log_index));
+        }
+        _ => {}
+    }
+    selected
+}
+
+fn event_cursor_proto(cursor: EventCursor) -> EventCursorProto {
+    EventCursorProto {
+        block_number: cursor.block_number,
+        log_index: cursor.log_index,
+    }
+}
+
+fn event_stream_item(world: &World, delivery: &EventDelivery) -> EventStreamItemProto {
+    EventStreamItemProto {
+        item: Some(match &delivery.event {
+            DeliveredEvent::Indexed(event) => {
+                event_stream_item_proto::Item::Event(fixtures::canonical_event(world, event))
+            }
+            DeliveredEvent::Retracted(retraction) => {
+                event_stream_item_proto::Item::Retraction(RetractedEventProto {
+                    sequence: retraction.sequence,
+                    event: Some(fixtures::canonical_event(world, &retraction.event)),
+                    block_hash: retraction.block_hash.clone(),
+                    retracted_at: retraction.retracted_at,
+                })
+            }
+        }),
+        resume_after: delivery.resume_after.map(event_cursor_proto),
+        last_retraction: delivery.last_retraction,
+    }
+}
+
+/// Events the RPCs answer from, in chain order
+enum EventLog<'a> {
+    /// The fixture world's simulated chain
+    Fixture(&'a World),
+    /// Blocks ingested from the L3 node
+    Indexed {
+        events: Arc<Vec<ChainEvent>>,
+        finalized_block: Option<u64>,
+    },
+}
+
+impl Deref for EventLog<'_> {
+    type Target = [ChainEvent];
+
+    fn deref(&self) -> &[ChainEvent] {
+        match self {
+            Self::Fixture(world) => &world.events,
+            Self::Indexed { events, .. } => events,
+        }
+    }
+}
+
+impl EventLog<'_> {
+    /// Highest block that can no longer be reorganized; fixture blocks are all final
+    fn finalized_block(&self) -> Option<u64> {
+        match self {
+            Self::Fixture(world) => Some(world.latest_block()),
+            Self::Indexed {
+                finalized_block, ..
+            } => *finalized_block,
+        }
+    }
+
+    fn for_player(&self, player_id: &str) -> Vec<&ChainEvent> {
+        match self {
+            Self::Fixture(world) => world.events_for_player(player_id).collect(),
+            Self::Indexed { events, .. } => events
+                .iter()
+                .filter(|event| event.payload.participants().contains(&player_id))
+                .collect(),
+        }
+    }
+}
+
 pub struct IndexerGrpcService {
-    stub: Arc<tokio::sync::Mutex<IndexerStub>>,
+    stub: SharedStub,
+    ingestion: Option<Ingestion>,
 }
 
 impl IndexerGrpcService {
-    pub fn new(config: StubConfiguration) -> Self {
-        Self {
-            stub: Arc::new(tokio::sync::Mutex::new(IndexerStub::new(config))),
-        }
+    pub fn new(stub: SharedStub, ingestion: Option<Ingestion>) -> Self {
+        Self { stub, ingestion }
     }
 
     async fn create_context(&self, trace_id: Option<String>) -> RequestContext {
-        let stub = self.stub.lock().await;
-        RequestContext {
-            request_id: Uuid::new_v4().to_string(),
-            trace_id,
-            timestamp: Utc::now(),
-            enable_crypto: stub.get_configuration().dual_mode.enable_crypto,
-        }
+        self.stub.lock().await.create_context(trace_id)
     }
 
     async fn simulate_latency_and_errors(
@@ -48,28 +190,38 @@ impl IndexerGrpcService {
         context: &RequestContext,
         method: &str,
     ) -> Result<(), Status> {
-        let stub = self.stub.lock().await;
-
-        // Simulate latency
-        let latency = stub.calculate_response_latency();
-        tokio::time::sleep(tokio::time::Duration::from_millis(
-            latency.as_millis() as u64
-        ))
-        .await;
-
-        // Check for error injection
-        if stub.should_inject_error_response() {
-            stub.log_response(context, method, latency.as_millis() as u64, 500, true);
-            return Err(Status::internal("Simulated indexer gRPC error"));
-        }
+        smart_stubs::grpc::simulate_call(
+            &self.stub,
+            context,
+            method,
+            "Simulated indexer gRPC error",
+        )
+        .await
+    }
+
+    async fn world(&self) -> WorldGuard {
+        self.stub.lock().await.world()
+    }
 
-        stub.log_response(context, method, latency.as_millis() as u64, 200, false);
-        Ok(())
+    /// Indexed events when ingestion is enabled
+    ///
+    /// Read before the world, which cannot be held while waiting on the index;
+    /// fall back to [`EventLog::Fixture`] without ingestion.
+    async fn indexed_log(&self) -> Option<EventLog<'static>> {
+        let ingestion = self.ingestion.as_ref()?;
+        let index = ingestion.index.read().await;
+        Some(EventLog::Indexed {
+            events: index.events(),
+            finalized_block: index.finalized_block(ingestion.confirmations),
+        })
     }
 }
 
 #[tonic::async_trait]
 impl indexer_service_server::IndexerService for IndexerGrpcService {
+    type SubscribeEventsStream =
+        Pin<Box<dyn Stream<Item = Result<EventStreamItemProto, Status>> + Send + 'static>>;
+
     async fn get_events(
         &self,
         request: Request<GetEventsRequest>,
@@ -90,37 +242,42 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         self.simulate_latency_and_errors(&context, "GetEvents")
             .await?;
 
-        // Return minimal mock events with correct schema structure
-        let mock_events = vec![bunkerverse::core::v1::CanonicalEventProto {
-            event_id: "event_001".to_string(),
-            block_number: 12345,
-            log_index: 0,
-            contract_address: "0xcontract123".to_string(),
-            transaction_hash: "0xabc123".to_string(),
-            block_timestamp: Utc::now().timestamp(),
-            payload: None, // Simplified - no payload for stub
-            schema_version: 1,
-        }];
+        let indexed = self.indexed_log().await;
+        let indexing_stats = match &self.ingestion {
+            Some(ingestion) => Some(ingestion.indexing_stats().await),
+            None => None,
+        };
+        let world = self.world().await;
+        let log = indexed.unwrap_or(EventLog::Fixture(&world));
+        let events = select_events(
+            log.iter(),
+            req.filters.as_ref(),
+            req.sort,
+            log.finalized_block(),
+        );
+        let pagination = req.pagination.unwrap_or_default();
+        let (page, page_info) = paginate(&events, pagination.page, pagination.page_size);
+        let indexing_stats = indexing_stats.unwrap_or_else(|| IndexingStatsProto {
+            total_events_indexed: world.events.len() as u64,
+            total_blocks_indexed: world.latest_block(),
+            events_per_second_current: 50,
+            events_per_second_average: 45,
+            contract_event_counts: fixtures::contract_event_counts(&world.events),
+            event_type_counts: fixtures::event_type_counts(&world.events),
+            indexing_start_time: GENESIS_TIMESTAMP,
+            last_successful_sync: world.events.last().map_or(0, |event| event.block_timestamp),
+            recent_errors: vec![],
+            database_size_bytes: 1048576, // 1MB
+        });
 
         let response = GetEventsResponse {
             result: Some(get_events_response::Result::Success(GetEventsSuccess {
-                events: mock_events,
-                pagination: req.pagination,
-                indexing_stats: Some(IndexingStatsProto {
-                    total_events_indexed: 10000,
-                    total_blocks_indexed: 5000,
-                    events_per_second_current: 50,
-                    events_per_second_average: 45,
-                    contract_event_counts: HashMap::new(),
-                    event_type_counts: HashMap::from([
-                        ("NftTransfer".to_string(), 3000),
-                        ("MissionCompleted".to_string(), 2000),
-                    ]),
-                    indexing_start_time: (Utc::now() - chrono::Duration::hours(24)).timestamp(),
-                    last_successful_sync: Utc::now().timestamp(),
-                    recent_errors: vec![],
-                    database_size_bytes: 1048576, // 1MB
-                }),
+                events: page
+                    .iter()
+                    .map(|event| fixtures::canonical_event(&world, event))
+                    .collect(),
+                pagination: Some(pagination_proto(page_info)),
+                indexing_stats: Some(indexing_stats),
             })),
         };
 
@@ -142,37 +299,53 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         self.simulate_latency_and_errors(&context, "GetEventsByPlayer")
             .await?;
 
-        let mock_events = vec![bunkerverse::core::v1::CanonicalEventProto {
-            event_id: "event_player_001".to_string(),
-            block_number: 12340,
-            log_index: 1,
-            contract_address: "0xmissioncontract456".to_string(),
-            transaction_hash: "0xdef456".to_string(),
-            block_timestamp: (Utc::now() - chrono::Duration::hours(1)).timestamp(),
-            payload: None, // Simplified
-            schema_version: 1,
-        }];
+        let indexed = self.indexed_log().await;
+        let world = self.world().await;
+        let log = indexed.unwrap_or(EventLog::Fixture(&world));
+        // Indexed events may name wallets that are not fixture players
+        let player_events = log.for_player(&req.player_id);
+        if world.player(&req.player_id).is_none() && player_events.is_empty() {
+            return Err(Status::not_found(format!(
+                "Player {} not found",
+                req.player_id
+            )));
+        }
+        let event_type_counts = fixtures::event_type_counts(player_events.iter().copied());
+        let count = |event_type: &str| event_type_counts.get(event_type).copied().unwrap_or(0);
+
+        let events = select_events(
+            player_events.iter().copied(),
+            req.filters.as_ref(),
+            req.sort,
+            log.finalized_block(),
+        );
+        let pagination = req.pagination.unwrap_or_default();
+        let (page, page_info) = paginate(&events, pagination.page, pagination.page_size);
 
         let response = GetEventsByPlayerResponse {
             result: Some(get_events_by_player_response::Result::Success(
                 GetEventsByPlayerSuccess {
-                    events: mock_events,
-                    pagination: req.pagination,
+                    events: page
+                        .iter()
+                        .map(|event| fixtures::canonical_event(&world, event))
+                        .collect(),
+                    pagination: Some(pagination_proto(page_info)),
                     player_stats: Some(PlayerEventStatsProto {
+                        total_events: player_events.len() as u64,
+                        nft_events: count("NftMinted")
+                            + count("ItemEquipped")
+                            + count("NftMarketListed"),
+                        transaction_events: count("NftMarketListed"),
+                        mission_events: count("MissionCompleted"),
+                        staking_events: count("NtcStakingInitiated"),
+                        first_event_timestamp: player_events
+                            .first()
+                            .map_or(0, |event| event.block_timestamp),
+                        last_event_timestamp: player_events
+                            .last()
+                            .map_or(0, |event| event.block_timestamp),
                         player_id: req.player_id,
-                        total_events: 25,
-                        nft_events: 10,
-                        transaction_events: 8,
-                        mission_events: 5,
-                        staking_events: 2,
-                        first_event_timestamp: (Utc::now() - chrono::Duration::days(30))
-                            .timestamp(),
-                        last_event_timestamp: Utc::now().timestamp(),
-                        event_type_counts: HashMap::from([
-                            ("NftTransfer".to_string(), 10),
-                            ("MissionCompleted".to_string(), 5),
-                            ("StakeDeposit".to_string(), 2),
-                        ]),
+                        event_type_counts,
                     }),
                 },
             )),
@@ -196,32 +369,64 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         self.simulate_latency_and_errors(&context, "GetEventsByType")
             .await?;
 
-        let mock_events = vec![bunkerverse::core::v1::CanonicalEventProto {
-            event_id: "event_type_001".to_string(),
-            block_number: 12350,
-            log_index: 0,
-            contract_address: "0xcontractabc789".to_string(),
-            transaction_hash: "0xghi789".to_string(),
-            block_timestamp: Utc::now().timestamp(),
-            payload: None, // Simplified
-            schema_version: 1,
-        }];
+        let indexed = self.indexed_log().await;
+        let world = self.world().await;
+        let log = indexed.unwrap_or(EventLog::Fixture(&world));
+        let typed: Vec<&ChainEvent> = log
+            .iter()
+            .filter(|event| event.payload.event_type() == req.event_type)
+            .collect();
+
+        // Windows are measured back from the chain head rather than wall-clock time
+        let head = log.last().map_or(0, |event| event.block_timestamp);
+        let count_since = |seconds: i64| {
+            typed
+                .iter()
+                .filter(|event| event.block_timestamp > head - seconds)
+                .count() as u64
+        };
+        let first_occurrence = typed.first().map_or(0, |event| event.block_timestamp);
+        let last_occurrence = typed.last().map_or(0, |event| event.block_timestamp);
+        let days = ((last_occurrence - first_occurrence) as f32 / 86_400.0).max(1.0);
+
+        let mut per_player: HashMap<&str, u64> = HashMap::new();
+        for event in &typed {
+            *per_player.entry(event.payload.player_id()).or_default() += 1;
+        }
+        let mut top_players: Vec<(&str, u64)> = per_player.into_iter().collect();
+        top_players.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        let events = select_events(
+            typed.iter().copied(),
+            req.filters.as_ref(),
+            req.sort,
+            log.finalized_block(),
+        );
+        let pagination = req.pagination.unwrap_or_default();
+        let (page, page_info) = paginate(&events, pagination.page, pagination.page_size);
 
         let response = GetEventsByTypeResponse {
             result: Some(get_events_by_type_response::Result::Success(
                 GetEventsByTypeSuccess {
-                    events: mock_events,
-                    pagination: req.pagination,
+                    events: page
+                        .iter()
+                        .map(|event| fixtures::canonical_event(&world, event))
+                        .collect(),
+                    pagination: Some(pagination_proto(page_info)),
                     type_stats: Some(EventTypeStatsProto {
                         event_type: req.event_type,
-                        total_count: 1500,
-                        count_24h: 50,
-                        count_7d: 300,
-                        count_30d: 1200,
-                        average_per_day: 40.0,
-                        first_occurrence: (Utc::now() - chrono::Duration::days(30)).timestamp(),
-                        last_occurrence: Utc::now().timestamp(),
-                        top_players: vec!["player_123".to_string(), "player_456".to_string()],
+                        total_count: typed.len() as u64,
+                        count_24h: count_since(86_400),
+                        count_7d: count_since(7 * 86_400),
+                        count_30d: count_since(30 * 86_400),
+                        average_per_day: typed.len() as f32 / days,
+                        first_occurrence,
+                        last_occurrence,
+                        top_players: top_players
+                            .into_iter()
+                            .take(TOP_PLAYERS)
+                            .map(|(player_id, _)| player_id.to_string())
+                            .collect(),
                     }),
                 },
             )),
@@ -250,34 +455,48 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         self.simulate_latency_and_errors(&context, "GetEventsByBlock")
             .await?;
 
-        let mock_events = vec![bunkerverse::core::v1::CanonicalEventProto {
-            event_id: "event_block_001".to_string(),
-            block_number: req.start_block,
-            log_index: 0,
-            contract_address: "0xsystemcontract".to_string(),
-            transaction_hash: "0xjkl012".to_string(),
-            block_timestamp: Utc::now().timestamp(),
-            payload: None, // Simplified
-            schema_version: 1,
-        }];
+        if req.end_block < req.start_block {
+            return Err(Status::invalid_argument(
+                "end_block must not be before start_block",
+            ));
+        }
+
+        let indexed = self.indexed_log().await;
+        let world = self.world().await;
+        let log = indexed.unwrap_or(EventLog::Fixture(&world));
+        let in_range = events_in_blocks(&log, req.start_block, req.end_block);
+        let mut events = select_events(
+            in_range.iter(),
+            req.filters.as_ref(),
+            None,
+            log.finalized_block(),
+        );
+        events.truncate(MAX_BLOCK_RANGE_EVENTS);
+        let transactions: std::collections::HashSet<&str> = in_range
+            .iter()
+            .map(|event| event.transaction_hash.as_str())
+            .collect();
 
         let response = GetEventsByBlockResponse {
             result: Some(get_events_by_block_response::Result::Success(
                 GetEventsByBlockSuccess {
-                    events: mock_events,
+                    events: events
+                        .iter()
+                        .map(|event| fixtures::canonical_event(&world, event))
+                        .collect(),
                     block_stats: Some(BlockRangeStatsProto {
                         start_block: req.start_block,
                         end_block: req.end_block,
-                        total_events: 5,
-                        total_transactions: 3,
-                        contract_event_counts: HashMap::new(),
-                        event_type_counts: HashMap::from([
-                            ("BlockProcessed".to_string(), 1),
-                            ("NftTransfer".to_string(), 2),
-                        ]),
-                        range_start_timestamp: (Utc::now() - chrono::Duration::seconds(30))
-                            .timestamp(),
-                        range_end_timestamp: Utc::now().timestamp(),
+                        total_events: in_range.len() as u64,
+                        total_transactions: transactions.len() as u64,
+                        contract_event_counts: fixtures::contract_event_counts(in_range),
+                        event_type_counts: fixtures::event_type_counts(in_range),
+                        range_start_timestamp: in_range
+                            .first()
+                            .map_or(0, |event| event.block_timestamp),
+                        range_end_timestamp: in_range
+                            .last()
+                            .map_or(0, |event| event.block_timestamp),
                     }),
                 },
             )),
@@ -286,6 +505,100 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         Ok(Response::new(response))
     }
 
+    async fn subscribe_events(
+        &self,
+        request: Request<SubscribeEventsRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "SubscribeEvents", "gRPC");
+
+            // Check crypto features for blockchain queries
+            if let Err(err) = stub.check_crypto_features(&context) {
+                return Err(Status::permission_denied(err));
+            }
+        }
+
+        self.simulate_latency_and_errors(&context, "SubscribeEvents")
+            .await?;
+
+        let filter = EventFeedFilter {
+            event_types: req.event_types,
+            player_ids: req.player_ids,
+            contract_addresses: req.contract_addresses,
+        };
+        let resume_after = req.resume_after.map(|cursor| EventCursor {
+            block_number: cursor.block_number,
+            log_index: cursor.log_index,
+        });
+        let Some(ingestion) = &self.ingestion else {
+            // Fixture blocks are final and never change, so the stream replays
+            // them and then stays open without further events
+            let first = match resume_after {
+                Some(cursor) => self
+                    .world()
+                    .await
+                    .events
+                    .partition_point(|event| EventCursor::of(event) <= cursor),
+                None => 0,
+            };
+            let last_retraction = req.last_retraction;
+            let stub = self.stub.clone();
+            let stream = futures_util::stream::unfold(first, move |mut position| {
+                let stub = stub.clone();
+                let filter = filter.clone();
+                async move {
+                    // The world is read one item at a time so updates are not held up
+                    let item = {
+                        let world = stub.lock().await.world();
+                        let mut item = None;
+                        while let Some(event) = world.events.get(position) {
+                            position += 1;
+                            if filter.matches(event) {
+                                let delivery = EventDelivery {
+                                    event: DeliveredEvent::Indexed(event.clone()),
+                                    resume_after: Some(EventCursor::of(event)),
+                                    last_retraction,
+                                };
+                                item = Some(event_stream_item(&world, &delivery));
+                                break;
+                            }
+                        }
+                        item
+                    };
+                    match item {
+                        Some(item) => Some((Ok(item), position)),
+                        None => std::future::pending().await,
+                    }
+                }
+            });
+            return Ok(Response::new(Box::pin(stream)));
+        };
+
+        let subscription = ingestion.subscribe(
+            filter,
+            resume_after,
+            req.last_retraction,
+            req.finalized_only,
+        );
+        // Items are read from the index as the client takes them, so a slow
+        // client holds back only its own stream
+        let stub = self.stub.clone();
+        let stream = futures_util::stream::unfold(subscription, move |mut subscription| {
+            let stub = stub.clone();
+            async move {
+                let delivery = subscription.next().await?;
+                let item = event_stream_item(&stub.lock().await.world(), &delivery);
+                Some((Ok(item), subscription))
+            }
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
     async fn get_player_chain_state(
         &self,
         request: Request<GetPlayerChainStateRequest>,
@@ -301,86 +614,40 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         self.simulate_latency_and_errors(&context, "GetPlayerChainState")
             .await?;
 
-        // Return simplified chain state with correct schema
-        let chain_state = bunkerverse::core::v1::AgentChainStateProto {
-            player_id: req.player_id,
-            balances: Some(bunkerverse::core::v1::BalancesProto {
-                xp: 12500,
-                ntc_balance: if context.enable_crypto {
-                    5000000000000000000u64
-                } else {
-                    5000
-                },
-                credits_balance: 10000,
-            }),
-            active_bunkerguard: Some(bunkerverse::core::v1::ActiveBunkerguardDataProto {
-                robot_id: Some("robot_001".to_string()),
-                level: 25,
-                current_class: Some(bunkerverse::core::v1::BunkerClassProto::Explorer as i32),
-                current_affiliation: bunkerverse::core::v1::ClassAffiliationProto::Loyal as i32,
-                final_stats: Some(bunkerverse::core::v1::CoreStatsProto {
-                    damage: 85,
-                    accuracy: 80,
-                    critical_chance: 20,
-                    armor_piercing: 30,
-                    speed: 90,
-                    agility: 60,
-                    stealth: 40,
-                    evasion: 70,
-                    health: 100,
-                    shield: 80,
-                    detection: 80,
-                    range: 90,
-                    combat_average: 54,
-                    mobility_average: 65,
-                    survivability_average: 90,
-                    sensors_average: 85,
-                }),
-                equipped_items: HashMap::from([
-                    ("weapon".to_string(), "nft_weapon_001".to_string()),
-                    ("armor".to_string(), "nft_armor_001".to_string()),
-                ]),
-                total_xp: 125000,
-                last_active_timestamp: Utc::now().timestamp(),
-            }),
-            owned_nft_ids_by_type: HashMap::from([
+        // One snapshot, so the whole answer is as of its block
+        let projections = match &self.ingestion {
+            Some(ingestion) => Some(ingestion.index.read().await.projections()),
+            None => None,
+        };
+        let world = self.world().await;
+        let not_found = || Status::not_found(format!("Player {} not found", req.player_id));
+        let (chain_state, as_of_block) = match projections {
+            Some(projections) => {
+                let projection = projections.player(&req.player_id).ok_or_else(not_found)?;
                 (
-                    "Weapon".to_string(),
-                    "nft_weapon_001,nft_weapon_002".to_string(),
-                ),
-                ("Armor".to_string(), "nft_armor_001".to_string()),
-            ]),
-            ntc_staking: if context.enable_crypto {
-                Some(bunkerverse::core::v1::NtcStakingDetailsProto {
-                    total_staked_ntc: 1000000000000000000u64,
-                    rewards_earned_ntc: 250000000000000000u64,
-                    stake_start_timestamp: (Utc::now() - chrono::Duration::days(30)).timestamp(),
-                    last_reward_claim_timestamp: (Utc::now() - chrono::Duration::days(7))
-                        .timestamp(),
-                    staking_tier: 3,
-                    auto_compound: true,
-                })
-            } else {
-                None
-            },
-            crypto_addresses: if context.enable_crypto {
-                Some(bunkerverse::core::v1::CryptoAddressesProto {
-                    l3_wallet_address: "0xplayer123456".to_string(),
-                    l2_wallet_address: "0xplayerarb789".to_string(),
-                    l1_wallet_address: "0xplayereth000".to_string(),
-                    addresses_updated_timestamp: Utc::now().timestamp(),
-                })
-            } else {
-                None
-            },
-            schema_version: 1,
-            last_updated_timestamp: Utc::now().timestamp(),
+                    fixtures::projected_chain_state(
+                        &world,
+                        &req.player_id,
+                        projection,
+                        context.enable_crypto,
+                    ),
+                    projections.block_height().unwrap_or(0),
+                )
+            }
+            None => {
+                let player = world.player(&req.player_id).ok_or_else(not_found)?;
+                (
+                    fixtures::chain_state(&world, player, context.enable_crypto),
+                    world.latest_block(),
+                )
+            }
         };
 
         let response = GetPlayerChainStateResponse {
             result: Some(get_player_chain_state_response::Result::ChainState(
                 chain_state,
             )),
+            as_of_block,
         };
 
         Ok(Response::new(response))
@@ -406,69 +673,24 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         self.simulate_latency_and_errors(&context, "GetNftOwnership")
             .await?;
 
+        let world = self.world().await;
+        let nft = world
+            .nft(&req.nft_id)
+            .ok_or_else(|| Status::not_found(format!("NFT {} not found", req.nft_id)))?;
+
         let ownership_data = GetNftOwnershipSuccess {
-            nft_details: Some(bunkerverse::core::v1::NftDetailsProto {
-                identifier: Some(bunkerverse::core::v1::NftIdentifierProto {
-                    nft_id: req.nft_id.clone(),
-                    token_id: 1,
-                    contract_address: "0xcontract456".to_string(),
-                }),
-                item_type: bunkerverse::core::v1::ItemTypeProto::Torso as i32,
-                item_rarity: bunkerverse::core::v1::ItemRarityProto::Advanced as i32,
-                base_stat_boosts: Some(bunkerverse::core::v1::CoreStatsProto {
-                    damage: 0,
-                    accuracy: 0,
-                    critical_chance: 0,
-                    armor_piercing: 10,
-                    speed: 0,
-                    agility: 0,
-                    stealth: 0,
-                    evasion: 0,
-                    health: 0,
-                    shield: 20,
-                    detection: 0,
-                    range: 0,
-                    combat_average: 3,
-                    mobility_average: 0,
-                    survivability_average: 10,
-                    sensors_average: 0,
-                }),
-                class_affinities: vec![bunkerverse::core::v1::BunkerClassProto::Explorer as i32],
-                trait_affiliation: bunkerverse::core::v1::ClassAffiliationProto::Loyal as i32,
-                construct_origin: "Factory Beta".to_string(),
-                metadata_pointer_uri: "Qm123456789abcdefghijklmnopqrstuvwxyzABCDEFGH".to_string(),
-                schema_version: 1,
-                created_timestamp: (Utc::now() - chrono::Duration::days(15)).timestamp(),
-            }),
-            current_state: Some(bunkerverse::core::v1::NftMutableStateProto {
-                current_owner_id: "player_123".to_string(),
-                current_condition: bunkerverse::core::v1::ItemConditionProto::NewState as i32,
-                is_soulbound: false,
-                market_status: bunkerverse::core::v1::MarketStatusProto::NotListed as i32,
-                market_price_ntc: 0,
-                last_updated_timestamp: Utc::now().timestamp(),
-            }),
+            nft_details: Some(fixtures::nft_details(nft)),
+            current_state: Some(fixtures::nft_state(&world, nft)),
             ownership_history: if req.include_history {
-                vec![
-                    NftOwnershipHistoryProto {
-                        previous_owner_id: "system".to_string(),
-                        new_owner_id: "player_456".to_string(),
-                        transfer_type: "mint".to_string(),
-                        transfer_price_wei: 0,
-                        transaction_hash: "0xmint123".to_string(),
-                        block_number: 12000,
-                        transfer_timestamp: (Utc::now() - chrono::Duration::days(15)).timestamp(),
-                    },
-                    NftOwnershipHistoryProto {
-                        previous_owner_id: "player_456".to_string(),
-                        new_owner_id: "player_123".to_string(),
-                        transfer_type: "purchase".to_string(),
-                        transfer_price_wei: 500000000000000000u64,
-                        transaction_hash: "0xpurchase456".to_string(),
-                        block_number: 12100,
-                        transfer_timestamp: (Utc::now() - chrono::Duration::days(7)).timestamp(),
-                    },
-                ]
+                vec![NftOwnershipHistoryProto {
+                    previous_owner_id: "system".to_string(),
+                    new_owner_id: nft.owner_id.clone(),
+                    transfer_type: "mint".to_string(),
+                    transfer_price_wei: 0,
+                    transaction_hash: nft.mint_tx_hash.clone(),
+                    block_number: nft.mint_block,
+                    transfer_timestamp: nft.minted_at,
+                }]
             } else {
                 vec![]
             },
@@ -532,6 +754,14 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         self.simulate_latency_and_errors(&context, "GetIndexingStatus")
             .await?;
 
+        if let Some(ingestion) = &self.ingestion {
+            return Ok(Response::new(GetIndexingStatusResponse {
+                result: Some(get_indexing_status_response::Result::Status(
+                    ingestion.indexing_status().await,
+                )),
+            }));
+        }
+
         let indexing_status = IndexingStatusProto {
             current_block: 12345,
             latest_block: 12350,
@@ -558,6 +788,10 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
                 recent_errors: vec![],
                 database_size_bytes: 104857600, // 100MB
             }),
+            finalized_block: 12344,
+            confirmation_depth: 6,
+            reorg_count: 0,
+            reindex_job: None,
         };
 
         let response = GetIndexingStatusResponse {
@@ -589,11 +823,33 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         self.simulate_latency_and_errors(&context, "ReindexFromBlock")
             .await?;
 
-        let reindex_result = ReindexFromBlockSuccess {
-            reindex_job_id: Uuid::new_v4().to_string(),
-            start_block: req.start_block,
-            estimated_blocks_to_process: 1000,
-            estimated_completion_time: (Utc::now() + chrono::Duration::minutes(30)).timestamp(),
+        let reindex_result = match &self.ingestion {
+            Some(ingestion) => {
+                let job = ingestion
+                    .reindex(
+                        req.start_block,
+                        &req.contract_addresses,
+                        req.preserve_existing,
+                    )
+                    .await
+                    .map_err(|err| Status::failed_precondition(format!("{err:#}")))?;
+                ReindexFromBlockSuccess {
+                    reindex_job_id: job.id.clone(),
+                    start_block: job.start_block,
+                    estimated_blocks_to_process: job.blocks_remaining(),
+                    // Measured once the first batch lands
+                    estimated_completion_time: 0,
+                    job: Some(ingestion::reindex_job_proto(&job, Utc::now().timestamp())),
+                }
+            }
+            // The fixture chain has nothing to decode again
+            None => ReindexFromBlockSuccess {
+                reindex_job_id: Uuid::new_v4().to_string(),
+                start_block: req.start_block,
+                estimated_blocks_to_process: 1000,
+                estimated_completion_time: (Utc::now() + chrono::Duration::minutes(30)).timestamp(),
+                job: None,
+            },
         };
 
         let response = ReindexFromBlockResponse {
@@ -603,6 +859,43 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         Ok(Response::new(response))
     }
 
+    async fn cancel_reindex(
+        &self,
+        request: Request<CancelReindexRequest>,
+    ) -> Result<Response<CancelReindexResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "CancelReindex", "gRPC");
+
+            if let Err(err) = stub.check_crypto_features(&context) {
+                return Err(Status::permission_denied(err));
+            }
+        }
+
+        self.simulate_latency_and_errors(&context, "CancelReindex")
+            .await?;
+
+        let Some(ingestion) = &self.ingestion else {
+            return Err(Status::not_found(format!(
+                "Reindex job {} not found",
+                req.reindex_job_id
+            )));
+        };
+        let job = ingestion
+            .cancel_reindex(&req.reindex_job_id)
+            .await
+            .map_err(|err| Status::failed_precondition(format!("{err:#}")))?;
+
+        Ok(Response::new(CancelReindexResponse {
+            result: Some(cancel_reindex_response::Result::Job(
+                ingestion::reindex_job_proto(&job, Utc::now().timestamp()),
+            )),
+        }))
+    }
+
     async fn health(
         &self,
         request: Request<HealthRequest>,
@@ -610,13 +903,14 @@ impl indexer_service_server::IndexerService for IndexerGrpcService {
         let _req = request.into_inner();
         let context = self.create_context(None).await;
 
-        {
+        let health = {
             let stub = self.stub.lock().await;
             stub.log_request(&context, "Health", "gRPC");
-        }
+            stub.health_check()
+        };
 
         let response = HealthResponse {
-            status: "HEALTHY".to_string(),
+            status: health.as_str().to_string(),
             version: "0.1.0".to_string(),
             timestamp: Utc::now().timestamp(),
             details: HashMap::new(),
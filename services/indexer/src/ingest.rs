@@ -0,0 +1,1671 @@
+//! Ingestion of a JSON-RPC L3 node into the chain index
+//! Polls a node such as a local anvil or hardhat instance for new blocks and
+//! their logs, decodes `BunkerverseNFT` and NTC token events into canonical
+//! events and appends everything to a
+//! [`ChainIndex`](crate::chain_index::ChainIndex) that survives restarts
+
+use crate::chain_index::{
+    IndexedBlock, IndexedTransaction, IngestedBlock, ReindexJob, ReindexStatus, SharedChainIndex,
+};
+use crate::chain_store::{ChainIndexBatch, ChainIndexStore, ChainIndexWriter};
+use crate::config::IngestConfig;
+use crate::event_feed::{EventCursor, EventFeedFilter, EventSubscription};
+use anyhow::{anyhow, bail, Context as _, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+use smart_stubs::stub::SharedStub;
+use smart_stubs::world::{hex, ChainEvent, EventPayload, MintedToken, World};
+use smart_stubs::{Clock, SystemClock};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Longest wait for one JSON-RPC reply
+const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `BunkerverseNFT` mint, with the recipient and token ID indexed
+pub const NFT_MINTED_SIGNATURE: &str = "NFTMinted(address,uint256,string)";
+/// ERC-20 transfers carry the amount in data; ERC-721 transfers index the token ID
+pub const TRANSFER_SIGNATURE: &str = "Transfer(address,address,uint256)";
+/// `BunkerverseNFT` transfer between two wallets, emitted next to the ERC-721 `Transfer`
+pub const NFT_TRANSFERRED_SIGNATURE: &str = "NFTTransferred(address,address,uint256)";
+
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+/// Log as returned by `eth_getLogs`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcLog {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+    pub block_number: String,
+    pub block_hash: String,
+    pub transaction_hash: String,
+    pub log_index: String,
+    #[serde(default)]
+    pub removed: bool,
+}
+
+/// Block with its transactions as returned by `eth_getBlockByNumber`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcBlock {
+    pub number: String,
+    pub hash: String,
+    pub parent_hash: String,
+    pub timestamp: String,
+    pub gas_used: String,
+    pub gas_limit: String,
+    #[serde(default)]
+    pub size: Option<String>,
+    #[serde(default)]
+    pub transactions: Vec<RpcTransaction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransaction {
+    pub hash: String,
+    pub from: String,
+    /// `None` for contract creations
+    pub to: Option<String>,
+    pub value: String,
+    #[serde(default)]
+    pub gas_price: Option<String>,
+}
+
+/// Receipt as returned by `eth_getTransactionReceipt`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcReceipt {
+    pub gas_used: String,
+    /// `0x1` on success; absent before Byzantium
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+/// The JSON-RPC calls ingestion makes against an L3 node
+#[tonic::async_trait]
+pub trait ChainRpc: Send + Sync + fmt::Debug {
+    async fn block_number(&self) -> Result<u64>;
+
+    /// Block with full transactions, `None` past the node's head
+    async fn block(&self, number: u64) -> Result<Option<RpcBlock>>;
+
+    /// Logs of the `addresses` contracts in the inclusive block range
+    async fn logs(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        addresses: &[String],
+    ) -> Result<Vec<RpcLog>>;
+
+    async fn receipt(&self, transaction_hash: &str) -> Result<Option<RpcReceipt>>;
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcReply {
+    #[serde(default)]
+    result: serde_json::Value,
+    #[serde(default)]
+    error: Option<RpcReplyError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcReplyError {
+    code: i64,
+    message: String,
+}
+
+/// [`ChainRpc`] over HTTP JSON-RPC 2.0
+#[derive(Debug, Clone)]
+pub struct JsonRpcClient {
+    url: http02::Uri,
+    client: hyper014::Client<hyper014::client::HttpConnector>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl JsonRpcClient {
+    /// # Errors
+    /// Returns an error if `url` is not a valid URI
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: url
+                .parse()
+                .map_err(|err| anyhow!("invalid rpc_url '{url}': {err}"))?,
+            client: hyper014::Client::new(),
+            next_id: Arc::new(AtomicU64::new(1)),
+        })
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T> {
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+            "method": method,
+            "params": params,
+        });
+        let request = http02::Request::post(self.url.clone())
+            .header(http02::header::CONTENT_TYPE, "application/json")
+            .body(hyper014::Body::from(serde_json::to_vec(&body)?))?;
+
+        let exchange = async {
+            let response = self.client.request(request).await?;
+            let status = response.status();
+            let body = hyper014::body::to_bytes(response.into_body()).await?;
+            anyhow::Ok((status, body))
+        };
+        let (status, body) = tokio::time::timeout(RPC_TIMEOUT, exchange)
+            .await
+            .map_err(|_| anyhow!("{method} timed out after {RPC_TIMEOUT:?}"))?
+            .with_context(|| format!("calling {method} on {}", self.url))?;
+        if !status.is_success() {
+            bail!("{method} returned HTTP {status}");
+        }
+
+        let reply: RpcReply =
+            serde_json::from_slice(&body).with_context(|| format!("parsing {method} reply"))?;
+        if let Some(error) = reply.error {
+            bail!(
+                "{method} failed with code {}: {}",
+                error.code,
+                error.message
+            );
+        }
+        serde_json::from_value(reply.result).with_context(|| format!("parsing {method} result"))
+    }
+}
+
+#[tonic::async_trait]
+impl ChainRpc for JsonRpcClient {
+    async fn block_number(&self) -> Result<u64> {
+        let number: String = self.call("eth_blockNumber", serde_json::json!([])).await?;
+        quantity(&number)
+    }
+
+    async fn block(&self, number: u64) -> Result<Option<RpcBlock>> {
+        self.call(
+            "eth_getBlockByNumber",
+            serde_json::json!([format!("{number:#x}"), true]),
+        )
+        .await
+    }
+
+    async fn logs(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        addresses: &[String],
+    ) -> Result<Vec<RpcLog>> {
+        // The node reads a missing address as every contract
+        if addresses.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filter = serde_json::json!({
+            "fromBlock": format!("{from_block:#x}"),
+            "toBlock": format!("{to_block:#x}"),
+            "address": addresses,
+        });
+        self.call("eth_getLogs", serde_json::json!([filter])).await
+    }
+
+    async fn receipt(&self, transaction_hash: &str) -> Result<Option<RpcReceipt>> {
+        self.call(
+            "eth_getTransactionReceipt",
+            serde_json::json!([transaction_hash]),
+        )
+        .await
+    }
+}
+
+/// Lookups that turn chain addresses and token IDs into platform IDs
+pub trait ChainDirectory {
+    /// Player whose L3 wallet is `address`
+    fn player_for_address(&self, address: &str) -> Option<&str>;
+
+    /// NFT minted as `token_id` on `contract_address`
+    fn nft_for_token(&self, contract_address: &str, token_id: u64) -> Option<&str>;
+}
+
+impl ChainDirectory for World {
+    fn player_for_address(&self, address: &str) -> Option<&str> {
+        self.player_by_wallet(address)
+            .map(|player| player.player_id.as_str())
+    }
+
+    fn nft_for_token(&self, contract_address: &str, token_id: u64) -> Option<&str> {
+        self.nft_by_token(contract_address, token_id)
+            .map(|nft| nft.nft_id.as_str())
+    }
+}
+
+/// `0x`-prefixed Keccak-256 hash of an event signature, its first log topic
+#[must_use]
+pub fn event_topic(signature: &str) -> String {
+    format!("0x{}", hex(&Keccak256::digest(signature.as_bytes())))
+}
+
+fn nft_minted_topic() -> &'static str {
+    static TOPIC: OnceLock<String> = OnceLock::new();
+    TOPIC.get_or_init(|| event_topic(NFT_MINTED_SIGNATURE))
+}
+
+fn transfer_topic() -> &'static str {
+    static TOPIC: OnceLock<String> = OnceLock::new();
+    TOPIC.get_or_init(|| event_topic(TRANSFER_SIGNATURE))
+}
+
+fn nft_transferred_topic() -> &'static str {
+    static TOPIC: OnceLock<String> = OnceLock::new();
+    TOPIC.get_or_init(|| event_topic(NFT_TRANSFERRED_SIGNATURE))
+}
+
+/// "mint", "burn" or "transfer" depending on which side is the zero address
+fn transfer_type(from: &str, to: &str) -> &'static str {
+    match (from, to) {
+        (ZERO_ADDRESS, _) => "mint",
+        (_, ZERO_ADDRESS) => "burn",
+        _ => "transfer",
+    }
+}
+
+/// Decode a log into a canonical event
+///
+/// Returns `Ok(None)` for logs without a canonical counterpart, such as
+/// events of unrelated contracts. Wallets and tokens the directory does not
+/// know keep their chain address and a `<contract>:<token ID>` NFT ID.
+///
+/// # Errors
+/// Returns an error if a recognised event is malformed, carries a token ID
+/// beyond `u64` or an amount beyond `u128`
+pub fn decode_log(
+    log: &RpcLog,
+    block_timestamp: i64,
+    directory: &impl ChainDirectory,
+) -> Result<Option<ChainEvent>> {
+    let Some(topic0) = log.topics.first() else {
+        return Ok(None);
+    };
+    let contract_address = log.address.to_ascii_lowercase();
+    let player = |address: String| {
+        directory
+            .player_for_address(&address)
+            .map_or(address, str::to_string)
+    };
+    // Transfers leave the side that is the zero address empty
+    let side = |address: String| {
+        if address == ZERO_ADDRESS {
+            String::new()
+        } else {
+            player(address)
+        }
+    };
+    let nft = |token_id: u64| {
+        directory
+            .nft_for_token(&contract_address, token_id)
+            .map_or_else(|| format!("{contract_address}:{token_id}"), str::to_string)
+    };
+
+    let payload = if topic0.eq_ignore_ascii_case(nft_minted_topic()) && log.topics.len() == 3 {
+        let token_id = word_u64(&log.topics[2]).context("NFTMinted token ID")?;
+        let token = match directory.nft_for_token(&contract_address, token_id) {
+            Some(_) => None,
+            None => Some(MintedToken {
+                token_id,
+                token_uri: abi_string(&log.data).context("NFTMinted token URI")?,
+            }),
+        };
+        EventPayload::NftMinted {
+            nft_id: nft(token_id),
+            player_id: player(word_address(&log.topics[1])?),
+            token,
+        }
+    } else if topic0.eq_ignore_ascii_case(transfer_topic()) && log.topics.len() == 3 {
+        let from = word_address(&log.topics[1])?;
+        let to = word_address(&log.topics[2])?;
+        EventPayload::NtcTransfer {
+            transfer_type: transfer_type(&from, &to).to_string(),
+            from_player_id: side(from),
+            to_player_id: side(to),
+            amount_wei: word_u128(&log.data).context("Transfer amount")?,
+        }
+    } else if (topic0.eq_ignore_ascii_case(transfer_topic())
+        || topic0.eq_ignore_ascii_case(nft_transferred_topic()))
+        && log.topics.len() == 4
+    {
+        let from = word_address(&log.topics[1])?;
+        let to = word_address(&log.topics[2])?;
+        EventPayload::NftTransferred {
+            nft_id: nft(word_u64(&log.topics[3]).context("NFT transfer token ID")?),
+            transfer_type: transfer_type(&from, &to).to_string(),
+            from_player_id: side(from),
+            to_player_id: side(to),
+        }
+    } else {
+        return Ok(None);
+    };
+
+    let log_index = quantity(&log.log_index)?;
+    Ok(Some(ChainEvent {
+        event_id: event_id(&log.block_hash, log_index),
+        block_number: quantity(&log.block_number)?,
+        log_index,
+        contract_address,
+        transaction_hash: log.transaction_hash.clone(),
+        block_timestamp,
+        payload,
+    }))
+}
+
+/// Same ID whenever the same log is ingested, so re-indexing is idempotent
+fn event_id(block_hash: &str, log_index: u64) -> String {
+    let digest = Keccak256::digest(format!("{block_hash}:{log_index}").as_bytes());
+    let mut bytes = [0_u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_random_bytes(bytes)
+        .into_uuid()
+        .to_string()
+}
+
+fn strip_hex(value: &str) -> &str {
+    value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value)
+}
+
+/// Parse a JSON-RPC hex quantity such as `0x1a`
+fn quantity(value: &str) -> Result<u64> {
+    let digits = strip_hex(value);
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 16).with_context(|| format!("invalid quantity '{value}'"))
+}
+
+/// Parse a hex quantity of up to 128 bits as a decimal string
+fn decimal(value: &str) -> Result<String> {
+    let digits = strip_hex(value);
+    if digits.is_empty() {
+        return Ok("0".to_string());
+    }
+    u128::from_str_radix(digits, 16)
+        .map(|value| value.to_string())
+        .with_context(|| format!("invalid quantity '{value}'"))
+}
+
+/// Last 32 byte word of `value` as a `u128`, rejecting larger values
+fn word_u128(value: &str) -> Result<u128> {
+    let digits = strip_hex(value);
+    let word = digits
+        .get(digits.len().saturating_sub(64)..)
+        .filter(|word| word.len() == 64)
+        .ok_or_else(|| anyhow!("expected a 32 byte word, got '{value}'"))?;
+    let (high, low) = word.split_at(32);
+    if high.bytes().any(|digit| digit != b'0') {
+        bail!("value {value} does not fit in 128 bits");
+    }
+    u128::from_str_radix(low, 16).with_context(|| format!("invalid word '{value}'"))
+}
+
+/// Last 32 byte word of `value` as a `u64`, rejecting larger values
+fn word_u64(value: &str) -> Result<u64> {
+    let wide = word_u128(value)?;
+    u64::try_from(wide).map_err(|_| anyhow!("value {value} does not fit in 64 bits"))
+}
+
+/// Address held in the low 20 bytes of a 32 byte topic, lowercased
+fn word_address(topic: &str) -> Result<String> {
+    let digits = strip_hex(topic);
+    if digits.len() != 64 || !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        bail!("expected a 32 byte topic, got '{topic}'");
+    }
+    Ok(format!("0x{}", digits[24..].to_ascii_lowercase()))
+}
+
+/// ABI-decode log data holding a single dynamic `string`
+fn abi_string(data: &str) -> Result<String> {
+    let bytes = hex_bytes(strip_hex(data))?;
+    let word = |at: usize| -> Result<usize> {
+        let word = bytes
+            .get(at..at + 32)
+            .ok_or_else(|| anyhow!("data ends before byte {}", at + 32))?;
+        if word[..24].iter().any(|byte| *byte != 0) {
+            bail!("offset or length at byte {at} is too large");
+        }
+        Ok(word[24..]
+            .iter()
+            .fold(0_usize, |value, byte| (value << 8) | usize::from(*byte)))
+    };
+    let offset = word(0)?;
+    let length = word(offset)?;
+    let start = offset + 32;
+    let text = bytes
+        .get(start..start + length)
+        .ok_or_else(|| anyhow!("string of {length} bytes overruns the data"))?;
+    String::from_utf8(text.to_vec()).context("string is not UTF-8")
+}
+
+fn hex_bytes(digits: &str) -> Result<Vec<u8>> {
+    digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| anyhow!("invalid hex byte '{}'", String::from_utf8_lossy(pair)))
+        })
+        .collect()
+}
+
+fn indexed_block(block: &RpcBlock) -> Result<IndexedBlock> {
+    Ok(IndexedBlock {
+        number: quantity(&block.number)?,
+        hash: block.hash.clone(),
+        parent_hash: block.parent_hash.clone(),
+        timestamp: i64::try_from(quantity(&block.timestamp)?).context("block timestamp")?,
+        transaction_count: u32::try_from(block.transactions.len()).unwrap_or(u32::MAX),
+        gas_used: quantity(&block.gas_used)?,
+        gas_limit: quantity(&block.gas_limit)?,
+        size: block.size.as_deref().map_or(Ok(0), quantity)?,
+        undecoded_logs: 0,
+    })
+}
+
+/// Decode a fetched block, adding log decoding failures to `errors`
+///
+/// `receipts` follow the order of the block's transactions.
+fn ingest_block(
+    block: &RpcBlock,
+    receipts: &[RpcReceipt],
+    mut logs: Vec<RpcLog>,
+    directory: &World,
+    errors: &mut Vec<String>,
+) -> Result<IngestedBlock> {
+    let mut header = indexed_block(block)?;
+    let mut transactions = Vec::with_capacity(block.transactions.len());
+    for (transaction, receipt) in block.transactions.iter().zip(receipts) {
+        transactions.push(IndexedTransaction {
+            hash: transaction.hash.clone(),
+            block_number: header.number,
+            from_address: transaction.from.to_ascii_lowercase(),
+            to_address: transaction
+                .to
+                .as_deref()
+                .unwrap_or_default()
+                .to_ascii_lowercase(),
+            value: decimal(&transaction.value)?,
+            gas_price: transaction.gas_price.as_deref().map_or(Ok(0), quantity)?,
+            gas_used: quantity(&receipt.gas_used)?,
+            succeeded: receipt.status.as_deref().map_or(Ok(true), |status| {
+                quantity(status).map(|status| status == 1)
+            })?,
+            timestamp: header.timestamp,
+        });
+    }
+
+    logs.sort_by_key(|log| quantity(&log.log_index).unwrap_or(u64::MAX));
+    let mut events = Vec::with_capacity(logs.len());
+    let mut undecoded_logs = 0;
+    for log in &logs {
+        if !log.block_hash.eq_ignore_ascii_case(&header.hash) {
+            bail!(
+                "block {} changed from {} to {} while it was being indexed",
+                header.number,
+                log.block_hash,
+                header.hash
+            );
+        }
+        match decode_log(log, header.timestamp, directory) {
+            Ok(Some(event)) => events.push(event),
+            Ok(None) => undecoded_logs += 1,
+            Err(err) => {
+                undecoded_logs += 1;
+                let error = format!(
+                    "log {} of transaction {}: {err:#}",
+                    log.log_index, log.transaction_hash
+                );
+                warn!(block = header.number, error = %error, "Skipping undecodable log");
+                errors.push(error);
+            }
+        }
+    }
+
+    header.undecoded_logs = undecoded_logs;
+    Ok(IngestedBlock {
+        block: header,
+        transactions,
+        events,
+    })
+}
+
+/// What one [`ChainIngester::poll`] did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestPoll {
+    pub blocks: u64,
+    pub events: u64,
+    /// Events dropped because the node switched to another branch
+    pub retracted: u64,
+    /// Head block the node reported
+    pub chain_head: u64,
+    /// Whether the index reached the node's head
+    pub caught_up: bool,
+}
+
+/// Background task that keeps a [`ChainIndex`](crate::chain_index::ChainIndex) in step with an L3 node
+#[derive(Debug, Clone)]
+pub struct ChainIngester {
+    stub: SharedStub,
+    rpc: Arc<dyn ChainRpc>,
+    index: SharedChainIndex,
+    writer: Option<Arc<ChainIndexWriter>>,
+    clock: Arc<dyn Clock>,
+    config: IngestConfig,
+    /// Notified whenever the index changes, to wake event subscribers
+    changes: Arc<watch::Sender<()>>,
+}
+
+impl ChainIngester {
+    /// Ingester that keeps the index in memory only
+    ///
+    /// The stub's fixture world resolves wallets and tokens to platform IDs.
+    #[must_use]
+    pub fn new(
+        stub: SharedStub,
+        rpc: Arc<dyn ChainRpc>,
+        index: SharedChainIndex,
+        config: IngestConfig,
+    ) -> Self {
+        Self {
+            stub,
+            rpc,
+            index,
+            writer: None,
+            clock: Arc::new(SystemClock),
+            config,
+            changes: Arc::new(watch::channel(()).0),
+        }
+    }
+
+    /// Write every change to the index to `store`
+    ///
+    /// Only the blocks a change touched are written, after the index is
+    /// unlocked.
+    #[must_use]
+    pub fn with_store(mut self, store: Arc<dyn ChainIndexStore>) -> Self {
+        self.writer = Some(Arc::new(ChainIndexWriter::new(store)));
+        self
+    }
+
+    #[cfg(test)]
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Stream indexed events after `resume_after` as this ingester indexes them
+    #[must_use]
+    pub fn subscribe(
+        &self,
+        filter: EventFeedFilter,
+        resume_after: Option<EventCursor>,
+        last_retraction: u64,
+    ) -> EventSubscription {
+        EventSubscription::open(
+            self.index.clone(),
+            self.changes.subscribe(),
+            filter,
+            resume_after,
+            last_retraction,
+        )
+    }
+
+    /// Index up to `max_blocks_per_poll` blocks past the current head
+    ///
+    /// Blocks are fetched before the index is locked and appended together,
+    /// so readers never see part of a poll.
+    ///
+    /// # Errors
+    /// Returns an error if the node cannot be reached, a block is missing or
+    /// malformed, or the chain changed under the poll. Nothing is appended
+    /// then and the next poll retries the same blocks.
+    pub async fn poll(&self) -> Result<IngestPoll> {
+        let chain_head = self.rpc.block_number().await?;
+        let mut poll = IngestPoll {
+            chain_head,
+            ..IngestPoll::default()
+        };
+        if let Some(fork_block) = self.find_fork().await? {
+            poll.retracted = self.roll_back(fork_block).await?;
+        }
+
+        let from_block = self.index.read().await.next_block(self.config.start_block);
+        if from_block > chain_head {
+            poll.caught_up = true;
+            self.index
+                .write()
+                .await
+                .record_sync(chain_head, self.clock.now());
+            // The node's head decides which blocks are final
+            self.changes.send_replace(());
+            // Retry anything an earlier poll failed to write
+            self.flush().await?;
+            return Ok(poll);
+        }
+        let to_block = chain_head.min(from_block + self.config.max_blocks_per_poll - 1);
+        let ingested = self
+            .fetch_blocks(from_block, to_block, &self.config.contract_addresses)
+            .await?;
+
+        let mut index = self.index.write().await;
+        for block in ingested {
+            poll.blocks += 1;
+            poll.events += block.events.len() as u64;
+            index.append(block)?;
+        }
+        poll.caught_up = to_block == chain_head;
+        index.record_sync(chain_head, self.clock.now());
+        self.queue(|| ChainIndexBatch {
+            blocks: index.ingested_blocks(from_block, to_block),
+            meta: index.meta(),
+            ..ChainIndexBatch::default()
+        });
+        drop(index);
+        self.changes.send_replace(());
+        self.flush().await?;
+        Ok(poll)
+    }
+
+    /// Fetch and decode the inclusive block range, checking it is one branch
+    async fn fetch_blocks(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        contract_addresses: &[String],
+    ) -> Result<Vec<IngestedBlock>> {
+        let mut logs_by_block: BTreeMap<u64, Vec<RpcLog>> = BTreeMap::new();
+        for log in self
+            .rpc
+            .logs(from_block, to_block, contract_addresses)
+            .await?
+        {
+            if !log.removed {
+                logs_by_block
+                    .entry(quantity(&log.block_number)?)
+                    .or_default()
+                    .push(log);
+            }
+        }
+
+        let mut fetched = Vec::new();
+        for number in from_block..=to_block {
+            let block = self
+                .rpc
+                .block(number)
+                .await?
+                .ok_or_else(|| anyhow!("node has no block {number}"))?;
+            let mut receipts = Vec::with_capacity(block.transactions.len());
+            for transaction in &block.transactions {
+                receipts.push(
+                    self.rpc
+                        .receipt(&transaction.hash)
+                        .await?
+                        .ok_or_else(|| anyhow!("node has no receipt for {}", transaction.hash))?,
+                );
+            }
+            let logs = logs_by_block.remove(&number).unwrap_or_default();
+            fetched.push((block, receipts, logs));
+        }
+
+        // Decoded without awaiting, so the world is not held across the RPCs
+        let mut errors = Vec::new();
+        let decoded = {
+            let directory = self.stub.lock().await.world();
+            fetched
+                .into_iter()
+                .map(|(block, receipts, logs)| {
+                    ingest_block(&block, &receipts, logs, &directory, &mut errors)
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        if !errors.is_empty() {
+            let mut index = self.index.write().await;
+            for error in errors {
+                index.record_error(error);
+            }
+        }
+        let ingested = decoded?;
+
+        // Only the first block can then be refused, before anything is appended
+        if let Some(pair) = ingested
+            .windows(2)
+            .find(|pair| pair[1].block.parent_hash != pair[0].block.hash)
+        {
+            bail!(
+                "block {} changed while blocks after it were being indexed",
+                pair[0].block.number
+            );
+        }
+        Ok(ingested)
+    }
+
+    /// Start a reindex job from `start_block` to the indexed head
+    ///
+    /// The job is stored before it returns; run it with
+    /// [`ChainIngester::spawn_reindex`].
+    ///
+    /// # Errors
+    /// Returns an error if a job is already running, `start_block` is not
+    /// indexed or the job cannot be stored
+    pub async fn start_reindex(
+        &self,
+        start_block: u64,
+        contract_addresses: &[String],
+        preserve_existing: bool,
+    ) -> Result<ReindexJob> {
+        let mut index = self.index.write().await;
+        let job = index
+            .start_reindex(
+                uuid::Uuid::new_v4().to_string(),
+                start_block,
+                contract_addresses
+                    .iter()
+                    .map(|address| address.to_ascii_lowercase())
+                    .collect(),
+                preserve_existing,
+                self.clock.now(),
+            )?
+            .clone();
+        self.queue(|| ChainIndexBatch {
+            meta: index.meta(),
+            ..ChainIndexBatch::default()
+        });
+        drop(index);
+        self.flush().await?;
+        Ok(job)
+    }
+
+    /// Stop the running reindex job `job_id` after its current batch
+    ///
+    /// # Errors
+    /// Returns an error if `job_id` is not running or the job cannot be stored
+    pub async fn cancel_reindex(&self, job_id: &str) -> Result<ReindexJob> {
+        let mut index = self.index.write().await;
+        let job = index.cancel_reindex(job_id, self.clock.now())?.clone();
+        self.queue(|| ChainIndexBatch {
+            meta: index.meta(),
+            ..ChainIndexBatch::default()
+        });
+        drop(index);
+        self.flush().await?;
+        Ok(job)
+    }
+
+    /// Reindex the next `max_blocks_per_poll` blocks of job `job_id` and
+    /// checkpoint it
+    ///
+    /// Blocks are fetched without holding the index, so the live tail keeps
+    /// polling meanwhile. Returns the job's progress, or `None` once it is
+    /// no longer running, including when it was cancelled during the batch.
+    ///
+    /// # Errors
+    /// Returns an error if the blocks cannot be fetched or the index cannot
+    /// be stored; a batch that was not fetched is retried from the last
+    /// checkpoint.
+    pub async fn reindex_step(&self, job_id: &str) -> Result<Option<ReindexJob>> {
+        let Some(job) = self
+            .index
+            .read()
+            .await
+            .reindex_job()
+            .filter(|job| job.id == job_id && job.status == ReindexStatus::Running)
+            .cloned()
+        else {
+            return Ok(None);
+        };
+        let to_block = job
+            .target_block
+            .min(job.next_block + self.config.max_blocks_per_poll - 1);
+        let contract_addresses = if job.contract_addresses.is_empty() {
+            &self.config.contract_addresses
+        } else {
+            &job.contract_addresses
+        };
+        let reindexed = self
+            .fetch_blocks(job.next_block, to_block, contract_addresses)
+            .await?;
+
+        let mut index = self.index.write().await;
+        if !index.apply_reindex(&job.id, reindexed, to_block, self.clock.now()) {
+            return Ok(None);
+        }
+        self.queue(|| ChainIndexBatch {
+            blocks: index.ingested_blocks(job.next_block, to_block),
+            meta: index.meta(),
+            ..ChainIndexBatch::default()
+        });
+        let progress = index.reindex_job().cloned();
+        drop(index);
+        self.changes.send_replace(());
+        self.flush().await?;
+        Ok(progress)
+    }
+
+    /// Run the saved reindex job, if any, until it completes or is cancelled
+    ///
+    /// Failed batches are retried after `poll_interval`.
+    pub fn spawn_reindex(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let Some(job_id) = self
+                .index
+                .write()
+                .await
+                .resume_reindex(self.clock.now())
+                .map(|job| job.id.clone())
+            else {
+                return;
+            };
+            info!(job_id = %job_id, "Reindexing");
+            loop {
+                match self.reindex_step(&job_id).await {
+                    Ok(Some(job)) if job.status == ReindexStatus::Running => continue,
+                    Ok(Some(job)) => {
+                        info!(job_id = %job.id, events = job.events_reindexed, "Reindex completed");
+                        return;
+                    }
+                    Ok(None) => {
+                        info!(job_id = %job_id, "Reindex stopped");
+                        return;
+                    }
+                    Err(err) => {
+                        warn!(job_id = %job_id, error = %format!("{err:#}"), "Reindex batch failed");
+                        self.index
+                            .write()
+                            .await
+                            .record_reindex_error(&job_id, format!("{err:#}"));
+                    }
+                }
+                tokio::time::sleep(self.config.poll_interval()).await;
+            }
+        })
+    }
+
+    /// First indexed block the node no longer has, if the indexed head left
+    /// the canonical chain
+    ///
+    /// Walks back from the head comparing hashes until the branches agree.
+    async fn find_fork(&self) -> Result<Option<u64>> {
+        let mut fork_block = None;
+        loop {
+            let indexed = {
+                let index = self.index.read().await;
+                let number = fork_block.map_or_else(
+                    || index.head().map(|head| head.number),
+                    |fork: u64| fork.checked_sub(1),
+                );
+                number
+                    .and_then(|number| index.block(number))
+                    .map(|block| (block.number, block.hash.clone()))
+            };
+            let Some((number, hash)) = indexed else {
+                // Every indexed block is orphaned
+                return Ok(fork_block);
+            };
+            match self.rpc.block(number).await? {
+                Some(block) if block.hash.eq_ignore_ascii_case(&hash) => return Ok(fork_block),
+                _ => fork_block = Some(number),
+            }
+        }
+    }
+
+    /// Roll the index back to `fork_block` so the new branch is indexed from there
+    async fn roll_back(&self, fork_block: u64) -> Result<u64> {
+        let mut index = self.index.write().await;
+        let head = index.head().map_or(fork_block, |head| head.number);
+        let finalized = index.finalized_block(self.config.confirmations);
+        let retracted = index.roll_back_to(fork_block, self.clock.now());
+        let depth = head - fork_block + 1;
+        warn!(
+            fork_block,
+            depth,
+            retracted = retracted.len(),
+            "Chain reorganization, rolling back the index"
+        );
+        if finalized.is_some_and(|finalized| fork_block <= finalized) {
+            index.record_error(format!(
+                "reorganization of {depth} blocks from block {fork_block} retracted events past the confirmation depth of {}",
+                self.config.confirmations
+            ));
+        }
+        let retracted_count = retracted.len() as u64;
+        self.queue(|| ChainIndexBatch {
+            truncate_from: Some(fork_block),
+            blocks: vec![],
+            retractions: retracted,
+            meta: index.meta(),
+        });
+        drop(index);
+        self.changes.send_replace(());
+        self.flush().await?;
+        Ok(retracted_count)
+    }
+
+    /// Queue the batch `changes` builds for the store, if there is one
+    fn queue(&self, changes: impl FnOnce() -> ChainIndexBatch) {
+        if let Some(writer) = &self.writer {
+            writer.queue(changes());
+        }
+    }
+
+    /// Write the queued batches, outside the index lock
+    async fn flush(&self) -> Result<()> {
+        match &self.writer {
+            Some(writer) => writer.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Poll until the runtime shuts down, waiting `poll_interval` once caught up
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let interval = self.config.poll_interval();
+            loop {
+                match self.poll().await {
+                    Ok(poll) if !poll.caught_up => continue,
+                    Ok(_) => {}
+                    Err(err) => {
+                        warn!(rpc_url = %self.config.rpc_url, error = %format!("{err:#}"), "Chain ingestion failed");
+                        self.index.write().await.record_error(format!("{err:#}"));
+                    }
+                }
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_index::ChainIndex;
+    use crate::chain_store::{FileChainStore, RedbChainStore};
+    use smart_stubs::stub::ServiceStub;
+    use smart_stubs::{Dataset, ManualClock, StubConfiguration};
+    use std::sync::Mutex;
+
+    const NOW: i64 = 1_800_000_000;
+    const NFT_CONTRACT: &str = "0x5fbdb2315678afecb367f032d93f642f64180aa3";
+    const TOKEN_CONTRACT: &str = "0xe7f1725e7734ce288f8367e1bb143e90bb3f0512";
+
+    fn topic_for(address: &str) -> String {
+        format!("0x{:0>64}", strip_hex(address))
+    }
+
+    fn word(value: u64) -> String {
+        format!("0x{value:064x}")
+    }
+
+    /// ABI encoding of a lone `string` argument
+    fn string_data(text: &str) -> String {
+        let padded = text.len().div_ceil(32) * 32;
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(padded, 0);
+        format!("0x{:064x}{:064x}{}", 32, text.len(), hex(&bytes))
+    }
+
+    fn log(
+        block_number: u64,
+        log_index: u64,
+        address: &str,
+        topics: Vec<String>,
+        data: String,
+    ) -> RpcLog {
+        RpcLog {
+            address: address.to_string(),
+            topics,
+            data,
+            block_number: format!("{block_number:#x}"),
+            block_hash: block_hash(block_number),
+            transaction_hash: format!("0x{:064x}", block_number * 100 + log_index),
+            log_index: format!("{log_index:#x}"),
+            removed: false,
+        }
+    }
+
+    fn block_hash(number: u64) -> String {
+        format!("0x{:064x}", 0xb10c_0000 + number)
+    }
+
+    fn mint_log(block_number: u64, log_index: u64, to: &str, token_id: u64) -> RpcLog {
+        log(
+            block_number,
+            log_index,
+            NFT_CONTRACT,
+            vec![
+                event_topic(NFT_MINTED_SIGNATURE),
+                topic_for(to),
+                word(token_id),
+            ],
+            string_data("ipfs://bafy/robot.json"),
+        )
+    }
+
+    /// ERC-721 `Transfer`, or the contract's `NFTTransferred` with the same topics
+    fn nft_transfer_log(
+        block_number: u64,
+        log_index: u64,
+        signature: &str,
+        from: &str,
+        to: &str,
+        token_id: u64,
+    ) -> RpcLog {
+        log(
+            block_number,
+            log_index,
+            NFT_CONTRACT,
+            vec![
+                event_topic(signature),
+                topic_for(from),
+                topic_for(to),
+                word(token_id),
+            ],
+            "0x".to_string(),
+        )
+    }
+
+    fn ntc_log(block_number: u64, log_index: u64, from: &str, to: &str, amount: u64) -> RpcLog {
+        log(
+            block_number,
+            log_index,
+            TOKEN_CONTRACT,
+            vec![
+                event_topic(TRANSFER_SIGNATURE),
+                topic_for(from),
+                topic_for(to),
+            ],
+            word(amount),
+        )
+    }
+
+    /// Node serving a chain of empty-transaction blocks and their logs, optionally forked
+    #[derive(Debug, Default)]
+    struct FakeNode {
+        head: u64,
+        logs: Vec<RpcLog>,
+        /// Block whose header hash differs from the one its logs carry
+        rewritten: Mutex<Option<u64>>,
+        /// Competing branch the node has switched to
+        fork: Mutex<Option<FakeFork>>,
+    }
+
+    #[derive(Debug)]
+    struct FakeFork {
+        from_block: u64,
+        head: u64,
+        logs: Vec<RpcLog>,
+    }
+
+    impl FakeNode {
+        fn head(&self) -> u64 {
+            self.fork
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map_or(self.head, |fork| fork.head)
+        }
+
+        fn hash_of(&self, number: u64) -> String {
+            match &*self.fork.lock().unwrap() {
+                Some(fork) if number >= fork.from_block => {
+                    format!("0x{:064x}", 0xf0_0000 + number)
+                }
+                _ => block_hash(number),
+            }
+        }
+    }
+
+    #[tonic::async_trait]
+    impl ChainRpc for FakeNode {
+        async fn block_number(&self) -> Result<u64> {
+            Ok(self.head())
+        }
+
+        async fn block(&self, number: u64) -> Result<Option<RpcBlock>> {
+            if number > self.head() {
+                return Ok(None);
+            }
+            let hash = if *self.rewritten.lock().unwrap() == Some(number) {
+                format!("0x{:064x}", 0xdead)
+            } else {
+                self.hash_of(number)
+            };
+            Ok(Some(RpcBlock {
+                number: format!("{number:#x}"),
+                hash,
+                parent_hash: number
+                    .checked_sub(1)
+                    .map_or_else(|| word(0), |parent| self.hash_of(parent)),
+                timestamp: format!("{:#x}", NOW + number as i64),
+                gas_used: "0x5208".to_string(),
+                gas_limit: "0x1c9c380".to_string(),
+                size: Some("0x260".to_string()),
+                transactions: vec![],
+            }))
+        }
+
+        async fn logs(
+            &self,
+            from_block: u64,
+            to_block: u64,
+            addresses: &[String],
+        ) -> Result<Vec<RpcLog>> {
+            let fork = self.fork.lock().unwrap();
+            let fork_block = fork.as_ref().map_or(u64::MAX, |fork| fork.from_block);
+            let branch: Vec<RpcLog> = fork.iter().flat_map(|fork| fork.logs.clone()).collect();
+            drop(fork);
+            Ok(self
+                .logs
+                .iter()
+                .filter(|log| quantity(&log.block_number).unwrap() < fork_block)
+                .chain(&branch)
+                .filter(|log| {
+                    let number = quantity(&log.block_number).unwrap();
+                    (from_block..=to_block).contains(&number) && addresses.contains(&log.address)
+                })
+                .map(|log| RpcLog {
+                    block_hash: self.hash_of(quantity(&log.block_number).unwrap()),
+                    ..log.clone()
+                })
+                .collect())
+        }
+
+        async fn receipt(&self, _transaction_hash: &str) -> Result<Option<RpcReceipt>> {
+            Ok(None)
+        }
+    }
+
+    /// Ingestion of both test contracts
+    fn ingest_config() -> IngestConfig {
+        IngestConfig {
+            enabled: true,
+            contract_addresses: vec![NFT_CONTRACT.to_string(), TOKEN_CONTRACT.to_string()],
+            ..IngestConfig::default()
+        }
+    }
+
+    fn stub() -> SharedStub {
+        let mut config = StubConfiguration::for_service("indexer-service-stub", 8082);
+        config.data.dataset = Dataset::Minimal;
+        ServiceStub::new(config).into_shared()
+    }
+
+    #[test]
+    fn test_transfer_signature_hashes_to_the_erc20_topic() {
+        assert_eq!(
+            event_topic(TRANSFER_SIGNATURE),
+            "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+    }
+
+    #[test]
+    fn test_mints_resolve_wallets_and_keep_unknown_tokens() {
+        let world = World::generate(Dataset::Minimal, 42);
+        let player = &world.players[0];
+
+        let event = decode_log(
+            &mint_log(7, 2, &player.l3_wallet_address, 9_999),
+            NOW,
+            &world,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(event.block_number, 7);
+        assert_eq!(event.log_index, 2);
+        assert_eq!(event.contract_address, NFT_CONTRACT);
+        assert_eq!(
+            event.payload,
+            EventPayload::NftMinted {
+                nft_id: format!("{NFT_CONTRACT}:9999"),
+                player_id: player.player_id.clone(),
+                token: Some(MintedToken {
+                    token_id: 9_999,
+                    token_uri: "ipfs://bafy/robot.json".to_string(),
+                }),
+            }
+        );
+        // Re-ingesting the same log yields the same event
+        let again = decode_log(
+            &mint_log(7, 2, &player.l3_wallet_address, 9_999),
+            NOW,
+            &world,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(again.event_id, event.event_id);
+
+        let nft = &world.nfts[0];
+        let known = decode_log(
+            &mint_log(
+                7,
+                3,
+                "0x00000000000000000000000000000000000000aa",
+                nft.token_id,
+            ),
+            NOW,
+            &ChainDirectoryOverride {
+                world: &world,
+                contract: NFT_CONTRACT,
+            },
+        )
+        .unwrap()
+        .unwrap();
+        assert!(matches!(
+            known.payload,
+            EventPayload::NftMinted { ref nft_id, ref player_id, token: None }
+                if nft_id == &nft.nft_id
+                    && player_id == "0x00000000000000000000000000000000000000aa"
+        ));
+    }
+
+    /// The fixture world with every NFT deployed at `contract`
+    struct ChainDirectoryOverride<'a> {
+        world: &'a World,
+        contract: &'a str,
+    }
+
+    impl ChainDirectory for ChainDirectoryOverride<'_> {
+        fn player_for_address(&self, address: &str) -> Option<&str> {
+            self.world.player_for_address(address)
+        }
+
+        fn nft_for_token(&self, contract_address: &str, token_id: u64) -> Option<&str> {
+            (contract_address == self.contract)
+                .then(|| self.world.nfts.iter().find(|nft| nft.token_id == token_id))
+                .flatten()
+                .map(|nft| nft.nft_id.as_str())
+        }
+    }
+
+    #[test]
+    fn test_token_and_nft_transfers_decode() {
+        let world = World::generate(Dataset::Minimal, 42);
+        let wallet = &world.players[1].l3_wallet_address;
+
+        let mint = decode_log(&ntc_log(3, 0, ZERO_ADDRESS, wallet, 5), NOW, &world)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            mint.payload,
+            EventPayload::NtcTransfer {
+                from_player_id: String::new(),
+                to_player_id: world.players[1].player_id.clone(),
+                amount_wei: 5,
+                transfer_type: "mint".to_string(),
+            }
+        );
+        assert_eq!(mint.payload.player_id(), world.players[1].player_id);
+
+        let other = "0x00000000000000000000000000000000000000bb";
+        for signature in [TRANSFER_SIGNATURE, NFT_TRANSFERRED_SIGNATURE] {
+            let transfer = decode_log(
+                &nft_transfer_log(3, 1, signature, wallet, other, 12),
+                NOW,
+                &world,
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(
+                transfer.payload,
+                EventPayload::NftTransferred {
+                    nft_id: format!("{NFT_CONTRACT}:12"),
+                    from_player_id: world.players[1].player_id.clone(),
+                    to_player_id: other.to_string(),
+                    transfer_type: "transfer".to_string(),
+                }
+            );
+        }
+        let burn = decode_log(
+            &nft_transfer_log(3, 2, TRANSFER_SIGNATURE, wallet, ZERO_ADDRESS, 12),
+            NOW,
+            &world,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(matches!(
+            burn.payload,
+            EventPayload::NftTransferred { ref to_player_id, ref transfer_type, .. }
+                if to_player_id.is_empty() && transfer_type == "burn"
+        ));
+
+        // 100 NTC is past 2^64 wei
+        let mut large = ntc_log(3, 3, wallet, ZERO_ADDRESS, 0);
+        large.data = format!("0x{:064x}", 100 * u128::from(smart_stubs::world::NTC_WEI));
+        assert!(matches!(
+            decode_log(&large, NOW, &world).unwrap().unwrap().payload,
+            EventPayload::NtcTransfer { amount_wei, .. }
+                if amount_wei == 100_000_000_000_000_000_000
+        ));
+
+        let mut too_large = ntc_log(3, 4, wallet, ZERO_ADDRESS, 0);
+        too_large.data = format!("0x{}", "f".repeat(64));
+        assert!(decode_log(&too_large, NOW, &world).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_nft_transfers_move_ownership_in_the_projections() {
+        let alice = "0x00000000000000000000000000000000000000aa";
+        let bob = "0x00000000000000000000000000000000000000bb";
+        let node = Arc::new(FakeNode {
+            head: 2,
+            logs: vec![
+                mint_log(1, 0, alice, 7),
+                nft_transfer_log(1, 1, TRANSFER_SIGNATURE, ZERO_ADDRESS, alice, 7),
+                nft_transfer_log(2, 0, TRANSFER_SIGNATURE, alice, bob, 7),
+                nft_transfer_log(2, 1, NFT_TRANSFERRED_SIGNATURE, alice, bob, 7),
+            ],
+            ..FakeNode::default()
+        });
+        let index = ChainIndex::new(NOW).into_shared();
+        let config = IngestConfig {
+            start_block: 1,
+            ..ingest_config()
+        };
+        let ingester = ChainIngester::new(stub(), node, index.clone(), config);
+
+        let poll = ingester.poll().await.unwrap();
+        assert_eq!((poll.blocks, poll.events), (2, 4));
+        let index = index.read().await;
+        assert_eq!(index.undecoded_logs(), 0);
+        let projections = index.projections();
+        let nft_id = format!("{NFT_CONTRACT}:7");
+        assert!(projections.player(alice).unwrap().owned_nft_ids.is_empty());
+        assert!(projections
+            .player(bob)
+            .unwrap()
+            .owned_nft_ids
+            .contains(&nft_id));
+    }
+
+    #[tokio::test]
+    async fn test_ingester_catches_up_in_batches_and_resumes_from_the_saved_index() {
+        let node = Arc::new(FakeNode {
+            head: 4,
+            logs: vec![
+                mint_log(1, 0, "0x00000000000000000000000000000000000000aa", 1),
+                // Transfers of tokens other than NTC are not fetched
+                RpcLog {
+                    address: "0x00000000000000000000000000000000000000cc".to_string(),
+                    ..ntc_log(
+                        2,
+                        0,
+                        ZERO_ADDRESS,
+                        "0x00000000000000000000000000000000000000aa",
+                        9,
+                    )
+                },
+                ntc_log(
+                    3,
+                    0,
+                    ZERO_ADDRESS,
+                    "0x00000000000000000000000000000000000000aa",
+                    7,
+                ),
+                ntc_log(
+                    3,
+                    1,
+                    "0x00000000000000000000000000000000000000aa",
+                    ZERO_ADDRESS,
+                    2,
+                ),
+                log(4, 0, TOKEN_CONTRACT, vec![word(1)], "0x".to_string()),
+            ],
+            ..FakeNode::default()
+        });
+        let path = std::env::temp_dir()
+            .join(format!("chain-ingest-{}", uuid::Uuid::new_v4()))
+            .join("index.jsonl");
+        let store: Arc<dyn ChainIndexStore> = Arc::new(FileChainStore::new(&path));
+        let config = IngestConfig {
+            start_block: 1,
+            max_blocks_per_poll: 2,
+            ..ingest_config()
+        };
+        let index = ChainIndex::new(NOW).into_shared();
+        let ingester = ChainIngester::new(stub(), node.clone(), index.clone(), config.clone())
+            .with_store(store.clone())
+            .with_clock(Arc::new(ManualClock::new(NOW)));
+
+        let first = ingester.poll().await.unwrap();
+        assert_eq!((first.blocks, first.events, first.caught_up), (2, 1, false));
+        let second = ingester.poll().await.unwrap();
+        assert_eq!(
+            (second.blocks, second.events, second.caught_up),
+            (2, 2, true)
+        );
+        assert_eq!(ingester.poll().await.unwrap().blocks, 0);
+
+        let saved = ChainIndex::load(store.as_ref()).unwrap().unwrap();
+        assert_eq!(saved.head().unwrap().number, 4);
+        assert_eq!(saved.events().len(), 3);
+        assert_eq!(saved.undecoded_logs(), 1);
+        assert_eq!(saved.events_in_blocks(3, 3)[1].log_index, 1);
+        assert_eq!(saved.last_synced_at(), NOW);
+
+        // A restart picks up after the saved head
+        let node = Arc::new(FakeNode {
+            head: 5,
+            logs: vec![ntc_log(
+                5,
+                0,
+                ZERO_ADDRESS,
+                "0x00000000000000000000000000000000000000bb",
+                1,
+            )],
+            ..FakeNode::default()
+        });
+        let ingester = ChainIngester::new(stub(), node, saved.into_shared(), config);
+        let resumed = ingester.poll().await.unwrap();
+        assert_eq!((resumed.blocks, resumed.events), (1, 1));
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_blocks_that_change_mid_poll_are_not_indexed() {
+        let node = Arc::new(FakeNode {
+            head: 2,
+            logs: vec![ntc_log(
+                2,
+                0,
+                ZERO_ADDRESS,
+                "0x00000000000000000000000000000000000000aa",
+                7,
+            )],
+            rewritten: Mutex::new(Some(2)),
+            ..FakeNode::default()
+        });
+        let index = ChainIndex::new(NOW).into_shared();
+        let ingester = ChainIngester::new(stub(), node.clone(), index.clone(), ingest_config());
+
+        assert!(ingester.poll().await.is_err());
+        assert!(index.read().await.head().is_none());
+
+        *node.rewritten.lock().unwrap() = None;
+        assert_eq!(ingester.poll().await.unwrap().blocks, 3);
+    }
+
+    #[tokio::test]
+    async fn test_reorgs_retract_the_orphaned_branch_and_index_the_new_one() {
+        let wallet = "0x00000000000000000000000000000000000000aa";
+        let node = Arc::new(FakeNode {
+            head: 4,
+            logs: vec![
+                ntc_log(2, 0, ZERO_ADDRESS, wallet, 1),
+                ntc_log(3, 0, ZERO_ADDRESS, wallet, 2),
+                ntc_log(4, 0, ZERO_ADDRESS, wallet, 3),
+            ],
+            ..FakeNode::default()
+        });
+        let path = std::env::temp_dir()
+            .join(format!("chain-reorg-{}", uuid::Uuid::new_v4()))
+            .join("index.redb");
+        let store: Arc<dyn ChainIndexStore> = Arc::new(RedbChainStore::open(&path).unwrap());
+        let index = ChainIndex::new(NOW).into_shared();
+        let config = IngestConfig {
+            start_block: 1,
+            confirmations: 1,
+            ..ingest_config()
+        };
+        let ingester = ChainIngester::new(stub(), node.clone(), index.clone(), config)
+            .with_store(store.clone());
+        ingester.poll().await.unwrap();
+        assert_eq!(index.read().await.finalized_block(1), Some(3));
+
+        // Blocks 4 onwards are replaced by a longer branch with other transfers
+        *node.fork.lock().unwrap() = Some(FakeFork {
+            from_block: 4,
+            head: 5,
+            logs: vec![
+                ntc_log(4, 0, ZERO_ADDRESS, wallet, 30),
+                ntc_log(5, 0, ZERO_ADDRESS, wallet, 40),
+            ],
+        });
+        let poll = ingester.poll().await.unwrap();
+        assert_eq!((poll.retracted, poll.blocks, poll.events), (1, 2, 2));
+
+        let index = index.read().await;
+        let retracted: Vec<_> = index.retractions_since(0).collect();
+        assert_eq!(retracted.len(), 1);
+        assert_eq!(retracted[0].block_hash, block_hash(4));
+        assert!(matches!(
+            retracted[0].event.payload,
+            EventPayload::NtcTransfer { amount_wei: 3, .. }
+        ));
+        let amounts: Vec<_> = index
+            .events()
+            .iter()
+            .map(|event| match event.payload {
+                EventPayload::NtcTransfer { amount_wei, .. } => amount_wei,
+                _ => 0,
+            })
+            .collect();
+        assert_eq!(amounts, [1, 2, 30, 40]);
+        assert_eq!(index.head().unwrap().hash, node.hash_of(5));
+        assert_eq!(index.reorgs(), 1);
+        // Block 4 was not yet final, so the reorg is not an error
+        assert_eq!(index.recent_errors().count(), 0);
+
+        // The store dropped the orphaned block and kept the retraction
+        let stored = ChainIndex::load(store.as_ref()).unwrap().unwrap();
+        assert_eq!(stored.events(), index.events());
+        assert_eq!(stored.head(), index.head());
+        assert_eq!(stored.retractions_since(0).count(), 1);
+        assert_eq!(stored.reorgs(), 1);
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[tokio::test]
+    async fn test_reorgs_past_the_confirmation_depth_are_reported() {
+        let node = Arc::new(FakeNode {
+            head: 5,
+            ..FakeNode::default()
+        });
+        let index = ChainIndex::new(NOW).into_shared();
+        let config = IngestConfig {
+            confirmations: 2,
+            ..ingest_config()
+        };
+        let ingester = ChainIngester::new(stub(), node.clone(), index.clone(), config);
+        ingester.poll().await.unwrap();
+
+        // The node was restarted on a shorter chain
+        *node.fork.lock().unwrap() = Some(FakeFork {
+            from_block: 1,
+            head: 2,
+            logs: vec![],
+        });
+        ingester.poll().await.unwrap();
+
+        let index = index.read().await;
+        assert_eq!(index.head().unwrap().number, 2);
+        assert_eq!(index.block(1).unwrap().hash, node.hash_of(1));
+        assert_eq!(index.recent_errors().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reindex_adds_a_new_contract_and_resumes_from_its_checkpoint() {
+        let wallet = "0x00000000000000000000000000000000000000aa";
+        let node = Arc::new(FakeNode {
+            head: 4,
+            logs: vec![
+                ntc_log(1, 0, ZERO_ADDRESS, wallet, 1),
+                mint_log(2, 1, wallet, 7),
+                mint_log(4, 0, wallet, 8),
+            ],
+            ..FakeNode::default()
+        });
+        let path = std::env::temp_dir()
+            .join(format!("chain-reindex-{}", uuid::Uuid::new_v4()))
+            .join("index.jsonl");
+        let store: Arc<dyn ChainIndexStore> = Arc::new(FileChainStore::new(&path));
+        let config = IngestConfig {
+            start_block: 1,
+            contract_addresses: vec![TOKEN_CONTRACT.to_string()],
+            max_blocks_per_poll: 2,
+            ..ingest_config()
+        };
+        let index = ChainIndex::new(NOW).into_shared();
+        let ingester = ChainIngester::new(stub(), node.clone(), index.clone(), config.clone())
+            .with_store(store.clone());
+        ingester.poll().await.unwrap();
+        ingester.poll().await.unwrap();
+        assert_eq!(index.read().await.events().len(), 1);
+
+        let job = ingester
+            .start_reindex(1, &[NFT_CONTRACT.to_string()], false)
+            .await
+            .unwrap();
+        assert_eq!((job.start_block, job.target_block), (1, 4));
+        let progress = ingester.reindex_step(&job.id).await.unwrap().unwrap();
+        assert_eq!(progress.current_block(), Some(2));
+        assert_eq!(progress.status, ReindexStatus::Running);
+
+        // A restarted indexer carries on from the saved checkpoint
+        let restored = ChainIndex::load(store.as_ref())
+            .unwrap()
+            .unwrap()
+            .into_shared();
+        let restarted =
+            ChainIngester::new(stub(), node, restored.clone(), config).with_store(store.clone());
+        let done = restarted.reindex_step(&job.id).await.unwrap().unwrap();
+        assert_eq!(done.status, ReindexStatus::Completed);
+        assert_eq!(done.events_reindexed, 2);
+        assert_eq!(restored.read().await.events().len(), 3);
+        assert!(restarted.reindex_step(&job.id).await.unwrap().is_none());
+
+        // A cancelled job is not picked up again
+        let job = restarted.start_reindex(3, &[], true).await.unwrap();
+        restarted.cancel_reindex(&job.id).await.unwrap();
+        restarted.clone().spawn_reindex().await.unwrap();
+        let saved = ChainIndex::load(store.as_ref()).unwrap().unwrap();
+        let cancelled = saved.reindex_job().unwrap();
+        assert_eq!(
+            (cancelled.status, cancelled.next_block),
+            (ReindexStatus::Cancelled, 3)
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+}
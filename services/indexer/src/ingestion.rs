@@ -0,0 +1,199 @@
+//! Chain ingestion for the indexer service
+//! Loads the saved chain index, keeps it in step with the configured L3 node
+//! and renders its progress for the status RPCs, including reindex jobs
+
+use crate::chain_index::{ChainIndex, ReindexJob, ReindexStatus, SharedChainIndex};
+use crate::chain_store::open_store;
+use crate::config::{IndexerConfig, StubConfiguration};
+use crate::event_feed::{EventCursor, EventFeedFilter, EventSubscription};
+use crate::fixtures;
+use crate::grpc_server::bunkerverse::services::v1::{
+    IndexingStatsProto, IndexingStatusProto, ReindexJobProto, ReindexJobStatusProto,
+};
+use crate::ingest::{ChainIngester, JsonRpcClient};
+use crate::stub::SharedStub;
+use anyhow::{Context as _, Result};
+use chrono::Utc;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::info;
+
+/// Window over which the current indexing rate is measured
+const CURRENT_RATE_WINDOW_SECS: i64 = 60;
+
+/// Render a reindex job's progress as of `now`
+pub fn reindex_job_proto(job: &ReindexJob, now: i64) -> ReindexJobProto {
+    ReindexJobProto {
+        reindex_job_id: job.id.clone(),
+        status: match job.status {
+            ReindexStatus::Running => ReindexJobStatusProto::ReindexJobStatusRunning,
+            ReindexStatus::Completed => ReindexJobStatusProto::ReindexJobStatusCompleted,
+            ReindexStatus::Cancelled => ReindexJobStatusProto::ReindexJobStatusCancelled,
+        }
+        .into(),
+        start_block: job.start_block,
+        target_block: job.target_block,
+        current_block: job.current_block().unwrap_or(0),
+        blocks_per_second: job.blocks_per_second(now) as f32,
+        estimated_completion_time: job.estimated_completion(now).unwrap_or(0),
+        events_reindexed: job.events_reindexed,
+        contract_addresses: job.contract_addresses.clone(),
+        preserve_existing: job.preserve_existing,
+        started_at: job.started_at,
+        finished_at: job.finished_at.unwrap_or(0),
+        last_error: job.last_error.clone().unwrap_or_default(),
+    }
+}
+
+/// Indexed chain data the RPCs answer from when ingestion is enabled
+#[derive(Debug, Clone)]
+pub struct Ingestion {
+    pub index: SharedChainIndex,
+    /// Polls the node; also runs reindex jobs beside the live tail
+    ingester: ChainIngester,
+    /// Blocks that must follow one before it is treated as final
+    pub confirmations: u64,
+    index_path: PathBuf,
+}
+
+impl Ingestion {
+    /// Load the saved index and start polling the node; `None` when ingestion is off
+    pub fn start(stub: SharedStub, config: &StubConfiguration) -> Result<Option<Self>> {
+        let ingest = &IndexerConfig::parse(config)?.ingest;
+        if !ingest.enabled {
+            return Ok(None);
+        }
+        ingest.validate()?;
+
+        let store = open_store(ingest, &config.base.name)?;
+        let index = ChainIndex::load(store.as_ref())
+            .context("loading chain index")?
+            .unwrap_or_else(|| ChainIndex::new(Utc::now().timestamp()));
+        info!(
+            rpc_url = %ingest.rpc_url,
+            next_block = index.next_block(ingest.start_block),
+            "Starting chain ingestion"
+        );
+        if let Some(job) = index.reindex_job() {
+            if job.status == ReindexStatus::Running {
+                info!(job_id = %job.id, next_block = job.next_block, "Resuming reindex job");
+            }
+        }
+        let index = index.into_shared();
+
+        let ingester = ChainIngester::new(
+            stub,
+            Arc::new(JsonRpcClient::new(&ingest.rpc_url)?),
+            index.clone(),
+            ingest.clone(),
+        )
+        .with_store(store);
+        ingester.clone().spawn();
+        ingester.clone().spawn_reindex();
+
+        Ok(Some(Self {
+            index,
+            ingester,
+            confirmations: ingest.confirmations,
+            index_path: ingest.index_path(&config.base.name),
+        }))
+    }
+
+    /// Start reindexing from `start_block` in the background
+    pub async fn reindex(
+        &self,
+        start_block: u64,
+        contract_addresses: &[String],
+        preserve_existing: bool,
+    ) -> Result<ReindexJob> {
+        let job = self
+            .ingester
+            .start_reindex(start_block, contract_addresses, preserve_existing)
+            .await?;
+        self.ingester.clone().spawn_reindex();
+        Ok(job)
+    }
+
+    pub async fn cancel_reindex(&self, job_id: &str) -> Result<ReindexJob> {
+        self.ingester.cancel_reindex(job_id).await
+    }
+
+    /// Stream indexed events after `resume_after` as they are ingested
+    pub fn subscribe(
+        &self,
+        filter: EventFeedFilter,
+        resume_after: Option<EventCursor>,
+        last_retraction: u64,
+        finalized_only: bool,
+    ) -> EventSubscription {
+        let subscription = self
+            .ingester
+            .subscribe(filter, resume_after, last_retraction);
+        if finalized_only {
+            subscription.finalized_only(self.confirmations)
+        } else {
+            subscription
+        }
+    }
+
+    fn database_size_bytes(&self) -> u64 {
+        std::fs::metadata(&self.index_path).map_or(0, |metadata| metadata.len())
+    }
+
+    /// Totals and rates of everything indexed so far
+    pub async fn indexing_stats(&self) -> IndexingStatsProto {
+        let index = self.index.read().await;
+        let events = index.events();
+        let elapsed = (index.last_synced_at() - index.started_at()).max(1);
+        let head_timestamp = index.head().map_or(0, |head| head.timestamp);
+        let recent = events
+            .iter()
+            .rev()
+            .take_while(|event| event.block_timestamp > head_timestamp - CURRENT_RATE_WINDOW_SECS)
+            .count() as u64;
+
+        IndexingStatsProto {
+            total_events_indexed: events.len() as u64,
+            total_blocks_indexed: index.blocks().count() as u64,
+            events_per_second_current: recent / CURRENT_RATE_WINDOW_SECS as u64,
+            events_per_second_average: events.len() as u64 / elapsed as u64,
+            contract_event_counts: fixtures::contract_event_counts(events.iter()),
+            event_type_counts: fixtures::event_type_counts(events.iter()),
+            indexing_start_time: index.started_at(),
+            last_successful_sync: index.last_synced_at(),
+            recent_errors: index.recent_errors().cloned().collect(),
+            database_size_bytes: self.database_size_bytes(),
+        }
+    }
+
+    /// How far the index is behind the node
+    pub async fn indexing_status(&self) -> IndexingStatusProto {
+        let stats = self.indexing_stats().await;
+        let index = self.index.read().await;
+        let current_block = index.head().map_or(0, |head| head.number);
+        let latest_block = index.chain_head().max(current_block);
+
+        IndexingStatusProto {
+            current_block,
+            latest_block,
+            blocks_behind: index.blocks_behind(),
+            is_syncing: index.blocks_behind() > 0,
+            sync_progress_percent: if latest_block == 0 {
+                100.0
+            } else {
+                current_block as f32 / latest_block as f32 * 100.0
+            },
+            last_sync_timestamp: index.last_synced_at(),
+            events_processed_total: stats.total_events_indexed,
+            events_per_second: stats.events_per_second_current,
+            unhealthy_contracts: vec![],
+            stats: Some(stats),
+            finalized_block: index.finalized_block(self.confirmations).unwrap_or(0),
+            confirmation_depth: self.confirmations,
+            reorg_count: index.reorgs(),
+            reindex_job: index
+                .reindex_job()
+                .map(|job| reindex_job_proto(job, Utc::now().timestamp())),
+        }
+    }
+}
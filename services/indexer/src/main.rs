@@ -1,29 +1,38 @@
+mod chain_index;
+mod chain_store;
 mod config;
+mod event_feed;
+mod fixtures;
 mod grpc_server;
+mod ingest;
+mod ingestion;
+mod projection;
 mod stub;
 
 use anyhow::Result;
 use axum::{
-    extract::{Path, Query, State},
+    extract::{FromRef, Path, Query, State},
     http::StatusCode,
     response::Json,
     routing::{get, post},
     Router,
 };
+use chain_index::{IndexedBlock, IndexedTransaction, RetractedEvent};
 use chrono::{DateTime, Utc};
 use config::StubConfiguration;
 use grpc_server::{
     bunkerverse::services::v1::indexer_service_server::IndexerServiceServer, IndexerGrpcService,
 };
+use ingestion::Ingestion;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use stub::{IndexerStub, RequestContext, SmartStub};
+use smart_stubs::{admin, StubLayer};
+use std::{collections::HashMap, net::SocketAddr};
+use stub::{RequestContext, SharedStub, SmartStub};
 use tokio::signal;
 use tonic::transport::Server;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-use uuid::Uuid;
 
 // API Request/Response Types
 #[derive(Debug, Deserialize)]
@@ -39,6 +48,13 @@ pub struct BlockQuery {
     pub include_transactions: Option<bool>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RetractionQuery {
+    /// Only retractions with a greater sequence number
+    pub after: Option<u64>,
+    pub limit: Option<u32>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BlockInfo {
     pub block_number: u64,
@@ -80,6 +96,26 @@ pub struct TransactionsResponse {
     pub limit: u32,
 }
 
+#[derive(Debug, Serialize)]
+pub struct RetractionInfo {
+    pub sequence: u64,
+    pub event_id: String,
+    pub event_type: String,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub log_index: u64,
+    pub transaction_hash: String,
+    pub retracted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetractionsResponse {
+    pub retractions: Vec<RetractionInfo>,
+    /// Sequence to pass as `after` on the next request
+    pub last_sequence: u64,
+    pub reorg_count: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ContractState {
     pub contract_address: String,
@@ -123,27 +159,87 @@ pub struct ErrorResponse {
     pub request_id: String,
 }
 
+fn block_info(block: &IndexedBlock) -> BlockInfo {
+    BlockInfo {
+        block_number: block.number,
+        block_hash: block.hash.clone(),
+        parent_hash: block.parent_hash.clone(),
+        timestamp: DateTime::from_timestamp(block.timestamp, 0).unwrap_or_default(),
+        transaction_count: block.transaction_count,
+        gas_used: block.gas_used.to_string(),
+        gas_limit: block.gas_limit.to_string(),
+        size: block.size,
+    }
+}
+
+fn transaction_info(transaction: &IndexedTransaction) -> TransactionInfo {
+    TransactionInfo {
+        tx_hash: transaction.hash.clone(),
+        block_number: transaction.block_number,
+        from_address: transaction.from_address.clone(),
+        to_address: transaction.to_address.clone(),
+        value: transaction.value.clone(),
+        gas_price: transaction.gas_price.to_string(),
+        gas_used: transaction.gas_used.to_string(),
+        status: if transaction.succeeded {
+            "success"
+        } else {
+            "failed"
+        }
+        .to_string(),
+        timestamp: DateTime::from_timestamp(transaction.timestamp, 0).unwrap_or_default(),
+    }
+}
+
+fn retraction_info(retraction: &RetractedEvent) -> RetractionInfo {
+    RetractionInfo {
+        sequence: retraction.sequence,
+        event_id: retraction.event.event_id.clone(),
+        event_type: retraction.event.payload.event_type().to_string(),
+        block_number: retraction.event.block_number,
+        block_hash: retraction.block_hash.clone(),
+        log_index: retraction.event.log_index,
+        transaction_hash: retraction.event.transaction_hash.clone(),
+        retracted_at: DateTime::from_timestamp(retraction.retracted_at, 0).unwrap_or_default(),
+    }
+}
+
+fn error_response(
+    status: StatusCode,
+    code: &str,
+    message: String,
+    request_id: String,
+) -> (StatusCode, Json<ErrorResponse>) {
+    let error = ErrorResponse {
+        error: message,
+        code: code.to_string(),
+        timestamp: Utc::now(),
+        request_id,
+    };
+    (status, Json(error))
+}
+
 // Application State
 #[derive(Clone)]
 pub struct AppState {
-    pub stub: Arc<tokio::sync::Mutex<IndexerStub>>,
+    pub stub: SharedStub,
+    /// Set when blocks are ingested from an L3 node instead of fabricated
+    pub ingestion: Option<Ingestion>,
 }
 
 impl AppState {
-    pub fn new(config: StubConfiguration) -> Self {
-        Self {
-            stub: Arc::new(tokio::sync::Mutex::new(IndexerStub::new(config))),
-        }
+    pub fn new(stub: SharedStub, ingestion: Option<Ingestion>) -> Self {
+        Self { stub, ingestion }
     }
 
     pub async fn create_context(&self, trace_id: Option<String>) -> RequestContext {
-        let stub = self.stub.lock().await;
-        RequestContext {
-            request_id: Uuid::new_v4().to_string(),
-            trace_id,
-            timestamp: Utc::now(),
-            enable_crypto: stub.get_configuration().dual_mode.enable_crypto,
-        }
+        self.stub.lock().await.create_context(trace_id)
+    }
+}
+
+impl FromRef<AppState> for SharedStub {
+    fn from_ref(state: &AppState) -> Self {
+        state.stub.clone()
     }
 }
 
@@ -198,7 +294,7 @@ pub async fn reset_stub_state(
 pub async fn get_blocks(
     State(state): State<AppState>,
     Query(pagination): Query<PaginationQuery>,
-    Query(_block_query): Query<BlockQuery>,
+    Query(block_query): Query<BlockQuery>,
 ) -> Result<Json<BlocksResponse>, (StatusCode, Json<ErrorResponse>)> {
     let context = state.create_context(None).await;
     let stub = state.stub.lock().await;
@@ -247,6 +343,41 @@ pub async fn get_blocks(
         return Err((StatusCode::FORBIDDEN, Json(error)));
     }
 
+    let page = pagination.page.unwrap_or(1);
+    let limit = pagination.limit.unwrap_or(10).min(100);
+
+    if let Some(ingestion) = &state.ingestion {
+        let index = ingestion.index.read().await;
+        let from_block = block_query.from_block.unwrap_or(0);
+        let to_block = block_query.to_block.unwrap_or(u64::MAX);
+        // Newest first
+        let in_range: Vec<&IndexedBlock> = index
+            .blocks()
+            .rev()
+            .filter(|block| (from_block..=to_block).contains(&block.number))
+            .collect();
+        let blocks = in_range
+            .iter()
+            .skip((page.max(1) as usize - 1) * limit as usize)
+            .take(limit as usize)
+            .map(|block| block_info(block))
+            .collect();
+
+        stub.log_response(
+            &context,
+            "/api/indexer/blocks",
+            latency.as_millis() as u64,
+            200,
+            false,
+        );
+        return Ok(Json(BlocksResponse {
+            blocks,
+            total_count: u32::try_from(in_range.len()).unwrap_or(u32::MAX),
+            page,
+            limit,
+        }));
+    }
+
     // Generate mock blocks
     let blocks = vec![
         BlockInfo {
@@ -271,9 +402,6 @@ pub async fn get_blocks(
         },
     ];
 
-    let page = pagination.page.unwrap_or(1);
-    let limit = pagination.limit.unwrap_or(10).min(100);
-
     let response = BlocksResponse {
         blocks,
         total_count: 2,
@@ -328,6 +456,31 @@ pub async fn get_block_details(
         return Err((StatusCode::FORBIDDEN, Json(error)));
     }
 
+    if let Some(ingestion) = &state.ingestion {
+        let path = format!("/api/indexer/blocks/{}", block_number);
+        let Ok(number) = block_number.parse::<u64>() else {
+            stub.log_response(&context, &path, latency.as_millis() as u64, 400, false);
+            return Err(error_response(
+                StatusCode::BAD_REQUEST,
+                "INVALID_BLOCK_NUMBER",
+                format!("Invalid block number {}", block_number),
+                context.request_id,
+            ));
+        };
+        let index = ingestion.index.read().await;
+        let Some(block) = index.block(number) else {
+            stub.log_response(&context, &path, latency.as_millis() as u64, 404, false);
+            return Err(error_response(
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                format!("Block {} has not been indexed", number),
+                context.request_id,
+            ));
+        };
+        stub.log_response(&context, &path, latency.as_millis() as u64, 200, false);
+        return Ok(Json(block_info(block)));
+    }
+
     let block = BlockInfo {
         block_number: block_number.parse().unwrap_or(12345),
         block_hash: "0xabc123def456".to_string(),
@@ -382,6 +535,36 @@ pub async fn get_transactions(
         return Err((StatusCode::FORBIDDEN, Json(error)));
     }
 
+    let page = pagination.page.unwrap_or(1);
+    let limit = pagination.limit.unwrap_or(10).min(100);
+
+    if let Some(ingestion) = &state.ingestion {
+        let index = ingestion.index.read().await;
+        // Newest first
+        let transactions = index
+            .transactions()
+            .iter()
+            .rev()
+            .skip((page.max(1) as usize - 1) * limit as usize)
+            .take(limit as usize)
+            .map(transaction_info)
+            .collect();
+
+        stub.log_response(
+            &context,
+            "/api/indexer/transactions",
+            latency.as_millis() as u64,
+            200,
+            false,
+        );
+        return Ok(Json(TransactionsResponse {
+            transactions,
+            total_count: u32::try_from(index.transactions().len()).unwrap_or(u32::MAX),
+            page,
+            limit,
+        }));
+    }
+
     let transactions = vec![TransactionInfo {
         tx_hash: "0x123abc456def".to_string(),
         block_number: 12345,
@@ -394,9 +577,6 @@ pub async fn get_transactions(
         timestamp: Utc::now(),
     }];
 
-    let page = pagination.page.unwrap_or(1);
-    let limit = pagination.limit.unwrap_or(10).min(100);
-
     let response = TransactionsResponse {
         transactions,
         total_count: 1,
@@ -446,12 +626,27 @@ pub async fn get_indexing_status(
         return Err((StatusCode::FORBIDDEN, Json(error)));
     }
 
-    let stats = IndexingStats {
-        current_block: 12345,
-        latest_block: 12350,
-        blocks_behind: 5,
-        indexing_rate: 2.5,
-        last_update: Utc::now(),
+    let stats = match &state.ingestion {
+        Some(ingestion) => {
+            let index = ingestion.index.read().await;
+            let current_block = index.head().map_or(0, |head| head.number);
+            let elapsed = (index.last_synced_at() - index.started_at()).max(1);
+            IndexingStats {
+                current_block,
+                latest_block: index.chain_head().max(current_block),
+                blocks_behind: index.blocks_behind(),
+                indexing_rate: index.blocks().count() as f64 / elapsed as f64,
+                last_update: DateTime::from_timestamp(index.last_synced_at(), 0)
+                    .unwrap_or_default(),
+            }
+        }
+        None => IndexingStats {
+            current_block: 12345,
+            latest_block: 12350,
+            blocks_behind: 5,
+            indexing_rate: 2.5,
+            last_update: Utc::now(),
+        },
     };
 
     stub.log_response(
@@ -464,6 +659,73 @@ pub async fn get_indexing_status(
     Ok(Json(stats))
 }
 
+/// Events dropped by chain reorganizations, in the order they were retracted
+pub async fn get_retractions(
+    State(state): State<AppState>,
+    Query(query): Query<RetractionQuery>,
+) -> Result<Json<RetractionsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let context = state.create_context(None).await;
+    let stub = state.stub.lock().await;
+
+    stub.log_request(&context, "/api/indexer/retractions", "GET");
+
+    // Simulate latency
+    let latency = stub.calculate_response_latency();
+    tokio::time::sleep(tokio::time::Duration::from_millis(
+        latency.as_millis() as u64
+    ))
+    .await;
+
+    if let Err(err) = stub.check_crypto_features(&context) {
+        stub.log_response(
+            &context,
+            "/api/indexer/retractions",
+            latency.as_millis() as u64,
+            403,
+            false,
+        );
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "FEATURE_NOT_ENABLED",
+            err,
+            context.request_id,
+        ));
+    }
+
+    let after = query.after.unwrap_or(0);
+    let limit = query.limit.unwrap_or(100).min(1_000);
+    // The fixture chain never reorganizes
+    let response = match &state.ingestion {
+        Some(ingestion) => {
+            let index = ingestion.index.read().await;
+            let retractions: Vec<RetractionInfo> = index
+                .retractions_since(after)
+                .take(limit as usize)
+                .map(retraction_info)
+                .collect();
+            RetractionsResponse {
+                last_sequence: retractions.last().map_or(after, |last| last.sequence),
+                retractions,
+                reorg_count: index.reorgs(),
+            }
+        }
+        None => RetractionsResponse {
+            retractions: vec![],
+            last_sequence: after,
+            reorg_count: 0,
+        },
+    };
+
+    stub.log_response(
+        &context,
+        "/api/indexer/retractions",
+        latency.as_millis() as u64,
+        200,
+        false,
+    );
+    Ok(Json(response))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize tracing with structured JSON logging
@@ -472,10 +734,17 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer().json())
         .init();
 
-    let config = StubConfiguration::default();
+    let config_source = config::config_source();
+    let config = config_source.load()?;
     let http_addr: SocketAddr = format!("0.0.0.0:{}", config.base.port).parse()?;
     let grpc_addr: SocketAddr = format!("0.0.0.0:{}", config.base.port + 1000).parse()?; // gRPC on port 9082
-    let state = AppState::new(config.clone());
+    let stub = stub::new_shared_stub(config.clone());
+    // Index the configured L3 node when enabled; otherwise serve the fixture world
+    let ingestion = Ingestion::start(stub.clone(), &config)?;
+    let state = AppState::new(stub.clone(), ingestion.clone());
+    // Hot reload latency, error and dataset settings when the config file changes
+    config_source.spawn_watcher(stub.clone());
+    smart_stubs::spawn_state_reset(stub.clone());
 
     info!(
         service_name = %config.base.name,
@@ -483,6 +752,8 @@ async fn main() -> Result<()> {
         http_address = %http_addr,
         grpc_address = %grpc_addr,
         enable_crypto = config.dual_mode.enable_crypto,
+        ingest = ingestion.is_some(),
+        config_path = ?config_source.path(),
         "Starting Indexer Service Smart Stub with HTTP and gRPC servers"
     );
 
@@ -491,12 +762,16 @@ async fn main() -> Result<()> {
         .route("/health", get(health_check))
         .route("/stub/config", get(get_stub_config))
         .route("/stub/reset", post(reset_stub_state))
+        .route("/stub/scenario", admin::scenario_route())
+        .route("/stub/world", admin::world_route())
         // Indexer API endpoints
         .route("/api/indexer/blocks", get(get_blocks))
         .route("/api/indexer/blocks/:block_number", get(get_block_details))
         .route("/api/indexer/transactions", get(get_transactions))
         .route("/api/indexer/status", get(get_indexing_status))
+        .route("/api/indexer/retractions", get(get_retractions))
         // Middleware
+        .layer(StubLayer::fault_rules(stub.clone()))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
         .with_state(state);
@@ -504,7 +779,7 @@ async fn main() -> Result<()> {
     let http_listener = tokio::net::TcpListener::bind(http_addr).await?;
 
     // gRPC Server
-    let grpc_service = IndexerGrpcService::new(config.clone());
+    let grpc_service = IndexerGrpcService::new(stub.clone(), ingestion);
 
     info!("HTTP server ready and listening on {}", http_addr);
     info!("gRPC server ready and listening on {}", grpc_addr);
@@ -513,6 +788,7 @@ async fn main() -> Result<()> {
     let http_server = axum::serve(http_listener, app).with_graceful_shutdown(shutdown_signal());
 
     let grpc_server = Server::builder()
+        .layer(StubLayer::fault_rules(stub))
         .add_service(IndexerServiceServer::new(grpc_service))
         .serve_with_shutdown(grpc_addr, shutdown_signal());
 
@@ -0,0 +1,364 @@
+//! Per-player chain state folded from canonical events
+//!
+//! The indexer keeps [`PlayerProjections`] in step with its event log one block
+//! at a time, so a projection always describes the chain at a single block
+//! height. World entities such as robot classes and NFT types are looked up
+//! when a projection is rendered.
+
+use smart_stubs::world::{ChainEvent, EventPayload};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Blocks between the projection snapshots the index keeps for rollbacks
+pub const SNAPSHOT_INTERVAL: u64 = 100;
+
+/// Snapshots kept; rolling back past the oldest refolds from the first block
+pub const SNAPSHOT_CAPACITY: usize = 32;
+
+/// Robot progression and equipment
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RobotProjection {
+    pub robot_id: String,
+    pub level: u32,
+    pub total_xp: u64,
+    /// Equipment slot name to item NFT ID
+    pub equipped: BTreeMap<String, String>,
+    pub last_active_at: i64,
+}
+
+/// Stake opened by `NtcStakingInitiated` events
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StakingProjection {
+    pub staked_ntc_wei: u64,
+    pub duration_days: u32,
+    /// When the first stake was opened
+    pub started_at: i64,
+}
+
+/// Chain state of one player as far as the folded events tell
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerProjection {
+    pub xp: u64,
+    /// Saturates at zero when the index starts after the wallet was funded
+    pub ntc_balance_wei: u128,
+    pub owned_nft_ids: BTreeSet<String>,
+    pub robot: Option<RobotProjection>,
+    pub staking: Option<StakingProjection>,
+    pub last_updated_at: i64,
+}
+
+impl PlayerProjection {
+    fn robot(&mut self, robot_id: &str, timestamp: i64) -> &mut RobotProjection {
+        let robot = self.robot.get_or_insert_with(RobotProjection::default);
+        if robot.robot_id != robot_id {
+            // A different robot became the active one
+            *robot = RobotProjection {
+                robot_id: robot_id.to_string(),
+                ..RobotProjection::default()
+            };
+        }
+        robot.last_active_at = timestamp;
+        robot
+    }
+}
+
+/// Projections of every player an event has named, as of one block
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerProjections {
+    /// Last block folded in, `None` before the first
+    block_height: Option<u64>,
+    players: HashMap<String, PlayerProjection>,
+}
+
+impl PlayerProjections {
+    /// Fold a chain-ordered event log from scratch
+    #[must_use]
+    pub fn fold<'a>(events: impl IntoIterator<Item = &'a ChainEvent>) -> Self {
+        let mut projections = Self::default();
+        for event in events {
+            projections.apply(event);
+            projections.block_height = Some(event.block_number);
+        }
+        projections
+    }
+
+    #[must_use]
+    pub fn block_height(&self) -> Option<u64> {
+        self.block_height
+    }
+
+    #[must_use]
+    pub fn player(&self, player_id: &str) -> Option<&PlayerProjection> {
+        self.players.get(player_id)
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = (&str, &PlayerProjection)> {
+        self.players
+            .iter()
+            .map(|(player_id, projection)| (player_id.as_str(), projection))
+    }
+
+    /// Fold the events of block `number`, in log order
+    pub fn apply_block(&mut self, number: u64, events: &[ChainEvent]) {
+        for event in events {
+            self.apply(event);
+        }
+        self.block_height = Some(number);
+    }
+
+    fn apply(&mut self, event: &ChainEvent) {
+        let timestamp = event.block_timestamp;
+        for player_id in event.payload.participants() {
+            self.player_mut(player_id).last_updated_at = timestamp;
+        }
+
+        match &event.payload {
+            EventPayload::UserRegistered { .. }
+            | EventPayload::NftMarketListed { .. }
+            | EventPayload::MissionCompleted { .. } => {}
+            EventPayload::NftMinted {
+                nft_id, player_id, ..
+            } => {
+                self.player_mut(player_id)
+                    .owned_nft_ids
+                    .insert(nft_id.clone());
+            }
+            EventPayload::NftMarketSold {
+                nft_id,
+                player_id,
+                seller_id,
+                ..
+            } => {
+                self.player_mut(seller_id).owned_nft_ids.remove(nft_id);
+                self.player_mut(player_id)
+                    .owned_nft_ids
+                    .insert(nft_id.clone());
+            }
+            // Moves are idempotent, so the contract's own event repeating
+            // the ERC-721 transfer leaves ownership as it was
+            EventPayload::NftTransferred {
+                nft_id,
+                from_player_id,
+                to_player_id,
+                ..
+            } => {
+                if !from_player_id.is_empty() {
+                    self.player_mut(from_player_id).owned_nft_ids.remove(nft_id);
+                }
+                if !to_player_id.is_empty() {
+                    self.player_mut(to_player_id)
+                        .owned_nft_ids
+                        .insert(nft_id.clone());
+                }
+            }
+            EventPayload::ItemEquipped {
+                player_id,
+                robot_id,
+                nft_id,
+                slot,
+            } => {
+                self.player_mut(player_id)
+                    .robot(robot_id, timestamp)
+                    .equipped
+                    .insert(slot.clone(), nft_id.clone());
+            }
+            EventPayload::NtcStakingInitiated {
+                player_id,
+                staked_amount_wei,
+                duration_days,
+            } => {
+                let staking =
+                    self.player_mut(player_id)
+                        .staking
+                        .get_or_insert_with(|| StakingProjection {
+                            started_at: timestamp,
+                            ..StakingProjection::default()
+                        });
+                staking.staked_ntc_wei = staking.staked_ntc_wei.saturating_add(*staked_amount_wei);
+                staking.duration_days = *duration_days;
+            }
+            EventPayload::XpAwarded {
+                player_id,
+                robot_id,
+                xp_amount,
+                ..
+            } => {
+                let player = self.player_mut(player_id);
+                player.xp = player.xp.saturating_add(*xp_amount);
+                if !robot_id.is_empty() {
+                    let robot = player.robot(robot_id, timestamp);
+                    robot.total_xp = robot.total_xp.saturating_add(*xp_amount);
+                }
+            }
+            EventPayload::RobotStatsUpdated {
+                player_id,
+                robot_id,
+                new_level,
+                total_xp,
+                ..
+            } => {
+                let robot = self.player_mut(player_id).robot(robot_id, timestamp);
+                robot.level = *new_level;
+                robot.total_xp = *total_xp;
+            }
+            EventPayload::NtcTransfer {
+                from_player_id,
+                to_player_id,
+                amount_wei,
+                ..
+            } => {
+                if !from_player_id.is_empty() {
+                    let sender = self.player_mut(from_player_id);
+                    sender.ntc_balance_wei = sender.ntc_balance_wei.saturating_sub(*amount_wei);
+                }
+                if !to_player_id.is_empty() {
+                    let recipient = self.player_mut(to_player_id);
+                    recipient.ntc_balance_wei =
+                        recipient.ntc_balance_wei.saturating_add(*amount_wei);
+                }
+            }
+        }
+    }
+
+    fn player_mut(&mut self, player_id: &str) -> &mut PlayerProjection {
+        self.players.entry(player_id.to_string()).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_800_000_000;
+
+    fn event(block_number: u64, payload: EventPayload) -> ChainEvent {
+        ChainEvent {
+            event_id: uuid::Uuid::new_v4().to_string(),
+            block_number,
+            log_index: 0,
+            contract_address: "0xcontract".to_string(),
+            transaction_hash: format!("0xtx{block_number}"),
+            block_timestamp: NOW + block_number as i64,
+            payload,
+        }
+    }
+
+    fn transfer(block_number: u64, from: &str, to: &str, amount_wei: u128) -> ChainEvent {
+        event(
+            block_number,
+            EventPayload::NtcTransfer {
+                from_player_id: from.to_string(),
+                to_player_id: to.to_string(),
+                amount_wei,
+                transfer_type: "transfer".to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_events_fold_into_player_state() {
+        let events = vec![
+            event(
+                1,
+                EventPayload::NftMinted {
+                    nft_id: "robot".to_string(),
+                    player_id: "alice".to_string(),
+                    token: None,
+                },
+            ),
+            event(
+                1,
+                EventPayload::NftMinted {
+                    nft_id: "helmet".to_string(),
+                    player_id: "alice".to_string(),
+                    token: None,
+                },
+            ),
+            transfer(2, "", "alice", 100),
+            transfer(3, "alice", "bob", 30),
+            event(
+                4,
+                EventPayload::ItemEquipped {
+                    player_id: "alice".to_string(),
+                    robot_id: "robot".to_string(),
+                    nft_id: "helmet".to_string(),
+                    slot: "head".to_string(),
+                },
+            ),
+            event(
+                5,
+                EventPayload::XpAwarded {
+                    player_id: "alice".to_string(),
+                    robot_id: "robot".to_string(),
+                    xp_amount: 400,
+                    xp_source: "mission".to_string(),
+                },
+            ),
+            event(
+                6,
+                EventPayload::RobotStatsUpdated {
+                    player_id: "alice".to_string(),
+                    robot_id: "robot".to_string(),
+                    old_level: 1,
+                    new_level: 2,
+                    xp_gained: 400,
+                    total_xp: 5_400,
+                    update_reason: "mission_completion".to_string(),
+                },
+            ),
+            event(
+                7,
+                EventPayload::NtcStakingInitiated {
+                    player_id: "alice".to_string(),
+                    staked_amount_wei: 50,
+                    duration_days: 90,
+                },
+            ),
+            event(
+                8,
+                EventPayload::NftMarketSold {
+                    nft_id: "helmet".to_string(),
+                    player_id: "bob".to_string(),
+                    seller_id: "alice".to_string(),
+                    price_ntc_wei: 10,
+                    marketplace_fee_wei: 0,
+                    royalty_wei: 0,
+                },
+            ),
+        ];
+        let projections = PlayerProjections::fold(&events);
+        assert_eq!(projections.block_height(), Some(8));
+
+        let alice = projections.player("alice").unwrap();
+        assert_eq!(alice.xp, 400);
+        assert_eq!(alice.ntc_balance_wei, 70);
+        assert_eq!(alice.owned_nft_ids, BTreeSet::from(["robot".to_string()]));
+        let robot = alice.robot.as_ref().unwrap();
+        assert_eq!((robot.level, robot.total_xp), (2, 5_400));
+        assert_eq!(robot.equipped["head"], "helmet");
+        assert_eq!(
+            alice.staking,
+            Some(StakingProjection {
+                staked_ntc_wei: 50,
+                duration_days: 90,
+                started_at: NOW + 7,
+            })
+        );
+        assert_eq!(alice.last_updated_at, NOW + 8);
+
+        let bob = projections.player("bob").unwrap();
+        assert_eq!(bob.ntc_balance_wei, 30);
+        assert!(bob.owned_nft_ids.contains("helmet"));
+        assert!(bob.robot.is_none());
+    }
+
+    #[test]
+    fn test_transfers_out_of_an_unfunded_wallet_saturate() {
+        let mut projections = PlayerProjections::default();
+        projections.apply_block(9, &[transfer(9, "carol", "", 5)]);
+        projections.apply_block(10, &[]);
+
+        assert_eq!(projections.block_height(), Some(10));
+        assert_eq!(projections.player("carol").unwrap().ntc_balance_wei, 0);
+        assert_eq!(projections.players().count(), 1);
+    }
+}
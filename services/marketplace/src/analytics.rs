@@ -0,0 +1,410 @@
+//! Sale history aggregation for market charts and price suggestions
+//! Sales are grouped by NFT template (item type and rarity) and rolled up into
+//! OHLC candles, volume, floor price, price change and rarity tier medians
+
+use smart_stubs::world::{Currency, ItemType, MarketStatus, Sale, Template, World};
+
+/// Seconds in a day
+pub const DAY_SECS: i64 = 86_400;
+
+/// History covered when the request does not set a start
+pub const DEFAULT_HISTORY_SECS: i64 = 30 * DAY_SECS;
+
+/// Most candles returned for one request; older periods are cut off
+pub const MAX_CANDLES: usize = 500;
+
+const BASIS_POINTS: i128 = 10_000;
+
+/// Width of one candle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CandleInterval {
+    Hour,
+    #[default]
+    Day,
+    Week,
+}
+
+impl CandleInterval {
+    /// Mirrors `CandleIntervalProto`; unspecified falls back to daily candles
+    #[must_use]
+    pub fn from_proto(value: i32) -> Self {
+        match value {
+            1 => Self::Hour,
+            3 => Self::Week,
+            _ => Self::Day,
+        }
+    }
+
+    #[must_use]
+    pub fn secs(self) -> i64 {
+        match self {
+            Self::Hour => 3_600,
+            Self::Day => DAY_SECS,
+            Self::Week => 7 * DAY_SECS,
+        }
+    }
+}
+
+/// Open, high, low and close sale prices over one interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candle {
+    /// Start of the interval, aligned to a multiple of its width
+    pub opens_at: i64,
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    pub volume_wei: u64,
+    /// Zero for intervals without sales, which repeat the previous close
+    pub sales: u32,
+}
+
+/// Sale count, volume and average price over a time window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SaleWindow {
+    pub sales: u32,
+    pub volume_wei: u64,
+    pub average_price_wei: u64,
+}
+
+impl SaleWindow {
+    /// Summarize the sales made at or after `since`
+    pub fn since<'a>(sales: impl IntoIterator<Item = &'a Sale>, since: i64) -> Self {
+        let mut sales_count = 0u32;
+        let mut volume = 0u128;
+        for sale in sales {
+            if sale.sold_at >= since {
+                sales_count += 1;
+                volume += u128::from(sale.price_ntc_wei);
+            }
+        }
+        Self {
+            sales: sales_count,
+            volume_wei: saturate(volume),
+            average_price_wei: if sales_count == 0 {
+                0
+            } else {
+                saturate(volume / u128::from(sales_count))
+            },
+        }
+    }
+}
+
+/// Price chart and summary statistics for one template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceHistory {
+    pub template: Template,
+    /// Oldest first, starting at the first sale in the requested range
+    pub candles: Vec<Candle>,
+    /// Lowest open fixed price listing of the template
+    pub floor_price_wei: Option<u64>,
+    pub last_price_wei: Option<u64>,
+    pub last_24h: SaleWindow,
+    /// Last price against the last price 24 hours ago
+    pub change_24h_basis_points: Option<i64>,
+    pub change_7d_basis_points: Option<i64>,
+    /// Median sale in the range across every item type of the same rarity
+    pub rarity_median_price_wei: Option<u64>,
+    /// Median sale of the template in the range, else the rarity median, else the floor
+    pub suggested_price_wei: Option<u64>,
+}
+
+fn saturate(value: u128) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+fn median(mut prices: Vec<u64>) -> Option<u64> {
+    if prices.is_empty() {
+        return None;
+    }
+    prices.sort_unstable();
+    let middle = prices.len() / 2;
+    Some(if prices.len() % 2 == 1 {
+        prices[middle]
+    } else {
+        saturate((u128::from(prices[middle - 1]) + u128::from(prices[middle])) / 2)
+    })
+}
+
+fn change_basis_points(from: u64, to: u64) -> Option<i64> {
+    if from == 0 {
+        return None;
+    }
+    let change = (i128::from(to) - i128::from(from)) * BASIS_POINTS / i128::from(from);
+    i64::try_from(change).ok()
+}
+
+/// Price of the last sale at or before `at`; `sales` must be oldest first
+fn price_at(sales: &[&Sale], at: i64) -> Option<u64> {
+    sales
+        .iter()
+        .rev()
+        .find(|sale| sale.sold_at <= at)
+        .map(|sale| sale.price_ntc_wei)
+}
+
+fn candles(sales: &[&Sale], interval: CandleInterval, from: i64, now: i64) -> Vec<Candle> {
+    let width = interval.secs();
+    let first_open = from.div_euclid(width) * width;
+    let last_open = now.div_euclid(width) * width;
+    let mut candles: Vec<Candle> = Vec::new();
+    let mut sales = sales
+        .iter()
+        .filter(|sale| sale.sold_at >= first_open && sale.sold_at <= now)
+        .peekable();
+
+    let mut opens_at = first_open;
+    while opens_at <= last_open {
+        let mut candle: Option<Candle> = None;
+        let mut volume = 0u128;
+        while let Some(sale) = sales.next_if(|sale| sale.sold_at < opens_at + width) {
+            let price = sale.price_ntc_wei;
+            volume += u128::from(price);
+            let candle = candle.get_or_insert(Candle {
+                opens_at,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume_wei: 0,
+                sales: 0,
+            });
+            candle.high = candle.high.max(price);
+            candle.low = candle.low.min(price);
+            candle.close = price;
+            candle.sales += 1;
+        }
+        match candle {
+            Some(mut candle) => {
+                candle.volume_wei = saturate(volume);
+                candles.push(candle);
+            }
+            None => {
+                if let Some(close) = candles.last().map(|previous| previous.close) {
+                    candles.push(Candle {
+                        opens_at,
+                        open: close,
+                        high: close,
+                        low: close,
+                        close,
+                        volume_wei: 0,
+                        sales: 0,
+                    });
+                }
+            }
+        }
+        opens_at += width;
+    }
+    candles
+}
+
+/// Price statistics derived from the sale history
+pub trait Analytics {
+    /// Lowest open fixed price NTC listing of NFTs of `template`
+    #[must_use]
+    fn floor_price(&self, template: Template) -> Option<u64>;
+
+    /// Chart and summary statistics for `template` from `from` until `now`
+    ///
+    /// `from` of 0 covers the last [`DEFAULT_HISTORY_SECS`]; ranges longer than
+    /// [`MAX_CANDLES`] intervals are cut to the most recent ones.
+    #[must_use]
+    fn price_history(
+        &self,
+        template: Template,
+        interval: CandleInterval,
+        from: i64,
+        now: i64,
+    ) -> PriceHistory;
+}
+
+impl Analytics for World {
+    fn floor_price(&self, template: Template) -> Option<u64> {
+        self.listings
+            .iter()
+            .filter(|listing| {
+                listing.listing_type == MarketStatus::ListedForSale
+                    && listing.currency == Currency::Ntc
+            })
+            .filter(|listing| {
+                self.nft(&listing.nft_id)
+                    .is_some_and(|nft| Template::of(nft) == template)
+            })
+            .map(|listing| listing.price_ntc_wei)
+            .min()
+    }
+
+    fn price_history(
+        &self,
+        template: Template,
+        interval: CandleInterval,
+        from: i64,
+        now: i64,
+    ) -> PriceHistory {
+        let from = if from == 0 {
+            now - DEFAULT_HISTORY_SECS
+        } else {
+            from
+        };
+        let from = from.max(now - interval.secs() * (MAX_CANDLES as i64 - 1));
+        let sales: Vec<&Sale> = self.sales_for_template(template).collect();
+        let in_range = |sale: &Sale| sale.sold_at >= from && sale.sold_at <= now;
+
+        let last_price_wei = price_at(&sales, now);
+        let change_since = |ago: i64| {
+            last_price_wei
+                .zip(price_at(&sales, now - ago))
+                .and_then(|(last, then)| change_basis_points(then, last))
+        };
+        let template_median = median(
+            sales
+                .iter()
+                .filter(|sale| in_range(sale))
+                .map(|sale| sale.price_ntc_wei)
+                .collect(),
+        );
+        let rarity_median_price_wei = median(
+            ItemType::ITEMS
+                .into_iter()
+                .chain([ItemType::BunkerguardRobot])
+                .flat_map(|item_type| {
+                    self.sales_for_template(Template {
+                        item_type,
+                        rarity: template.rarity,
+                    })
+                })
+                .filter(|sale| in_range(sale))
+                .map(|sale| sale.price_ntc_wei)
+                .collect(),
+        );
+        let floor_price_wei = self.floor_price(template);
+
+        PriceHistory {
+            template,
+            candles: candles(&sales, interval, from, now),
+            floor_price_wei,
+            last_price_wei,
+            last_24h: SaleWindow::since(sales.iter().copied(), now - DAY_SECS),
+            change_24h_basis_points: change_since(DAY_SECS),
+            change_7d_basis_points: change_since(7 * DAY_SECS),
+            rarity_median_price_wei,
+            suggested_price_wei: template_median
+                .or(rarity_median_price_wei)
+                .or(floor_price_wei),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smart_stubs::world::{FeeBreakdown, NTC_WEI};
+    use smart_stubs::Dataset;
+
+    const NOW: i64 = 1_800_000_000;
+
+    /// Record a sale of `nft_id` without moving it
+    fn record_sale(world: &mut World, nft_id: &str, price_ntc_wei: u64, sold_at: i64) {
+        let nft = world.nft(nft_id).unwrap().clone();
+        let buyer_id = world.players[0].player_id.clone();
+        world.insert_sale(Sale {
+            listing_id: uuid::Uuid::new_v4().to_string(),
+            nft_id: nft.nft_id,
+            seller_id: nft.owner_id,
+            buyer_id,
+            price_ntc_wei,
+            marketplace_fee_wei: 0,
+            seller_proceeds_wei: price_ntc_wei,
+            fees: FeeBreakdown::default(),
+            sold_at,
+            tx_hash: String::new(),
+        });
+    }
+
+    fn first_nft(world: &World, item_type: ItemType) -> (String, Template) {
+        let nft = world
+            .nfts
+            .iter()
+            .find(|nft| nft.item_type == item_type)
+            .unwrap();
+        (nft.nft_id.clone(), Template::of(nft))
+    }
+
+    #[test]
+    fn test_candles_roll_up_sales_and_fill_gaps() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let (nft_id, template) = first_nft(&world, ItemType::BunkerguardRobot);
+        let day = NOW.div_euclid(DAY_SECS) * DAY_SECS;
+        record_sale(&mut world, &nft_id, 4 * NTC_WEI, day - 3 * DAY_SECS + 10);
+        record_sale(&mut world, &nft_id, 6 * NTC_WEI, day - 3 * DAY_SECS + 20);
+        record_sale(&mut world, &nft_id, 5 * NTC_WEI, day - 3 * DAY_SECS + 30);
+        record_sale(&mut world, &nft_id, 8 * NTC_WEI, day + 5);
+
+        let history = world.price_history(template, CandleInterval::Day, 0, NOW);
+        assert_eq!(history.candles.len(), 4);
+        assert_eq!(
+            history.candles[0],
+            Candle {
+                opens_at: day - 3 * DAY_SECS,
+                open: 4 * NTC_WEI,
+                high: 6 * NTC_WEI,
+                low: 4 * NTC_WEI,
+                close: 5 * NTC_WEI,
+                volume_wei: 15 * NTC_WEI,
+                sales: 3,
+            }
+        );
+        assert_eq!(history.candles[1].sales, 0);
+        assert_eq!(history.candles[2].close, 5 * NTC_WEI);
+        assert_eq!(history.candles[3].open, 8 * NTC_WEI);
+        assert_eq!(history.last_price_wei, Some(8 * NTC_WEI));
+        assert_eq!(history.last_24h.sales, 1);
+        // 5 NTC a day ago to 8 NTC now
+        assert_eq!(history.change_24h_basis_points, Some(6_000));
+        assert_eq!(history.change_7d_basis_points, None);
+    }
+
+    #[test]
+    fn test_medians_drive_the_price_suggestion() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let (robot_id, robot) = first_nft(&world, ItemType::BunkerguardRobot);
+        let other = world
+            .nfts
+            .iter()
+            .find(|nft| nft.rarity == robot.rarity && nft.item_type != robot.item_type)
+            .map(|nft| (nft.nft_id.clone(), Template::of(nft)));
+
+        record_sale(&mut world, &robot_id, 2 * NTC_WEI, NOW - 100);
+        record_sale(&mut world, &robot_id, 4 * NTC_WEI, NOW - 50);
+        let history = world.price_history(robot, CandleInterval::Hour, 0, NOW);
+        assert_eq!(history.suggested_price_wei, Some(3 * NTC_WEI));
+        assert_eq!(history.rarity_median_price_wei, Some(3 * NTC_WEI));
+
+        // A template without sales borrows the median of its rarity tier
+        if let Some((_, template)) = other {
+            let history = world.price_history(template, CandleInterval::Hour, 0, NOW);
+            assert!(history.candles.is_empty());
+            assert_eq!(history.suggested_price_wei, Some(3 * NTC_WEI));
+        }
+    }
+
+    #[test]
+    fn test_sale_window_counts_recent_sales_only() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let (nft_id, _) = first_nft(&world, ItemType::BunkerguardRobot);
+        record_sale(&mut world, &nft_id, 2 * NTC_WEI, NOW - 2 * DAY_SECS);
+        record_sale(&mut world, &nft_id, 3 * NTC_WEI, NOW - 60);
+        record_sale(&mut world, &nft_id, 5 * NTC_WEI, NOW - 30);
+
+        let window = SaleWindow::since(&world.sales, NOW - DAY_SECS);
+        assert_eq!(
+            window,
+            SaleWindow {
+                sales: 2,
+                volume_wei: 8 * NTC_WEI,
+                average_price_wei: 4 * NTC_WEI,
+            }
+        );
+        assert_eq!(world.sales_for_nft(&nft_id).count(), 3);
+    }
+}
@@ -0,0 +1,486 @@
+//! English auctions over order book listings
+//! Bidding with held funds, reserve prices, minimum increments, anti-sniping and settlement
+
+use crate::config::FeeSchedule;
+use crate::feed::{MarketJournal, MarketUpdateKind};
+use crate::fees::Fees;
+use crate::market::MarketError;
+use crate::state::Market;
+use smart_stubs::world::{
+    random_hash, Bid, BidStatus, Currency, EventPayload, Listing, MarketStatus, Sale, World,
+};
+
+/// Each bid must beat the current one by at least 5%
+pub const MIN_BID_INCREMENT_BASIS_POINTS: u64 = 500;
+
+/// Bids landing this close to the end push the end out
+pub const ANTI_SNIPING_WINDOW_SECS: i64 = 5 * 60;
+
+/// Time left on an auction after a late bid extends it
+pub const ANTI_SNIPING_EXTENSION_SECS: i64 = 5 * 60;
+
+const BASIS_POINTS: u64 = 10_000;
+
+/// Outcome of [`Auctions::place_bid`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacedBid {
+    pub bid: Bid,
+    pub minimum_next_bid_wei: u64,
+    pub ends_at: i64,
+    /// Whether the bid triggered anti-sniping
+    pub extended: bool,
+}
+
+/// Outcome of [`Auctions::settle_auction`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuctionSettlement {
+    pub listing: Listing,
+    /// `None` when there were no bids or the reserve was not met
+    pub sale: Option<Sale>,
+    pub tx_hash: String,
+}
+
+/// Standing bids on auction listings
+pub trait Bids {
+    /// Current highest bid on an auction
+    #[must_use]
+    fn highest_bid(&self, listing_id: &str) -> Option<&Bid>;
+
+    /// Smallest bid an auction will accept next
+    #[must_use]
+    fn minimum_next_bid(&self, listing: &Listing) -> u64;
+}
+
+/// Bidding on and settling auction listings
+pub trait Auctions {
+    /// Bid on an open auction, holding the amount from the bidder's balance
+    /// and refunding the bid it overtakes
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the listing or bidder is unknown, the
+    /// listing is not an open auction, the bidder is the seller, the bid is
+    /// below the minimum or the bidder cannot cover it
+    fn place_bid(
+        &mut self,
+        bidder_id: &str,
+        listing_id: &str,
+        amount_ntc_wei: u64,
+        now: i64,
+    ) -> Result<PlacedBid, MarketError>;
+
+    /// Close an auction that has reached its end
+    ///
+    /// The highest bid wins if it meets the reserve: the NFT moves to the
+    /// bidder, the seller and creator are paid according to `fees` and an
+    /// `NftMarketSold` event is emitted.
+    /// Otherwise the held bid is refunded and the NFT stays with the seller.
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the listing is unknown, is not an auction
+    /// or is still accepting bids
+    fn settle_auction(
+        &mut self,
+        listing_id: &str,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<AuctionSettlement, MarketError>;
+}
+
+impl Bids for World {
+    fn highest_bid(&self, listing_id: &str) -> Option<&Bid> {
+        self.bids_for_listing(listing_id)
+            .find(|bid| bid.status == BidStatus::Active)
+    }
+
+    fn minimum_next_bid(&self, listing: &Listing) -> u64 {
+        match self.highest_bid(&listing.listing_id) {
+            Some(bid) => {
+                let increment = (u128::from(bid.amount_ntc_wei)
+                    * u128::from(MIN_BID_INCREMENT_BASIS_POINTS)
+                    / u128::from(BASIS_POINTS)) as u64;
+                bid.amount_ntc_wei.saturating_add(increment.max(1))
+            }
+            None => listing.price_ntc_wei,
+        }
+    }
+}
+
+impl Auctions for Market<'_> {
+    fn place_bid(
+        &mut self,
+        bidder_id: &str,
+        listing_id: &str,
+        amount_ntc_wei: u64,
+        now: i64,
+    ) -> Result<PlacedBid, MarketError> {
+        let listing = self
+            .listing(listing_id)
+            .ok_or_else(|| MarketError::ListingNotFound(listing_id.to_string()))?;
+        let bidder = self
+            .player(bidder_id)
+            .ok_or_else(|| MarketError::PlayerNotFound(bidder_id.to_string()))?;
+        if listing.listing_type != MarketStatus::ListedForAuction {
+            return Err(MarketError::NotAnAuction(listing_id.to_string()));
+        }
+        if listing.expires_at <= now {
+            return Err(MarketError::AuctionEnded(listing_id.to_string()));
+        }
+        if listing.seller_id == bidder_id {
+            return Err(MarketError::OwnListing(listing_id.to_string()));
+        }
+        let minimum = self.minimum_next_bid(listing);
+        if amount_ntc_wei < minimum {
+            return Err(MarketError::BidTooLow {
+                offered: amount_ntc_wei,
+                minimum,
+            });
+        }
+        if bidder.ntc_balance_wei < amount_ntc_wei {
+            return Err(MarketError::InsufficientFunds {
+                player_id: bidder_id.to_string(),
+                required: amount_ntc_wei,
+            });
+        }
+
+        let outbid = self.highest_bid(listing_id).map(|bid| {
+            (
+                bid.bid_id.clone(),
+                bid.bidder_id.clone(),
+                bid.amount_ntc_wei,
+            )
+        });
+        if let Some((bid_id, previous_bidder, amount)) = outbid {
+            refund_bid(self, &bid_id, &previous_bidder, amount, BidStatus::Outbid);
+        }
+        if let Some(bidder) = self.player_mut(bidder_id) {
+            bidder.ntc_balance_wei -= amount_ntc_wei;
+        }
+
+        let listing = self
+            .listing_mut(listing_id)
+            .expect("listing was just found");
+        let extended = listing.expires_at - now <= ANTI_SNIPING_WINDOW_SECS;
+        let previous_end = listing.expires_at;
+        if extended {
+            listing.expires_at = now + ANTI_SNIPING_EXTENSION_SECS;
+        }
+        let ends_at = listing.expires_at;
+        if extended {
+            self.state
+                .expiries
+                .reschedule(listing_id, previous_end, ends_at);
+        }
+
+        let bid = Bid {
+            bid_id: uuid::Uuid::new_v4().to_string(),
+            listing_id: listing_id.to_string(),
+            bidder_id: bidder_id.to_string(),
+            amount_ntc_wei,
+            placed_at: now,
+            status: BidStatus::Active,
+        };
+        self.insert_bid(bid.clone());
+        let listing = self
+            .listing(listing_id)
+            .expect("listing was just found")
+            .clone();
+        self.record_market_update(
+            MarketUpdateKind::PriceChanged,
+            &listing,
+            amount_ntc_wei,
+            None,
+            now,
+        );
+        Ok(PlacedBid {
+            minimum_next_bid_wei: self.minimum_next_bid(&listing),
+            bid,
+            ends_at,
+            extended,
+        })
+    }
+
+    fn settle_auction(
+        &mut self,
+        listing_id: &str,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<AuctionSettlement, MarketError> {
+        let listing = self
+            .listing(listing_id)
+            .ok_or_else(|| MarketError::ListingNotFound(listing_id.to_string()))?;
+        if listing.listing_type != MarketStatus::ListedForAuction {
+            return Err(MarketError::NotAnAuction(listing_id.to_string()));
+        }
+        if listing.expires_at > now {
+            return Err(MarketError::AuctionNotEnded(listing_id.to_string()));
+        }
+        let nft = self
+            .nft(&listing.nft_id)
+            .ok_or_else(|| MarketError::NftNotFound(listing.nft_id.clone()))?
+            .clone();
+        let reserve = listing.reserve_price_ntc_wei;
+        let winning = self.highest_bid(listing_id).map(|bid| {
+            (
+                bid.bid_id.clone(),
+                bid.bidder_id.clone(),
+                bid.amount_ntc_wei,
+            )
+        });
+
+        let listing = self
+            .remove_listing(listing_id)
+            .expect("listing was just found");
+        let tx_hash = random_hash(&mut rand::thread_rng());
+        let (bid_id, winner_id, price) = match winning {
+            Some(winning) if winning.2 >= reserve => winning,
+            unsold => {
+                if let Some((bid_id, bidder_id, amount)) = unsold {
+                    refund_bid(self, &bid_id, &bidder_id, amount, BidStatus::Rejected);
+                }
+                self.record_market_update(
+                    MarketUpdateKind::Expired,
+                    &listing,
+                    listing.price_ntc_wei,
+                    None,
+                    now,
+                );
+                return Ok(AuctionSettlement {
+                    listing,
+                    sale: None,
+                    tx_hash,
+                });
+            }
+        };
+
+        let fees = self.fee_breakdown(fees, &nft, &listing.seller_id, price);
+        let marketplace = self.contracts.marketplace.clone();
+        let sold_at = self
+            .append_event(
+                marketplace,
+                tx_hash.clone(),
+                now,
+                EventPayload::NftMarketSold {
+                    nft_id: listing.nft_id.clone(),
+                    player_id: winner_id.clone(),
+                    seller_id: listing.seller_id.clone(),
+                    price_ntc_wei: price,
+                    marketplace_fee_wei: fees.marketplace_fee,
+                    royalty_wei: fees.royalty,
+                },
+            )
+            .block_timestamp;
+        if let Some(bid) = self.bid_mut(&bid_id) {
+            bid.status = BidStatus::Accepted;
+        }
+        // The winning amount was already held from the bidder when they bid
+        self.pay_out(&listing.seller_id, &fees, Currency::Ntc);
+        self.transfer_nft(&listing.nft_id, &winner_id, sold_at);
+        self.record_market_update(
+            MarketUpdateKind::Sold,
+            &listing,
+            price,
+            Some(&winner_id),
+            sold_at,
+        );
+
+        let sale = Sale {
+            listing_id: listing.listing_id.clone(),
+            nft_id: listing.nft_id.clone(),
+            seller_id: listing.seller_id.clone(),
+            buyer_id: winner_id,
+            price_ntc_wei: price,
+            marketplace_fee_wei: fees.marketplace_fee,
+            seller_proceeds_wei: fees.seller_proceeds,
+            fees,
+            sold_at,
+            tx_hash: tx_hash.clone(),
+        };
+        self.insert_sale(sale.clone());
+        Ok(AuctionSettlement {
+            listing,
+            sale: Some(sale),
+            tx_hash,
+        })
+    }
+}
+
+fn refund_bid(world: &mut World, bid_id: &str, bidder_id: &str, amount: u64, status: BidStatus) {
+    if let Some(bid) = world.bid_mut(bid_id) {
+        bid.status = status;
+    }
+    if let Some(bidder) = world.player_mut(bidder_id) {
+        bidder.ntc_balance_wei = bidder.ntc_balance_wei.saturating_add(amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::{Marketability, NewListing, OrderBook};
+    use crate::state::{Market, MarketState};
+    use smart_stubs::stub::WorldState;
+    use smart_stubs::world::NTC_WEI;
+    use smart_stubs::Dataset;
+
+    const NOW: i64 = 1_800_000_000;
+    const HOUR: i64 = 3_600;
+
+    /// World and market state with one fresh auction and two funded bidders
+    fn auction(reserve_price_ntc_wei: u64) -> (World, MarketState, String, String, String) {
+        let mut world = World::generate(Dataset::Minimal, 31);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let nft = market
+            .nfts
+            .iter()
+            .find(|nft| market.is_marketable(nft))
+            .unwrap()
+            .clone();
+        let bidders: Vec<String> = market
+            .players
+            .iter()
+            .filter(|player| player.player_id != nft.owner_id)
+            .take(2)
+            .map(|player| player.player_id.clone())
+            .collect();
+        for bidder in &bidders {
+            market.player_mut(bidder).unwrap().ntc_balance_wei = 10 * NTC_WEI;
+        }
+        let listing_id = market
+            .create_listing(
+                NewListing {
+                    seller_id: nft.owner_id.clone(),
+                    nft_id: nft.nft_id.clone(),
+                    price: NTC_WEI,
+                    currency: Currency::Ntc,
+                    listing_type: MarketStatus::ListedForAuction,
+                    reserve_price_ntc_wei,
+                    expires_at: NOW + HOUR,
+                },
+                NOW,
+            )
+            .unwrap()
+            .listing_id
+            .clone();
+        (
+            world,
+            state,
+            listing_id,
+            bidders[0].clone(),
+            bidders[1].clone(),
+        )
+    }
+
+    #[test]
+    fn test_bids_must_clear_minimum_increment() {
+        let (mut world, mut state, listing_id, alice, bob) = auction(0);
+        let mut market = Market::new(&mut world, &mut state);
+
+        assert!(matches!(
+            market.place_bid(&alice, &listing_id, NTC_WEI / 2, NOW),
+            Err(MarketError::BidTooLow { .. })
+        ));
+        let placed = market.place_bid(&alice, &listing_id, NTC_WEI, NOW).unwrap();
+        assert_eq!(placed.minimum_next_bid_wei, NTC_WEI + NTC_WEI / 20);
+        assert!(!placed.extended);
+        assert_eq!(market.player(&alice).unwrap().ntc_balance_wei, 9 * NTC_WEI);
+
+        assert!(matches!(
+            market.place_bid(&bob, &listing_id, NTC_WEI + 1, NOW),
+            Err(MarketError::BidTooLow { .. })
+        ));
+        market
+            .place_bid(&bob, &listing_id, 2 * NTC_WEI, NOW + 60)
+            .unwrap();
+
+        // Alice was refunded when Bob took the lead
+        assert_eq!(market.player(&alice).unwrap().ntc_balance_wei, 10 * NTC_WEI);
+        let statuses: Vec<BidStatus> = market
+            .bids_for_listing(&listing_id)
+            .map(|bid| bid.status)
+            .collect();
+        assert_eq!(statuses, vec![BidStatus::Outbid, BidStatus::Active]);
+        assert_eq!(
+            market.cancel_listing(
+                &market.listing(&listing_id).unwrap().seller_id.clone(),
+                &listing_id,
+                NOW
+            ),
+            Err(MarketError::AuctionHasBids(listing_id.clone()))
+        );
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_late_bid_extends_auction() {
+        let (mut world, mut state, listing_id, alice, _) = auction(0);
+        let mut market = Market::new(&mut world, &mut state);
+
+        let late = NOW + HOUR - 30;
+        let placed = market
+            .place_bid(&alice, &listing_id, NTC_WEI, late)
+            .unwrap();
+        assert!(placed.extended);
+        assert_eq!(placed.ends_at, late + ANTI_SNIPING_EXTENSION_SECS);
+        assert_eq!(
+            market.settle_auction(&listing_id, &FeeSchedule::default(), NOW + HOUR),
+            Err(MarketError::AuctionNotEnded(listing_id.clone()))
+        );
+    }
+
+    #[test]
+    fn test_settlement_sells_to_highest_bidder() {
+        let (mut world, mut state, listing_id, alice, bob) = auction(NTC_WEI);
+        let mut market = Market::new(&mut world, &mut state);
+        let listing = market.listing(&listing_id).unwrap().clone();
+        let seller_balance = market.player(&listing.seller_id).unwrap().ntc_balance_wei;
+        market.place_bid(&alice, &listing_id, NTC_WEI, NOW).unwrap();
+        market
+            .place_bid(&bob, &listing_id, 3 * NTC_WEI, NOW + 60)
+            .unwrap();
+
+        let settlement = market
+            .settle_auction(&listing_id, &FeeSchedule::default(), NOW + HOUR)
+            .unwrap();
+        let sale = settlement.sale.unwrap();
+        assert_eq!(sale.buyer_id, bob);
+        assert_eq!(sale.price_ntc_wei, 3 * NTC_WEI);
+        assert_eq!(market.nft(&listing.nft_id).unwrap().owner_id, bob);
+        assert_eq!(market.player(&bob).unwrap().ntc_balance_wei, 7 * NTC_WEI);
+        assert_eq!(
+            market.player(&listing.seller_id).unwrap().ntc_balance_wei,
+            seller_balance + sale.seller_proceeds_wei
+        );
+        assert_eq!(
+            market.events.last().unwrap().payload.event_type(),
+            "NftMarketSold"
+        );
+        assert!(market.listing(&listing_id).is_none());
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_unmet_reserve_refunds_and_keeps_nft() {
+        let (mut world, mut state, listing_id, alice, _) = auction(5 * NTC_WEI);
+        let mut market = Market::new(&mut world, &mut state);
+        let listing = market.listing(&listing_id).unwrap().clone();
+        market
+            .place_bid(&alice, &listing_id, 2 * NTC_WEI, NOW)
+            .unwrap();
+
+        let settlement = market
+            .settle_auction(&listing_id, &FeeSchedule::default(), NOW + HOUR)
+            .unwrap();
+        assert!(settlement.sale.is_none());
+        assert_eq!(
+            market.nft(&listing.nft_id).unwrap().owner_id,
+            listing.seller_id
+        );
+        assert_eq!(market.player(&alice).unwrap().ntc_balance_wei, 10 * NTC_WEI);
+        assert_eq!(
+            market.bids_for_listing(&listing_id).next().unwrap().status,
+            BidStatus::Rejected
+        );
+        market.validate().unwrap();
+    }
+}
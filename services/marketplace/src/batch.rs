@@ -0,0 +1,428 @@
+//! Batch order book operations for power traders
+//! Listing, cancelling and buying several items in one request, either all or
+//! nothing or as many as succeed, with a result for every item
+
+use crate::config::FeeSchedule;
+use crate::credits::{Credits, CreditsSale};
+use crate::escrow::{Escrow, Escrows};
+use crate::market::{MarketError, NewListing, OrderBook};
+use crate::state::Market;
+use smart_stubs::world::{Listing, Sale};
+
+/// Most items accepted in one batch request
+pub const MAX_BATCH_ITEMS: usize = 50;
+
+/// How a batch treats items that fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchMode {
+    /// Any failure leaves the world as it was before the batch
+    #[default]
+    AllOrNothing,
+    /// Items that succeed stay applied whatever happens to the others
+    BestEffort,
+}
+
+impl BatchMode {
+    /// Mirrors `BatchModeProto`; unspecified falls back to all or nothing
+    #[must_use]
+    pub fn from_proto(value: i32) -> Self {
+        match value {
+            2 => Self::BestEffort,
+            _ => Self::AllOrNothing,
+        }
+    }
+}
+
+/// What happened to one item of a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchItemResult<T> {
+    Applied(T),
+    Failed(MarketError),
+    /// Succeeded on its own but was undone because another item failed
+    RolledBack,
+}
+
+impl<T> BatchItemResult<T> {
+    #[must_use]
+    pub fn is_applied(&self) -> bool {
+        matches!(self, Self::Applied(_))
+    }
+}
+
+/// Results of a batch in request order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchOutcome<T> {
+    pub results: Vec<BatchItemResult<T>>,
+    /// False when an all or nothing batch was rolled back
+    pub committed: bool,
+}
+
+impl<T> BatchOutcome<T> {
+    #[must_use]
+    pub fn applied(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.is_applied())
+            .count()
+    }
+}
+
+/// One listing in a shopping cart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeIntent {
+    pub listing_id: String,
+    /// In wei for NTC listings, whole credits otherwise
+    pub offered_price: u64,
+}
+
+/// Order book operations applied to many items at once
+pub trait Batches {
+    /// Open a listing for each request, as [`World::create_listing`] would
+    fn create_listings(
+        &mut self,
+        requests: Vec<NewListing>,
+        mode: BatchMode,
+        now: i64,
+    ) -> BatchOutcome<Listing>;
+
+    /// Withdraw each of the seller's listings, as [`World::cancel_listing`] would
+    fn cancel_listings(
+        &mut self,
+        player_id: &str,
+        listing_ids: &[String],
+        mode: BatchMode,
+        now: i64,
+    ) -> BatchOutcome<Listing>;
+
+    /// Buy every listing in a cart, as [`World::execute_trade`] would
+    ///
+    /// Items are paid for in order, so a buyer who can only afford part of
+    /// the cart gets the earlier listings in best effort mode.
+    fn execute_trades(
+        &mut self,
+        buyer_id: &str,
+        cart: Vec<TradeIntent>,
+        mode: BatchMode,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> BatchOutcome<Sale>;
+
+    /// Buy every credits listing in a cart, as [`World::execute_credits_trade`] would
+    fn execute_credits_trades(
+        &mut self,
+        buyer_id: &str,
+        cart: Vec<TradeIntent>,
+        mode: BatchMode,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> BatchOutcome<CreditsSale>;
+
+    /// Reserve every listing in a cart, as [`World::reserve_trade`] would
+    fn reserve_trades(
+        &mut self,
+        buyer_id: &str,
+        cart: Vec<TradeIntent>,
+        mode: BatchMode,
+        fees: &FeeSchedule,
+        now: i64,
+        lock_secs: i64,
+    ) -> BatchOutcome<Escrow>;
+}
+
+impl Batches for Market<'_> {
+    fn create_listings(
+        &mut self,
+        requests: Vec<NewListing>,
+        mode: BatchMode,
+        now: i64,
+    ) -> BatchOutcome<Listing> {
+        apply_batch(self, mode, requests, |market, request| {
+            market.create_listing(request, now).cloned()
+        })
+    }
+
+    fn cancel_listings(
+        &mut self,
+        player_id: &str,
+        listing_ids: &[String],
+        mode: BatchMode,
+        now: i64,
+    ) -> BatchOutcome<Listing> {
+        apply_batch(self, mode, listing_ids, |market, listing_id| {
+            market.cancel_listing(player_id, listing_id, now)
+        })
+    }
+
+    fn execute_trades(
+        &mut self,
+        buyer_id: &str,
+        cart: Vec<TradeIntent>,
+        mode: BatchMode,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> BatchOutcome<Sale> {
+        apply_batch(self, mode, cart, |market, intent| {
+            market.execute_trade(
+                buyer_id,
+                &intent.listing_id,
+                intent.offered_price,
+                fees,
+                now,
+            )
+        })
+    }
+
+    fn execute_credits_trades(
+        &mut self,
+        buyer_id: &str,
+        cart: Vec<TradeIntent>,
+        mode: BatchMode,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> BatchOutcome<CreditsSale> {
+        apply_batch(self, mode, cart, |market, intent| {
+            market.execute_credits_trade(
+                buyer_id,
+                &intent.listing_id,
+                intent.offered_price,
+                fees,
+                now,
+            )
+        })
+    }
+
+    fn reserve_trades(
+        &mut self,
+        buyer_id: &str,
+        cart: Vec<TradeIntent>,
+        mode: BatchMode,
+        fees: &FeeSchedule,
+        now: i64,
+        lock_secs: i64,
+    ) -> BatchOutcome<Escrow> {
+        apply_batch(self, mode, cart, |market, intent| {
+            market.reserve_trade(
+                buyer_id,
+                &intent.listing_id,
+                intent.offered_price,
+                fees,
+                now,
+                lock_secs,
+            )
+        })
+    }
+}
+
+/// Apply every item in order, each through the same checks as its single
+/// item counterpart
+///
+/// Every item is attempted even after a failure so the caller learns about
+/// all of them at once. An all or nothing batch with a failure restores the
+/// world and market state from a snapshot taken before the first item.
+fn apply_batch<I, T>(
+    market: &mut Market<'_>,
+    mode: BatchMode,
+    items: impl IntoIterator<Item = I>,
+    mut apply: impl FnMut(&mut Market<'_>, I) -> Result<T, MarketError>,
+) -> BatchOutcome<T> {
+    let snapshot = (mode == BatchMode::AllOrNothing).then(|| market.snapshot());
+    let mut results: Vec<_> = items
+        .into_iter()
+        .map(|item| match apply(market, item) {
+            Ok(applied) => BatchItemResult::Applied(applied),
+            Err(err) => BatchItemResult::Failed(err),
+        })
+        .collect();
+    let failed = results.iter().any(|result| !result.is_applied());
+    let committed = match snapshot {
+        Some(snapshot) if failed => {
+            market.restore(snapshot);
+            for result in &mut results {
+                if result.is_applied() {
+                    *result = BatchItemResult::RolledBack;
+                }
+            }
+            false
+        }
+        _ => true,
+    };
+    BatchOutcome { results, committed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::Marketability;
+    use crate::state::{Market, MarketState};
+    use smart_stubs::stub::WorldState;
+    use smart_stubs::world::{Currency, MarketStatus, World, NTC_WEI};
+    use smart_stubs::Dataset;
+
+    const NOW: i64 = 1_800_000_000;
+
+    fn new_listing(seller_id: &str, nft_id: &str) -> NewListing {
+        NewListing {
+            seller_id: seller_id.to_string(),
+            nft_id: nft_id.to_string(),
+            price: 2 * NTC_WEI,
+            currency: Currency::Ntc,
+            listing_type: MarketStatus::ListedForSale,
+            reserve_price_ntc_wei: 0,
+            expires_at: 0,
+        }
+    }
+
+    /// A seller and two NFTs they could list
+    fn seller_with_two_marketable(world: &World) -> (String, Vec<String>) {
+        world
+            .players
+            .iter()
+            .find_map(|player| {
+                let nft_ids: Vec<_> = world
+                    .nfts
+                    .iter()
+                    .filter(|nft| nft.owner_id == player.player_id && world.is_marketable(nft))
+                    .map(|nft| nft.nft_id.clone())
+                    .take(2)
+                    .collect();
+                (nft_ids.len() == 2).then(|| (player.player_id.clone(), nft_ids))
+            })
+            .expect("minimal world has a seller with two marketable NFTs")
+    }
+
+    #[test]
+    fn test_all_or_nothing_rolls_back_on_any_failure() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let (seller_id, nft_ids) = seller_with_two_marketable(&market);
+        let listings = market.listings.len();
+        let last_sequence = market.state.feed.last_sequence();
+
+        let outcome = market.create_listings(
+            vec![
+                new_listing(&seller_id, &nft_ids[0]),
+                new_listing(&seller_id, "not-a-uuid"),
+                new_listing(&seller_id, &nft_ids[1]),
+            ],
+            BatchMode::AllOrNothing,
+            NOW,
+        );
+        assert!(!outcome.committed);
+        assert_eq!(outcome.results[0], BatchItemResult::RolledBack);
+        assert_eq!(
+            outcome.results[1],
+            BatchItemResult::Failed(MarketError::InvalidNftId("not-a-uuid".to_string()))
+        );
+        assert_eq!(outcome.results[2], BatchItemResult::RolledBack);
+        assert_eq!(market.listings.len(), listings);
+        assert_eq!(market.state.feed.last_sequence(), last_sequence);
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_best_effort_keeps_the_items_that_succeed() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let (seller_id, nft_ids) = seller_with_two_marketable(&market);
+        let stranger_nft = market
+            .nfts
+            .iter()
+            .find(|nft| nft.owner_id != seller_id)
+            .unwrap()
+            .nft_id
+            .clone();
+
+        let outcome = market.create_listings(
+            vec![
+                new_listing(&seller_id, &nft_ids[0]),
+                new_listing(&seller_id, &stranger_nft),
+                new_listing(&seller_id, &nft_ids[1]),
+            ],
+            BatchMode::BestEffort,
+            NOW,
+        );
+        assert!(outcome.committed);
+        assert_eq!(outcome.applied(), 2);
+        assert!(matches!(
+            outcome.results[1],
+            BatchItemResult::Failed(MarketError::NotOwner { .. })
+        ));
+
+        let listing_ids: Vec<_> = outcome
+            .results
+            .iter()
+            .filter_map(|result| match result {
+                BatchItemResult::Applied(listing) => Some(listing.listing_id.clone()),
+                _ => None,
+            })
+            .collect();
+        let cancelled =
+            market.cancel_listings("someone-else", &listing_ids, BatchMode::BestEffort, NOW);
+        assert_eq!(cancelled.applied(), 0);
+        let cancelled =
+            market.cancel_listings(&seller_id, &listing_ids, BatchMode::BestEffort, NOW);
+        assert_eq!(cancelled.applied(), 2);
+        assert!(listing_ids
+            .iter()
+            .all(|listing_id| market.listing(listing_id).is_none()));
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_cart_checkout_is_atomic_unless_best_effort() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let (seller_id, nft_ids) = seller_with_two_marketable(&market);
+        let cart: Vec<_> = market
+            .create_listings(
+                nft_ids
+                    .iter()
+                    .map(|nft_id| new_listing(&seller_id, nft_id))
+                    .collect(),
+                BatchMode::AllOrNothing,
+                NOW,
+            )
+            .results
+            .into_iter()
+            .map(|result| match result {
+                BatchItemResult::Applied(listing) => TradeIntent {
+                    listing_id: listing.listing_id,
+                    offered_price: listing.price_ntc_wei,
+                },
+                other => panic!("listing failed: {other:?}"),
+            })
+            .collect();
+        let buyer_id = market
+            .players
+            .iter()
+            .find(|player| player.player_id != seller_id)
+            .unwrap()
+            .player_id
+            .clone();
+        // Enough for one of the two listings
+        market.player_mut(&buyer_id).unwrap().ntc_balance_wei = 3 * NTC_WEI;
+        let fees = FeeSchedule::default();
+
+        let outcome =
+            market.execute_trades(&buyer_id, cart.clone(), BatchMode::AllOrNothing, &fees, NOW);
+        assert!(!outcome.committed);
+        assert!(matches!(
+            outcome.results[1],
+            BatchItemResult::Failed(MarketError::InsufficientFunds { .. })
+        ));
+        assert_eq!(
+            market.player(&buyer_id).unwrap().ntc_balance_wei,
+            3 * NTC_WEI
+        );
+        assert_eq!(market.nft(&nft_ids[0]).unwrap().owner_id, seller_id);
+
+        let outcome = market.execute_trades(&buyer_id, cart, BatchMode::BestEffort, &fees, NOW);
+        assert_eq!(outcome.applied(), 1);
+        assert_eq!(market.nft(&nft_ids[0]).unwrap().owner_id, buyer_id);
+        assert_eq!(market.player(&buyer_id).unwrap().ntc_balance_wei, NTC_WEI);
+        market.validate().unwrap();
+    }
+}
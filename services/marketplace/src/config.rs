@@ -1,121 +1,273 @@
+//! Marketplace service stub configuration
+//! The shared stub settings, extended with the fee schedule and escrow settings
+
+use anyhow::{anyhow, bail, Result};
 use serde::{Deserialize, Serialize};
+use smart_stubs::world::{ItemRarity, ItemType, Nft};
+use std::collections::BTreeMap;
+use tracing::warn;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct StubConfiguration {
-    pub base: BaseConfig,
-    pub dual_mode: DualModeConfig,
-    pub latency: LatencyConfig,
-    pub errors: ErrorConfig,
-    pub data: DataConfig,
-}
+pub use smart_stubs::{ConfigSource, StubConfiguration};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct BaseConfig {
-    pub name: String,
-    pub version: String,
-    pub port: u16,
-    pub enabled: bool,
+/// Configuration source for the marketplace service stub
+///
+/// Starts from the service defaults, then reads the file named by
+/// `STUB_CONFIG_PATH` (YAML, TOML or JSON) and `STUB__` env overrides.
+pub fn config_source() -> ConfigSource {
+    ConfigSource::from_env(defaults())
+        .with_validator(|config| MarketplaceConfig::parse(config)?.validate())
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct DualModeConfig {
-    pub enable_crypto: bool,
-    pub crypto_response_mode: CryptoResponseMode,
+fn defaults() -> StubConfiguration {
+    StubConfiguration::for_service("marketplace-service-stub", 8081)
+        .with_extension(&MarketplaceConfig::default())
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum CryptoResponseMode {
-    Disabled,
-    Error,
-    Mock,
+/// Sections the marketplace adds to the shared stub configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MarketplaceConfig {
+    /// Fees charged on marketplace sales
+    pub fees: FeeSchedule,
+    /// Two-phase settlement of marketplace purchases
+    pub escrow: EscrowConfig,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct LatencyConfig {
-    pub min_response_time_ms: u64,
-    pub max_response_time_ms: u64,
-    pub distribution: LatencyDistribution,
-    pub network_condition: NetworkCondition,
-}
+impl MarketplaceConfig {
+    /// Marketplace sections of `config`
+    ///
+    /// # Errors
+    /// Returns an error if the `fees` or `escrow` section does not parse
+    pub fn parse(config: &StubConfiguration) -> Result<Self> {
+        config.extension()
+    }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum LatencyDistribution {
-    Uniform,
-    Normal,
-    Exponential,
+    /// Marketplace sections of the running configuration
+    ///
+    /// Loaded configurations were validated already; sections that no longer
+    /// parse fall back to the defaults.
+    #[must_use]
+    pub fn of(config: &StubConfiguration) -> Self {
+        Self::parse(config).unwrap_or_else(|err| {
+            warn!(error = %err, "Invalid marketplace configuration, using the defaults");
+            Self::default()
+        })
+    }
+
+    /// Check the fee rates and the escrow lock timeout
+    ///
+    /// # Errors
+    /// Returns an error if either section is unusable
+    pub fn validate(&self) -> Result<()> {
+        self.fees.validate()?;
+        self.escrow.validate()
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum NetworkCondition {
-    Good,
-    Poor,
-    Variable,
+/// Platform fee taken from a sale when no override applies, 2.5%
+pub const DEFAULT_PLATFORM_FEE_BASIS_POINTS: u32 = 250;
+
+const BASIS_POINTS: u32 = 10_000;
+
+/// Discount on the platform fee for sellers staking at `min_tier` or above
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StakingDiscount {
+    /// Lowest `NtcStakingDetailsProto.staking_tier` that earns the discount
+    pub min_tier: u32,
+    /// Share of the platform fee waived
+    pub discount_basis_points: u32,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct ErrorConfig {
-    pub error_rate: f64,
-    pub specific_errors: Vec<SpecificError>,
-    pub timeout_rate: f64,
+/// Fee rates applied to every marketplace sale
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FeeSchedule {
+    pub platform_fee_basis_points: u32,
+    /// Platform fee for items of a rarity
+    pub rarity_overrides: BTreeMap<ItemRarity, u32>,
+    /// Platform fee for an item type; wins over a rarity override
+    pub item_type_overrides: BTreeMap<ItemType, u32>,
+    /// Paid to the NFT's creator whenever someone else sells it
+    pub creator_royalty_basis_points: u32,
+    /// The highest tier the seller reaches applies
+    pub staking_discounts: Vec<StakingDiscount>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct SpecificError {
-    pub endpoint: String,
-    pub status_code: u16,
-    pub rate: f64,
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self {
+            platform_fee_basis_points: DEFAULT_PLATFORM_FEE_BASIS_POINTS,
+            rarity_overrides: BTreeMap::new(),
+            item_type_overrides: BTreeMap::new(),
+            creator_royalty_basis_points: 0,
+            staking_discounts: Vec::new(),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct DataConfig {
-    pub dataset: Dataset,
-    pub persist_state: bool,
-    pub state_reset_interval: String,
+impl FeeSchedule {
+    /// Check every rate is a valid share of a sale
+    ///
+    /// # Errors
+    /// Returns an error if a rate exceeds 100% or the largest platform fee
+    /// plus the royalty could exceed the price
+    pub fn validate(&self) -> Result<()> {
+        let platform_rates = std::iter::once(self.platform_fee_basis_points)
+            .chain(self.rarity_overrides.values().copied())
+            .chain(self.item_type_overrides.values().copied());
+        let highest_fee = platform_rates.max().unwrap_or_default();
+        if highest_fee.saturating_add(self.creator_royalty_basis_points) > BASIS_POINTS {
+            bail!(
+                "platform fee of up to {highest_fee} and royalty of {} basis points exceed the sale price",
+                self.creator_royalty_basis_points
+            );
+        }
+        if let Some(discount) = self
+            .staking_discounts
+            .iter()
+            .find(|discount| discount.discount_basis_points > BASIS_POINTS)
+        {
+            bail!(
+                "staking tier {} discount of {} basis points exceeds the platform fee",
+                discount.min_tier,
+                discount.discount_basis_points
+            );
+        }
+        Ok(())
+    }
+
+    /// Platform fee rate for `nft` before any staking discount
+    #[must_use]
+    pub fn platform_fee_basis_points(&self, nft: &Nft) -> u32 {
+        self.item_type_overrides
+            .get(&nft.item_type)
+            .or_else(|| self.rarity_overrides.get(&nft.rarity))
+            .copied()
+            .unwrap_or(self.platform_fee_basis_points)
+    }
+
+    /// Share of the platform fee waived for a seller staking at `tier`
+    #[must_use]
+    pub fn staking_discount_basis_points(&self, tier: u32) -> u32 {
+        self.staking_discounts
+            .iter()
+            .filter(|discount| discount.min_tier <= tier)
+            .max_by_key(|discount| discount.min_tier)
+            .map_or(0, |discount| discount.discount_basis_points)
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum Dataset {
-    Minimal,
-    Development,
-    StressTest,
+/// Lock lifetime when the configuration does not set a valid one
+pub const DEFAULT_LOCK_TIMEOUT_SECS: i64 = 300;
+
+/// Two-phase settlement settings
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EscrowConfig {
+    /// Hold purchases until their transaction settles; when off, trades complete at once
+    pub enabled: bool,
+    /// Duration such as `5m` after which an unsettled purchase is rolled back
+    pub lock_timeout: String,
 }
 
-impl Default for StubConfiguration {
+impl Default for EscrowConfig {
     fn default() -> Self {
         Self {
-            base: BaseConfig {
-                name: "marketplace-service-stub".to_string(),
-                version: "1.0.0".to_string(),
-                port: 8081,
-                enabled: true,
-            },
-            dual_mode: DualModeConfig {
-                enable_crypto: std::env::var("ENABLE_CRYPTO")
-                    .unwrap_or_else(|_| "false".to_string())
-                    .parse()
-                    .unwrap_or(false),
-                crypto_response_mode: CryptoResponseMode::Disabled,
-            },
-            latency: LatencyConfig {
-                min_response_time_ms: 10,
-                max_response_time_ms: 500,
-                distribution: LatencyDistribution::Normal,
-                network_condition: NetworkCondition::Good,
-            },
-            errors: ErrorConfig {
-                error_rate: 0.05,
-                specific_errors: vec![],
-                timeout_rate: 0.01,
-            },
-            data: DataConfig {
-                dataset: Dataset::Development,
-                persist_state: false,
-                state_reset_interval: "24h".to_string(),
-            },
+            enabled: true,
+            lock_timeout: "5m".to_string(),
+        }
+    }
+}
+
+impl EscrowConfig {
+    /// Check the lock timeout is a positive duration
+    ///
+    /// # Errors
+    /// Returns an error if `lock_timeout` does not parse or is zero
+    pub fn validate(&self) -> Result<()> {
+        let timeout = common_rust::time::parse_duration(&self.lock_timeout)
+            .map_err(|err| anyhow!("invalid lock_timeout '{}': {err}", self.lock_timeout))?;
+        if timeout.is_zero() {
+            return Err(anyhow!("lock_timeout must be greater than zero"));
         }
+        Ok(())
+    }
+
+    /// Parsed `lock_timeout` in seconds, [`DEFAULT_LOCK_TIMEOUT_SECS`] if invalid
+    #[must_use]
+    pub fn lock_secs(&self) -> i64 {
+        common_rust::time::parse_duration(&self.lock_timeout)
+            .ok()
+            .filter(|timeout| !timeout.is_zero())
+            .map_or(DEFAULT_LOCK_TIMEOUT_SECS, |timeout| {
+                timeout.as_secs().max(1) as i64
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_rejects_rates_above_the_price() {
+        assert!(FeeSchedule::default().validate().is_ok());
+
+        let schedule = FeeSchedule {
+            rarity_overrides: BTreeMap::from([(ItemRarity::Eternal, 9_600)]),
+            creator_royalty_basis_points: 500,
+            ..FeeSchedule::default()
+        };
+        assert!(schedule.validate().is_err());
+
+        let schedule = FeeSchedule {
+            staking_discounts: vec![StakingDiscount {
+                min_tier: 2,
+                discount_basis_points: 12_000,
+            }],
+            ..FeeSchedule::default()
+        };
+        assert!(schedule.validate().is_err());
+    }
+
+    #[test]
+    fn test_schedule_deserializes_partial_config() {
+        let mut config = defaults();
+        config.extensions.insert(
+            "fees".to_string(),
+            serde_json::json!({
+                "item_type_overrides": {"cosmetic_skin": 100},
+                "staking_discounts": [{"min_tier": 5, "discount_basis_points": 2500}],
+            }),
+        );
+        let MarketplaceConfig {
+            fees: schedule,
+            escrow,
+        } = MarketplaceConfig::parse(&config).unwrap();
+        assert_eq!(escrow, EscrowConfig::default());
+        assert_eq!(schedule.platform_fee_basis_points, 250);
+        assert_eq!(
+            schedule.item_type_overrides.get(&ItemType::CosmeticSkin),
+            Some(&100)
+        );
+        assert_eq!(schedule.staking_discount_basis_points(7), 2_500);
+        assert_eq!(schedule.staking_discount_basis_points(4), 0);
+    }
+
+    #[test]
+    fn test_lock_timeout_parses_with_fallback() {
+        let config = EscrowConfig {
+            lock_timeout: "90s".to_string(),
+            ..EscrowConfig::default()
+        };
+        assert_eq!(config.lock_secs(), 90);
+        assert!(config.validate().is_ok());
+
+        let config = EscrowConfig {
+            lock_timeout: "soon".to_string(),
+            ..EscrowConfig::default()
+        };
+        assert_eq!(config.lock_secs(), DEFAULT_LOCK_TIMEOUT_SECS);
+        assert!(config.validate().is_err());
     }
 }
@@ -0,0 +1,322 @@
+//! Off-chain marketplace settlement for players without wallets
+//! Credits listings are paid for from credits balances through the payment
+//! ledger, and the NFT changes hands locally without an L3 transaction
+
+use crate::config::FeeSchedule;
+use crate::feed::{MarketJournal, MarketUpdateKind};
+use crate::fees::Fees;
+use crate::market::{MarketError, OrderBook};
+use crate::state::Market;
+use smart_stubs::world::{
+    CreditLedgerEntry, CreditTransactionType, Currency, FeeBreakdown, Listing, World,
+};
+
+/// Purchase of a credits listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreditsSale {
+    /// The listing as it was when bought
+    pub listing: Listing,
+    pub buyer_id: String,
+    /// Whole credits taken from the buyer
+    pub price_credits: u64,
+    pub fees: FeeBreakdown,
+    /// Ledger entry of the buyer's payment
+    pub transaction_id: String,
+    pub sold_at: i64,
+}
+
+/// Trades settled on the off-chain credits ledger
+pub trait Credits {
+    /// Buy a credits listing, settling on the payment ledger
+    ///
+    /// The buyer is debited the asking price and the seller and the NFT's
+    /// creator are credited their shares according to `fees`, each movement
+    /// recorded in [`World::credit_ledger`]. No chain event is emitted and
+    /// the sale does not enter the NTC price history.
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the listing or buyer is unknown, the listing
+    /// is not an open credits sale, or the buyer cannot cover the asking price
+    fn execute_credits_trade(
+        &mut self,
+        buyer_id: &str,
+        listing_id: &str,
+        offered_price_credits: u64,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<CreditsSale, MarketError>;
+}
+
+impl Credits for Market<'_> {
+    fn execute_credits_trade(
+        &mut self,
+        buyer_id: &str,
+        listing_id: &str,
+        offered_price_credits: u64,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<CreditsSale, MarketError> {
+        let (price, fees) = self.check_purchase(
+            buyer_id,
+            listing_id,
+            offered_price_credits,
+            Currency::Credits,
+            fees,
+            now,
+        )?;
+        let listing = self
+            .remove_listing(listing_id)
+            .expect("purchased listing is open");
+
+        if let Some(buyer) = self.player_mut(buyer_id) {
+            buyer.credits_balance -= price;
+        }
+        let transaction_id = record_credit_movement(
+            self,
+            buyer_id,
+            CreditTransactionType::Spend,
+            -ledger_amount(price),
+            format!("Bought NFT {}", listing.nft_id),
+            &listing.listing_id,
+            now,
+        );
+        self.pay_out(&listing.seller_id, &fees, Currency::Credits);
+        record_credit_movement(
+            self,
+            &listing.seller_id,
+            CreditTransactionType::Sale,
+            ledger_amount(fees.seller_proceeds),
+            format!("Sold NFT {}", listing.nft_id),
+            &listing.listing_id,
+            now,
+        );
+        if let Some(creator_id) = fees.royalty_recipient_id.clone() {
+            record_credit_movement(
+                self,
+                &creator_id,
+                CreditTransactionType::Sale,
+                ledger_amount(fees.royalty),
+                format!("Royalty on NFT {}", listing.nft_id),
+                &listing.listing_id,
+                now,
+            );
+        }
+
+        self.transfer_nft(&listing.nft_id, buyer_id, now);
+        self.record_market_update(MarketUpdateKind::Sold, &listing, price, Some(buyer_id), now);
+
+        Ok(CreditsSale {
+            listing,
+            buyer_id: buyer_id.to_string(),
+            price_credits: price,
+            fees,
+            transaction_id,
+            sold_at: now,
+        })
+    }
+}
+
+/// Append a ledger entry and return its transaction ID
+fn record_credit_movement(
+    world: &mut World,
+    player_id: &str,
+    transaction_type: CreditTransactionType,
+    credit_amount: i64,
+    description: String,
+    reference_id: &str,
+    now: i64,
+) -> String {
+    let transaction_id = uuid::Uuid::new_v4().to_string();
+    world.credit_ledger.push(CreditLedgerEntry {
+        transaction_id: transaction_id.clone(),
+        player_id: player_id.to_string(),
+        transaction_type,
+        credit_amount,
+        description,
+        reference_id: reference_id.to_string(),
+        timestamp: now,
+    });
+    transaction_id
+}
+
+/// Credits listings are capped well inside `i64`
+fn ledger_amount(credits: u64) -> i64 {
+    i64::try_from(credits).unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::{Marketability, NewListing};
+    use crate::state::{Market, MarketState};
+    use smart_stubs::stub::WorldState;
+    use smart_stubs::world::{MarketStatus, NTC_WEI};
+    use smart_stubs::Dataset;
+
+    const NOW: i64 = 1_800_000_000;
+
+    fn new_listing(seller_id: &str, nft_id: &str, currency: Currency, price: u64) -> NewListing {
+        NewListing {
+            seller_id: seller_id.to_string(),
+            nft_id: nft_id.to_string(),
+            price,
+            currency,
+            listing_type: MarketStatus::ListedForSale,
+            reserve_price_ntc_wei: 0,
+            expires_at: 0,
+        }
+    }
+
+    /// An NFT its owner can list and a buyer holding 1 000 credits
+    fn seller_and_buyer(market: &mut Market<'_>) -> (String, String, String) {
+        let nft = market
+            .nfts
+            .iter()
+            .find(|nft| market.is_marketable(nft))
+            .expect("minimal market has a marketable NFT")
+            .clone();
+        let buyer_id = market
+            .players
+            .iter()
+            .find(|player| player.player_id != nft.owner_id)
+            .unwrap()
+            .player_id
+            .clone();
+        market.player_mut(&buyer_id).unwrap().credits_balance = 1_000;
+        (nft.owner_id, nft.nft_id, buyer_id)
+    }
+
+    #[test]
+    fn test_credits_trade_settles_on_the_ledger_without_chain_events() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let (seller_id, nft_id, buyer_id) = seller_and_buyer(&mut market);
+        let seller_credits = market.player(&seller_id).unwrap().credits_balance;
+        let events = market.events.len();
+        let sales = market.sales.len();
+
+        let listing = market
+            .create_listing(
+                new_listing(&seller_id, &nft_id, Currency::Credits, 400),
+                NOW,
+            )
+            .unwrap()
+            .clone();
+        assert_eq!(listing.currency, Currency::Credits);
+        assert_eq!(listing.price(), 400);
+        assert_eq!(listing.price_ntc_wei, 0);
+        assert!(listing.tx_hash.is_empty());
+
+        let sale = market
+            .execute_credits_trade(
+                &buyer_id,
+                &listing.listing_id,
+                400,
+                &FeeSchedule::default(),
+                NOW,
+            )
+            .unwrap();
+        assert_eq!(market.nft(&nft_id).unwrap().owner_id, buyer_id);
+        assert_eq!(market.player(&buyer_id).unwrap().credits_balance, 600);
+        assert_eq!(
+            market.player(&seller_id).unwrap().credits_balance,
+            seller_credits + sale.fees.seller_proceeds
+        );
+        assert_eq!(market.events.len(), events);
+        assert_eq!(market.sales.len(), sales);
+
+        let buyer_history: Vec<_> = market.credit_history(&buyer_id).collect();
+        assert_eq!(buyer_history.len(), 1);
+        assert_eq!(buyer_history[0].transaction_id, sale.transaction_id);
+        assert_eq!(buyer_history[0].credit_amount, -400);
+        let seller_history: Vec<_> = market.credit_history(&seller_id).collect();
+        assert_eq!(
+            seller_history[0].transaction_type,
+            CreditTransactionType::Sale
+        );
+        assert_eq!(
+            market.state.feed.since(0).last().unwrap().currency,
+            Currency::Credits
+        );
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_listings_are_bought_only_in_their_own_currency() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let (seller_id, nft_id, buyer_id) = seller_and_buyer(&mut market);
+        let fees = FeeSchedule::default();
+        market.player_mut(&buyer_id).unwrap().ntc_balance_wei = 5 * NTC_WEI;
+
+        let ntc_listing = market
+            .create_listing(
+                new_listing(&seller_id, &nft_id, Currency::Ntc, NTC_WEI),
+                NOW,
+            )
+            .unwrap()
+            .listing_id
+            .clone();
+        assert_eq!(
+            market.execute_credits_trade(&buyer_id, &ntc_listing, 1_000, &fees, NOW),
+            Err(MarketError::CurrencyMismatch {
+                listing_id: ntc_listing.clone(),
+                currency: Currency::Ntc,
+            })
+        );
+        market
+            .cancel_listing(&seller_id, &ntc_listing, NOW)
+            .unwrap();
+
+        let credits_listing = market
+            .create_listing(
+                new_listing(&seller_id, &nft_id, Currency::Credits, 400),
+                NOW,
+            )
+            .unwrap()
+            .listing_id
+            .clone();
+        assert!(matches!(
+            market.execute_trade(&buyer_id, &credits_listing, 5 * NTC_WEI, &fees, NOW),
+            Err(MarketError::CurrencyMismatch { .. })
+        ));
+        assert!(matches!(
+            market.execute_credits_trade(&buyer_id, &credits_listing, 399, &fees, NOW),
+            Err(MarketError::PriceBelowAsk { .. })
+        ));
+        market.player_mut(&buyer_id).unwrap().credits_balance = 100;
+        assert!(matches!(
+            market.execute_credits_trade(&buyer_id, &credits_listing, 400, &fees, NOW),
+            Err(MarketError::InsufficientCredits { .. })
+        ));
+        assert_eq!(
+            market.player(&buyer_id).unwrap().ntc_balance_wei,
+            5 * NTC_WEI
+        );
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_credits_listings_are_fixed_price_within_the_credit_cap() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let (seller_id, nft_id, _) = seller_and_buyer(&mut market);
+
+        let mut auction = new_listing(&seller_id, &nft_id, Currency::Credits, 400);
+        auction.listing_type = MarketStatus::ListedForAuction;
+        assert_eq!(
+            market.create_listing(auction, NOW),
+            Err(MarketError::InvalidListingType)
+        );
+        assert_eq!(
+            market.create_listing(
+                new_listing(&seller_id, &nft_id, Currency::Credits, NTC_WEI),
+                NOW
+            ),
+            Err(MarketError::InvalidPrice)
+        );
+    }
+}
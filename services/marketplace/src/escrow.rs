@@ -0,0 +1,438 @@
+//! Two-phase settlement for marketplace purchases
+//! Reserving a purchase holds the buyer's NTC and locks the listing until the
+//! transaction receipt confirms or fails it, or the lock expires
+
+use crate::config::FeeSchedule;
+use crate::market::{MarketError, OrderBook};
+use crate::state::Market;
+use smart_stubs::world::{random_hash, Currency, FeeBreakdown, Sale};
+use std::collections::{BTreeSet, HashMap};
+
+/// Mirrors `TransactionStatusProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum EscrowStatus {
+    /// Buyer's funds and the listing are held until the transaction settles
+    Pending = 1,
+    /// The sale went through
+    Confirmed = 2,
+    /// Funds refunded and the listing unlocked
+    Failed = 3,
+    /// Lock expired before a receipt arrived; handled like a failure
+    Timeout = 4,
+}
+
+/// Purchase of a fixed price listing waiting for its L3 transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escrow {
+    /// Transaction that settles the purchase
+    pub tx_hash: String,
+    pub listing_id: String,
+    pub nft_id: String,
+    pub seller_id: String,
+    pub buyer_id: String,
+    /// Held from the buyer's balance while pending
+    pub price_ntc_wei: u64,
+    /// Split fixed when the funds were reserved
+    pub fees: FeeBreakdown,
+    pub created_at: i64,
+    /// Pending escrows are released once this passes
+    pub expires_at: i64,
+    pub status: EscrowStatus,
+    pub settled_at: Option<i64>,
+}
+
+/// Two-phase purchases in the order they were reserved
+#[derive(Debug, Clone, Default)]
+pub struct EscrowBook {
+    escrows: Vec<Escrow>,
+    by_tx_hash: HashMap<String, usize>,
+    pending_by_listing: HashMap<String, usize>,
+    /// Pending escrows by lock expiry and transaction
+    pending_by_expiry: BTreeSet<(i64, String)>,
+}
+
+impl EscrowBook {
+    /// Every escrow, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Escrow> {
+        self.escrows.iter()
+    }
+
+    /// Escrow settled by the transaction `tx_hash`
+    #[must_use]
+    pub fn get(&self, tx_hash: &str) -> Option<&Escrow> {
+        self.by_tx_hash
+            .get(tx_hash)
+            .map(|&position| &self.escrows[position])
+    }
+
+    /// Purchase holding the lock on a listing
+    #[must_use]
+    pub fn pending(&self, listing_id: &str) -> Option<&Escrow> {
+        self.pending_by_listing
+            .get(listing_id)
+            .map(|&position| &self.escrows[position])
+    }
+
+    /// Pending purchases whose lock has expired at `now`, earliest first
+    pub fn lapsed(&self, now: i64) -> impl Iterator<Item = &Escrow> {
+        self.pending_by_expiry
+            .range(..(now.saturating_add(1), String::new()))
+            .filter_map(|(_, tx_hash)| self.get(tx_hash))
+    }
+
+    /// Record an escrow, keeping the lookup tables current
+    pub fn insert(&mut self, escrow: Escrow) {
+        let position = self.escrows.len();
+        self.by_tx_hash.insert(escrow.tx_hash.clone(), position);
+        if escrow.status == EscrowStatus::Pending {
+            self.pending_by_listing
+                .insert(escrow.listing_id.clone(), position);
+            self.pending_by_expiry
+                .insert((escrow.expires_at, escrow.tx_hash.clone()));
+        }
+        self.escrows.push(escrow);
+    }
+
+    /// Move a pending escrow to its final `status`, releasing its lock
+    pub fn close(&mut self, tx_hash: &str, status: EscrowStatus, now: i64) -> Option<&Escrow> {
+        let position = *self.by_tx_hash.get(tx_hash)?;
+        let escrow = &mut self.escrows[position];
+        escrow.status = status;
+        escrow.settled_at = Some(now);
+        self.pending_by_listing.remove(&escrow.listing_id);
+        self.pending_by_expiry
+            .remove(&(escrow.expires_at, escrow.tx_hash.clone()));
+        Some(escrow)
+    }
+}
+
+/// Two-phase settlement of fixed price purchases
+pub trait Escrows {
+    /// First phase of a purchase: hold the asking price from the buyer and
+    /// lock the listing until `now + lock_secs`
+    ///
+    /// The listing stays open but cannot be bought or cancelled while locked.
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] for anything that would stop
+    /// [`World::execute_trade`], including a lock held by another purchase
+    fn reserve_trade(
+        &mut self,
+        buyer_id: &str,
+        listing_id: &str,
+        offered_price_wei: u64,
+        fees: &FeeSchedule,
+        now: i64,
+        lock_secs: i64,
+    ) -> Result<Escrow, MarketError>;
+
+    /// Second phase after a CONFIRMED receipt: complete the sale with the
+    /// held funds
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if no purchase was reserved under `tx_hash`,
+    /// it has already settled or its lock has expired
+    fn confirm_escrow(&mut self, tx_hash: &str, now: i64) -> Result<Sale, MarketError>;
+
+    /// Second phase after a FAILED or TIMEOUT receipt: refund the buyer and
+    /// unlock the listing
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if no purchase was reserved under `tx_hash`
+    /// or it has already settled
+    fn release_escrow(
+        &mut self,
+        tx_hash: &str,
+        status: EscrowStatus,
+        now: i64,
+    ) -> Result<Escrow, MarketError>;
+
+    /// Release every pending purchase whose lock has expired
+    fn expire_escrows(&mut self, now: i64) -> Vec<Escrow>;
+}
+
+impl Escrows for Market<'_> {
+    fn reserve_trade(
+        &mut self,
+        buyer_id: &str,
+        listing_id: &str,
+        offered_price_wei: u64,
+        fees: &FeeSchedule,
+        now: i64,
+        lock_secs: i64,
+    ) -> Result<Escrow, MarketError> {
+        let (price, fees) = self.check_purchase(
+            buyer_id,
+            listing_id,
+            offered_price_wei,
+            Currency::Ntc,
+            fees,
+            now,
+        )?;
+        let listing = self.listing(listing_id).expect("purchase was just checked");
+        let escrow = Escrow {
+            tx_hash: random_hash(&mut rand::thread_rng()),
+            listing_id: listing_id.to_string(),
+            nft_id: listing.nft_id.clone(),
+            seller_id: listing.seller_id.clone(),
+            buyer_id: buyer_id.to_string(),
+            price_ntc_wei: price,
+            fees,
+            created_at: now,
+            expires_at: now + lock_secs,
+            status: EscrowStatus::Pending,
+            settled_at: None,
+        };
+        let buyer = self
+            .player_mut(buyer_id)
+            .ok_or_else(|| MarketError::PlayerNotFound(buyer_id.to_string()))?;
+        buyer.ntc_balance_wei = buyer.ntc_balance_wei.checked_sub(price).ok_or_else(|| {
+            MarketError::InsufficientFunds {
+                player_id: buyer_id.to_string(),
+                required: price,
+            }
+        })?;
+        self.state.escrows.insert(escrow.clone());
+        Ok(escrow)
+    }
+
+    fn confirm_escrow(&mut self, tx_hash: &str, now: i64) -> Result<Sale, MarketError> {
+        let escrow = pending_escrow_for(self, tx_hash)?.clone();
+        if escrow.expires_at <= now {
+            return Err(MarketError::EscrowExpired(tx_hash.to_string()));
+        }
+        self.state
+            .escrows
+            .close(tx_hash, EscrowStatus::Confirmed, now)
+            .ok_or_else(|| MarketError::EscrowNotFound(tx_hash.to_string()))?;
+        Ok(self.complete_sale(
+            &escrow.listing_id,
+            &escrow.buyer_id,
+            escrow.price_ntc_wei,
+            escrow.fees,
+            escrow.tx_hash,
+            now,
+        ))
+    }
+
+    fn release_escrow(
+        &mut self,
+        tx_hash: &str,
+        status: EscrowStatus,
+        now: i64,
+    ) -> Result<Escrow, MarketError> {
+        debug_assert!(matches!(
+            status,
+            EscrowStatus::Failed | EscrowStatus::Timeout
+        ));
+        let escrow = pending_escrow_for(self, tx_hash)?;
+        let (buyer_id, price) = (escrow.buyer_id.clone(), escrow.price_ntc_wei);
+        if let Some(buyer) = self.player_mut(&buyer_id) {
+            buyer.ntc_balance_wei = buyer.ntc_balance_wei.saturating_add(price);
+        }
+        self.state
+            .escrows
+            .close(tx_hash, status, now)
+            .cloned()
+            .ok_or_else(|| MarketError::EscrowNotFound(tx_hash.to_string()))
+    }
+
+    fn expire_escrows(&mut self, now: i64) -> Vec<Escrow> {
+        let expired: Vec<_> = self
+            .state
+            .escrows
+            .lapsed(now)
+            .map(|escrow| escrow.tx_hash.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|tx_hash| {
+                self.release_escrow(tx_hash, EscrowStatus::Timeout, now)
+                    .ok()
+            })
+            .collect()
+    }
+}
+
+fn pending_escrow_for<'a>(
+    market: &'a Market<'_>,
+    tx_hash: &str,
+) -> Result<&'a Escrow, MarketError> {
+    let escrow = market
+        .state
+        .escrows
+        .get(tx_hash)
+        .ok_or_else(|| MarketError::EscrowNotFound(tx_hash.to_string()))?;
+    if escrow.status != EscrowStatus::Pending {
+        return Err(MarketError::EscrowSettled(tx_hash.to_string()));
+    }
+    Ok(escrow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::{Marketability, NewListing};
+    use crate::state::{Market, MarketState};
+    use smart_stubs::stub::WorldState;
+    use smart_stubs::world::{MarketStatus, World, NTC_WEI};
+    use smart_stubs::Dataset;
+
+    const NOW: i64 = 1_800_000_000;
+    const LOCK_SECS: i64 = 300;
+
+    /// World and market state with one fixed price listing and a buyer holding 15 NTC
+    fn world_with_listing() -> (World, MarketState, String, String) {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let nft = market
+            .nfts
+            .iter()
+            .find(|nft| market.is_marketable(nft))
+            .unwrap()
+            .clone();
+        let listing_id = market
+            .create_listing(
+                NewListing {
+                    seller_id: nft.owner_id.clone(),
+                    nft_id: nft.nft_id,
+                    price: 10 * NTC_WEI,
+                    currency: Currency::Ntc,
+                    listing_type: MarketStatus::ListedForSale,
+                    reserve_price_ntc_wei: 0,
+                    expires_at: 0,
+                },
+                NOW,
+            )
+            .unwrap()
+            .listing_id
+            .clone();
+        let buyer_id = market
+            .players
+            .iter()
+            .find(|player| player.player_id != nft.owner_id)
+            .unwrap()
+            .player_id
+            .clone();
+        market.player_mut(&buyer_id).unwrap().ntc_balance_wei = 15 * NTC_WEI;
+        (world, state, listing_id, buyer_id)
+    }
+
+    #[test]
+    fn test_reserved_purchase_locks_listing_until_confirmed() {
+        let (mut world, mut state, listing_id, buyer_id) = world_with_listing();
+        let mut market = Market::new(&mut world, &mut state);
+        let fees = FeeSchedule::default();
+        let escrow = market
+            .reserve_trade(&buyer_id, &listing_id, 10 * NTC_WEI, &fees, NOW, LOCK_SECS)
+            .unwrap();
+
+        assert_eq!(
+            market.player(&buyer_id).unwrap().ntc_balance_wei,
+            5 * NTC_WEI
+        );
+        assert_eq!(
+            market.state.market_status(&market, &escrow.nft_id),
+            MarketStatus::Locked
+        );
+        // Neither a second buyer nor the seller can take the listing meanwhile
+        let rival_id = market
+            .players
+            .iter()
+            .find(|player| player.player_id != buyer_id && player.player_id != escrow.seller_id)
+            .unwrap()
+            .player_id
+            .clone();
+        market.player_mut(&rival_id).unwrap().ntc_balance_wei = 15 * NTC_WEI;
+        assert_eq!(
+            market.execute_trade(&rival_id, &listing_id, 10 * NTC_WEI, &fees, NOW),
+            Err(MarketError::ListingLocked(listing_id.clone()))
+        );
+        assert_eq!(
+            market.cancel_listing(&escrow.seller_id, &listing_id, NOW),
+            Err(MarketError::ListingLocked(listing_id.clone()))
+        );
+
+        let sale = market.confirm_escrow(&escrow.tx_hash, NOW + 10).unwrap();
+        assert_eq!(sale.tx_hash, escrow.tx_hash);
+        assert_eq!(market.nft(&escrow.nft_id).unwrap().owner_id, buyer_id);
+        assert!(market.listing(&listing_id).is_none());
+        assert_eq!(
+            market.state.escrows.get(&escrow.tx_hash).unwrap().status,
+            EscrowStatus::Confirmed
+        );
+        assert_eq!(
+            market.player(&buyer_id).unwrap().ntc_balance_wei,
+            5 * NTC_WEI
+        );
+        assert_eq!(
+            market.confirm_escrow(&escrow.tx_hash, NOW + 10),
+            Err(MarketError::EscrowSettled(escrow.tx_hash.clone()))
+        );
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_failed_receipt_refunds_buyer_and_unlocks_listing() {
+        let (mut world, mut state, listing_id, buyer_id) = world_with_listing();
+        let mut market = Market::new(&mut world, &mut state);
+        let fees = FeeSchedule::default();
+        let escrow = market
+            .reserve_trade(&buyer_id, &listing_id, 10 * NTC_WEI, &fees, NOW, LOCK_SECS)
+            .unwrap();
+
+        market
+            .release_escrow(&escrow.tx_hash, EscrowStatus::Failed, NOW + 10)
+            .unwrap();
+        assert_eq!(
+            market.player(&buyer_id).unwrap().ntc_balance_wei,
+            15 * NTC_WEI
+        );
+        assert_eq!(
+            market.state.market_status(&market, &escrow.nft_id),
+            MarketStatus::ListedForSale
+        );
+        assert_eq!(
+            market.nft(&escrow.nft_id).unwrap().owner_id,
+            escrow.seller_id
+        );
+        // The listing can be bought again
+        market
+            .execute_trade(&buyer_id, &listing_id, 10 * NTC_WEI, &fees, NOW + 20)
+            .unwrap();
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_expired_locks_are_released_as_timeouts() {
+        let (mut world, mut state, listing_id, buyer_id) = world_with_listing();
+        let mut market = Market::new(&mut world, &mut state);
+        let escrow = market
+            .reserve_trade(
+                &buyer_id,
+                &listing_id,
+                10 * NTC_WEI,
+                &FeeSchedule::default(),
+                NOW,
+                LOCK_SECS,
+            )
+            .unwrap();
+
+        assert!(market.expire_escrows(NOW + LOCK_SECS - 1).is_empty());
+        assert_eq!(
+            market.confirm_escrow(&escrow.tx_hash, NOW + LOCK_SECS),
+            Err(MarketError::EscrowExpired(escrow.tx_hash.clone()))
+        );
+        let expired = market.expire_escrows(NOW + LOCK_SECS);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].status, EscrowStatus::Timeout);
+        assert_eq!(
+            market.player(&buyer_id).unwrap().ntc_balance_wei,
+            15 * NTC_WEI
+        );
+        assert!(market.state.escrows.pending(&listing_id).is_none());
+        market.validate().unwrap();
+    }
+}
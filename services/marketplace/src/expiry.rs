@@ -0,0 +1,477 @@
+//! Background expiry of order book entries that outlive their deadline
+//! Releases lapsed escrow locks, closes expired fixed price listings and
+//! settles or voids ended auctions, against a clock tests can fast-forward
+
+use crate::auction::{AuctionSettlement, Auctions};
+use crate::config::{FeeSchedule, MarketplaceConfig};
+use crate::escrow::{Escrow, Escrows};
+use crate::feed::{MarketJournal, MarketUpdateKind};
+use crate::state::{Market, MarketState, UpdateMarket};
+use serde::Serialize;
+use smart_stubs::stub::{SharedStub, SmartStub};
+use smart_stubs::world::{Listing, MarketStatus, World};
+use smart_stubs::{Clock, SystemClock};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// How often the sweeper looks for expired entries
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What one expiry pass closed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpirySweep {
+    /// Purchases rolled back because their lock ran out
+    pub released_escrows: Vec<Escrow>,
+    /// Fixed price listings that reached their expiry unsold
+    pub expired_listings: Vec<Listing>,
+    /// Ended auctions; those without a sale were voided
+    pub settled_auctions: Vec<AuctionSettlement>,
+}
+
+/// Running totals of everything the sweeper has closed
+#[derive(Debug, Default)]
+pub struct ExpiryMetrics {
+    sweeps: AtomicU64,
+    escrows_released: AtomicU64,
+    listings_expired: AtomicU64,
+    auctions_settled: AtomicU64,
+    auctions_voided: AtomicU64,
+}
+
+/// Point in time copy of [`ExpiryMetrics`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ExpiryCounts {
+    pub sweeps: u64,
+    pub escrows_released: u64,
+    pub listings_expired: u64,
+    pub auctions_settled: u64,
+    pub auctions_voided: u64,
+}
+
+impl ExpiryMetrics {
+    #[must_use]
+    pub fn counts(&self) -> ExpiryCounts {
+        ExpiryCounts {
+            sweeps: self.sweeps.load(Ordering::Relaxed),
+            escrows_released: self.escrows_released.load(Ordering::Relaxed),
+            listings_expired: self.listings_expired.load(Ordering::Relaxed),
+            auctions_settled: self.auctions_settled.load(Ordering::Relaxed),
+            auctions_voided: self.auctions_voided.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, sweep: &ExpirySweep) {
+        let voided = sweep
+            .settled_auctions
+            .iter()
+            .filter(|settlement| settlement.sale.is_none())
+            .count();
+        self.sweeps.fetch_add(1, Ordering::Relaxed);
+        self.escrows_released
+            .fetch_add(sweep.released_escrows.len() as u64, Ordering::Relaxed);
+        self.listings_expired
+            .fetch_add(sweep.expired_listings.len() as u64, Ordering::Relaxed);
+        self.auctions_settled.fetch_add(
+            (sweep.settled_auctions.len() - voided) as u64,
+            Ordering::Relaxed,
+        );
+        self.auctions_voided
+            .fetch_add(voided as u64, Ordering::Relaxed);
+    }
+}
+
+impl ExpiryCounts {
+    /// Counters in the Prometheus text exposition format
+    #[must_use]
+    pub fn to_prometheus(self, prefix: &str) -> String {
+        [
+            ("expiry_sweeps_total", "Expiry passes run", self.sweeps),
+            (
+                "escrows_released_total",
+                "Pending purchases rolled back when their lock expired",
+                self.escrows_released,
+            ),
+            (
+                "listings_expired_total",
+                "Fixed price listings closed at their expiry",
+                self.listings_expired,
+            ),
+            (
+                "auctions_settled_total",
+                "Ended auctions sold to the highest bidder",
+                self.auctions_settled,
+            ),
+            (
+                "auctions_voided_total",
+                "Ended auctions closed without a sale",
+                self.auctions_voided,
+            ),
+        ]
+        .iter()
+        .fold(String::new(), |mut exposition, (name, help, value)| {
+            let _ = write!(
+                exposition,
+                "# HELP {prefix}_{name} {help}\n# TYPE {prefix}_{name} counter\n{prefix}_{name} {value}\n"
+            );
+            exposition
+        })
+    }
+}
+
+/// Open listings in the order their deadlines pass
+///
+/// Lets a sweep visit only the listings that are due instead of the whole
+/// order book.
+#[derive(Debug, Clone, Default)]
+pub struct ExpiryQueue {
+    deadlines: BTreeSet<(i64, String)>,
+}
+
+impl ExpiryQueue {
+    /// Queue of the listings open in `world`
+    #[must_use]
+    pub fn build(world: &World) -> Self {
+        let mut queue = Self::default();
+        for listing in &world.listings {
+            queue.insert(listing);
+        }
+        queue
+    }
+
+    pub fn insert(&mut self, listing: &Listing) {
+        self.deadlines
+            .insert((listing.expires_at, listing.listing_id.clone()));
+    }
+
+    pub fn remove(&mut self, listing: &Listing) {
+        self.deadlines
+            .remove(&(listing.expires_at, listing.listing_id.clone()));
+    }
+
+    /// Move a listing's deadline from `from` to `to`
+    pub fn reschedule(&mut self, listing_id: &str, from: i64, to: i64) {
+        if self.deadlines.remove(&(from, listing_id.to_string())) {
+            self.deadlines.insert((to, listing_id.to_string()));
+        }
+    }
+
+    /// Listings whose deadline has passed at `now`, earliest first
+    pub fn due(&self, now: i64) -> impl Iterator<Item = &str> {
+        self.deadlines
+            .range(..(now.saturating_add(1), String::new()))
+            .map(|(_, listing_id)| listing_id.as_str())
+    }
+}
+
+/// Whether anything is due for [`Expiry::expire_listings`] at `now`
+#[must_use]
+pub fn has_expired_entries(state: &MarketState, now: i64) -> bool {
+    state.escrows.lapsed(now).next().is_some()
+        || state
+            .expiries
+            .due(now)
+            .any(|listing_id| state.escrows.pending(listing_id).is_none())
+}
+
+/// Closing order book entries past their deadline
+pub trait Expiry {
+    /// Close everything whose deadline has passed at `now`
+    ///
+    /// Lapsed escrow locks are released first, so a listing they held can
+    /// expire in the same pass. A listing with a purchase still pending is
+    /// left for the purchase to settle. Auctions go through
+    /// [`Auctions::settle_auction`]; the others are closed and announced as
+    /// expired, leaving their NFT `NOT_LISTED`.
+    fn expire_listings(&mut self, fees: &FeeSchedule, now: i64) -> ExpirySweep;
+}
+
+impl Expiry for Market<'_> {
+    fn expire_listings(&mut self, fees: &FeeSchedule, now: i64) -> ExpirySweep {
+        let released_escrows = self.expire_escrows(now);
+        let due: Vec<_> = self
+            .state
+            .expiries
+            .due(now)
+            .filter(|listing_id| self.state.escrows.pending(listing_id).is_none())
+            .filter_map(|listing_id| self.listing(listing_id))
+            .map(|listing| (listing.listing_id.clone(), listing.listing_type))
+            .collect();
+
+        let mut sweep = ExpirySweep {
+            released_escrows,
+            ..ExpirySweep::default()
+        };
+        for (listing_id, listing_type) in due {
+            if listing_type == MarketStatus::ListedForAuction {
+                if let Ok(settlement) = self.settle_auction(&listing_id, fees, now) {
+                    sweep.settled_auctions.push(settlement);
+                }
+                continue;
+            }
+            let Some(listing) = self.remove_listing(&listing_id) else {
+                continue;
+            };
+            self.record_market_update(
+                MarketUpdateKind::Expired,
+                &listing,
+                listing.price(),
+                None,
+                now,
+            );
+            sweep.expired_listings.push(listing);
+        }
+        sweep
+    }
+}
+
+/// Background task that expires order book entries as their deadlines pass
+#[derive(Debug, Clone)]
+pub struct ExpirySweeper {
+    stub: SharedStub,
+    clock: Arc<dyn Clock>,
+    metrics: Arc<ExpiryMetrics>,
+}
+
+impl ExpirySweeper {
+    /// Sweeper on the wall clock
+    #[must_use]
+    pub fn new(stub: SharedStub) -> Self {
+        Self::with_clock(stub, Arc::new(SystemClock))
+    }
+
+    #[must_use]
+    pub fn with_clock(stub: SharedStub, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            stub,
+            clock,
+            metrics: Arc::default(),
+        }
+    }
+
+    /// Counters shared with the running task
+    #[must_use]
+    pub fn metrics(&self) -> Arc<ExpiryMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Run one pass at the clock's current time
+    pub async fn sweep(&self) -> ExpirySweep {
+        let now = self.clock.now();
+        let stub = self.stub.lock().await;
+        // Read first so an idle pass does not wait for readers to let go of the world
+        let due = has_expired_entries(stub.world_with::<MarketState>().state(), now);
+        let sweep = if due {
+            let fees = &MarketplaceConfig::of(stub.get_configuration()).fees;
+            stub.update_market(|market| market.expire_listings(fees, now))
+        } else {
+            ExpirySweep::default()
+        };
+        self.metrics.record(&sweep);
+        sweep
+    }
+
+    /// Sweep every second until the runtime shuts down
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.sweep().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::{Marketability, NewListing, OrderBook};
+    use crate::state::{Market, MarketState};
+    use smart_stubs::stub::ServiceStub;
+    use smart_stubs::stub::WorldState;
+    use smart_stubs::world::{Currency, NTC_WEI};
+    use smart_stubs::{Dataset, ManualClock, StubConfiguration};
+
+    const NOW: i64 = 1_800_000_000;
+    const HOUR: i64 = 3_600;
+
+    fn list_next_marketable(market: &mut Market<'_>, listing_type: MarketStatus) -> String {
+        let nft = market
+            .nfts
+            .iter()
+            .find(|nft| market.is_marketable(nft))
+            .unwrap()
+            .clone();
+        market
+            .create_listing(
+                NewListing {
+                    seller_id: nft.owner_id,
+                    nft_id: nft.nft_id,
+                    price: 2 * NTC_WEI,
+                    currency: Currency::Ntc,
+                    listing_type,
+                    reserve_price_ntc_wei: 0,
+                    expires_at: NOW + HOUR,
+                },
+                NOW,
+            )
+            .unwrap()
+            .listing_id
+            .clone()
+    }
+
+    fn bidder_for(world: &World, listing_id: &str) -> String {
+        let seller_id = &world.listing(listing_id).unwrap().seller_id;
+        world
+            .players
+            .iter()
+            .find(|player| &player.player_id != seller_id)
+            .unwrap()
+            .player_id
+            .clone()
+    }
+
+    #[test]
+    fn test_expired_listings_close_and_auctions_settle_or_void() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let sale_id = list_next_marketable(&mut market, MarketStatus::ListedForSale);
+        let won_id = list_next_marketable(&mut market, MarketStatus::ListedForAuction);
+        let unbid_id = list_next_marketable(&mut market, MarketStatus::ListedForAuction);
+        let bidder_id = bidder_for(&market, &won_id);
+        market.player_mut(&bidder_id).unwrap().ntc_balance_wei = 5 * NTC_WEI;
+        market
+            .place_bid(&bidder_id, &won_id, 3 * NTC_WEI, NOW + 60)
+            .unwrap();
+        let fees = FeeSchedule::default();
+
+        assert!(!has_expired_entries(market.state, NOW + HOUR - 1));
+        assert_eq!(
+            market.expire_listings(&fees, NOW + HOUR - 1),
+            ExpirySweep::default()
+        );
+
+        let sweep = market.expire_listings(&fees, NOW + HOUR);
+        let sale_nft = sweep.expired_listings[0].nft_id.clone();
+        assert_eq!(sweep.expired_listings.len(), 1);
+        assert_eq!(sweep.expired_listings[0].listing_id, sale_id);
+        assert_eq!(market.market_status(&sale_nft), MarketStatus::NotListed);
+        assert_eq!(sweep.settled_auctions.len(), 2);
+        let won = sweep
+            .settled_auctions
+            .iter()
+            .find(|settlement| settlement.listing.listing_id == won_id)
+            .unwrap();
+        assert_eq!(won.sale.as_ref().unwrap().buyer_id, bidder_id);
+        assert!(sweep
+            .settled_auctions
+            .iter()
+            .any(
+                |settlement| settlement.listing.listing_id == unbid_id && settlement.sale.is_none()
+            ));
+
+        let kinds: Vec<_> = market
+            .state
+            .feed
+            .since(0)
+            .filter(|update| update.timestamp >= NOW + HOUR)
+            .map(|update| (update.listing_id.clone(), update.kind))
+            .collect();
+        assert!(kinds.contains(&(sale_id, MarketUpdateKind::Expired)));
+        assert!(kinds.contains(&(unbid_id, MarketUpdateKind::Expired)));
+        assert!(!has_expired_entries(market.state, NOW + HOUR));
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_listing_with_pending_purchase_waits_for_its_lock() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let listing_id = list_next_marketable(&mut market, MarketStatus::ListedForSale);
+        let buyer_id = bidder_for(&market, &listing_id);
+        market.player_mut(&buyer_id).unwrap().ntc_balance_wei = 5 * NTC_WEI;
+        let fees = FeeSchedule::default();
+        market
+            .reserve_trade(
+                &buyer_id,
+                &listing_id,
+                2 * NTC_WEI,
+                &fees,
+                NOW + HOUR - 10,
+                60,
+            )
+            .unwrap();
+
+        let sweep = market.expire_listings(&fees, NOW + HOUR);
+        assert_eq!(sweep, ExpirySweep::default());
+        assert!(market.listing(&listing_id).is_some());
+
+        // Once the lock lapses the purchase is refunded and the listing expires
+        let sweep = market.expire_listings(&fees, NOW + HOUR + 50);
+        assert_eq!(sweep.released_escrows.len(), 1);
+        assert_eq!(sweep.expired_listings.len(), 1);
+        assert_eq!(
+            market.player(&buyer_id).unwrap().ntc_balance_wei,
+            5 * NTC_WEI
+        );
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_extended_auction_expires_at_its_new_deadline() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let listing_id = list_next_marketable(&mut market, MarketStatus::ListedForAuction);
+        let bidder_id = bidder_for(&market, &listing_id);
+        market.player_mut(&bidder_id).unwrap().ntc_balance_wei = 5 * NTC_WEI;
+        market
+            .place_bid(&bidder_id, &listing_id, 3 * NTC_WEI, NOW + HOUR - 1)
+            .unwrap();
+        let ends_at = market.listing(&listing_id).unwrap().expires_at;
+        assert!(ends_at > NOW + HOUR);
+        let fees = FeeSchedule::default();
+
+        assert!(!has_expired_entries(market.state, NOW + HOUR));
+        assert_eq!(
+            market.expire_listings(&fees, NOW + HOUR),
+            ExpirySweep::default()
+        );
+        assert!(has_expired_entries(market.state, ends_at));
+        let sweep = market.expire_listings(&fees, ends_at);
+        assert_eq!(sweep.settled_auctions.len(), 1);
+        assert!(!has_expired_entries(market.state, ends_at));
+        market.validate().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sweeper_follows_the_clock_and_counts() {
+        let mut config = StubConfiguration::for_service("marketplace-service-stub", 9000);
+        config.data.dataset = Dataset::Minimal;
+        let stub = ServiceStub::new(config).into_shared();
+        let listing_id = stub
+            .lock()
+            .await
+            .update_market(|market| list_next_marketable(market, MarketStatus::ListedForSale));
+        let clock = ManualClock::new(NOW);
+        let sweeper = ExpirySweeper::with_clock(stub.clone(), Arc::new(clock.clone()));
+
+        assert_eq!(sweeper.sweep().await, ExpirySweep::default());
+        clock.advance(HOUR);
+        let sweep = sweeper.sweep().await;
+        assert_eq!(sweep.expired_listings[0].listing_id, listing_id);
+        assert!(stub.lock().await.world().listing(&listing_id).is_none());
+
+        let counts = sweeper.metrics().counts();
+        assert_eq!(counts.sweeps, 2);
+        assert_eq!(counts.listings_expired, 1);
+        let exposition = counts.to_prometheus("marketplace");
+        assert!(exposition.contains("marketplace_listings_expired_total 1\n"));
+    }
+}
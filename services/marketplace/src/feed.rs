@@ -0,0 +1,390 @@
+//! Market feed recording and subscriptions
+//! Every new listing, price change, sale and cancellation is journaled in the
+//! marketplace's market feed, which subscribers stream and resume by sequence number
+
+use crate::state::{Market, MarketState};
+use serde::{Deserialize, Serialize};
+use smart_stubs::stub::SharedStub;
+use smart_stubs::world::{Currency, ItemRarity, ItemType, Listing, MarketStatus};
+use std::collections::VecDeque;
+use tokio::sync::watch;
+
+/// Updates kept for resuming subscribers; older ones are dropped
+pub const MARKET_FEED_CAPACITY: usize = 1_000;
+
+/// What happened to a listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketUpdateKind {
+    Listed,
+    /// A new highest bid on an auction
+    PriceChanged,
+    Sold,
+    /// Withdrawn by the seller
+    Cancelled,
+    /// Reached its expiry unsold, including auctions without a winning bid
+    Expired,
+}
+
+impl MarketUpdateKind {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Listed => "listed",
+            Self::PriceChanged => "price_changed",
+            Self::Sold => "sold",
+            Self::Cancelled => "cancelled",
+            Self::Expired => "expired",
+        }
+    }
+}
+
+/// One change to the order book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketUpdate {
+    /// Starts at 1 and increases by one per update
+    pub sequence: u64,
+    pub kind: MarketUpdateKind,
+    pub listing_id: String,
+    pub nft_id: String,
+    pub seller_id: String,
+    /// Only set for sales
+    pub buyer_id: Option<String>,
+    pub listing_type: MarketStatus,
+    pub item_type: ItemType,
+    pub rarity: ItemRarity,
+    /// Asking price, highest bid or sale price depending on `kind`, in wei
+    /// for NTC listings and whole credits otherwise
+    pub price_ntc_wei: u64,
+    pub currency: Currency,
+    pub timestamp: i64,
+}
+
+/// Bounded journal of the most recent [`MarketUpdate`]s
+#[derive(Debug, Clone, Default)]
+pub struct MarketFeed {
+    last_sequence: u64,
+    updates: VecDeque<MarketUpdate>,
+}
+
+impl MarketFeed {
+    /// Sequence number of the newest update, 0 before the first
+    #[must_use]
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    /// Retained updates with a sequence number above `after`, oldest first
+    ///
+    /// Sequence numbers are contiguous, so a first update above `after + 1`
+    /// means the ones in between were dropped and the client should refetch
+    /// the listings. A resume point ahead of the feed, as after the world was
+    /// replaced and the feed started over, replays everything retained.
+    pub fn since(&self, after: u64) -> impl Iterator<Item = &MarketUpdate> {
+        let after = if after > self.last_sequence { 0 } else { after };
+        self.updates
+            .iter()
+            .filter(move |update| update.sequence > after)
+    }
+
+    /// Append `update` under the next sequence number
+    pub fn push(&mut self, mut update: MarketUpdate) {
+        self.last_sequence += 1;
+        update.sequence = self.last_sequence;
+        if self.updates.len() == MARKET_FEED_CAPACITY {
+            self.updates.pop_front();
+        }
+        self.updates.push_back(update);
+    }
+}
+
+/// Which updates a subscriber wants; empty lists match everything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarketFeedFilter {
+    pub item_types: Vec<ItemType>,
+    pub rarities: Vec<ItemRarity>,
+    pub seller_ids: Vec<String>,
+}
+
+impl MarketFeedFilter {
+    #[must_use]
+    pub fn matches(&self, update: &MarketUpdate) -> bool {
+        (self.item_types.is_empty() || self.item_types.contains(&update.item_type))
+            && (self.rarities.is_empty() || self.rarities.contains(&update.rarity))
+            && (self.seller_ids.is_empty() || self.seller_ids.contains(&update.seller_id))
+    }
+}
+
+/// Recording order book changes in the market feed
+pub trait MarketJournal {
+    /// Journal a change to `listing` at `price_ntc_wei`, given in the listing's currency
+    fn record_market_update(
+        &mut self,
+        kind: MarketUpdateKind,
+        listing: &Listing,
+        price_ntc_wei: u64,
+        buyer_id: Option<&str>,
+        now: i64,
+    );
+}
+
+impl MarketJournal for Market<'_> {
+    fn record_market_update(
+        &mut self,
+        kind: MarketUpdateKind,
+        listing: &Listing,
+        price_ntc_wei: u64,
+        buyer_id: Option<&str>,
+        now: i64,
+    ) {
+        let Some(nft) = self.nft(&listing.nft_id) else {
+            return;
+        };
+        let update = MarketUpdate {
+            sequence: 0,
+            kind,
+            listing_id: listing.listing_id.clone(),
+            nft_id: listing.nft_id.clone(),
+            seller_id: listing.seller_id.clone(),
+            buyer_id: buyer_id.map(str::to_string),
+            listing_type: listing.listing_type,
+            item_type: nft.item_type,
+            rarity: nft.rarity,
+            price_ntc_wei,
+            currency: listing.currency,
+            timestamp: now,
+        };
+        self.state.feed.push(update);
+    }
+}
+
+/// Live view of the market feed for one subscriber
+///
+/// Replays the retained updates after the resume point, then waits for new
+/// ones. Used by the gRPC stream and the WebSocket route alike.
+#[derive(Debug)]
+pub struct MarketSubscription {
+    stub: SharedStub,
+    changes: watch::Receiver<u64>,
+    filter: MarketFeedFilter,
+    last_sequence: u64,
+    pending: VecDeque<MarketUpdate>,
+}
+
+impl MarketSubscription {
+    /// Subscribe to updates after `resume_after`; 0 starts from the oldest retained
+    pub async fn open(stub: SharedStub, filter: MarketFeedFilter, resume_after: u64) -> Self {
+        let changes = stub.lock().await.subscribe_world_updates();
+        Self {
+            stub,
+            changes,
+            filter,
+            last_sequence: resume_after,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Next matching update, waiting for one if none is pending
+    ///
+    /// Returns `None` once the stub is dropped.
+    pub async fn next(&mut self) -> Option<MarketUpdate> {
+        loop {
+            if let Some(update) = self.pending.pop_front() {
+                return Some(update);
+            }
+            // Mark the current value seen before reading so no update slips between
+            self.changes.borrow_and_update();
+            {
+                let market = self.stub.lock().await.world_with::<MarketState>();
+                let feed = &market.state().feed;
+                self.pending.extend(
+                    feed.since(self.last_sequence)
+                        .filter(|update| self.filter.matches(update))
+                        .cloned(),
+                );
+                self.last_sequence = feed.last_sequence();
+            }
+            if self.pending.is_empty() {
+                self.changes.changed().await.ok()?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auction::Auctions;
+    use crate::config::FeeSchedule;
+    use crate::market::{Marketability, NewListing, OrderBook};
+    use crate::state::{Market, MarketState, UpdateMarket};
+    use smart_stubs::stub::ServiceStub;
+    use smart_stubs::stub::WorldState;
+    use smart_stubs::world::{World, NTC_WEI};
+    use smart_stubs::{Dataset, StubConfiguration};
+
+    const NOW: i64 = 1_800_000_000;
+
+    fn list_first_marketable(market: &mut Market<'_>, listing_type: MarketStatus) -> String {
+        let nft = market
+            .nfts
+            .iter()
+            .find(|nft| market.is_marketable(nft))
+            .expect("minimal market has a marketable NFT")
+            .clone();
+        market
+            .create_listing(
+                NewListing {
+                    seller_id: nft.owner_id,
+                    nft_id: nft.nft_id,
+                    price: 10 * NTC_WEI,
+                    currency: Currency::Ntc,
+                    listing_type,
+                    reserve_price_ntc_wei: 0,
+                    expires_at: NOW + 3_600,
+                },
+                NOW,
+            )
+            .unwrap()
+            .listing_id
+            .clone()
+    }
+
+    fn buyer_for(world: &World, listing_id: &str) -> String {
+        let seller_id = &world.listing(listing_id).unwrap().seller_id;
+        world
+            .players
+            .iter()
+            .find(|player| &player.player_id != seller_id)
+            .unwrap()
+            .player_id
+            .clone()
+    }
+
+    #[test]
+    fn test_order_book_changes_are_journaled_in_sequence() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        assert_eq!(market.state.feed.last_sequence(), 0);
+
+        let sale_id = list_first_marketable(&mut market, MarketStatus::ListedForSale);
+        let auction_id = list_first_marketable(&mut market, MarketStatus::ListedForAuction);
+        let buyer_id = buyer_for(&market, &sale_id);
+        market.player_mut(&buyer_id).unwrap().ntc_balance_wei = 15 * NTC_WEI;
+
+        market
+            .execute_trade(
+                &buyer_id,
+                &sale_id,
+                10 * NTC_WEI,
+                &FeeSchedule::default(),
+                NOW,
+            )
+            .unwrap();
+        let bidder_id = buyer_for(&market, &auction_id);
+        market.player_mut(&bidder_id).unwrap().ntc_balance_wei = 15 * NTC_WEI;
+        market
+            .place_bid(&bidder_id, &auction_id, 12 * NTC_WEI, NOW)
+            .unwrap();
+
+        let updates: Vec<_> = market.state.feed.since(0).collect();
+        let kinds: Vec<_> = updates.iter().map(|update| update.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                MarketUpdateKind::Listed,
+                MarketUpdateKind::Listed,
+                MarketUpdateKind::Sold,
+                MarketUpdateKind::PriceChanged,
+            ]
+        );
+        assert_eq!(
+            updates
+                .iter()
+                .map(|update| update.sequence)
+                .collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(updates[2].buyer_id.as_deref(), Some(buyer_id.as_str()));
+        assert_eq!(updates[3].price_ntc_wei, 12 * NTC_WEI);
+        assert_eq!(market.state.feed.since(2).count(), 2);
+        // A resume point from a replaced market replays everything retained
+        assert_eq!(market.state.feed.since(99).count(), 4);
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_feed_keeps_only_the_most_recent_updates() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let listing = market.listings[0].clone();
+        for _ in 0..MARKET_FEED_CAPACITY + 5 {
+            market.record_market_update(
+                MarketUpdateKind::PriceChanged,
+                &listing,
+                NTC_WEI,
+                None,
+                NOW,
+            );
+        }
+
+        let oldest = market.state.feed.since(0).next().unwrap();
+        assert_eq!(oldest.sequence, 6);
+        assert_eq!(
+            market.state.feed.last_sequence(),
+            (MARKET_FEED_CAPACITY + 5) as u64
+        );
+    }
+
+    #[test]
+    fn test_filter_matches_item_type_rarity_and_seller() {
+        let mut world = World::generate(Dataset::Minimal, 42);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        list_first_marketable(&mut market, MarketStatus::ListedForSale);
+        let update = market.state.feed.since(0).next().unwrap().clone();
+
+        assert!(MarketFeedFilter::default().matches(&update));
+        assert!(MarketFeedFilter {
+            item_types: vec![update.item_type],
+            rarities: vec![update.rarity],
+            seller_ids: vec![update.seller_id.clone()],
+        }
+        .matches(&update));
+        assert!(!MarketFeedFilter {
+            seller_ids: vec!["someone-else".to_string()],
+            ..MarketFeedFilter::default()
+        }
+        .matches(&update));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_resumes_and_waits_for_new_updates() {
+        let mut config = StubConfiguration::for_service("marketplace-service-stub", 9000);
+        config.data.dataset = Dataset::Minimal;
+        let stub = ServiceStub::new(config).into_shared();
+        stub.lock().await.update_market(|market| {
+            list_first_marketable(market, MarketStatus::ListedForSale);
+            list_first_marketable(market, MarketStatus::ListedForSale);
+        });
+
+        let mut subscription =
+            MarketSubscription::open(stub.clone(), MarketFeedFilter::default(), 1).await;
+        assert_eq!(subscription.next().await.unwrap().sequence, 2);
+
+        let waiting = tokio::spawn(async move { subscription.next().await });
+        tokio::task::yield_now().await;
+        stub.lock().await.update_market(|market| {
+            list_first_marketable(market, MarketStatus::ListedForAuction);
+        });
+        let update = tokio::time::timeout(std::time::Duration::from_secs(1), waiting)
+            .await
+            .expect("subscriber is woken by the update")
+            .unwrap()
+            .unwrap();
+        assert_eq!(update.sequence, 3);
+        assert_eq!(update.listing_type, MarketStatus::ListedForAuction);
+    }
+}
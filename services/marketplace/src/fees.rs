@@ -0,0 +1,235 @@
+//! Marketplace fee engine
+//! Platform fees with rarity and item type overrides, creator royalties and
+//! staking tier discounts, computed in exact integer wei
+
+use crate::config::FeeSchedule;
+use smart_stubs::world::{Currency, FeeBreakdown, Nft, World};
+
+const BASIS_POINTS: u32 = 10_000;
+
+/// Add `amount` to a player's balance in `currency`
+pub fn credit(world: &mut World, player_id: &str, currency: Currency, amount: u64) {
+    if let Some(player) = world.player_mut(player_id) {
+        let balance = match currency {
+            Currency::Ntc => &mut player.ntc_balance_wei,
+            Currency::Credits => &mut player.credits_balance,
+        };
+        *balance = balance.saturating_add(amount);
+    }
+}
+
+/// `basis_points` of `amount`, rounded down
+fn share(amount: u64, basis_points: u32) -> u64 {
+    (u128::from(amount) * u128::from(basis_points.min(BASIS_POINTS)) / u128::from(BASIS_POINTS))
+        as u64
+}
+
+/// Split `price` for `nft` sold by a seller staking at `seller_staking_tier`
+/// under `fees`
+///
+/// `royalty_recipient_id` is the creator owed a royalty, `None` when the
+/// creator is the seller or unknown. Every share is rounded down and the
+/// remainder goes to the seller, so nothing is lost to rounding.
+#[must_use]
+pub fn breakdown(
+    fees: &FeeSchedule,
+    price: u64,
+    nft: &Nft,
+    seller_staking_tier: u32,
+    royalty_recipient_id: Option<&str>,
+) -> FeeBreakdown {
+    let platform_fee_basis_points = fees.platform_fee_basis_points(nft);
+    let platform_fee = share(price, platform_fee_basis_points);
+    let staking_discount = share(
+        platform_fee,
+        fees.staking_discount_basis_points(seller_staking_tier),
+    );
+    let marketplace_fee = platform_fee - staking_discount;
+    let (royalty_basis_points, royalty) = match royalty_recipient_id {
+        Some(_) => (
+            fees.creator_royalty_basis_points,
+            share(price, fees.creator_royalty_basis_points).min(price - marketplace_fee),
+        ),
+        None => (0, 0),
+    };
+
+    FeeBreakdown {
+        platform_fee_basis_points,
+        platform_fee,
+        seller_staking_tier,
+        staking_discount,
+        marketplace_fee,
+        royalty_basis_points,
+        royalty,
+        royalty_recipient_id: royalty_recipient_id
+            .filter(|_| royalty > 0)
+            .map(str::to_string),
+        seller_proceeds: price - marketplace_fee - royalty,
+    }
+}
+
+/// Fee splits and payouts of marketplace sales
+pub trait Fees {
+    /// Fees due when `seller_id` sells `nft` for `price`
+    #[must_use]
+    fn fee_breakdown(
+        &self,
+        fees: &FeeSchedule,
+        nft: &Nft,
+        seller_id: &str,
+        price: u64,
+    ) -> FeeBreakdown;
+
+    /// Credit the seller and the creator with their shares of a sale
+    fn pay_out(&mut self, seller_id: &str, fees: &FeeBreakdown, currency: Currency);
+}
+
+impl Fees for World {
+    fn fee_breakdown(
+        &self,
+        fees: &FeeSchedule,
+        nft: &Nft,
+        seller_id: &str,
+        price: u64,
+    ) -> FeeBreakdown {
+        let seller_staking_tier = self
+            .player(seller_id)
+            .and_then(|seller| seller.staking.as_ref())
+            .map_or(0, |staking| staking.tier);
+        let creator_id = nft
+            .creator_id
+            .as_deref()
+            .filter(|creator_id| *creator_id != seller_id && self.player(creator_id).is_some());
+        breakdown(fees, price, nft, seller_staking_tier, creator_id)
+    }
+
+    fn pay_out(&mut self, seller_id: &str, fees: &FeeBreakdown, currency: Currency) {
+        credit(self, seller_id, currency, fees.seller_proceeds);
+        if let Some(creator_id) = &fees.royalty_recipient_id {
+            credit(self, creator_id, currency, fees.royalty);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StakingDiscount;
+    use smart_stubs::world::{ItemRarity, ItemType, NTC_WEI};
+    use smart_stubs::Dataset;
+    use std::collections::BTreeMap;
+
+    fn item(world: &World, item_type: ItemType) -> Nft {
+        let mut nft = world
+            .nfts
+            .iter()
+            .find(|nft| nft.item_type != ItemType::BunkerguardRobot)
+            .unwrap()
+            .clone();
+        nft.item_type = item_type;
+        nft.rarity = ItemRarity::Supreme;
+        nft
+    }
+
+    #[test]
+    fn test_overrides_pick_the_most_specific_rate() {
+        let world = World::generate(Dataset::Minimal, 5);
+        let schedule = FeeSchedule {
+            rarity_overrides: BTreeMap::from([(ItemRarity::Supreme, 400)]),
+            item_type_overrides: BTreeMap::from([(ItemType::CosmeticSkin, 100)]),
+            ..FeeSchedule::default()
+        };
+
+        let mut standard = item(&world, ItemType::Head);
+        standard.rarity = ItemRarity::Standard;
+        assert_eq!(schedule.platform_fee_basis_points(&standard), 250);
+        assert_eq!(
+            schedule.platform_fee_basis_points(&item(&world, ItemType::Head)),
+            400
+        );
+        assert_eq!(
+            schedule.platform_fee_basis_points(&item(&world, ItemType::CosmeticSkin)),
+            100
+        );
+    }
+
+    #[test]
+    fn test_breakdown_splits_price_exactly() {
+        let world = World::generate(Dataset::Minimal, 5);
+        let schedule = FeeSchedule {
+            platform_fee_basis_points: 333,
+            creator_royalty_basis_points: 777,
+            staking_discounts: vec![
+                StakingDiscount {
+                    min_tier: 1,
+                    discount_basis_points: 1_000,
+                },
+                StakingDiscount {
+                    min_tier: 3,
+                    discount_basis_points: 5_000,
+                },
+            ],
+            ..FeeSchedule::default()
+        };
+        let nft = item(&world, ItemType::Gear);
+        let price = 10 * NTC_WEI + 7;
+
+        let unstaked = breakdown(&schedule, price, &nft, 0, Some("creator"));
+        assert_eq!(unstaked.staking_discount, 0);
+        assert_eq!(
+            unstaked.marketplace_fee,
+            (u128::from(price) * 333 / 10_000) as u64
+        );
+        assert_eq!(unstaked.royalty, (u128::from(price) * 777 / 10_000) as u64);
+        assert_eq!(unstaked.royalty_recipient_id.as_deref(), Some("creator"));
+
+        let staked = breakdown(&schedule, price, &nft, 4, Some("creator"));
+        assert_eq!(staked.staking_discount, staked.platform_fee / 2);
+        assert_eq!(staked.seller_staking_tier, 4);
+        for breakdown in [&unstaked, &staked] {
+            assert_eq!(
+                breakdown.marketplace_fee + breakdown.royalty + breakdown.seller_proceeds,
+                price
+            );
+        }
+
+        let own = breakdown(&schedule, price, &nft, 0, None);
+        assert_eq!((own.royalty, own.royalty_basis_points), (0, 0));
+        assert!(own.royalty_recipient_id.is_none());
+    }
+
+    #[test]
+    fn test_world_pays_royalty_to_known_creator_only() {
+        let world = World::generate(Dataset::Minimal, 5);
+        let schedule = FeeSchedule {
+            creator_royalty_basis_points: 500,
+            ..FeeSchedule::default()
+        };
+        let mut nft = item(&world, ItemType::Torso);
+        let creator = nft.creator_id.clone().unwrap();
+        let seller = world
+            .players
+            .iter()
+            .find(|player| player.player_id != creator)
+            .unwrap()
+            .player_id
+            .clone();
+
+        let resale = world.fee_breakdown(&schedule, &nft, &seller, NTC_WEI);
+        assert_eq!(resale.royalty, NTC_WEI / 20);
+        assert_eq!(resale.royalty_recipient_id, Some(creator.clone()));
+        assert_eq!(
+            world
+                .fee_breakdown(&schedule, &nft, &creator, NTC_WEI)
+                .royalty,
+            0
+        );
+        nft.creator_id = Some("ghost".to_string());
+        assert_eq!(
+            world
+                .fee_breakdown(&schedule, &nft, &seller, NTC_WEI)
+                .royalty,
+            0
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! Conversions from the shared fixture world into marketplace protos
+
+use crate::analytics::{Analytics, SaleWindow, DAY_SECS};
+use crate::grpc_server::bunkerverse::core::v1::{
+    CoreStatsProto, NftDetailsProto, NftIdentifierProto, NftMutableStateProto,
+};
+use crate::grpc_server::bunkerverse::services::v1::{
+    ItemTypeStatsProto, MarketAnalyticsProto, MarketListingProto, PriceHistoryEntryProto,
+};
+use crate::state::MarketState;
+use smart_stubs::world::{
+    credits_for_price, CoreStats, Currency, Listing, Nft, Sale, Template, World, SCHEMA_VERSION,
+};
+use std::collections::BTreeMap;
+
+/// Similar listings attached to a listing detail
+const SIMILAR_LISTINGS: usize = 5;
+
+/// Sales attached to market analytics, newest first
+const RECENT_SALES: usize = 10;
+
+pub fn core_stats(stats: &CoreStats) -> CoreStatsProto {
+    CoreStatsProto {
+        damage: stats.damage,
+        accuracy: stats.accuracy,
+        critical_chance: stats.critical_chance,
+        armor_piercing: stats.armor_piercing,
+        speed: stats.speed,
+        agility: stats.agility,
+        stealth: stats.stealth,
+        evasion: stats.evasion,
+        health: stats.health,
+        shield: stats.shield,
+        detection: stats.detection,
+        range: stats.range,
+        combat_average: stats.combat_average(),
+        mobility_average: stats.mobility_average(),
+        survivability_average: stats.survivability_average(),
+        sensors_average: stats.sensors_average(),
+    }
+}
+
+pub fn nft_details(nft: &Nft, enable_crypto: bool) -> NftDetailsProto {
+    NftDetailsProto {
+        identifier: Some(NftIdentifierProto {
+            nft_id: nft.nft_id.clone(),
+            token_id: nft.token_id,
+            contract_address: if enable_crypto {
+                nft.contract_address.clone()
+            } else {
+                "".to_string()
+            },
+        }),
+        item_type: nft.item_type as i32,
+        item_rarity: nft.rarity as i32,
+        base_stat_boosts: Some(core_stats(&nft.stat_boosts)),
+        class_affinities: nft
+            .class_affinities
+            .iter()
+            .map(|class| *class as i32)
+            .collect(),
+        trait_affiliation: nft.affiliation as i32,
+        construct_origin: nft.construct_origin.clone(),
+        metadata_pointer_uri: nft.metadata_cid.clone(),
+        schema_version: SCHEMA_VERSION,
+        created_timestamp: nft.minted_at,
+    }
+}
+
+pub fn nft_state(
+    world: &World,
+    state: &MarketState,
+    nft: &Nft,
+    enable_crypto: bool,
+) -> NftMutableStateProto {
+    let listing = world.listing_for_nft(&nft.nft_id);
+    NftMutableStateProto {
+        current_owner_id: nft.owner_id.clone(),
+        current_condition: nft.condition as i32,
+        is_soulbound: nft.is_soulbound,
+        market_status: state.market_status(world, &nft.nft_id) as i32,
+        market_price_ntc: listing.map_or(0, |listing| listing_price(listing, enable_crypto)),
+        last_updated_timestamp: listing.map_or(nft.minted_at, |listing| listing.created_at),
+    }
+}
+
+/// Currency new listings and purchases settle in: NTC on chain, or credits
+/// on the payment ledger when crypto features are off
+pub fn mode_currency(enable_crypto: bool) -> Currency {
+    if enable_crypto {
+        Currency::Ntc
+    } else {
+        Currency::Credits
+    }
+}
+
+/// Whether a listing is priced in [`mode_currency`], so it can be bought in this mode
+pub fn in_mode_currency(listing: &Listing, enable_crypto: bool) -> bool {
+    listing.currency == mode_currency(enable_crypto)
+}
+
+/// Currency a listing's price is shown in: its own, or credits when crypto
+/// features are off
+pub fn listing_currency(listing: &Listing, enable_crypto: bool) -> Currency {
+    if enable_crypto {
+        listing.currency
+    } else {
+        Currency::Credits
+    }
+}
+
+/// Listing price in [`listing_currency`]: NTC wei or credits
+pub fn listing_price(listing: &Listing, enable_crypto: bool) -> u64 {
+    match listing_currency(listing, enable_crypto) {
+        Currency::Ntc => listing.price_ntc_wei,
+        Currency::Credits => listing.price_credits,
+    }
+}
+
+/// Amount in NTC wei, or in credits when crypto features are off
+pub fn price(price_ntc_wei: u64, enable_crypto: bool) -> u64 {
+    if enable_crypto || price_ntc_wei == 0 {
+        price_ntc_wei
+    } else {
+        credits_for_price(price_ntc_wei)
+    }
+}
+
+pub fn market_listing(
+    world: &World,
+    state: &MarketState,
+    listing: &Listing,
+    enable_crypto: bool,
+) -> MarketListingProto {
+    let nft = world.nft(&listing.nft_id);
+    MarketListingProto {
+        listing_id: listing.listing_id.clone(),
+        nft_details: nft.map(|nft| nft_details(nft, enable_crypto)),
+        nft_state: nft.map(|nft| nft_state(world, state, nft, enable_crypto)),
+        seller_player_id: listing.seller_id.clone(),
+        seller_bunker_tag: world
+            .player(&listing.seller_id)
+            .map(|player| player.bunker_tag.clone())
+            .unwrap_or_default(),
+        listing_price_ntc_wei: listing_price(listing, enable_crypto),
+        listing_type: listing.listing_type as i32,
+        listing_created_at: listing.created_at,
+        listing_expires_at: listing.expires_at,
+        view_count: listing.view_count,
+        favorite_count: listing.favorite_count,
+        similar_listings: similar_listings(world, listing)
+            .map(|similar| similar.listing_id.clone())
+            .collect(),
+        currency: listing.currency as i32,
+    }
+}
+
+/// Other open listings for the same item type
+pub fn similar_listings<'a>(
+    world: &'a World,
+    listing: &'a Listing,
+) -> impl Iterator<Item = &'a Listing> + 'a {
+    let item_type = world.nft(&listing.nft_id).map(|nft| nft.item_type);
+    world
+        .listings
+        .iter()
+        .filter(move |other| {
+            other.listing_id != listing.listing_id
+                && world.nft(&other.nft_id).map(|nft| nft.item_type) == item_type
+        })
+        .take(SIMILAR_LISTINGS)
+}
+
+/// Per item type listing counts, floor and average prices and 24h volume
+pub fn item_type_stats(
+    world: &World,
+    listings: &[&Listing],
+    enable_crypto: bool,
+    now: i64,
+) -> Vec<ItemTypeStatsProto> {
+    let mut prices: BTreeMap<i32, Vec<u64>> = BTreeMap::new();
+    for listing in listings {
+        if let Some(nft) = world.nft(&listing.nft_id) {
+            prices
+                .entry(nft.item_type as i32)
+                .or_default()
+                .push(listing_price(listing, enable_crypto));
+        }
+    }
+    let mut sales: BTreeMap<i32, Vec<&Sale>> = BTreeMap::new();
+    for sale in &world.sales {
+        if let Some(nft) = world.nft(&sale.nft_id) {
+            sales.entry(nft.item_type as i32).or_default().push(sale);
+        }
+    }
+
+    prices
+        .into_iter()
+        .map(|(item_type, prices)| {
+            let last_24h = SaleWindow::since(
+                sales.get(&item_type).into_iter().flatten().copied(),
+                now - DAY_SECS,
+            );
+            ItemTypeStatsProto {
+                item_type,
+                active_listings: prices.len() as u32,
+                floor_price_wei: prices.iter().copied().min().unwrap_or_default(),
+                average_price_wei: average(&prices),
+                volume_24h_wei: price(last_24h.volume_wei, enable_crypto),
+            }
+        })
+        .collect()
+}
+
+pub fn price_history_entry(sale: &Sale, enable_crypto: bool) -> PriceHistoryEntryProto {
+    PriceHistoryEntryProto {
+        sale_price_wei: price(sale.price_ntc_wei, enable_crypto),
+        sale_timestamp: sale.sold_at,
+        buyer_player_id: sale.buyer_id.clone(),
+        seller_player_id: sale.seller_id.clone(),
+    }
+}
+
+/// Floor price and 7 and 30 day sale statistics for an NFT template
+pub fn market_analytics(
+    world: &World,
+    template: Template,
+    enable_crypto: bool,
+    now: i64,
+) -> MarketAnalyticsProto {
+    let sales: Vec<&Sale> = world.sales_for_template(template).collect();
+    let last_7d = SaleWindow::since(sales.iter().copied(), now - 7 * DAY_SECS);
+    let last_30d = SaleWindow::since(sales.iter().copied(), now - 30 * DAY_SECS);
+    MarketAnalyticsProto {
+        floor_price_wei: price(
+            world.floor_price(template).unwrap_or_default(),
+            enable_crypto,
+        ),
+        average_price_7d_wei: price(last_7d.average_price_wei, enable_crypto),
+        average_price_30d_wei: price(last_30d.average_price_wei, enable_crypto),
+        total_sales_7d: last_7d.sales,
+        total_sales_30d: last_30d.sales,
+        recent_sales: sales
+            .iter()
+            .rev()
+            .take(RECENT_SALES)
+            .map(|sale| price_history_entry(sale, enable_crypto))
+            .collect(),
+    }
+}
+
+pub fn average(prices: &[u64]) -> u64 {
+    if prices.is_empty() {
+        return 0;
+    }
+    (prices.iter().map(|&price| u128::from(price)).sum::<u128>() / prices.len() as u128) as u64
+}
+
+/// ERC-721 style metadata document for an NFT
+pub fn metadata_json(nft: &Nft) -> String {
+    serde_json::json!({
+        "name": nft.name(),
+        "description": format!("{} forged at {}", nft.item_type.as_str(), nft.construct_origin),
+        "image": format!("ipfs://{}", nft.metadata_cid),
+        "attributes": [
+            {"trait_type": "rarity", "value": nft.rarity.as_str()},
+            {"trait_type": "item_type", "value": nft.item_type.as_str()},
+        ],
+    })
+    .to_string()
+}
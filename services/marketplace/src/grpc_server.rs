@@ -1,10 +1,32 @@
-use crate::config::StubConfiguration;
-use crate::stub::{MarketplaceStub, RequestContext, SmartStub};
+use crate::analytics::{Analytics, CandleInterval, SaleWindow, DAY_SECS};
+use crate::auction::{Auctions, Bids};
+use crate::batch::{
+    BatchItemResult, BatchMode, BatchOutcome, Batches, TradeIntent, MAX_BATCH_ITEMS,
+};
+use crate::config::MarketplaceConfig;
+use crate::credits::Credits;
+use crate::escrow::{EscrowStatus, Escrows};
+use crate::feed::{MarketFeedFilter, MarketSubscription, MarketUpdate, MarketUpdateKind};
+use crate::fixtures;
+use crate::market::{MarketError, Marketability, NewListing, OrderBook};
+use crate::offer::{NewOffer, Offer, OfferStatus, Offers};
+use crate::search::{SearchQuery, SearchSort, StatCategory, StatRange};
+use crate::state::{MarketGuard, MarketState, UpdateMarket};
+use crate::stub::{RequestContext, SharedStub, SmartStub};
 use anyhow::Result;
 use chrono::Utc;
-use std::sync::Arc;
+use futures_util::Stream;
+use smart_stubs::stub::WorldGuard;
+use smart_stubs::world::{
+    credits_for_price, paginate, random_hash, Bid, BunkerClass, ChainEvent, ClassAffiliation,
+    Currency, FeeBreakdown, ItemCondition, ItemRarity, ItemType, Listing, MarketStatus, Nft, Page,
+    Template, World,
+};
+use std::pin::Pin;
 use tonic::{Request, Response, Status};
-use uuid::Uuid;
+
+/// Gas every transaction burns before executing; lower limits always fail
+const INTRINSIC_GAS: u64 = 21_000;
 
 // Include the generated protobuf code
 pub mod bunkerverse {
@@ -22,40 +44,278 @@ pub mod bunkerverse {
 
 // Import all necessary types from generated protobuf modules
 use bunkerverse::services::v1::{
-    cancel_listing_response, create_listing_response, execute_trade_intent_response,
-    get_listing_details_response, get_market_listings_response, get_nft_details_response,
-    get_player_owned_nfts_response, marketplace_service_server, search_marketplace_response,
-    CancelListingRequest, CancelListingResponse, CancelListingSuccess, CreateListingRequest,
-    CreateListingResponse, CreateListingSuccess, ExecuteTradeIntentRequest,
-    ExecuteTradeIntentResponse, ExecuteTradeIntentSuccess, GetListingDetailsRequest,
+    cancel_listing_response, cancel_listings_response, cancel_offer_response,
+    create_listing_response, create_listings_response, execute_trade_intent_response,
+    execute_trade_intents_response, get_bids_response, get_listing_details_response,
+    get_market_listings_response, get_nft_details_response, get_offers_response,
+    get_player_owned_nfts_response, get_price_history_response, make_offer_response,
+    marketplace_service_server, place_bid_response, respond_to_offer_response,
+    search_marketplace_response, settle_auction_response, AuctionStateProto, BatchItemOutcomeProto,
+    BatchItemStatusProto, BidProto, CancelListingRequest, CancelListingResponse,
+    CancelListingResultProto, CancelListingSuccess, CancelListingsRequest, CancelListingsResponse,
+    CancelListingsSuccess, CancelOfferRequest, CancelOfferResponse, CancelOfferSuccess,
+    CreateListingRequest, CreateListingResponse, CreateListingResultProto, CreateListingSuccess,
+    CreateListingsRequest, CreateListingsResponse, CreateListingsSuccess,
+    ExecuteTradeIntentRequest, ExecuteTradeIntentResponse, ExecuteTradeIntentSuccess,
+    ExecuteTradeIntentsRequest, ExecuteTradeIntentsResponse, ExecuteTradeIntentsSuccess,
+    FeeBreakdownProto, GetBidsRequest, GetBidsResponse, GetBidsSuccess, GetListingDetailsRequest,
     GetListingDetailsResponse, GetMarketListingsRequest, GetMarketListingsResponse,
-    GetMarketListingsSuccess, GetNftDetailsRequest, GetNftDetailsResponse,
-    GetPlayerOwnedNftsRequest, GetPlayerOwnedNftsResponse, GetPlayerOwnedNftsSuccess,
-    GetTransactionReceiptRequest, HealthRequest, HealthResponse, InventoryStatsProto,
-    MarketAnalyticsProto, MarketListingDetailProto, MarketListingProto, MarketStatsProto,
-    NftDetailsResponseProto, PlayerOwnedNftProto, SearchMarketplaceRequest,
-    SearchMarketplaceResponse, SearchMarketplaceSuccess,
+    GetMarketListingsSuccess, GetNftDetailsRequest, GetNftDetailsResponse, GetOffersRequest,
+    GetOffersResponse, GetOffersSuccess, GetPlayerOwnedNftsRequest, GetPlayerOwnedNftsResponse,
+    GetPlayerOwnedNftsSuccess, GetPriceHistoryRequest, GetPriceHistoryResponse,
+    GetPriceHistorySuccess, GetTransactionReceiptRequest, HealthRequest, HealthResponse,
+    InventoryStatsProto, MakeOfferRequest, MakeOfferResponse, MakeOfferSuccess, MarketEventProto,
+    MarketEventTypeProto, MarketListingDetailProto, MarketStatsProto, MarketplaceFiltersProto,
+    MarketplaceSortProto, NftDetailsResponseProto, OfferProto, OfferResponseActionProto,
+    PlaceBidRequest, PlaceBidResponse, PlaceBidSuccess, PlayerOwnedNftProto, PriceCandleProto,
+    RespondToOfferRequest, RespondToOfferResponse, RespondToOfferSuccess, SearchMarketplaceRequest,
+    SearchMarketplaceResponse, SearchMarketplaceSuccess, SettleAuctionRequest,
+    SettleAuctionResponse, SettleAuctionSuccess, SubscribeMarketEventsRequest,
+    TradeIntentResultProto,
 };
 
+fn bid_proto(bid: &Bid) -> BidProto {
+    BidProto {
+        bid_id: bid.bid_id.clone(),
+        listing_id: bid.listing_id.clone(),
+        bidder_player_id: bid.bidder_id.clone(),
+        amount_ntc_wei: bid.amount_ntc_wei,
+        placed_at: bid.placed_at,
+        status: bid.status as i32,
+    }
+}
+
+fn offer_proto(offer: &Offer, now: i64) -> OfferProto {
+    OfferProto {
+        offer_id: offer.offer_id.clone(),
+        nft_id: offer.nft_id.clone(),
+        buyer_player_id: offer.buyer_id.clone(),
+        owner_player_id: offer.owner_id.clone(),
+        amount: offer.amount,
+        currency: offer.currency as i32,
+        from_owner: offer.from_owner,
+        counter_of_offer_id: offer.counter_of.clone().unwrap_or_default(),
+        created_at: offer.created_at,
+        expires_at: offer.expires_at,
+        status: offer.status_at(now) as i32,
+    }
+}
+
+fn fee_breakdown_proto(sale_price: u64, fees: &FeeBreakdown) -> FeeBreakdownProto {
+    FeeBreakdownProto {
+        sale_price,
+        platform_fee_basis_points: fees.platform_fee_basis_points,
+        platform_fee: fees.platform_fee,
+        seller_staking_tier: fees.seller_staking_tier,
+        staking_discount: fees.staking_discount,
+        marketplace_fee: fees.marketplace_fee,
+        royalty_basis_points: fees.royalty_basis_points,
+        royalty: fees.royalty,
+        royalty_recipient_player_id: fees.royalty_recipient_id.clone().unwrap_or_default(),
+        seller_proceeds: fees.seller_proceeds,
+    }
+}
+
+fn batch_outcome_proto<T>(index: usize, result: &BatchItemResult<T>) -> BatchItemOutcomeProto {
+    let (status, error) = match result {
+        BatchItemResult::Applied(_) => (BatchItemStatusProto::BatchItemStatusApplied, None),
+        BatchItemResult::Failed(err) => (BatchItemStatusProto::BatchItemStatusFailed, Some(err)),
+        BatchItemResult::RolledBack => (BatchItemStatusProto::BatchItemStatusRolledBack, None),
+    };
+    BatchItemOutcomeProto {
+        index: index as u32,
+        status: status as i32,
+        error_code: error.map(|err| err.code().to_string()).unwrap_or_default(),
+        error_message: error.map(ToString::to_string).unwrap_or_default(),
+    }
+}
+
+/// Rejects empty batches and batches over [`MAX_BATCH_ITEMS`]
+fn check_batch_size(items: usize) -> Result<(), String> {
+    if items == 0 || items > MAX_BATCH_ITEMS {
+        return Err(format!(
+            "A batch must hold between 1 and {MAX_BATCH_ITEMS} items, got {items}"
+        ));
+    }
+    Ok(())
+}
+
+/// Result per cart item; `applied` fills in what an applied item bought
+fn trade_intent_results<T>(
+    outcome: &BatchOutcome<T>,
+    listing_ids: Vec<String>,
+    applied: impl Fn(&T) -> TradeIntentResultProto,
+) -> Vec<TradeIntentResultProto> {
+    outcome
+        .results
+        .iter()
+        .zip(listing_ids)
+        .enumerate()
+        .map(|(index, (result, listing_id))| {
+            let item = match result {
+                BatchItemResult::Applied(purchase) => applied(purchase),
+                _ => TradeIntentResultProto::default(),
+            };
+            TradeIntentResultProto {
+                outcome: Some(batch_outcome_proto(index, result)),
+                listing_id,
+                ..item
+            }
+        })
+        .collect()
+}
+
+/// Receipt for an order book transaction, reflecting any escrow it settles
+fn transaction_receipt(
+    world: &World,
+    state: &MarketState,
+    transaction_hash: String,
+) -> bunkerverse::core::v1::TransactionReceiptProto {
+    use bunkerverse::core::v1::TransactionStatusProto;
+
+    let events: Vec<&ChainEvent> = world.events_for_transaction(&transaction_hash).collect();
+    let mut receipt = bunkerverse::core::v1::TransactionReceiptProto {
+        status: TransactionStatusProto::Confirmed as i32,
+        block_number: events.first().map_or(12345, |event| event.block_number),
+        gas_used: 21000,
+        error_message: "".to_string(),
+        emitted_events: events
+            .iter()
+            .map(|event| event.payload.event_type().to_string())
+            .collect(),
+        confirmation_timestamp: events
+            .first()
+            .map_or_else(|| Utc::now().timestamp(), |event| event.block_timestamp),
+        transaction_hash,
+    };
+    let Some(escrow) = state.escrows.get(&receipt.transaction_hash) else {
+        return receipt;
+    };
+    receipt.status = escrow.status as i32;
+    match escrow.status {
+        EscrowStatus::Confirmed => {}
+        EscrowStatus::Pending => {
+            receipt.block_number = 0;
+            receipt.gas_used = 0;
+            receipt.confirmation_timestamp = 0;
+        }
+        EscrowStatus::Failed | EscrowStatus::Timeout => {
+            receipt.block_number = 0;
+            receipt.confirmation_timestamp = escrow.settled_at.unwrap_or_default();
+            receipt.error_message = if escrow.status == EscrowStatus::Failed {
+                "Transaction reverted; held funds returned to the buyer".to_string()
+            } else {
+                "Purchase lock expired before the transaction settled".to_string()
+            };
+        }
+    }
+    receipt
+}
+
+fn market_event_proto(update: &MarketUpdate, enable_crypto: bool) -> MarketEventProto {
+    MarketEventProto {
+        sequence: update.sequence,
+        event_type: match update.kind {
+            MarketUpdateKind::Listed => MarketEventTypeProto::MarketEventTypeListed,
+            MarketUpdateKind::PriceChanged => MarketEventTypeProto::MarketEventTypePriceChanged,
+            MarketUpdateKind::Sold => MarketEventTypeProto::MarketEventTypeSold,
+            MarketUpdateKind::Cancelled => MarketEventTypeProto::MarketEventTypeCancelled,
+            MarketUpdateKind::Expired => MarketEventTypeProto::MarketEventTypeExpired,
+        } as i32,
+        listing_id: update.listing_id.clone(),
+        nft_id: update.nft_id.clone(),
+        seller_player_id: update.seller_id.clone(),
+        buyer_player_id: update.buyer_id.clone().unwrap_or_default(),
+        listing_type: update.listing_type as i32,
+        item_type: update.item_type as i32,
+        item_rarity: update.rarity as i32,
+        price_ntc_wei: if enable_crypto || update.currency == Currency::Credits {
+            update.price_ntc_wei
+        } else {
+            credits_for_price(update.price_ntc_wei)
+        },
+        timestamp: update.timestamp,
+        currency: update.currency as i32,
+    }
+}
+
+/// Search over the order book as described by request filters and sort
+fn search_query(
+    text: String,
+    filters: Option<MarketplaceFiltersProto>,
+    sort: Option<MarketplaceSortProto>,
+    requesting_player_id: &str,
+) -> SearchQuery {
+    let filters = filters.unwrap_or_default();
+    SearchQuery {
+        text,
+        item_types: filters
+            .item_types
+            .into_iter()
+            .filter_map(ItemType::from_proto)
+            .collect(),
+        rarities: filters
+            .rarities
+            .into_iter()
+            .filter_map(ItemRarity::from_proto)
+            .collect(),
+        conditions: filters
+            .conditions
+            .into_iter()
+            .filter_map(ItemCondition::from_proto)
+            .collect(),
+        class_affinities: filters
+            .class_affinities
+            .into_iter()
+            .filter_map(BunkerClass::from_proto)
+            .collect(),
+        affiliations: filters
+            .affiliations
+            .into_iter()
+            .filter_map(ClassAffiliation::from_proto)
+            .collect(),
+        min_price_ntc_wei: filters.min_price_ntc_wei,
+        max_price_ntc_wei: filters.max_price_ntc_wei,
+        seller_ids: filters.seller_player_ids,
+        exclude_seller_id: (filters.exclude_own_listings && !requesting_player_id.is_empty())
+            .then(|| requesting_player_id.to_string()),
+        stat_ranges: filters
+            .stat_ranges
+            .into_iter()
+            .filter_map(|range| {
+                Some(StatRange {
+                    category: StatCategory::from_proto(range.category)?,
+                    min: range.min_value,
+                    max: range.max_value,
+                })
+            })
+            .collect(),
+        sort: sort.map_or(SearchSort::Listed, |sort| {
+            SearchSort::from_proto(sort.sort_field)
+        }),
+    }
+}
+
+fn pagination_proto(page: Page) -> bunkerverse::core::v1::PaginationProto {
+    bunkerverse::core::v1::PaginationProto {
+        page: page.page,
+        page_size: page.page_size,
+        total_items: page.total_items,
+        total_pages: page.total_pages,
+    }
+}
+
 pub struct MarketplaceGrpcService {
-    stub: Arc<tokio::sync::Mutex<MarketplaceStub>>,
+    stub: SharedStub,
 }
 
 impl MarketplaceGrpcService {
-    pub fn new(config: StubConfiguration) -> Self {
-        Self {
-            stub: Arc::new(tokio::sync::Mutex::new(MarketplaceStub::new(config))),
-        }
+    pub fn new(stub: SharedStub) -> Self {
+        Self { stub }
     }
 
     async fn create_context(&self, trace_id: Option<String>) -> RequestContext {
-        let stub = self.stub.lock().await;
-        RequestContext {
-            request_id: Uuid::new_v4().to_string(),
-            trace_id,
-            timestamp: Utc::now(),
-            enable_crypto: stub.get_configuration().dual_mode.enable_crypto,
-        }
+        self.stub.lock().await.create_context(trace_id)
     }
 
     async fn simulate_latency_and_errors(
@@ -63,89 +323,23 @@ impl MarketplaceGrpcService {
         context: &RequestContext,
         method: &str,
     ) -> Result<(), Status> {
-        let stub = self.stub.lock().await;
-
-        // Simulate latency
-        let latency = stub.calculate_response_latency();
-        tokio::time::sleep(tokio::time::Duration::from_millis(
-            latency.as_millis() as u64
-        ))
-        .await;
-
-        // Check for error injection
-        if stub.should_inject_error_response() {
-            stub.log_response(context, method, latency.as_millis() as u64, 500, true);
-            return Err(Status::internal("Simulated gRPC error"));
-        }
-
-        stub.log_response(context, method, latency.as_millis() as u64, 200, false);
-        Ok(())
+        smart_stubs::grpc::simulate_call(&self.stub, context, method, "Simulated gRPC error").await
     }
 
-    fn create_mock_nft_details(
-        nft_id: &str,
-        enable_crypto: bool,
-    ) -> bunkerverse::core::v1::NftDetailsProto {
-        bunkerverse::core::v1::NftDetailsProto {
-            identifier: Some(bunkerverse::core::v1::NftIdentifierProto {
-                nft_id: nft_id.to_string(),
-                token_id: 1,
-                contract_address: if enable_crypto {
-                    "0xabcdef1234567890".to_string()
-                } else {
-                    "".to_string()
-                },
-            }),
-            item_type: bunkerverse::core::v1::ItemTypeProto::Head as i32,
-            item_rarity: bunkerverse::core::v1::ItemRarityProto::Advanced as i32,
-            base_stat_boosts: Some(bunkerverse::core::v1::CoreStatsProto {
-                damage: 100,
-                accuracy: 80,
-                critical_chance: 20,
-                armor_piercing: 30,
-                speed: 50,
-                agility: 60,
-                stealth: 40,
-                evasion: 70,
-                health: 200,
-                shield: 150,
-                detection: 80,
-                range: 90,
-                combat_average: 57,
-                mobility_average: 55,
-                survivability_average: 175,
-                sensors_average: 85,
-            }),
-            class_affinities: vec![bunkerverse::core::v1::BunkerClassProto::Explorer as i32],
-            trait_affiliation: bunkerverse::core::v1::ClassAffiliationProto::Loyal as i32,
-            construct_origin: "Factory Alpha".to_string(),
-            metadata_pointer_uri: "Qm123456789abcdefghijklmnopqrstuvwxyzABCDEFGH".to_string(),
-            schema_version: 1,
-            created_timestamp: Utc::now().timestamp(),
-        }
+    async fn world(&self) -> WorldGuard {
+        self.stub.lock().await.world()
     }
 
-    fn create_mock_nft_state(
-        player_id: &str,
-        enable_crypto: bool,
-    ) -> bunkerverse::core::v1::NftMutableStateProto {
-        bunkerverse::core::v1::NftMutableStateProto {
-            current_owner_id: player_id.to_string(),
-            current_condition: bunkerverse::core::v1::ItemConditionProto::NewState as i32,
-            is_soulbound: false,
-            market_status: bunkerverse::core::v1::MarketStatusProto::ListedForSale as i32,
-            market_price_ntc: if enable_crypto {
-                1000000000000000000u64
-            } else {
-                100
-            },
-            last_updated_timestamp: Utc::now().timestamp(),
-        }
+    async fn market(&self) -> MarketGuard {
+        self.stub.lock().await.world_with()
     }
 }
 
 #[tonic::async_trait]
 impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
+    type SubscribeMarketEventsStream =
+        Pin<Box<dyn Stream<Item = Result<MarketEventProto, Status>> + Send + 'static>>;
+
     async fn get_market_listings(
         &self,
         request: Request<GetMarketListingsRequest>,
@@ -161,58 +355,56 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         self.simulate_latency_and_errors(&context, "GetMarketListings")
             .await?;
 
-        // Generate mock listings based on dual-mode configuration
-        let listings = if context.enable_crypto {
-            vec![MarketListingProto {
-                listing_id: "listing_001".to_string(),
-                nft_details: Some(Self::create_mock_nft_details("nft_001", true)),
-                nft_state: Some(Self::create_mock_nft_state("player_123", true)),
-                seller_player_id: "player_123".to_string(),
-                seller_bunker_tag: "TestSeller".to_string(),
-                listing_price_ntc_wei: 1000000000000000000u64, // 1 NTC
-                listing_type: bunkerverse::core::v1::MarketStatusProto::ListedForSale as i32,
-                listing_created_at: Utc::now().timestamp(),
-                listing_expires_at: (Utc::now() + chrono::Duration::days(7)).timestamp(),
-                view_count: 42,
-                favorite_count: 5,
-                similar_listings: vec!["listing_002".to_string()],
-            }]
-        } else {
-            vec![MarketListingProto {
-                listing_id: "listing_001".to_string(),
-                nft_details: Some(Self::create_mock_nft_details("item_001", false)),
-                nft_state: Some(Self::create_mock_nft_state("player_456", false)),
-                seller_player_id: "player_456".to_string(),
-                seller_bunker_tag: "MVEPlayer".to_string(),
-                listing_price_ntc_wei: 100, // 100 credits in MVE mode
-                listing_type: bunkerverse::core::v1::MarketStatusProto::ListedForSale as i32,
-                listing_created_at: Utc::now().timestamp(),
-                listing_expires_at: (Utc::now() + chrono::Duration::days(30)).timestamp(),
-                view_count: 12,
-                favorite_count: 1,
-                similar_listings: vec![],
-            }]
-        };
+        let world = self.market().await;
+        // Listings in the other currency cannot be bought in this mode
+        let results = world.state().search.search(
+            &world,
+            &search_query(String::new(), req.filters, req.sort, ""),
+        );
+        let matched: Vec<&Listing> = results
+            .listings
+            .into_iter()
+            .filter(|listing| fixtures::in_mode_currency(listing, context.enable_crypto))
+            .collect();
+        let pagination = req.pagination.unwrap_or_default();
+        let (page, page_info) = paginate(&matched, pagination.page, pagination.page_size);
+        let listings = page
+            .iter()
+            .map(|listing| {
+                fixtures::market_listing(&world, world.state(), listing, context.enable_crypto)
+            })
+            .collect();
+        let market: Vec<&Listing> = world
+            .listings
+            .iter()
+            .filter(|listing| fixtures::in_mode_currency(listing, context.enable_crypto))
+            .collect();
+        let prices: Vec<u64> = market
+            .iter()
+            .map(|listing| fixtures::listing_price(listing, context.enable_crypto))
+            .collect();
+        let now = Utc::now().timestamp();
+        let last_24h = SaleWindow::since(&world.sales, now - DAY_SECS);
 
         let response = GetMarketListingsResponse {
             result: Some(get_market_listings_response::Result::Success(
                 GetMarketListingsSuccess {
                     listings,
-                    pagination: req.pagination,
+                    pagination: Some(pagination_proto(page_info)),
                     market_stats: Some(MarketStatsProto {
-                        total_listings: if context.enable_crypto { 2 } else { 1 },
-                        total_volume_24h_wei: if context.enable_crypto {
-                            5000000000000000000u64
-                        } else {
-                            500
-                        },
-                        average_price_wei: if context.enable_crypto {
-                            750000000000000000u64
-                        } else {
-                            150
-                        },
-                        total_sales_24h: 3,
-                        item_type_stats: vec![],
+                        total_listings: market.len() as u32,
+                        total_volume_24h_wei: fixtures::price(
+                            last_24h.volume_wei,
+                            context.enable_crypto,
+                        ),
+                        average_price_wei: fixtures::average(&prices),
+                        total_sales_24h: last_24h.sales,
+                        item_type_stats: fixtures::item_type_stats(
+                            &world,
+                            &market,
+                            context.enable_crypto,
+                            now,
+                        ),
                     }),
                 },
             )),
@@ -236,53 +428,40 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         self.simulate_latency_and_errors(&context, "GetListingDetails")
             .await?;
 
+        let world = self.market().await;
+        let listing = world
+            .listing(&req.listing_id)
+            .ok_or_else(|| Status::not_found(format!("Listing {} not found", req.listing_id)))?;
+        let similar: Vec<&Listing> = fixtures::similar_listings(&world, listing).collect();
+        let template = world
+            .nft(&listing.nft_id)
+            .map(Template::of)
+            .ok_or_else(|| Status::not_found(format!("NFT {} not found", listing.nft_id)))?;
+        let now = Utc::now().timestamp();
+
         let listing_detail = MarketListingDetailProto {
-            listing: Some(MarketListingProto {
-                listing_id: req.listing_id.clone(),
-                nft_details: Some(Self::create_mock_nft_details(
-                    "nft_001",
-                    context.enable_crypto,
-                )),
-                nft_state: Some(Self::create_mock_nft_state(
-                    "player_123",
-                    context.enable_crypto,
-                )),
-                seller_player_id: "player_123".to_string(),
-                seller_bunker_tag: "TestSeller".to_string(),
-                listing_price_ntc_wei: if context.enable_crypto {
-                    1000000000000000000u64
-                } else {
-                    100
-                },
-                listing_type: bunkerverse::core::v1::MarketStatusProto::ListedForSale as i32,
-                listing_created_at: (Utc::now() - chrono::Duration::hours(1)).timestamp(),
-                listing_expires_at: (Utc::now() + chrono::Duration::days(1)).timestamp(),
-                view_count: 42,
-                favorite_count: 5,
-                similar_listings: vec![],
-            }),
-            price_history: vec![],
-            similar_listings: vec![],
-            analytics: Some(MarketAnalyticsProto {
-                floor_price_wei: if context.enable_crypto {
-                    500000000000000000u64
-                } else {
-                    50
-                },
-                average_price_7d_wei: if context.enable_crypto {
-                    750000000000000000u64
-                } else {
-                    75
-                },
-                average_price_30d_wei: if context.enable_crypto {
-                    800000000000000000u64
-                } else {
-                    80
-                },
-                total_sales_7d: 15,
-                total_sales_30d: 50,
-                recent_sales: vec![],
-            }),
+            listing: Some(fixtures::market_listing(
+                &world,
+                world.state(),
+                listing,
+                context.enable_crypto,
+            )),
+            price_history: world
+                .sales_for_nft(&listing.nft_id)
+                .map(|sale| fixtures::price_history_entry(sale, context.enable_crypto))
+                .collect(),
+            similar_listings: similar
+                .iter()
+                .map(|similar| {
+                    fixtures::market_listing(&world, world.state(), similar, context.enable_crypto)
+                })
+                .collect(),
+            analytics: Some(fixtures::market_analytics(
+                &world,
+                template,
+                context.enable_crypto,
+                now,
+            )),
             is_favorited_by_requester: false,
         };
 
@@ -310,13 +489,99 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         self.simulate_latency_and_errors(&context, "SearchMarketplace")
             .await?;
 
+        let world = self.market().await;
+        let results = world.state().search.search(
+            &world,
+            &search_query(
+                req.search_query,
+                req.filters,
+                req.sort,
+                &req.requesting_player_id,
+            ),
+        );
+        let pagination = req.pagination.unwrap_or_default();
+        let (page, page_info) = paginate(&results.listings, pagination.page, pagination.page_size);
+
         let response = SearchMarketplaceResponse {
             result: Some(search_marketplace_response::Result::Success(
                 SearchMarketplaceSuccess {
-                    listings: vec![], // Empty for now
-                    pagination: req.pagination,
-                    search_suggestions: vec!["weapon".to_string(), "armor".to_string()],
-                    facet_counts: std::collections::HashMap::new(),
+                    listings: page
+                        .iter()
+                        .map(|listing| {
+                            fixtures::market_listing(
+                                &world,
+                                world.state(),
+                                listing,
+                                context.enable_crypto,
+                            )
+                        })
+                        .collect(),
+                    pagination: Some(pagination_proto(page_info)),
+                    search_suggestions: results.suggestions,
+                    facet_counts: results.facet_counts.into_iter().collect(),
+                },
+            )),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_price_history(
+        &self,
+        request: Request<GetPriceHistoryRequest>,
+    ) -> Result<Response<GetPriceHistoryResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "GetPriceHistory", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "GetPriceHistory")
+            .await?;
+
+        let template = Template {
+            item_type: ItemType::from_proto(req.item_type)
+                .ok_or_else(|| Status::invalid_argument("item_type is required"))?,
+            rarity: ItemRarity::from_proto(req.item_rarity)
+                .ok_or_else(|| Status::invalid_argument("item_rarity is required"))?,
+        };
+        let world = self.world().await;
+        let history = world.price_history(
+            template,
+            CandleInterval::from_proto(req.interval),
+            req.from_timestamp,
+            Utc::now().timestamp(),
+        );
+        let price = |amount: u64| fixtures::price(amount, context.enable_crypto);
+
+        let response = GetPriceHistoryResponse {
+            result: Some(get_price_history_response::Result::Success(
+                GetPriceHistorySuccess {
+                    candles: history
+                        .candles
+                        .iter()
+                        .map(|candle| PriceCandleProto {
+                            opens_at: candle.opens_at,
+                            open_wei: price(candle.open),
+                            high_wei: price(candle.high),
+                            low_wei: price(candle.low),
+                            close_wei: price(candle.close),
+                            volume_wei: price(candle.volume_wei),
+                            sales: candle.sales,
+                        })
+                        .collect(),
+                    floor_price_wei: price(history.floor_price_wei.unwrap_or_default()),
+                    last_price_wei: price(history.last_price_wei.unwrap_or_default()),
+                    volume_24h_wei: price(history.last_24h.volume_wei),
+                    sales_24h: history.last_24h.sales,
+                    change_24h_basis_points: history.change_24h_basis_points.unwrap_or_default(),
+                    change_7d_basis_points: history.change_7d_basis_points.unwrap_or_default(),
+                    rarity_median_price_wei: price(
+                        history.rarity_median_price_wei.unwrap_or_default(),
+                    ),
+                    suggested_price_wei: price(history.suggested_price_wei.unwrap_or_default()),
                 },
             )),
         };
@@ -346,26 +611,33 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         self.simulate_latency_and_errors(&context, "GetNftDetails")
             .await?;
 
+        let world = self.market().await;
+        let now = Utc::now().timestamp();
+        let nft = world
+            .nft(&req.nft_id)
+            .ok_or_else(|| Status::not_found(format!("NFT {} not found", req.nft_id)))?;
+
         let nft_details = NftDetailsResponseProto {
-            nft_details: Some(Self::create_mock_nft_details(&req.nft_id, true)),
-            nft_state: Some(bunkerverse::core::v1::NftMutableStateProto {
-                current_owner_id: "player_123".to_string(),
-                current_condition: bunkerverse::core::v1::ItemConditionProto::NewState as i32,
-                is_soulbound: false,
-                market_status: bunkerverse::core::v1::MarketStatusProto::NotListed as i32,
-                market_price_ntc: 0,
-                last_updated_timestamp: Utc::now().timestamp(),
-            }),
-            price_history: vec![],
-            market_analytics: Some(MarketAnalyticsProto {
-                floor_price_wei: 500000000000000000u64,
-                average_price_7d_wei: 750000000000000000u64,
-                average_price_30d_wei: 800000000000000000u64,
-                total_sales_7d: 15,
-                total_sales_30d: 50,
-                recent_sales: vec![],
-            }),
-            metadata_json: r#"{"name":"Mock NFT","description":"A mock NFT for testing","image":"https://example.com/nft.png","attributes":[{"trait_type":"rarity","value":"advanced"}]}"#.to_string(),
+            nft_details: Some(fixtures::nft_details(nft, true)),
+            nft_state: Some(fixtures::nft_state(&world, world.state(), nft, true)),
+            price_history: world
+                .sales_for_nft(&nft.nft_id)
+                .map(|sale| fixtures::price_history_entry(sale, true))
+                .collect(),
+            market_analytics: Some(fixtures::market_analytics(
+                &world,
+                Template::of(nft),
+                true,
+                now,
+            )),
+            metadata_json: fixtures::metadata_json(nft),
+            open_offers: world
+                .state()
+                .offers
+                .for_nft(&nft.nft_id)
+                .filter(|offer| offer.status_at(now) == OfferStatus::Open)
+                .map(|offer| offer_proto(offer, now))
+                .collect(),
         };
 
         let response = GetNftDetailsResponse {
@@ -390,43 +662,70 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         self.simulate_latency_and_errors(&context, "GetPlayerOwnedNfts")
             .await?;
 
-        let owned_nfts = if context.enable_crypto {
-            vec![PlayerOwnedNftProto {
-                nft_details: Some(Self::create_mock_nft_details("nft_player_001", true)),
-                nft_state: Some(bunkerverse::core::v1::NftMutableStateProto {
-                    current_owner_id: req.player_id.clone(),
-                    current_condition: bunkerverse::core::v1::ItemConditionProto::NewState as i32,
-                    is_soulbound: false,
-                    market_status: bunkerverse::core::v1::MarketStatusProto::NotListed as i32,
-                    market_price_ntc: 0,
-                    last_updated_timestamp: Utc::now().timestamp(),
-                }),
-                acquired_at: (Utc::now() - chrono::Duration::days(30)).timestamp(),
-                acquisition_method: "purchase".to_string(),
-                is_equipped: true,
-                estimated_market_value_wei: 1200000000000000000u64,
-            }]
+        let world = self.market().await;
+        if world.player(&req.player_id).is_none() {
+            return Err(Status::not_found(format!(
+                "Player {} not found",
+                req.player_id
+            )));
+        }
+
+        // NFTs are not exposed in MVE mode
+        let owned: Vec<&Nft> = if context.enable_crypto {
+            world.nfts_owned_by(&req.player_id).collect()
         } else {
-            vec![] // Empty for MVE mode
+            vec![]
         };
 
+        let mut inventory_stats = InventoryStatsProto::default();
+        for nft in &owned {
+            let estimated_value = world
+                .listing_for_nft(&nft.nft_id)
+                .map_or(0, |listing| listing.price_ntc_wei);
+            inventory_stats.total_nfts += 1;
+            inventory_stats.estimated_total_value_wei += estimated_value;
+            *inventory_stats
+                .item_type_counts
+                .entry(nft.item_type.as_str().to_string())
+                .or_default() += 1;
+            *inventory_stats
+                .rarity_counts
+                .entry(nft.rarity.as_str().to_string())
+                .or_default() += 1;
+            if world.is_equipped(nft) {
+                inventory_stats.equipped_items += 1;
+            }
+            if world.is_marketable(nft) {
+                inventory_stats.marketable_items += 1;
+            }
+        }
+
+        let pagination = req.pagination.unwrap_or_default();
+        let (page, page_info) = paginate(&owned, pagination.page, pagination.page_size);
+        let owned_nfts = page
+            .iter()
+            .map(|nft| PlayerOwnedNftProto {
+                nft_details: Some(fixtures::nft_details(nft, true)),
+                nft_state: Some(fixtures::nft_state(&world, world.state(), nft, true)),
+                acquired_at: nft.acquired_at.unwrap_or(nft.minted_at),
+                acquisition_method: if nft.acquired_at.is_some() {
+                    "purchase".to_string()
+                } else {
+                    nft.mint_reason.clone()
+                },
+                is_equipped: world.is_equipped(nft),
+                estimated_market_value_wei: world
+                    .listing_for_nft(&nft.nft_id)
+                    .map_or(0, |listing| listing.price_ntc_wei),
+            })
+            .collect();
+
         let response = GetPlayerOwnedNftsResponse {
             result: Some(get_player_owned_nfts_response::Result::Success(
                 GetPlayerOwnedNftsSuccess {
                     owned_nfts,
-                    pagination: req.pagination,
-                    inventory_stats: Some(InventoryStatsProto {
-                        total_nfts: if context.enable_crypto { 1 } else { 0 },
-                        estimated_total_value_wei: if context.enable_crypto {
-                            1200000000000000000u64
-                        } else {
-                            0
-                        },
-                        item_type_counts: std::collections::HashMap::new(),
-                        rarity_counts: std::collections::HashMap::new(),
-                        equipped_items: if context.enable_crypto { 1 } else { 0 },
-                        marketable_items: if context.enable_crypto { 1 } else { 0 },
-                    }),
+                    pagination: Some(pagination_proto(page_info)),
+                    inventory_stats: Some(inventory_stats),
                 },
             )),
         };
@@ -444,26 +743,35 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         {
             let stub = self.stub.lock().await;
             stub.log_request(&context, "CreateListing", "gRPC");
-
-            // Check crypto features for blockchain operations
-            if let Err(err) = stub.check_crypto_features(&context) {
-                return Err(Status::permission_denied(err));
-            }
         }
 
         self.simulate_latency_and_errors(&context, "CreateListing")
             .await?;
 
+        let listing_type =
+            MarketStatus::from_proto(req.listing_type).ok_or(MarketError::InvalidListingType)?;
+        // With crypto off the listing is priced in credits and never goes on chain
+        let new_listing = NewListing {
+            seller_id: req.player_id,
+            nft_id: req.nft_id,
+            price: req.price_ntc_wei,
+            currency: fixtures::mode_currency(context.enable_crypto),
+            listing_type,
+            reserve_price_ntc_wei: req.reserve_price_ntc_wei,
+            expires_at: req.expiry_timestamp,
+        };
+        let (listing_id, transaction_hash) = self.stub.lock().await.update_market(|market| {
+            market
+                .create_listing(new_listing, Utc::now().timestamp())
+                .map(|listing| (listing.listing_id.clone(), listing.tx_hash.clone()))
+        })?;
+
         let response = CreateListingResponse {
             result: Some(create_listing_response::Result::Success(
                 CreateListingSuccess {
-                    listing_id: Uuid::new_v4().to_string(),
-                    transaction_hash: if context.enable_crypto {
-                        "0xmocktxhash12345".to_string()
-                    } else {
-                        "".to_string()
-                    },
-                    transaction_status: bunkerverse::core::v1::TransactionStatusProto::Pending
+                    listing_id,
+                    transaction_hash,
+                    transaction_status: bunkerverse::core::v1::TransactionStatusProto::Confirmed
                         as i32,
                 },
             )),
@@ -487,16 +795,20 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         self.simulate_latency_and_errors(&context, "CancelListing")
             .await?;
 
+        self.stub.lock().await.update_market(|market| {
+            market.cancel_listing(&req.player_id, &req.listing_id, Utc::now().timestamp())
+        })?;
+
         let response = CancelListingResponse {
             result: Some(cancel_listing_response::Result::Success(
                 CancelListingSuccess {
                     cancelled: true,
                     transaction_hash: if context.enable_crypto {
-                        "0xmocktxhash67890".to_string()
+                        random_hash(&mut rand::thread_rng())
                     } else {
                         "".to_string()
                     },
-                    transaction_status: bunkerverse::core::v1::TransactionStatusProto::Pending
+                    transaction_status: bunkerverse::core::v1::TransactionStatusProto::Confirmed
                         as i32,
                 },
             )),
@@ -515,6 +827,341 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         {
             let stub = self.stub.lock().await;
             stub.log_request(&context, "ExecuteTradeIntent", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "ExecuteTradeIntent")
+            .await?;
+
+        let success = {
+            let stub = self.stub.lock().await;
+            let config = MarketplaceConfig::of(stub.get_configuration());
+            let now = Utc::now().timestamp();
+            if !context.enable_crypto {
+                // Credits settle on the payment ledger at once, with nothing to escrow
+                let sale = stub.update_market(|market| {
+                    market.execute_credits_trade(
+                        &req.buyer_player_id,
+                        &req.listing_id,
+                        req.offered_price_ntc_wei,
+                        &config.fees,
+                        now,
+                    )
+                })?;
+                ExecuteTradeIntentSuccess {
+                    transaction_hash: String::new(),
+                    transaction_status: bunkerverse::core::v1::TransactionStatusProto::Confirmed
+                        as i32,
+                    final_price_paid_wei: sale.price_credits,
+                    marketplace_fee_wei: sale.fees.marketplace_fee,
+                    fee_breakdown: Some(fee_breakdown_proto(sale.price_credits, &sale.fees)),
+                    escrow_expires_at: 0,
+                    ledger_transaction_id: sale.transaction_id,
+                }
+            } else if config.escrow.enabled {
+                let escrow = stub.update_market(|market| {
+                    market.reserve_trade(
+                        &req.buyer_player_id,
+                        &req.listing_id,
+                        req.offered_price_ntc_wei,
+                        &config.fees,
+                        now,
+                        config.escrow.lock_secs(),
+                    )
+                })?;
+                ExecuteTradeIntentSuccess {
+                    transaction_hash: escrow.tx_hash,
+                    transaction_status: bunkerverse::core::v1::TransactionStatusProto::Pending
+                        as i32,
+                    final_price_paid_wei: escrow.price_ntc_wei,
+                    marketplace_fee_wei: escrow.fees.marketplace_fee,
+                    fee_breakdown: Some(fee_breakdown_proto(escrow.price_ntc_wei, &escrow.fees)),
+                    escrow_expires_at: escrow.expires_at,
+                    ledger_transaction_id: String::new(),
+                }
+            } else {
+                let sale = stub.update_market(|market| {
+                    market.execute_trade(
+                        &req.buyer_player_id,
+                        &req.listing_id,
+                        req.offered_price_ntc_wei,
+                        &config.fees,
+                        now,
+                    )
+                })?;
+                ExecuteTradeIntentSuccess {
+                    transaction_hash: sale.tx_hash,
+                    transaction_status: bunkerverse::core::v1::TransactionStatusProto::Confirmed
+                        as i32,
+                    final_price_paid_wei: sale.price_ntc_wei,
+                    marketplace_fee_wei: sale.marketplace_fee_wei,
+                    fee_breakdown: Some(fee_breakdown_proto(sale.price_ntc_wei, &sale.fees)),
+                    escrow_expires_at: 0,
+                    ledger_transaction_id: String::new(),
+                }
+            }
+        };
+
+        let response = ExecuteTradeIntentResponse {
+            result: Some(execute_trade_intent_response::Result::Success(success)),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn create_listings(
+        &self,
+        request: Request<CreateListingsRequest>,
+    ) -> Result<Response<CreateListingsResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "CreateListings", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "CreateListings")
+            .await?;
+
+        check_batch_size(req.items.len()).map_err(Status::invalid_argument)?;
+        let nft_ids: Vec<_> = req.items.iter().map(|item| item.nft_id.clone()).collect();
+        let new_listings = req
+            .items
+            .into_iter()
+            .map(|item| {
+                // Unknown types fail on their own item as INVALID_LISTING_TYPE
+                let listing_type =
+                    MarketStatus::from_proto(item.listing_type).unwrap_or(MarketStatus::NotListed);
+                NewListing {
+                    seller_id: req.player_id.clone(),
+                    nft_id: item.nft_id,
+                    price: item.price_ntc_wei,
+                    currency: fixtures::mode_currency(context.enable_crypto),
+                    listing_type,
+                    reserve_price_ntc_wei: item.reserve_price_ntc_wei,
+                    expires_at: item.expiry_timestamp,
+                }
+            })
+            .collect();
+        let outcome = self.stub.lock().await.update_market(|market| {
+            market.create_listings(
+                new_listings,
+                BatchMode::from_proto(req.mode),
+                Utc::now().timestamp(),
+            )
+        });
+
+        let results = outcome
+            .results
+            .iter()
+            .zip(nft_ids)
+            .enumerate()
+            .map(|(index, (result, nft_id))| {
+                let (listing_id, transaction_hash) = match result {
+                    BatchItemResult::Applied(listing) => {
+                        (listing.listing_id.clone(), listing.tx_hash.clone())
+                    }
+                    _ => Default::default(),
+                };
+                CreateListingResultProto {
+                    outcome: Some(batch_outcome_proto(index, result)),
+                    nft_id,
+                    listing_id,
+                    transaction_hash,
+                }
+            })
+            .collect();
+
+        let response = CreateListingsResponse {
+            result: Some(create_listings_response::Result::Success(
+                CreateListingsSuccess {
+                    results,
+                    committed: outcome.committed,
+                    applied_count: outcome.applied() as u32,
+                },
+            )),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn cancel_listings(
+        &self,
+        request: Request<CancelListingsRequest>,
+    ) -> Result<Response<CancelListingsResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "CancelListings", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "CancelListings")
+            .await?;
+
+        check_batch_size(req.listing_ids.len()).map_err(Status::invalid_argument)?;
+        let outcome = self.stub.lock().await.update_market(|market| {
+            market.cancel_listings(
+                &req.player_id,
+                &req.listing_ids,
+                BatchMode::from_proto(req.mode),
+                Utc::now().timestamp(),
+            )
+        });
+
+        let results = outcome
+            .results
+            .iter()
+            .zip(req.listing_ids)
+            .enumerate()
+            .map(|(index, (result, listing_id))| CancelListingResultProto {
+                outcome: Some(batch_outcome_proto(index, result)),
+                listing_id,
+                transaction_hash: if context.enable_crypto && result.is_applied() {
+                    random_hash(&mut rand::thread_rng())
+                } else {
+                    "".to_string()
+                },
+            })
+            .collect();
+
+        let response = CancelListingsResponse {
+            result: Some(cancel_listings_response::Result::Success(
+                CancelListingsSuccess {
+                    results,
+                    committed: outcome.committed,
+                    applied_count: outcome.applied() as u32,
+                },
+            )),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn execute_trade_intents(
+        &self,
+        request: Request<ExecuteTradeIntentsRequest>,
+    ) -> Result<Response<ExecuteTradeIntentsResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "ExecuteTradeIntents", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "ExecuteTradeIntents")
+            .await?;
+
+        check_batch_size(req.items.len()).map_err(Status::invalid_argument)?;
+        let cart: Vec<_> = req
+            .items
+            .into_iter()
+            .map(|item| TradeIntent {
+                listing_id: item.listing_id,
+                offered_price: item.offered_price_ntc_wei,
+            })
+            .collect();
+        let listing_ids: Vec<_> = cart.iter().map(|item| item.listing_id.clone()).collect();
+        let (results, committed, applied_count) = {
+            let stub = self.stub.lock().await;
+            let config = MarketplaceConfig::of(stub.get_configuration());
+            let mode = BatchMode::from_proto(req.mode);
+            let now = Utc::now().timestamp();
+            if !context.enable_crypto {
+                let outcome = stub.update_market(|market| {
+                    market.execute_credits_trades(
+                        &req.buyer_player_id,
+                        cart,
+                        mode,
+                        &config.fees,
+                        now,
+                    )
+                });
+                let results =
+                    trade_intent_results(&outcome, listing_ids, |sale| TradeIntentResultProto {
+                        nft_id: sale.listing.nft_id.clone(),
+                        final_price_paid_wei: sale.price_credits,
+                        fee_breakdown: Some(fee_breakdown_proto(sale.price_credits, &sale.fees)),
+                        transaction_status: bunkerverse::core::v1::TransactionStatusProto::Confirmed
+                            as i32,
+                        ledger_transaction_id: sale.transaction_id.clone(),
+                        ..Default::default()
+                    });
+                (results, outcome.committed, outcome.applied())
+            } else if config.escrow.enabled {
+                let outcome = stub.update_market(|market| {
+                    market.reserve_trades(
+                        &req.buyer_player_id,
+                        cart,
+                        mode,
+                        &config.fees,
+                        now,
+                        config.escrow.lock_secs(),
+                    )
+                });
+                let results =
+                    trade_intent_results(&outcome, listing_ids, |escrow| TradeIntentResultProto {
+                        nft_id: escrow.nft_id.clone(),
+                        transaction_hash: escrow.tx_hash.clone(),
+                        final_price_paid_wei: escrow.price_ntc_wei,
+                        fee_breakdown: Some(fee_breakdown_proto(
+                            escrow.price_ntc_wei,
+                            &escrow.fees,
+                        )),
+                        transaction_status: bunkerverse::core::v1::TransactionStatusProto::Pending
+                            as i32,
+                        escrow_expires_at: escrow.expires_at,
+                        ..Default::default()
+                    });
+                (results, outcome.committed, outcome.applied())
+            } else {
+                let outcome = stub.update_market(|market| {
+                    market.execute_trades(&req.buyer_player_id, cart, mode, &config.fees, now)
+                });
+                let results =
+                    trade_intent_results(&outcome, listing_ids, |sale| TradeIntentResultProto {
+                        nft_id: sale.nft_id.clone(),
+                        transaction_hash: sale.tx_hash.clone(),
+                        final_price_paid_wei: sale.price_ntc_wei,
+                        fee_breakdown: Some(fee_breakdown_proto(sale.price_ntc_wei, &sale.fees)),
+                        transaction_status: bunkerverse::core::v1::TransactionStatusProto::Confirmed
+                            as i32,
+                        ..Default::default()
+                    });
+                (results, outcome.committed, outcome.applied())
+            }
+        };
+        let total_price_paid_wei = results
+            .iter()
+            .map(|result| result.final_price_paid_wei)
+            .sum();
+
+        let response = ExecuteTradeIntentsResponse {
+            result: Some(execute_trade_intents_response::Result::Success(
+                ExecuteTradeIntentsSuccess {
+                    results,
+                    committed,
+                    applied_count: applied_count as u32,
+                    total_price_paid_wei,
+                },
+            )),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn place_bid(
+        &self,
+        request: Request<PlaceBidRequest>,
+    ) -> Result<Response<PlaceBidResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "PlaceBid", "gRPC");
 
             // Check crypto features for blockchain operations
             if let Err(err) = stub.check_crypto_features(&context) {
@@ -522,17 +1169,116 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
             }
         }
 
-        self.simulate_latency_and_errors(&context, "ExecuteTradeIntent")
+        self.simulate_latency_and_errors(&context, "PlaceBid")
             .await?;
 
-        let response = ExecuteTradeIntentResponse {
-            result: Some(execute_trade_intent_response::Result::Success(
-                ExecuteTradeIntentSuccess {
-                    transaction_hash: "0xmocktxhash11111".to_string(),
-                    transaction_status: bunkerverse::core::v1::TransactionStatusProto::Pending
+        let placed = self.stub.lock().await.update_market(|market| {
+            market.place_bid(
+                &req.bidder_player_id,
+                &req.listing_id,
+                req.amount_ntc_wei,
+                Utc::now().timestamp(),
+            )
+        })?;
+
+        let response = PlaceBidResponse {
+            result: Some(place_bid_response::Result::Success(PlaceBidSuccess {
+                bid: Some(bid_proto(&placed.bid)),
+                minimum_next_bid_wei: placed.minimum_next_bid_wei,
+                auction_ends_at: placed.ends_at,
+                auction_extended: placed.extended,
+            })),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_bids(
+        &self,
+        request: Request<GetBidsRequest>,
+    ) -> Result<Response<GetBidsResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "GetBids", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "GetBids")
+            .await?;
+
+        let world = self.world().await;
+        let bids: Vec<BidProto> = world
+            .bids_for_listing(&req.listing_id)
+            .map(bid_proto)
+            .collect();
+        let auction = world
+            .listing(&req.listing_id)
+            .map(|listing| AuctionStateProto {
+                starting_price_ntc_wei: listing.price_ntc_wei,
+                reserve_met: world
+                    .highest_bid(&listing.listing_id)
+                    .is_some_and(|bid| bid.amount_ntc_wei >= listing.reserve_price_ntc_wei),
+                minimum_next_bid_wei: world.minimum_next_bid(listing),
+                ends_at: listing.expires_at,
+            });
+        // Settled auctions keep their bid history after the listing is gone
+        if auction.is_none() && bids.is_empty() {
+            return Err(MarketError::ListingNotFound(req.listing_id).into());
+        }
+
+        let response = GetBidsResponse {
+            result: Some(get_bids_response::Result::Success(GetBidsSuccess {
+                bids,
+                auction,
+            })),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn settle_auction(
+        &self,
+        request: Request<SettleAuctionRequest>,
+    ) -> Result<Response<SettleAuctionResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "SettleAuction", "gRPC");
+
+            // Check crypto features for blockchain operations
+            if let Err(err) = stub.check_crypto_features(&context) {
+                return Err(Status::permission_denied(err));
+            }
+        }
+
+        self.simulate_latency_and_errors(&context, "SettleAuction")
+            .await?;
+
+        let settlement = {
+            let stub = self.stub.lock().await;
+            let fees = &MarketplaceConfig::of(stub.get_configuration()).fees;
+            stub.update_market(|market| {
+                market.settle_auction(&req.listing_id, fees, Utc::now().timestamp())
+            })?
+        };
+
+        let sale = settlement.sale.as_ref();
+        let response = SettleAuctionResponse {
+            result: Some(settle_auction_response::Result::Success(
+                SettleAuctionSuccess {
+                    sold: sale.is_some(),
+                    winner_player_id: sale.map(|sale| sale.buyer_id.clone()).unwrap_or_default(),
+                    final_price_wei: sale.map_or(0, |sale| sale.price_ntc_wei),
+                    marketplace_fee_wei: sale.map_or(0, |sale| sale.marketplace_fee_wei),
+                    transaction_hash: settlement.tx_hash,
+                    transaction_status: bunkerverse::core::v1::TransactionStatusProto::Confirmed
                         as i32,
-                    final_price_paid_wei: req.offered_price_ntc_wei,
-                    marketplace_fee_wei: req.offered_price_ntc_wei / 40, // 2.5% fee
+                    fee_breakdown: sale
+                        .map(|sale| fee_breakdown_proto(sale.price_ntc_wei, &sale.fees)),
                 },
             )),
         };
@@ -540,6 +1286,241 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         Ok(Response::new(response))
     }
 
+    async fn make_offer(
+        &self,
+        request: Request<MakeOfferRequest>,
+    ) -> Result<Response<MakeOfferResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+        let currency = Currency::from_proto(req.currency).ok_or(MarketError::InvalidCurrency)?;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "MakeOffer", "gRPC");
+
+            // NTC offers need crypto features, credit offers do not
+            if currency == Currency::Ntc {
+                if let Err(err) = stub.check_crypto_features(&context) {
+                    return Err(Status::permission_denied(err));
+                }
+            }
+        }
+
+        self.simulate_latency_and_errors(&context, "MakeOffer")
+            .await?;
+
+        let now = Utc::now().timestamp();
+        let new_offer = NewOffer {
+            buyer_id: req.buyer_player_id,
+            nft_id: req.nft_id,
+            amount: req.amount,
+            currency,
+            expires_at: req.expiry_timestamp,
+        };
+        let offer = self.stub.lock().await.update_market(|market| {
+            market
+                .make_offer(new_offer, now)
+                .map(|offer| offer_proto(offer, now))
+        })?;
+
+        let response = MakeOfferResponse {
+            result: Some(make_offer_response::Result::Success(MakeOfferSuccess {
+                offer: Some(offer),
+            })),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn respond_to_offer(
+        &self,
+        request: Request<RespondToOfferRequest>,
+    ) -> Result<Response<RespondToOfferResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "RespondToOffer", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "RespondToOffer")
+            .await?;
+
+        let now = Utc::now().timestamp();
+        let action = OfferResponseActionProto::try_from(req.action)
+            .unwrap_or(OfferResponseActionProto::OfferResponseActionUnspecified);
+        let success = {
+            let stub = self.stub.lock().await;
+            let fees = &MarketplaceConfig::of(stub.get_configuration()).fees;
+            stub.update_market(|market| {
+                let mut success = RespondToOfferSuccess::default();
+                match action {
+                    OfferResponseActionProto::OfferResponseActionAccept => {
+                        let trade =
+                            market.accept_offer(&req.player_id, &req.offer_id, fees, now)?;
+                        success.offer = Some(offer_proto(&trade.offer, now));
+                        success.transaction_hash = trade.tx_hash.unwrap_or_default();
+                        success.transaction_status =
+                            bunkerverse::core::v1::TransactionStatusProto::Confirmed as i32;
+                        success.marketplace_fee = trade.fees.marketplace_fee;
+                        success.seller_proceeds = trade.fees.seller_proceeds;
+                        success.fee_breakdown =
+                            Some(fee_breakdown_proto(trade.offer.amount, &trade.fees));
+                    }
+                    OfferResponseActionProto::OfferResponseActionReject => {
+                        let offer = market.reject_offer(&req.player_id, &req.offer_id, now)?;
+                        success.offer = Some(offer_proto(&offer, now));
+                    }
+                    OfferResponseActionProto::OfferResponseActionCounter => {
+                        let counter = market.counter_offer(
+                            &req.player_id,
+                            &req.offer_id,
+                            req.counter_amount,
+                            req.counter_expiry_timestamp,
+                            now,
+                        )?;
+                        success.counter_offer = Some(offer_proto(counter, now));
+                        success.offer = market
+                            .state
+                            .offers
+                            .get(&req.offer_id)
+                            .map(|offer| offer_proto(offer, now));
+                    }
+                    OfferResponseActionProto::OfferResponseActionUnspecified => {
+                        return Err(MarketError::InvalidOfferAction);
+                    }
+                }
+                Ok(success)
+            })?
+        };
+
+        let response = RespondToOfferResponse {
+            result: Some(respond_to_offer_response::Result::Success(success)),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn cancel_offer(
+        &self,
+        request: Request<CancelOfferRequest>,
+    ) -> Result<Response<CancelOfferResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "CancelOffer", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "CancelOffer")
+            .await?;
+
+        let now = Utc::now().timestamp();
+        let offer = self
+            .stub
+            .lock()
+            .await
+            .update_market(|market| market.cancel_offer(&req.player_id, &req.offer_id, now))?;
+
+        let response = CancelOfferResponse {
+            result: Some(cancel_offer_response::Result::Success(CancelOfferSuccess {
+                offer: Some(offer_proto(&offer, now)),
+            })),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_offers(
+        &self,
+        request: Request<GetOffersRequest>,
+    ) -> Result<Response<GetOffersResponse>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "GetOffers", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "GetOffers")
+            .await?;
+
+        let world = self.market().await;
+        let now = Utc::now().timestamp();
+        let offers: Vec<&Offer> = if !req.nft_id.is_empty() {
+            if world.nft(&req.nft_id).is_none() {
+                return Err(MarketError::NftNotFound(req.nft_id).into());
+            }
+            world.state().offers.for_nft(&req.nft_id).collect()
+        } else if !req.player_id.is_empty() {
+            world
+                .state()
+                .offers
+                .iter()
+                .filter(|offer| offer.buyer_id == req.player_id || offer.owner_id == req.player_id)
+                .collect()
+        } else {
+            return Err(Status::invalid_argument(
+                "Either nft_id or player_id is required",
+            ));
+        };
+
+        let response = GetOffersResponse {
+            result: Some(get_offers_response::Result::Success(GetOffersSuccess {
+                offers: offers
+                    .into_iter()
+                    .filter(|offer| req.include_closed || offer.status_at(now) == OfferStatus::Open)
+                    .map(|offer| offer_proto(offer, now))
+                    .collect(),
+            })),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn subscribe_market_events(
+        &self,
+        request: Request<SubscribeMarketEventsRequest>,
+    ) -> Result<Response<Self::SubscribeMarketEventsStream>, Status> {
+        let req = request.into_inner();
+        let context = self.create_context(Some(req.trace_id.clone())).await;
+
+        {
+            let stub = self.stub.lock().await;
+            stub.log_request(&context, "SubscribeMarketEvents", "gRPC");
+        }
+
+        self.simulate_latency_and_errors(&context, "SubscribeMarketEvents")
+            .await?;
+
+        let filter = MarketFeedFilter {
+            item_types: req
+                .item_types
+                .into_iter()
+                .filter_map(ItemType::from_proto)
+                .collect(),
+            rarities: req
+                .rarities
+                .into_iter()
+                .filter_map(ItemRarity::from_proto)
+                .collect(),
+            seller_ids: req.seller_player_ids,
+        };
+        let subscription =
+            MarketSubscription::open(self.stub.clone(), filter, req.resume_after_sequence).await;
+        let enable_crypto = context.enable_crypto;
+        let stream =
+            futures_util::stream::unfold(subscription, move |mut subscription| async move {
+                let update = subscription.next().await?;
+                Some((Ok(market_event_proto(&update, enable_crypto)), subscription))
+            });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
     async fn submit_transaction(
         &self,
         request: Request<bunkerverse::core::v1::TransactionRequestProto>,
@@ -555,14 +1536,46 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         self.simulate_latency_and_errors(&context, "SubmitTransaction")
             .await?;
 
-        let response = bunkerverse::core::v1::TransactionReceiptProto {
-            transaction_hash: "0xmocktxhash99999".to_string(),
-            status: bunkerverse::core::v1::TransactionStatusProto::Confirmed as i32,
-            block_number: 12345,
-            gas_used: 21000,
-            error_message: "".to_string(),
-            emitted_events: vec![],
-            confirmation_timestamp: Utc::now().timestamp(),
+        let transaction_hash = req.transaction_data.trim().to_string();
+        let now = Utc::now().timestamp();
+        let escrowed = self.stub.lock().await.update_market(|market| {
+            // Settle the escrowed purchase this transaction pays for, if any
+            let pending = market
+                .state
+                .escrows
+                .get(&transaction_hash)
+                .filter(|escrow| escrow.status == EscrowStatus::Pending)
+                .map(|escrow| escrow.expires_at);
+            match pending {
+                Some(expires_at) if expires_at <= now => {
+                    market.release_escrow(&transaction_hash, EscrowStatus::Timeout, now)?;
+                }
+                Some(_) if req.gas_limit != 0 && req.gas_limit < INTRINSIC_GAS => {
+                    market.release_escrow(&transaction_hash, EscrowStatus::Failed, now)?;
+                }
+                Some(_) => {
+                    market.confirm_escrow(&transaction_hash, now)?;
+                }
+                None => {}
+            }
+            Ok::<_, MarketError>(market.state.escrows.get(&transaction_hash).is_some())
+        })?;
+
+        let response = if escrowed {
+            {
+                let market = self.market().await;
+                transaction_receipt(&market, market.state(), transaction_hash)
+            }
+        } else {
+            bunkerverse::core::v1::TransactionReceiptProto {
+                transaction_hash: "0xmocktxhash99999".to_string(),
+                status: bunkerverse::core::v1::TransactionStatusProto::Confirmed as i32,
+                block_number: 12345,
+                gas_used: INTRINSIC_GAS,
+                error_message: "".to_string(),
+                emitted_events: vec![],
+                confirmation_timestamp: now,
+            }
         };
 
         Ok(Response::new(response))
@@ -583,15 +1596,29 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         self.simulate_latency_and_errors(&context, "GetTransactionReceipt")
             .await?;
 
-        let response = bunkerverse::core::v1::TransactionReceiptProto {
-            transaction_hash: req.transaction_hash,
-            status: bunkerverse::core::v1::TransactionStatusProto::Confirmed as i32,
-            block_number: 12345,
-            gas_used: 21000,
-            error_message: "".to_string(),
-            emitted_events: vec![],
-            confirmation_timestamp: Utc::now().timestamp(),
-        };
+        // Purchases whose lock ran out report TIMEOUT even before the sweeper runs
+        let now = Utc::now().timestamp();
+        let expired = self
+            .market()
+            .await
+            .state()
+            .escrows
+            .get(&req.transaction_hash)
+            .is_some_and(|escrow| {
+                escrow.status == EscrowStatus::Pending && escrow.expires_at <= now
+            });
+        if expired {
+            // The sweeper may have released it in the meantime
+            self.stub.lock().await.update_market(|market| {
+                market
+                    .release_escrow(&req.transaction_hash, EscrowStatus::Timeout, now)
+                    .ok()
+            });
+        }
+
+        // Transactions from the order book report the block and events they produced
+        let market = self.market().await;
+        let response = transaction_receipt(&market, market.state(), req.transaction_hash);
 
         Ok(Response::new(response))
     }
@@ -603,13 +1630,14 @@ impl marketplace_service_server::MarketplaceService for MarketplaceGrpcService {
         let _req = request.into_inner();
         let context = self.create_context(None).await;
 
-        {
+        let health = {
             let stub = self.stub.lock().await;
             stub.log_request(&context, "Health", "gRPC");
-        }
+            stub.health_check()
+        };
 
         let response = HealthResponse {
-            status: "HEALTHY".to_string(),
+            status: health.as_str().to_string(),
             version: "0.1.0".to_string(),
             timestamp: Utc::now().timestamp(),
             details: std::collections::HashMap::new(),
@@ -1,30 +1,54 @@
+mod analytics;
+mod auction;
+mod batch;
 mod config;
+mod credits;
+mod escrow;
+mod expiry;
+mod feed;
+mod fees;
+mod fixtures;
 mod grpc_server;
+mod market;
+mod offer;
+mod search;
+mod state;
 mod stub;
 
+use crate::market::OrderBook;
+use crate::state::UpdateMarket;
 use anyhow::Result;
 use axum::{
-    extract::{Path, Query, State},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        FromRef, Path, Query, State,
+    },
     http::StatusCode,
-    response::Json,
+    response::{Json, Response},
     routing::{get, post},
     Router,
 };
 use chrono::{DateTime, Utc};
 use config::StubConfiguration;
+use expiry::{ExpiryMetrics, ExpirySweeper};
+use feed::{MarketFeedFilter, MarketSubscription, MarketUpdate, MarketUpdateKind};
 use grpc_server::{
     bunkerverse::services::v1::marketplace_service_server::MarketplaceServiceServer,
     MarketplaceGrpcService,
 };
+use market::{MarketError, NewListing};
 use serde::{Deserialize, Serialize};
+use smart_stubs::world::{
+    credits_for_price, paginate, Currency, ItemRarity, ItemType, Listing, MarketStatus, Nft, World,
+};
+use smart_stubs::{admin, StubLayer};
 use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use stub::{MarketplaceStub, RequestContext, SmartStub};
+use stub::{RequestContext, SharedStub, SmartStub};
 use tokio::signal;
 use tonic::transport::Server;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-use uuid::Uuid;
 
 // API Request/Response Types
 #[derive(Debug, Deserialize)]
@@ -81,6 +105,9 @@ pub struct CreateListingRequest {
     pub currency: String,
     pub listing_type: String,
     pub expires_at: Option<DateTime<Utc>>,
+    /// Auctions only; bids below it do not win at settlement
+    #[serde(default)]
+    pub reserve_price_wei: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -90,6 +117,35 @@ pub struct CreateListingResponse {
     pub estimated_gas_fee: Option<String>,
 }
 
+/// Filters for the `/ws` market feed; each one is optional
+#[derive(Debug, Deserialize)]
+pub struct MarketFeedQuery {
+    pub item_type: Option<ItemType>,
+    pub rarity: Option<ItemRarity>,
+    pub seller: Option<String>,
+    /// Last sequence number received before reconnecting
+    #[serde(default)]
+    pub resume_after: u64,
+}
+
+/// One order book change pushed over the `/ws` market feed
+#[derive(Debug, Serialize)]
+pub struct MarketEvent {
+    pub sequence: u64,
+    #[serde(rename = "type")]
+    pub event_type: MarketUpdateKind,
+    pub listing_id: String,
+    pub nft_id: String,
+    pub seller_id: String,
+    pub buyer_id: Option<String>,
+    pub listing_type: String,
+    pub item_type: ItemType,
+    pub rarity: ItemRarity,
+    pub price_wei: String,
+    pub currency: String,
+    pub timestamp: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct HealthResponse {
     pub status: String,
@@ -117,27 +173,133 @@ pub struct ErrorResponse {
 // Application State
 #[derive(Clone)]
 pub struct AppState {
-    pub stub: Arc<tokio::sync::Mutex<MarketplaceStub>>,
+    pub stub: SharedStub,
+    /// Counters of the listing expiry sweeper
+    pub expiry_metrics: Arc<ExpiryMetrics>,
 }
 
 impl AppState {
-    pub fn new(config: StubConfiguration) -> Self {
+    pub fn new(stub: SharedStub, expiry_metrics: Arc<ExpiryMetrics>) -> Self {
         Self {
-            stub: Arc::new(tokio::sync::Mutex::new(MarketplaceStub::new(config))),
+            stub,
+            expiry_metrics,
         }
     }
 
     pub async fn create_context(&self, trace_id: Option<String>) -> RequestContext {
-        let stub = self.stub.lock().await;
-        RequestContext {
-            request_id: Uuid::new_v4().to_string(),
-            trace_id,
-            timestamp: Utc::now(),
-            enable_crypto: stub.get_configuration().dual_mode.enable_crypto,
+        self.stub.lock().await.create_context(trace_id)
+    }
+}
+
+impl FromRef<AppState> for SharedStub {
+    fn from_ref(state: &AppState) -> Self {
+        state.stub.clone()
+    }
+}
+
+fn market_listing(world: &World, listing: &Listing, enable_crypto: bool) -> MarketListing {
+    let seller = world.player(&listing.seller_id);
+    MarketListing {
+        listing_id: listing.listing_id.clone(),
+        nft_id: listing.nft_id.clone(),
+        seller_address: match seller {
+            Some(seller) if enable_crypto => seller.l3_wallet_address.clone(),
+            _ => listing.seller_id.clone(),
+        },
+        price_wei: fixtures::listing_price(listing, enable_crypto).to_string(),
+        currency: fixtures::listing_currency(listing, enable_crypto)
+            .as_str()
+            .to_string(),
+        listing_type: match listing.listing_type {
+            MarketStatus::ListedForAuction => "auction",
+            _ => "fixed_price",
+        }
+        .to_string(),
+        created_at: DateTime::from_timestamp(listing.created_at, 0).unwrap_or_default(),
+        expires_at: DateTime::from_timestamp(listing.expires_at, 0),
+        status: "active".to_string(),
+    }
+}
+
+fn market_event(update: MarketUpdate, enable_crypto: bool) -> MarketEvent {
+    MarketEvent {
+        sequence: update.sequence,
+        event_type: update.kind,
+        listing_id: update.listing_id,
+        nft_id: update.nft_id,
+        seller_id: update.seller_id,
+        buyer_id: update.buyer_id,
+        listing_type: match update.listing_type {
+            MarketStatus::ListedForAuction => "auction",
+            _ => "fixed_price",
+        }
+        .to_string(),
+        item_type: update.item_type,
+        rarity: update.rarity,
+        price_wei: if enable_crypto || update.currency == Currency::Credits {
+            update.price_ntc_wei
+        } else {
+            credits_for_price(update.price_ntc_wei)
+        }
+        .to_string(),
+        currency: if enable_crypto {
+            update.currency
+        } else {
+            Currency::Credits
         }
+        .as_str()
+        .to_string(),
+        timestamp: DateTime::from_timestamp(update.timestamp, 0).unwrap_or_default(),
+    }
+}
+
+fn nft_details(world: &World, nft: &Nft) -> NftDetails {
+    let metadata: serde_json::Value =
+        serde_json::from_str(&fixtures::metadata_json(nft)).unwrap_or_default();
+    NftDetails {
+        nft_id: nft.nft_id.clone(),
+        token_id: nft.token_id.to_string(),
+        contract_address: Some(nft.contract_address.clone()),
+        name: metadata["name"].as_str().unwrap_or_default().to_string(),
+        description: metadata["description"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        image_url: metadata["image"].as_str().unwrap_or_default().to_string(),
+        owner_address: world
+            .player(&nft.owner_id)
+            .map(|owner| owner.l3_wallet_address.clone())
+            .unwrap_or_default(),
+        metadata: HashMap::from([
+            ("type".to_string(), nft.item_type.as_str().to_string()),
+            ("rarity".to_string(), nft.rarity.as_str().to_string()),
+            ("origin".to_string(), nft.construct_origin.clone()),
+        ]),
     }
 }
 
+fn not_found(message: &str, request_id: String) -> (StatusCode, Json<ErrorResponse>) {
+    let error = ErrorResponse {
+        error: message.to_string(),
+        code: "NOT_FOUND".to_string(),
+        timestamp: Utc::now(),
+        request_id,
+    };
+    (StatusCode::NOT_FOUND, Json(error))
+}
+
+fn market_error(err: &MarketError, request_id: String) -> (StatusCode, Json<ErrorResponse>) {
+    let error = ErrorResponse {
+        error: err.to_string(),
+        code: err.code().to_string(),
+        timestamp: Utc::now(),
+        request_id,
+    };
+    let status =
+        StatusCode::from_u16(err.http_status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, Json(error))
+}
+
 // Handler Functions
 pub async fn health_check(
     State(state): State<AppState>,
@@ -162,6 +324,11 @@ pub async fn health_check(
     Ok(Json(response))
 }
 
+/// Expiry sweeper counters in the Prometheus text format
+pub async fn metrics(State(state): State<AppState>) -> String {
+    state.expiry_metrics.counts().to_prometheus("marketplace")
+}
+
 pub async fn get_stub_config(
     State(state): State<AppState>,
 ) -> Result<Json<StubConfigResponse>, StatusCode> {
@@ -220,56 +387,26 @@ pub async fn get_market_listings(
         return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error)));
     }
 
-    // Generate mock listings based on dual-mode configuration
-    let listings = if context.enable_crypto {
-        // Full crypto mode - return NFT marketplace listings
-        vec![
-            MarketListing {
-                listing_id: "listing_001".to_string(),
-                nft_id: "nft_001".to_string(),
-                seller_address: "0x1234567890abcdef".to_string(),
-                price_wei: "1000000000000000000".to_string(), // 1 ETH
-                currency: "ETH".to_string(),
-                listing_type: "auction".to_string(),
-                created_at: Utc::now(),
-                expires_at: Some(Utc::now() + chrono::Duration::days(1)),
-                status: "active".to_string(),
-            },
-            MarketListing {
-                listing_id: "listing_002".to_string(),
-                nft_id: "nft_002".to_string(),
-                seller_address: "0xabcdef1234567890".to_string(),
-                price_wei: "500000000000000000".to_string(), // 0.5 ETH
-                currency: "ETH".to_string(),
-                listing_type: "fixed_price".to_string(),
-                created_at: Utc::now() - chrono::Duration::hours(2),
-                expires_at: None,
-                status: "active".to_string(),
-            },
-        ]
-    } else {
-        // MVE mode - return non-crypto marketplace items
-        vec![MarketListing {
-            listing_id: "listing_001".to_string(),
-            nft_id: "item_001".to_string(),
-            seller_address: "player_123".to_string(),
-            price_wei: "100".to_string(),
-            currency: "CREDITS".to_string(),
-            listing_type: "fixed_price".to_string(),
-            created_at: Utc::now(),
-            expires_at: Some(Utc::now() + chrono::Duration::days(7)),
-            status: "active".to_string(),
-        }]
-    };
-
-    let page = pagination.page.unwrap_or(1);
-    let limit = pagination.limit.unwrap_or(10).min(100); // Cap at 100
-
+    let world = stub.world();
+    // Only listings buyable in this mode, as over gRPC
+    let listings: Vec<&Listing> = world
+        .listings
+        .iter()
+        .filter(|listing| fixtures::in_mode_currency(listing, context.enable_crypto))
+        .collect();
+    let (page, page_info) = paginate(
+        &listings,
+        pagination.page.unwrap_or(1),
+        pagination.limit.unwrap_or(10),
+    );
     let response = MarketListingsResponse {
-        listings,
-        total_count: if context.enable_crypto { 2 } else { 1 },
-        page,
-        limit,
+        listings: page
+            .iter()
+            .map(|listing| market_listing(&world, listing, context.enable_crypto))
+            .collect(),
+        total_count: page_info.total_items as u32,
+        page: page_info.page,
+        limit: page_info.page_size,
     };
 
     stub.log_response(
@@ -319,30 +456,18 @@ pub async fn get_listing_details(
         return Err((StatusCode::NOT_FOUND, Json(error)));
     }
 
-    let listing = MarketListing {
-        listing_id: listing_id.clone(),
-        nft_id: "nft_001".to_string(),
-        seller_address: if context.enable_crypto {
-            "0x1234567890abcdef".to_string()
-        } else {
-            "player_123".to_string()
-        },
-        price_wei: if context.enable_crypto {
-            "1000000000000000000".to_string()
-        } else {
-            "100".to_string()
-        },
-        currency: if context.enable_crypto {
-            "ETH"
-        } else {
-            "CREDITS"
-        }
-        .to_string(),
-        listing_type: "fixed_price".to_string(),
-        created_at: Utc::now() - chrono::Duration::hours(1),
-        expires_at: Some(Utc::now() + chrono::Duration::days(1)),
-        status: "active".to_string(),
+    let world = stub.world();
+    let Some(listing) = world.listing(&listing_id) else {
+        stub.log_response(
+            &context,
+            &format!("/api/marketplace/listings/{}", listing_id),
+            latency.as_millis() as u64,
+            404,
+            false,
+        );
+        return Err(not_found("Listing not found", context.request_id));
     };
+    let listing = market_listing(&world, listing, context.enable_crypto);
 
     stub.log_response(
         &context,
@@ -392,21 +517,18 @@ pub async fn get_nft_details(
         return Err((StatusCode::FORBIDDEN, Json(error)));
     }
 
-    let nft = NftDetails {
-        nft_id: nft_id.clone(),
-        token_id: "1".to_string(),
-        contract_address: Some("0xabcdef1234567890".to_string()),
-        name: "Mock NFT".to_string(),
-        description: "A mock NFT for testing".to_string(),
-        image_url: "https://example.com/nft.png".to_string(),
-        owner_address: "0x1234567890abcdef".to_string(),
-        metadata: {
-            let mut meta = HashMap::new();
-            meta.insert("rarity".to_string(), "epic".to_string());
-            meta.insert("level".to_string(), "5".to_string());
-            meta
-        },
+    let world = stub.world();
+    let Some(nft) = world.nft(&nft_id) else {
+        stub.log_response(
+            &context,
+            &format!("/api/marketplace/nfts/{}", nft_id),
+            latency.as_millis() as u64,
+            404,
+            false,
+        );
+        return Err(not_found("NFT not found", context.request_id));
     };
+    let nft = nft_details(&world, nft);
 
     stub.log_response(
         &context,
@@ -420,7 +542,7 @@ pub async fn get_nft_details(
 
 pub async fn create_listing(
     State(state): State<AppState>,
-    Json(_request): Json<CreateListingRequest>,
+    Json(request): Json<CreateListingRequest>,
 ) -> Result<Json<CreateListingResponse>, (StatusCode, Json<ErrorResponse>)> {
     let context = state.create_context(None).await;
     let stub = state.stub.lock().await;
@@ -434,36 +556,75 @@ pub async fn create_listing(
     ))
     .await;
 
-    // Check crypto features for blockchain operations
-    if let Err(err) = stub.check_crypto_features(&context) {
-        stub.log_response(
-            &context,
-            "/api/marketplace/listings",
-            latency.as_millis() as u64,
-            403,
-            false,
-        );
-        let error = ErrorResponse {
-            error: err,
-            code: "FEATURE_NOT_ENABLED".to_string(),
-            timestamp: Utc::now(),
-            request_id: context.request_id,
-        };
-        return Err((StatusCode::FORBIDDEN, Json(error)));
-    }
+    // Listings are priced in NTC on chain, or in credits when crypto is off
+    let currency = fixtures::mode_currency(context.enable_crypto);
+    let created = if request.currency.eq_ignore_ascii_case(currency.as_str()) {
+        Ok(())
+    } else {
+        Err(MarketError::InvalidCurrency)
+    };
+
+    // The HTTP API carries no player identity, so the NFT's owner is the seller
+    let created = created.and_then(|()| {
+        request
+            .price_wei
+            .parse::<u64>()
+            .ok()
+            .zip(
+                request
+                    .reserve_price_wei
+                    .as_deref()
+                    .map_or(Some(0), |reserve| reserve.parse::<u64>().ok()),
+            )
+            .ok_or(MarketError::InvalidPrice)
+            .and_then(|(price, reserve_price_ntc_wei)| {
+                let listing_type = match request.listing_type.as_str() {
+                    "fixed_price" => MarketStatus::ListedForSale,
+                    "auction" => MarketStatus::ListedForAuction,
+                    _ => return Err(MarketError::InvalidListingType),
+                };
+                stub.update_market(|market| {
+                    let seller_id = market
+                        .nft(&request.nft_id)
+                        .map(|nft| nft.owner_id.clone())
+                        .ok_or_else(|| MarketError::NftNotFound(request.nft_id.clone()))?;
+                    let new_listing = NewListing {
+                        seller_id,
+                        nft_id: request.nft_id.clone(),
+                        price,
+                        currency,
+                        listing_type,
+                        reserve_price_ntc_wei,
+                        expires_at: request
+                            .expires_at
+                            .map_or(0, |expires_at| expires_at.timestamp()),
+                    };
+                    market
+                        .create_listing(new_listing, Utc::now().timestamp())
+                        .map(|listing| (listing.listing_id.clone(), listing.tx_hash.clone()))
+                })
+            })
+    });
+    let (listing_id, transaction_hash) = match created {
+        Ok(created) => created,
+        Err(err) => {
+            stub.log_response(
+                &context,
+                "/api/marketplace/listings",
+                latency.as_millis() as u64,
+                err.http_status(),
+                false,
+            );
+            return Err(market_error(&err, context.request_id));
+        }
+    };
 
+    // Credits listings never touch the chain
+    let on_chain = !transaction_hash.is_empty();
     let response = CreateListingResponse {
-        listing_id: Uuid::new_v4().to_string(),
-        transaction_hash: if context.enable_crypto {
-            Some("0xmocktxhash12345".to_string())
-        } else {
-            None
-        },
-        estimated_gas_fee: if context.enable_crypto {
-            Some("21000".to_string())
-        } else {
-            None
-        },
+        listing_id,
+        transaction_hash: on_chain.then_some(transaction_hash),
+        estimated_gas_fee: on_chain.then(|| "21000".to_string()),
     };
 
     stub.log_response(
@@ -515,29 +676,29 @@ pub async fn get_player_nfts(
         return Ok(Json(response));
     }
 
-    // Return mock NFTs for crypto mode
-    let nfts = vec![NftDetails {
-        nft_id: "nft_player_001".to_string(),
-        token_id: "42".to_string(),
-        contract_address: Some("0xabcdef1234567890".to_string()),
-        name: "Player's Epic Weapon".to_string(),
-        description: "A legendary weapon NFT owned by the player".to_string(),
-        image_url: "https://example.com/player-weapon.png".to_string(),
-        owner_address: player_address.clone(),
-        metadata: {
-            let mut meta = HashMap::new();
-            meta.insert("type".to_string(), "weapon".to_string());
-            meta.insert("rarity".to_string(), "epic".to_string());
-            meta.insert("damage".to_string(), "150".to_string());
-            meta
-        },
-    }];
+    // Players are addressed by player ID or L3 wallet address
+    let world = stub.world();
+    let owner_id = world
+        .players
+        .iter()
+        .find(|player| {
+            player.player_id == player_address || player.l3_wallet_address == player_address
+        })
+        .map(|player| player.player_id.clone())
+        .unwrap_or_default();
+    let owned: Vec<&Nft> = world.nfts_owned_by(&owner_id).collect();
+    let (page, page_info) = paginate(
+        &owned,
+        pagination.page.unwrap_or(1),
+        pagination.limit.unwrap_or(10),
+    );
+    let nfts: Vec<NftDetails> = page.iter().map(|nft| nft_details(&world, nft)).collect();
 
     let response = serde_json::json!({
         "nfts": nfts,
-        "total_count": 1,
-        "page": pagination.page.unwrap_or(1),
-        "limit": pagination.limit.unwrap_or(10)
+        "total_count": page_info.total_items,
+        "page": page_info.page,
+        "limit": page_info.page_size
     });
 
     stub.log_response(
@@ -550,6 +711,52 @@ pub async fn get_player_nfts(
     Ok(Json(response))
 }
 
+/// Upgrade to a WebSocket that pushes order book changes as JSON [`MarketEvent`]s
+pub async fn market_feed_websocket(
+    State(state): State<AppState>,
+    Query(query): Query<MarketFeedQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let context = state.create_context(None).await;
+    state.stub.lock().await.log_request(&context, "/ws", "GET");
+
+    let filter = MarketFeedFilter {
+        item_types: query.item_type.into_iter().collect(),
+        rarities: query.rarity.into_iter().collect(),
+        seller_ids: query.seller.into_iter().collect(),
+    };
+    let subscription = MarketSubscription::open(state.stub, filter, query.resume_after).await;
+    ws.on_upgrade(move |socket| stream_market_events(socket, subscription, context.enable_crypto))
+}
+
+async fn stream_market_events(
+    mut socket: WebSocket,
+    mut subscription: MarketSubscription,
+    enable_crypto: bool,
+) {
+    loop {
+        tokio::select! {
+            update = subscription.next() => {
+                let Some(update) = update else {
+                    break;
+                };
+                let event = market_event(update, enable_crypto);
+                let Ok(text) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+    info!("Market feed WebSocket closed");
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize tracing with structured JSON logging
@@ -558,10 +765,18 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer().json())
         .init();
 
-    let config = StubConfiguration::default();
+    let config_source = config::config_source();
+    let config = config_source.load()?;
     let http_addr: SocketAddr = format!("0.0.0.0:{}", config.base.port).parse()?;
     let grpc_addr: SocketAddr = format!("0.0.0.0:{}", config.base.port + 1000).parse()?; // gRPC on port 5080
-    let state = AppState::new(config.clone());
+    let stub = stub::new_shared_stub(config.clone());
+    // Expire listings, settle ended auctions and release lapsed escrow locks
+    let expiry_sweeper = ExpirySweeper::new(stub.clone());
+    let state = AppState::new(stub.clone(), expiry_sweeper.metrics());
+    expiry_sweeper.spawn();
+    // Hot reload latency, error and dataset settings when the config file changes
+    config_source.spawn_watcher(stub.clone());
+    smart_stubs::spawn_state_reset(stub.clone());
 
     info!(
         service_name = %config.base.name,
@@ -569,6 +784,7 @@ async fn main() -> Result<()> {
         http_address = %http_addr,
         grpc_address = %grpc_addr,
         enable_crypto = config.dual_mode.enable_crypto,
+        config_path = ?config_source.path(),
         "Starting Marketplace Service Smart Stub with HTTP and gRPC servers"
     );
 
@@ -576,8 +792,11 @@ async fn main() -> Result<()> {
     let app = Router::new()
         // Health and configuration endpoints
         .route("/health", get(health_check))
+        .route("/metrics", get(metrics))
         .route("/stub/config", get(get_stub_config))
         .route("/stub/reset", post(reset_stub_state))
+        .route("/stub/scenario", admin::scenario_route())
+        .route("/stub/world", admin::world_route())
         // Marketplace API endpoints
         .route("/api/marketplace/listings", get(get_market_listings))
         .route("/api/marketplace/listings", post(create_listing))
@@ -590,7 +809,10 @@ async fn main() -> Result<()> {
             "/api/marketplace/players/:player_address/nfts",
             get(get_player_nfts),
         )
+        // Live market updates
+        .route("/ws", get(market_feed_websocket))
         // Middleware
+        .layer(StubLayer::fault_rules(stub.clone()))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
         .with_state(state);
@@ -598,7 +820,7 @@ async fn main() -> Result<()> {
     let http_listener = tokio::net::TcpListener::bind(http_addr).await?;
 
     // gRPC Server
-    let grpc_service = MarketplaceGrpcService::new(config.clone());
+    let grpc_service = MarketplaceGrpcService::new(stub.clone());
 
     info!("HTTP server ready and listening on {}", http_addr);
     info!("gRPC server ready and listening on {}", grpc_addr);
@@ -607,6 +829,7 @@ async fn main() -> Result<()> {
     let http_server = axum::serve(http_listener, app).with_graceful_shutdown(shutdown_signal());
 
     let grpc_server = Server::builder()
+        .layer(StubLayer::fault_rules(stub))
         .add_service(MarketplaceServiceServer::new(grpc_service))
         .serve_with_shutdown(grpc_addr, shutdown_signal());
 
@@ -657,3 +880,85 @@ async fn shutdown_signal() {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::Marketability;
+    use smart_stubs::Dataset;
+
+    #[tokio::test]
+    async fn test_http_listings_only_include_the_mode_currency() {
+        let mut config = StubConfiguration::for_service("marketplace-service-stub", 9000);
+        config.data.dataset = Dataset::Minimal;
+        config.dual_mode.enable_crypto = false;
+        config.errors.error_rate = 0.0;
+        config.latency.min_response_time_ms = 0;
+        config.latency.max_response_time_ms = 0;
+        let shared = stub::new_shared_stub(config);
+        let credits_listing = shared.lock().await.update_market(|market| {
+            let nft = market
+                .nfts
+                .iter()
+                .find(|nft| market.is_marketable(nft))
+                .unwrap()
+                .clone();
+            market
+                .create_listing(
+                    NewListing {
+                        seller_id: nft.owner_id,
+                        nft_id: nft.nft_id,
+                        price: 500,
+                        currency: Currency::Credits,
+                        listing_type: MarketStatus::ListedForSale,
+                        reserve_price_ntc_wei: 0,
+                        expires_at: 0,
+                    },
+                    Utc::now().timestamp(),
+                )
+                .unwrap()
+                .listing_id
+                .clone()
+        });
+        let (expected, ntc_listings) = {
+            let stub = shared.lock().await;
+            let world = stub.world();
+            let (credits, ntc): (Vec<&Listing>, Vec<&Listing>) = world
+                .listings
+                .iter()
+                .partition(|listing| listing.currency == Currency::Credits);
+            let expected: Vec<String> = credits
+                .iter()
+                .map(|listing| listing.listing_id.clone())
+                .collect();
+            (expected, ntc.len())
+        };
+        assert!(ntc_listings > 0);
+
+        let state = AppState::new(shared.clone(), ExpirySweeper::new(shared).metrics());
+        let Json(response) = get_market_listings(
+            State(state),
+            Query(PaginationQuery {
+                page: Some(1),
+                limit: Some(100),
+            }),
+            Query(MarketplaceFilters {
+                category: None,
+                min_price: None,
+                max_price: None,
+                seller: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        let listed: Vec<String> = response
+            .listings
+            .iter()
+            .map(|listing| listing.listing_id.clone())
+            .collect();
+        assert_eq!(listed, expected);
+        assert!(listed.contains(&credits_listing));
+        assert_eq!(response.total_count as usize, expected.len());
+    }
+}
@@ -0,0 +1,924 @@
+//! In-memory order book over the fixture world
+//! Listing creation, cancellation and trades that move NFT ownership, emit chain events
+//! and feed market subscribers
+
+use crate::auction::Bids;
+use crate::config::FeeSchedule;
+use crate::feed::{MarketJournal, MarketUpdateKind};
+use crate::fees::Fees;
+use crate::state::Market;
+use common_rust::types::CreditAmount;
+use common_rust::validation::validate_nft_id;
+use smart_stubs::grpc::code_for_http_status;
+use smart_stubs::world::{
+    credits_for_price, random_hash, Currency, EventPayload, FeeBreakdown, Listing, MarketStatus,
+    Nft, Sale, World,
+};
+use std::fmt;
+
+/// Listing lifetime when the request does not set an expiry
+pub const DEFAULT_LISTING_DURATION_SECS: i64 = 30 * 86_400;
+
+/// Why an order book operation was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketError {
+    PlayerNotFound(String),
+    NftNotFound(String),
+    ListingNotFound(String),
+    InvalidNftId(String),
+    NotOwner {
+        player_id: String,
+        nft_id: String,
+    },
+    NotSeller {
+        player_id: String,
+        listing_id: String,
+    },
+    Soulbound(String),
+    Equipped(String),
+    ActiveRobot(String),
+    AlreadyListed(String),
+    InvalidListingType,
+    InvalidPrice,
+    InvalidExpiry,
+    NotForSale(String),
+    ListingExpired(String),
+    OwnListing(String),
+    PriceBelowAsk {
+        offered: u64,
+        asking: u64,
+    },
+    InsufficientFunds {
+        player_id: String,
+        required: u64,
+    },
+    NotAnAuction(String),
+    AuctionEnded(String),
+    AuctionNotEnded(String),
+    AuctionHasBids(String),
+    BidTooLow {
+        offered: u64,
+        minimum: u64,
+    },
+    OfferNotFound(String),
+    NotOfferResponder {
+        player_id: String,
+        offer_id: String,
+    },
+    NotOfferProposer {
+        player_id: String,
+        offer_id: String,
+    },
+    InvalidCurrency,
+    InvalidOfferAmount,
+    /// Offer response that is not an accept, reject or counter
+    InvalidOfferAction,
+    OwnNft(String),
+    OfferNotOpen(String),
+    OfferExpired(String),
+    InsufficientCredits {
+        player_id: String,
+        required: u64,
+    },
+    ListingLocked(String),
+    EscrowNotFound(String),
+    EscrowSettled(String),
+    EscrowExpired(String),
+    /// Bought in a currency other than the one the listing is priced in
+    CurrencyMismatch {
+        listing_id: String,
+        currency: Currency,
+    },
+}
+
+impl MarketError {
+    /// HTTP status code for the error
+    #[must_use]
+    pub fn http_status(&self) -> u16 {
+        match self {
+            Self::PlayerNotFound(_)
+            | Self::NftNotFound(_)
+            | Self::ListingNotFound(_)
+            | Self::OfferNotFound(_)
+            | Self::EscrowNotFound(_) => 404,
+            Self::NotOwner { .. }
+            | Self::NotSeller { .. }
+            | Self::NotOfferResponder { .. }
+            | Self::NotOfferProposer { .. } => 403,
+            Self::InvalidNftId(_)
+            | Self::InvalidListingType
+            | Self::InvalidPrice
+            | Self::InvalidExpiry
+            | Self::InvalidCurrency
+            | Self::InvalidOfferAmount
+            | Self::InvalidOfferAction => 400,
+            Self::Soulbound(_)
+            | Self::Equipped(_)
+            | Self::ActiveRobot(_)
+            | Self::AlreadyListed(_)
+            | Self::NotForSale(_)
+            | Self::ListingExpired(_)
+            | Self::OwnListing(_)
+            | Self::PriceBelowAsk { .. }
+            | Self::InsufficientFunds { .. }
+            | Self::NotAnAuction(_)
+            | Self::AuctionEnded(_)
+            | Self::AuctionNotEnded(_)
+            | Self::AuctionHasBids(_)
+            | Self::BidTooLow { .. }
+            | Self::OwnNft(_)
+            | Self::OfferNotOpen(_)
+            | Self::OfferExpired(_)
+            | Self::InsufficientCredits { .. }
+            | Self::ListingLocked(_)
+            | Self::EscrowSettled(_)
+            | Self::EscrowExpired(_)
+            | Self::CurrencyMismatch { .. } => 412,
+        }
+    }
+
+    /// Machine readable error code
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::PlayerNotFound(_) => "PLAYER_NOT_FOUND",
+            Self::NftNotFound(_) => "NFT_NOT_FOUND",
+            Self::ListingNotFound(_) => "LISTING_NOT_FOUND",
+            Self::InvalidNftId(_) => "INVALID_NFT_ID",
+            Self::NotOwner { .. } => "NOT_NFT_OWNER",
+            Self::NotSeller { .. } => "NOT_LISTING_SELLER",
+            Self::Soulbound(_) => "NFT_SOULBOUND",
+            Self::Equipped(_) => "NFT_EQUIPPED",
+            Self::ActiveRobot(_) => "ACTIVE_ROBOT",
+            Self::AlreadyListed(_) => "NFT_ALREADY_LISTED",
+            Self::InvalidListingType => "INVALID_LISTING_TYPE",
+            Self::InvalidPrice => "INVALID_PRICE",
+            Self::InvalidExpiry => "INVALID_EXPIRY",
+            Self::NotForSale(_) => "LISTING_NOT_FOR_SALE",
+            Self::ListingExpired(_) => "LISTING_EXPIRED",
+            Self::OwnListing(_) => "OWN_LISTING",
+            Self::PriceBelowAsk { .. } => "PRICE_BELOW_ASK",
+            Self::InsufficientFunds { .. } => "INSUFFICIENT_FUNDS",
+            Self::NotAnAuction(_) => "NOT_AN_AUCTION",
+            Self::AuctionEnded(_) => "AUCTION_ENDED",
+            Self::AuctionNotEnded(_) => "AUCTION_NOT_ENDED",
+            Self::AuctionHasBids(_) => "AUCTION_HAS_BIDS",
+            Self::BidTooLow { .. } => "BID_TOO_LOW",
+            Self::OfferNotFound(_) => "OFFER_NOT_FOUND",
+            Self::NotOfferResponder { .. } => "NOT_OFFER_RESPONDER",
+            Self::NotOfferProposer { .. } => "NOT_OFFER_PROPOSER",
+            Self::InvalidCurrency => "INVALID_CURRENCY",
+            Self::InvalidOfferAmount => "INVALID_OFFER_AMOUNT",
+            Self::InvalidOfferAction => "INVALID_OFFER_ACTION",
+            Self::OwnNft(_) => "OWN_NFT",
+            Self::OfferNotOpen(_) => "OFFER_NOT_OPEN",
+            Self::OfferExpired(_) => "OFFER_EXPIRED",
+            Self::InsufficientCredits { .. } => "INSUFFICIENT_CREDITS",
+            Self::ListingLocked(_) => "LISTING_LOCKED",
+            Self::EscrowNotFound(_) => "ESCROW_NOT_FOUND",
+            Self::EscrowSettled(_) => "ESCROW_ALREADY_SETTLED",
+            Self::EscrowExpired(_) => "ESCROW_EXPIRED",
+            Self::CurrencyMismatch { .. } => "CURRENCY_MISMATCH",
+        }
+    }
+}
+
+impl fmt::Display for MarketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PlayerNotFound(player_id) => write!(f, "Player {player_id} not found"),
+            Self::NftNotFound(nft_id) => write!(f, "NFT {nft_id} not found"),
+            Self::ListingNotFound(listing_id) => write!(f, "Listing {listing_id} not found"),
+            Self::InvalidNftId(nft_id) => write!(f, "NFT id {nft_id:?} is not a valid UUID"),
+            Self::NotOwner { player_id, nft_id } => {
+                write!(f, "Player {player_id} does not own NFT {nft_id}")
+            }
+            Self::NotSeller {
+                player_id,
+                listing_id,
+            } => write!(
+                f,
+                "Player {player_id} is not the seller of listing {listing_id}"
+            ),
+            Self::Soulbound(nft_id) => write!(f, "NFT {nft_id} is soulbound"),
+            Self::Equipped(nft_id) => write!(f, "NFT {nft_id} is equipped and cannot be listed"),
+            Self::ActiveRobot(nft_id) => {
+                write!(f, "NFT {nft_id} is the owner's active Bunkerguard")
+            }
+            Self::AlreadyListed(nft_id) => write!(f, "NFT {nft_id} is already listed"),
+            Self::InvalidListingType => {
+                write!(
+                    f,
+                    "Listing type must be LISTED_FOR_SALE or LISTED_FOR_AUCTION"
+                )
+            }
+            Self::InvalidPrice => write!(f, "Listing price must be greater than zero"),
+            Self::InvalidExpiry => write!(f, "Expiry must be in the future"),
+            Self::NotForSale(listing_id) => {
+                write!(f, "Listing {listing_id} is not a fixed price sale")
+            }
+            Self::ListingExpired(listing_id) => write!(f, "Listing {listing_id} has expired"),
+            Self::OwnListing(listing_id) => {
+                write!(f, "Sellers cannot buy their own listing {listing_id}")
+            }
+            Self::PriceBelowAsk { offered, asking } => {
+                write!(f, "Offered {offered} is below the asking price of {asking}")
+            }
+            Self::InsufficientFunds {
+                player_id,
+                required,
+            } => {
+                write!(f, "Player {player_id} cannot cover {required} wei")
+            }
+            Self::NotAnAuction(listing_id) => write!(f, "Listing {listing_id} is not an auction"),
+            Self::AuctionEnded(listing_id) => write!(f, "Auction {listing_id} has ended"),
+            Self::AuctionNotEnded(listing_id) => {
+                write!(f, "Auction {listing_id} is still accepting bids")
+            }
+            Self::AuctionHasBids(listing_id) => {
+                write!(f, "Auction {listing_id} has bids and cannot be cancelled")
+            }
+            Self::BidTooLow { offered, minimum } => {
+                write!(
+                    f,
+                    "Bid of {offered} wei is below the minimum of {minimum} wei"
+                )
+            }
+            Self::OfferNotFound(offer_id) => write!(f, "Offer {offer_id} not found"),
+            Self::NotOfferResponder {
+                player_id,
+                offer_id,
+            } => write!(f, "Player {player_id} cannot respond to offer {offer_id}"),
+            Self::NotOfferProposer {
+                player_id,
+                offer_id,
+            } => write!(f, "Player {player_id} did not make offer {offer_id}"),
+            Self::InvalidCurrency => write!(f, "Currency must be NTC or CREDITS"),
+            Self::InvalidOfferAmount => write!(f, "Offer amount must be greater than zero"),
+            Self::InvalidOfferAction => write!(f, "Action must be ACCEPT, REJECT or COUNTER"),
+            Self::OwnNft(nft_id) => {
+                write!(f, "Owners cannot make offers on their own NFT {nft_id}")
+            }
+            Self::OfferNotOpen(offer_id) => write!(f, "Offer {offer_id} is no longer open"),
+            Self::OfferExpired(offer_id) => write!(f, "Offer {offer_id} has expired"),
+            Self::InsufficientCredits {
+                player_id,
+                required,
+            } => {
+                write!(f, "Player {player_id} cannot cover {required} credits")
+            }
+            Self::ListingLocked(listing_id) => {
+                write!(f, "Listing {listing_id} has a purchase pending settlement")
+            }
+            Self::EscrowNotFound(tx_hash) => {
+                write!(f, "No escrowed purchase for transaction {tx_hash}")
+            }
+            Self::EscrowSettled(tx_hash) => {
+                write!(f, "Purchase for transaction {tx_hash} is already settled")
+            }
+            Self::EscrowExpired(tx_hash) => {
+                write!(f, "Purchase lock for transaction {tx_hash} has expired")
+            }
+            Self::CurrencyMismatch {
+                listing_id,
+                currency,
+            } => write!(
+                f,
+                "Listing {listing_id} is priced in {} and can only be bought with it",
+                currency.as_str()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MarketError {}
+
+impl From<MarketError> for tonic::Status {
+    fn from(err: MarketError) -> Self {
+        tonic::Status::new(code_for_http_status(err.http_status()), err.to_string())
+    }
+}
+
+/// Listing as requested by a seller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewListing {
+    pub seller_id: String,
+    pub nft_id: String,
+    /// In wei for NTC listings, whole credits otherwise
+    pub price: u64,
+    /// Fixed for the life of the listing; credits listings are fixed price only
+    pub currency: Currency,
+    pub listing_type: MarketStatus,
+    /// Lowest winning bid for auctions, ignored for fixed price sales
+    pub reserve_price_ntc_wei: u64,
+    /// Unix timestamp; 0 uses [`DEFAULT_LISTING_DURATION_SECS`]
+    pub expires_at: i64,
+}
+
+/// Whether NFTs can be put on the market
+pub trait Marketability {
+    /// Whether the owner of `nft` could list it right now
+    #[must_use]
+    fn is_marketable(&self, nft: &Nft) -> bool;
+
+    fn check_marketable(&self, nft: &Nft) -> Result<(), MarketError>;
+}
+
+/// Listing, delisting and buying NFTs
+pub trait OrderBook {
+    /// Open a listing for an NFT the seller owns and can trade
+    ///
+    /// NTC listings are announced on chain. Credits listings are recorded
+    /// locally only and carry no transaction hash.
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the NFT id is malformed, the seller or NFT
+    /// is unknown, the seller does not own the NFT, the NFT is soulbound,
+    /// equipped or already listed, or a credits listing is an auction or
+    /// priced beyond what a credits balance can hold
+    fn create_listing(&mut self, request: NewListing, now: i64) -> Result<&Listing, MarketError>;
+
+    /// Withdraw a listing on behalf of its seller
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the listing is unknown or belongs to another seller
+    fn cancel_listing(
+        &mut self,
+        player_id: &str,
+        listing_id: &str,
+        now: i64,
+    ) -> Result<Listing, MarketError>;
+
+    /// Buy a fixed price NTC listing, moving the NFT and NTC between the players
+    ///
+    /// The price is split between the platform, the NFT's creator and the
+    /// seller according to `fees`.
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the listing or buyer is unknown, the listing
+    /// is not an open fixed price NTC sale or is locked by a pending purchase,
+    /// or the buyer cannot pay the asking price
+    fn execute_trade(
+        &mut self,
+        buyer_id: &str,
+        listing_id: &str,
+        offered_price_wei: u64,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<Sale, MarketError>;
+
+    /// Asking price of a listing the buyer can purchase right now in
+    /// `currency`, and its split
+    fn check_purchase(
+        &self,
+        buyer_id: &str,
+        listing_id: &str,
+        offered_price: u64,
+        currency: Currency,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<(u64, FeeBreakdown), MarketError>;
+
+    /// Close a purchased listing: pay the seller, move the NFT to the buyer
+    /// and record the sale under `tx_hash`
+    ///
+    /// The buyer must already have paid `price`.
+    fn complete_sale(
+        &mut self,
+        listing_id: &str,
+        buyer_id: &str,
+        price: u64,
+        fees: FeeBreakdown,
+        tx_hash: String,
+        now: i64,
+    ) -> Sale;
+}
+
+impl Marketability for World {
+    fn is_marketable(&self, nft: &Nft) -> bool {
+        self.check_marketable(nft).is_ok()
+    }
+
+    fn check_marketable(&self, nft: &Nft) -> Result<(), MarketError> {
+        if nft.is_soulbound {
+            return Err(MarketError::Soulbound(nft.nft_id.clone()));
+        }
+        if self.is_equipped(nft) {
+            return Err(MarketError::Equipped(nft.nft_id.clone()));
+        }
+        if self
+            .robot_for(&nft.owner_id)
+            .is_some_and(|robot| robot.robot_id == nft.nft_id)
+        {
+            return Err(MarketError::ActiveRobot(nft.nft_id.clone()));
+        }
+        if self.listing_for_nft(&nft.nft_id).is_some() {
+            return Err(MarketError::AlreadyListed(nft.nft_id.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl OrderBook for Market<'_> {
+    fn create_listing(&mut self, request: NewListing, now: i64) -> Result<&Listing, MarketError> {
+        if validate_nft_id(&request.nft_id).is_err() {
+            return Err(MarketError::InvalidNftId(request.nft_id));
+        }
+        if self.player(&request.seller_id).is_none() {
+            return Err(MarketError::PlayerNotFound(request.seller_id));
+        }
+        let nft = self
+            .nft(&request.nft_id)
+            .ok_or_else(|| MarketError::NftNotFound(request.nft_id.clone()))?;
+        if nft.owner_id != request.seller_id {
+            return Err(MarketError::NotOwner {
+                player_id: request.seller_id,
+                nft_id: request.nft_id,
+            });
+        }
+        self.check_marketable(nft)?;
+        let listing_types: &[MarketStatus] = match request.currency {
+            Currency::Ntc => &[MarketStatus::ListedForSale, MarketStatus::ListedForAuction],
+            // Bids are held in NTC, so credits are only taken at a fixed price
+            Currency::Credits => &[MarketStatus::ListedForSale],
+        };
+        if !listing_types.contains(&request.listing_type) {
+            return Err(MarketError::InvalidListingType);
+        }
+        let max_price = match request.currency {
+            Currency::Ntc => u64::MAX,
+            Currency::Credits => CreditAmount::MAX_CREDITS / CreditAmount::CENTS_PER_CREDIT,
+        };
+        if request.price == 0 || request.price > max_price {
+            return Err(MarketError::InvalidPrice);
+        }
+        let expires_at = if request.expires_at == 0 {
+            now + DEFAULT_LISTING_DURATION_SECS
+        } else {
+            request.expires_at
+        };
+        if expires_at <= now {
+            return Err(MarketError::InvalidExpiry);
+        }
+
+        let listing_id = uuid::Uuid::new_v4().to_string();
+        let (price_ntc_wei, price_credits, tx_hash, created_at) = match request.currency {
+            Currency::Ntc => {
+                let tx_hash = random_hash(&mut rand::thread_rng());
+                let marketplace = self.contracts.marketplace.clone();
+                let event = self.append_event(
+                    marketplace,
+                    tx_hash.clone(),
+                    now,
+                    EventPayload::NftMarketListed {
+                        listing_id: listing_id.clone(),
+                        player_id: request.seller_id.clone(),
+                        nft_id: request.nft_id.clone(),
+                        price_ntc_wei: request.price,
+                        listing_type: request.listing_type,
+                        expires_at,
+                    },
+                );
+                let created_at = event.block_timestamp;
+                (
+                    request.price,
+                    credits_for_price(request.price),
+                    tx_hash,
+                    created_at,
+                )
+            }
+            Currency::Credits => (0, request.price, String::new(), now),
+        };
+
+        let listing = Listing {
+            listing_id: listing_id.clone(),
+            nft_id: request.nft_id,
+            seller_id: request.seller_id,
+            listing_type: request.listing_type,
+            price_ntc_wei,
+            price_credits,
+            currency: request.currency,
+            reserve_price_ntc_wei: if request.listing_type == MarketStatus::ListedForAuction {
+                request.reserve_price_ntc_wei
+            } else {
+                0
+            },
+            created_at,
+            expires_at,
+            view_count: 0,
+            favorite_count: 0,
+            tx_hash,
+        };
+        self.record_market_update(
+            MarketUpdateKind::Listed,
+            &listing,
+            listing.price(),
+            None,
+            created_at,
+        );
+        self.insert_listing(listing);
+        Ok(self
+            .listing(&listing_id)
+            .expect("listing was just inserted"))
+    }
+
+    fn cancel_listing(
+        &mut self,
+        player_id: &str,
+        listing_id: &str,
+        now: i64,
+    ) -> Result<Listing, MarketError> {
+        let listing = self
+            .listing(listing_id)
+            .ok_or_else(|| MarketError::ListingNotFound(listing_id.to_string()))?;
+        if listing.seller_id != player_id {
+            return Err(MarketError::NotSeller {
+                player_id: player_id.to_string(),
+                listing_id: listing_id.to_string(),
+            });
+        }
+        if self.highest_bid(listing_id).is_some() {
+            return Err(MarketError::AuctionHasBids(listing_id.to_string()));
+        }
+        if self.state.escrows.pending(listing_id).is_some() {
+            return Err(MarketError::ListingLocked(listing_id.to_string()));
+        }
+        let listing = self
+            .remove_listing(listing_id)
+            .expect("listing was just found");
+        self.record_market_update(
+            MarketUpdateKind::Cancelled,
+            &listing,
+            listing.price(),
+            None,
+            now,
+        );
+        Ok(listing)
+    }
+
+    fn execute_trade(
+        &mut self,
+        buyer_id: &str,
+        listing_id: &str,
+        offered_price_wei: u64,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<Sale, MarketError> {
+        let (price, fees) = self.check_purchase(
+            buyer_id,
+            listing_id,
+            offered_price_wei,
+            Currency::Ntc,
+            fees,
+            now,
+        )?;
+        if let Some(buyer) = self.player_mut(buyer_id) {
+            buyer.ntc_balance_wei -= price;
+        }
+        let tx_hash = random_hash(&mut rand::thread_rng());
+        Ok(self.complete_sale(listing_id, buyer_id, price, fees, tx_hash, now))
+    }
+
+    fn check_purchase(
+        &self,
+        buyer_id: &str,
+        listing_id: &str,
+        offered_price: u64,
+        currency: Currency,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<(u64, FeeBreakdown), MarketError> {
+        let listing = self
+            .listing(listing_id)
+            .ok_or_else(|| MarketError::ListingNotFound(listing_id.to_string()))?;
+        let buyer = self
+            .player(buyer_id)
+            .ok_or_else(|| MarketError::PlayerNotFound(buyer_id.to_string()))?;
+        if listing.listing_type != MarketStatus::ListedForSale {
+            return Err(MarketError::NotForSale(listing_id.to_string()));
+        }
+        if listing.expires_at <= now {
+            return Err(MarketError::ListingExpired(listing_id.to_string()));
+        }
+        if self.state.escrows.pending(listing_id).is_some() {
+            return Err(MarketError::ListingLocked(listing_id.to_string()));
+        }
+        if listing.seller_id == buyer_id {
+            return Err(MarketError::OwnListing(listing_id.to_string()));
+        }
+        if listing.currency != currency {
+            return Err(MarketError::CurrencyMismatch {
+                listing_id: listing_id.to_string(),
+                currency: listing.currency,
+            });
+        }
+        let price = listing.price();
+        if offered_price < price {
+            return Err(MarketError::PriceBelowAsk {
+                offered: offered_price,
+                asking: price,
+            });
+        }
+        match currency {
+            Currency::Ntc if buyer.ntc_balance_wei < price => {
+                return Err(MarketError::InsufficientFunds {
+                    player_id: buyer_id.to_string(),
+                    required: price,
+                });
+            }
+            Currency::Credits if buyer.credits_balance < price => {
+                return Err(MarketError::InsufficientCredits {
+                    player_id: buyer_id.to_string(),
+                    required: price,
+                });
+            }
+            _ => {}
+        }
+
+        let nft = self
+            .nft(&listing.nft_id)
+            .ok_or_else(|| MarketError::NftNotFound(listing.nft_id.clone()))?;
+        Ok((
+            price,
+            self.fee_breakdown(fees, nft, &listing.seller_id, price),
+        ))
+    }
+
+    fn complete_sale(
+        &mut self,
+        listing_id: &str,
+        buyer_id: &str,
+        price: u64,
+        fees: FeeBreakdown,
+        tx_hash: String,
+        now: i64,
+    ) -> Sale {
+        let listing = self
+            .remove_listing(listing_id)
+            .expect("purchased listing is open");
+        let marketplace = self.contracts.marketplace.clone();
+        let sold_at = self
+            .append_event(
+                marketplace,
+                tx_hash.clone(),
+                now,
+                EventPayload::NftMarketSold {
+                    nft_id: listing.nft_id.clone(),
+                    player_id: buyer_id.to_string(),
+                    seller_id: listing.seller_id.clone(),
+                    price_ntc_wei: price,
+                    marketplace_fee_wei: fees.marketplace_fee,
+                    royalty_wei: fees.royalty,
+                },
+            )
+            .block_timestamp;
+
+        self.pay_out(&listing.seller_id, &fees, Currency::Ntc);
+        self.transfer_nft(&listing.nft_id, buyer_id, sold_at);
+        self.record_market_update(
+            MarketUpdateKind::Sold,
+            &listing,
+            price,
+            Some(buyer_id),
+            sold_at,
+        );
+
+        let sale = Sale {
+            listing_id: listing.listing_id,
+            nft_id: listing.nft_id,
+            seller_id: listing.seller_id,
+            buyer_id: buyer_id.to_string(),
+            price_ntc_wei: price,
+            marketplace_fee_wei: fees.marketplace_fee,
+            seller_proceeds_wei: fees.seller_proceeds,
+            fees,
+            sold_at,
+            tx_hash,
+        };
+        self.insert_sale(sale.clone());
+        sale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Market, MarketState};
+    use smart_stubs::stub::WorldState;
+    use smart_stubs::world::NTC_WEI;
+    use smart_stubs::Dataset;
+
+    const NOW: i64 = 1_800_000_000;
+
+    fn marketable_nft(world: &World) -> (String, String) {
+        let nft = world
+            .nfts
+            .iter()
+            .find(|nft| world.is_marketable(nft))
+            .unwrap();
+        (nft.owner_id.clone(), nft.nft_id.clone())
+    }
+
+    fn new_listing(seller_id: &str, nft_id: &str) -> NewListing {
+        NewListing {
+            seller_id: seller_id.to_string(),
+            nft_id: nft_id.to_string(),
+            price: 2 * NTC_WEI,
+            currency: Currency::Ntc,
+            listing_type: MarketStatus::ListedForSale,
+            reserve_price_ntc_wei: 0,
+            expires_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_create_and_cancel_listing() {
+        let mut world = World::generate(Dataset::Minimal, 21);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let (seller_id, nft_id) = marketable_nft(&market);
+        let listings = market.listings.len();
+
+        let listing_id = market
+            .create_listing(new_listing(&seller_id, &nft_id), NOW)
+            .unwrap()
+            .listing_id
+            .clone();
+        assert_eq!(market.listings.len(), listings + 1);
+        assert_eq!(market.market_status(&nft_id), MarketStatus::ListedForSale);
+        assert_eq!(
+            market.events.last().unwrap().payload.event_type(),
+            "NftMarketListed"
+        );
+        assert_eq!(
+            market.create_listing(new_listing(&seller_id, &nft_id), NOW),
+            Err(MarketError::AlreadyListed(nft_id.clone()))
+        );
+
+        let err = market
+            .cancel_listing("someone-else", &listing_id, NOW)
+            .unwrap_err();
+        assert_eq!(err.http_status(), 403);
+        market.cancel_listing(&seller_id, &listing_id, NOW).unwrap();
+        assert!(market.listing(&listing_id).is_none());
+        assert_eq!(market.market_status(&nft_id), MarketStatus::NotListed);
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_listing_requires_ownership() {
+        let mut world = World::generate(Dataset::Minimal, 21);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let (seller_id, nft_id) = marketable_nft(&market);
+        let other = market
+            .players
+            .iter()
+            .find(|player| player.player_id != seller_id)
+            .unwrap()
+            .player_id
+            .clone();
+
+        let err = market
+            .create_listing(new_listing(&other, &nft_id), NOW)
+            .unwrap_err();
+        assert!(matches!(err, MarketError::NotOwner { .. }));
+        assert_eq!(
+            tonic::Status::from(err).code(),
+            tonic::Code::PermissionDenied
+        );
+
+        let robot = market
+            .robots
+            .iter()
+            .find(|robot| !robot.equipped.is_empty())
+            .unwrap()
+            .clone();
+        let equipped_id = robot.equipped.values().next().unwrap();
+        assert!(matches!(
+            market.create_listing(new_listing(&robot.owner_id, equipped_id), NOW),
+            Err(MarketError::Equipped(_))
+        ));
+        assert!(matches!(
+            market.create_listing(new_listing(&robot.owner_id, &robot.robot_id), NOW),
+            Err(MarketError::ActiveRobot(_))
+        ));
+    }
+
+    #[test]
+    fn test_trade_moves_ownership_and_funds() {
+        let mut world = World::generate(Dataset::Minimal, 21);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let fees = FeeSchedule::default();
+        let (seller_id, nft_id) = marketable_nft(&market);
+        let buyer_id = market
+            .players
+            .iter()
+            .find(|player| player.player_id != seller_id && player.ntc_balance_wei >= 2 * NTC_WEI)
+            .unwrap()
+            .player_id
+            .clone();
+        let buyer_balance = market.player(&buyer_id).unwrap().ntc_balance_wei;
+        let seller_balance = market.player(&seller_id).unwrap().ntc_balance_wei;
+        let listing_id = market
+            .create_listing(new_listing(&seller_id, &nft_id), NOW)
+            .unwrap()
+            .listing_id
+            .clone();
+
+        assert!(matches!(
+            market.execute_trade(&buyer_id, &listing_id, NTC_WEI, &fees, NOW),
+            Err(MarketError::PriceBelowAsk { .. })
+        ));
+        assert!(matches!(
+            market.execute_trade(&seller_id, &listing_id, 2 * NTC_WEI, &fees, NOW),
+            Err(MarketError::OwnListing(_))
+        ));
+
+        let sale = market
+            .execute_trade(&buyer_id, &listing_id, 2 * NTC_WEI, &fees, NOW + 60)
+            .unwrap();
+        assert_eq!(sale.marketplace_fee_wei, 2 * NTC_WEI / 40);
+        assert_eq!(market.nft(&nft_id).unwrap().owner_id, buyer_id);
+        assert_eq!(market.nft(&nft_id).unwrap().acquired_at, Some(sale.sold_at));
+        assert!(market
+            .nfts_owned_by(&buyer_id)
+            .any(|nft| nft.nft_id == nft_id));
+        assert!(!market
+            .nfts_owned_by(&seller_id)
+            .any(|nft| nft.nft_id == nft_id));
+        assert_eq!(market.market_status(&nft_id), MarketStatus::NotListed);
+        assert_eq!(
+            market.player(&buyer_id).unwrap().ntc_balance_wei,
+            buyer_balance - 2 * NTC_WEI
+        );
+        assert_eq!(
+            market.player(&seller_id).unwrap().ntc_balance_wei,
+            seller_balance + sale.seller_proceeds_wei
+        );
+
+        let sold = market.events.last().unwrap();
+        assert_eq!(sold.payload.event_type(), "NftMarketSold");
+        assert_eq!(sold.transaction_hash, sale.tx_hash);
+        assert!(market
+            .events_for_player(&seller_id)
+            .any(|event| event.event_id == sold.event_id));
+        assert_eq!(
+            market.execute_trade(&buyer_id, &listing_id, 2 * NTC_WEI, &fees, NOW),
+            Err(MarketError::ListingNotFound(listing_id))
+        );
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_resale_pays_creator_royalty() {
+        let mut world = World::generate(Dataset::Minimal, 21);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let (creator_id, nft_id) = marketable_nft(&market);
+        let mut players = market
+            .players
+            .iter()
+            .filter(|player| player.player_id != creator_id)
+            .map(|player| player.player_id.clone());
+        let (seller_id, buyer_id) = (players.next().unwrap(), players.next().unwrap());
+        market.transfer_nft(&nft_id, &seller_id, NOW);
+        market.player_mut(&buyer_id).unwrap().ntc_balance_wei = 2 * NTC_WEI;
+        let creator_balance = market.player(&creator_id).unwrap().ntc_balance_wei;
+        let fees = FeeSchedule {
+            creator_royalty_basis_points: 500,
+            ..FeeSchedule::default()
+        };
+
+        let listing_id = market
+            .create_listing(new_listing(&seller_id, &nft_id), NOW)
+            .unwrap()
+            .listing_id
+            .clone();
+        let sale = market
+            .execute_trade(&buyer_id, &listing_id, 2 * NTC_WEI, &fees, NOW + 60)
+            .unwrap();
+
+        assert_eq!(sale.fees.royalty, 2 * NTC_WEI / 20);
+        assert_eq!(
+            sale.fees.royalty_recipient_id.as_deref(),
+            Some(creator_id.as_str())
+        );
+        assert_eq!(
+            sale.marketplace_fee_wei + sale.fees.royalty + sale.seller_proceeds_wei,
+            2 * NTC_WEI
+        );
+        assert_eq!(
+            market.player(&creator_id).unwrap().ntc_balance_wei,
+            creator_balance + sale.fees.royalty
+        );
+        match &market.events.last().unwrap().payload {
+            EventPayload::NftMarketSold { royalty_wei, .. } => {
+                assert_eq!(*royalty_wei, sale.fees.royalty);
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+        market.validate().unwrap();
+    }
+}
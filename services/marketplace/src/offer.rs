@@ -0,0 +1,717 @@
+//! Offers and counter-offers on NFTs that are not listed
+//! Buyer offers hold their amount in escrow until accepted, rejected, withdrawn,
+//! countered, expired or voided by the NFT moving
+
+use crate::config::FeeSchedule;
+use crate::fees::{credit, Fees};
+use crate::market::{MarketError, Marketability};
+use crate::state::Market;
+use smart_stubs::world::{random_hash, Currency, EventPayload, FeeBreakdown, Sale, World};
+use std::collections::HashMap;
+
+/// Offer lifetime when the request does not set an expiry
+pub const DEFAULT_OFFER_DURATION_SECS: i64 = 7 * 86_400;
+
+/// Offer as requested by a buyer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewOffer {
+    pub buyer_id: String,
+    pub nft_id: String,
+    pub amount: u64,
+    pub currency: Currency,
+    /// Unix timestamp; 0 uses [`DEFAULT_OFFER_DURATION_SECS`]
+    pub expires_at: i64,
+}
+
+/// Mirrors `OfferStatusProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum OfferStatus {
+    /// Awaiting a response; a buyer's offer holds its amount in escrow
+    Open = 1,
+    Accepted = 2,
+    Rejected = 3,
+    /// Superseded by a counter-offer
+    Countered = 4,
+    /// Withdrawn, or voided because the NFT moved or was listed
+    Cancelled = 5,
+    Expired = 6,
+}
+
+/// Offer to buy an NFT outside the order book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Offer {
+    pub offer_id: String,
+    pub nft_id: String,
+    pub buyer_id: String,
+    /// Owner of the NFT when the offer was made
+    pub owner_id: String,
+    pub amount: u64,
+    pub currency: Currency,
+    /// Counter-offers made by the owner are answered by the buyer
+    pub from_owner: bool,
+    /// Offer this one counters
+    pub counter_of: Option<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub status: OfferStatus,
+}
+
+impl Offer {
+    /// Player who made the offer and may withdraw it
+    #[must_use]
+    pub fn proposer_id(&self) -> &str {
+        if self.from_owner {
+            &self.owner_id
+        } else {
+            &self.buyer_id
+        }
+    }
+
+    /// Player who has to accept, reject or counter the offer
+    #[must_use]
+    pub fn responder_id(&self) -> &str {
+        if self.from_owner {
+            &self.buyer_id
+        } else {
+            &self.owner_id
+        }
+    }
+
+    /// Whether the amount is held from the buyer; the owner's counter-offers
+    /// hold nothing until the buyer accepts them
+    #[must_use]
+    pub fn is_escrowed(&self) -> bool {
+        self.status == OfferStatus::Open && !self.from_owner
+    }
+
+    /// Status as of `now`, reporting lapsed open offers as expired
+    #[must_use]
+    pub fn status_at(&self, now: i64) -> OfferStatus {
+        if self.status == OfferStatus::Open && self.expires_at <= now {
+            OfferStatus::Expired
+        } else {
+            self.status
+        }
+    }
+}
+
+/// Offers and counter-offers in the order they were made
+#[derive(Debug, Clone, Default)]
+pub struct OfferBook {
+    offers: Vec<Offer>,
+    by_nft: HashMap<String, Vec<usize>>,
+}
+
+impl OfferBook {
+    /// Every offer, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Offer> {
+        self.offers.iter()
+    }
+
+    /// Offers on an NFT in the order they were made
+    pub fn for_nft<'a>(&'a self, nft_id: &str) -> impl Iterator<Item = &'a Offer> + 'a {
+        self.by_nft
+            .get(nft_id)
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.offers[position])
+    }
+
+    #[must_use]
+    pub fn get(&self, offer_id: &str) -> Option<&Offer> {
+        self.offers
+            .iter()
+            .rev()
+            .find(|offer| offer.offer_id == offer_id)
+    }
+
+    /// Mutable access to an offer; IDs must not be changed through it
+    pub fn get_mut(&mut self, offer_id: &str) -> Option<&mut Offer> {
+        self.offers
+            .iter_mut()
+            .rev()
+            .find(|offer| offer.offer_id == offer_id)
+    }
+
+    /// Record an offer, keeping the lookup table current
+    pub fn insert(&mut self, offer: Offer) -> &Offer {
+        let position = self.offers.len();
+        self.by_nft
+            .entry(offer.nft_id.clone())
+            .or_default()
+            .push(position);
+        self.offers.push(offer);
+        &self.offers[position]
+    }
+}
+
+impl Market<'_> {
+    /// Cancel every open offer on an NFT, refunding escrow
+    pub fn void_offers(&mut self, nft_id: &str) {
+        let open: Vec<String> = self
+            .state
+            .offers
+            .for_nft(nft_id)
+            .filter(|offer| offer.status == OfferStatus::Open)
+            .map(|offer| offer.offer_id.clone())
+            .collect();
+        for offer_id in &open {
+            self.close_offer(offer_id, OfferStatus::Cancelled);
+        }
+    }
+
+    /// Close an open offer as `status`, refunding its escrow
+    ///
+    /// Returns `None` if the offer does not exist.
+    pub fn close_offer(&mut self, offer_id: &str, status: OfferStatus) -> Option<Offer> {
+        let offer = self.state.offers.get_mut(offer_id)?;
+        let refund = offer.is_escrowed();
+        offer.status = status;
+        let offer = offer.clone();
+        if refund {
+            credit(self, &offer.buyer_id, offer.currency, offer.amount);
+        }
+        Some(offer)
+    }
+}
+
+/// Trade settled by [`Offers::accept_offer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OfferTrade {
+    pub offer: Offer,
+    /// In the offer currency
+    pub fees: FeeBreakdown,
+    /// Only NTC trades go on chain
+    pub tx_hash: Option<String>,
+}
+
+/// Side of an offer allowed to act on it
+#[derive(Debug, Clone, Copy)]
+enum Party {
+    Proposer,
+    Responder,
+}
+
+fn offer_expiry(expires_at: i64, now: i64) -> Result<i64, MarketError> {
+    let expires_at = if expires_at == 0 {
+        now + DEFAULT_OFFER_DURATION_SECS
+    } else {
+        expires_at
+    };
+    if expires_at <= now {
+        return Err(MarketError::InvalidExpiry);
+    }
+    Ok(expires_at)
+}
+
+/// Offers and counter-offers on unlisted NFTs
+pub trait Offers {
+    /// Offer to buy an NFT from its owner, holding the amount in escrow
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the buyer or NFT is unknown, the buyer owns
+    /// the NFT, the NFT cannot be traded or is listed, or the buyer cannot
+    /// cover the amount
+    fn make_offer(&mut self, request: NewOffer, now: i64) -> Result<&Offer, MarketError>;
+
+    /// Accept an open offer, moving the NFT to the buyer and paying the owner
+    /// and creator according to `fees`
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the offer is unknown, not open or expired,
+    /// the player is not the one to respond, the NFT can no longer be traded,
+    /// or the buyer cannot cover an accepted counter-offer
+    fn accept_offer(
+        &mut self,
+        player_id: &str,
+        offer_id: &str,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<OfferTrade, MarketError>;
+
+    /// Decline an open offer, refunding anything held in escrow
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the offer is unknown, not open or expired,
+    /// or the player is not the one to respond
+    fn reject_offer(
+        &mut self,
+        player_id: &str,
+        offer_id: &str,
+        now: i64,
+    ) -> Result<Offer, MarketError>;
+
+    /// Answer an open offer with a different amount
+    ///
+    /// The original offer is closed and refunded. A counter from the buyer
+    /// holds the new amount in escrow; a counter from the owner holds nothing
+    /// until the buyer accepts it.
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the offer is unknown, not open or expired,
+    /// the player is not the one to respond, the amount or expiry is invalid,
+    /// or a countering buyer cannot cover the new amount
+    fn counter_offer(
+        &mut self,
+        player_id: &str,
+        offer_id: &str,
+        amount: u64,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<&Offer, MarketError>;
+
+    /// Withdraw an open offer on behalf of the player who made it
+    ///
+    /// # Errors
+    /// Returns a [`MarketError`] if the offer is unknown, not open or expired,
+    /// or the player did not make it
+    fn cancel_offer(
+        &mut self,
+        player_id: &str,
+        offer_id: &str,
+        now: i64,
+    ) -> Result<Offer, MarketError>;
+
+    /// Close every open offer that has lapsed by `now`, refunding escrow
+    ///
+    /// Returns the number of offers expired.
+    fn expire_offers(&mut self, now: i64) -> usize;
+}
+
+impl Offers for Market<'_> {
+    fn make_offer(&mut self, request: NewOffer, now: i64) -> Result<&Offer, MarketError> {
+        self.expire_offers(now);
+        if self.player(&request.buyer_id).is_none() {
+            return Err(MarketError::PlayerNotFound(request.buyer_id));
+        }
+        let nft = self
+            .nft(&request.nft_id)
+            .ok_or_else(|| MarketError::NftNotFound(request.nft_id.clone()))?;
+        if nft.owner_id == request.buyer_id {
+            return Err(MarketError::OwnNft(request.nft_id));
+        }
+        self.check_marketable(nft)?;
+        if request.amount == 0 {
+            return Err(MarketError::InvalidOfferAmount);
+        }
+        let expires_at = offer_expiry(request.expires_at, now)?;
+        let owner_id = nft.owner_id.clone();
+        debit(self, &request.buyer_id, request.currency, request.amount)?;
+
+        Ok(self.state.offers.insert(Offer {
+            offer_id: uuid::Uuid::new_v4().to_string(),
+            nft_id: request.nft_id,
+            buyer_id: request.buyer_id,
+            owner_id,
+            amount: request.amount,
+            currency: request.currency,
+            from_owner: false,
+            counter_of: None,
+            created_at: now,
+            expires_at,
+            status: OfferStatus::Open,
+        }))
+    }
+
+    fn accept_offer(
+        &mut self,
+        player_id: &str,
+        offer_id: &str,
+        fees: &FeeSchedule,
+        now: i64,
+    ) -> Result<OfferTrade, MarketError> {
+        let offer = open_offer_for(self, player_id, offer_id, now, Party::Responder)?;
+        let nft = self
+            .nft(&offer.nft_id)
+            .ok_or_else(|| MarketError::NftNotFound(offer.nft_id.clone()))?;
+        self.check_marketable(nft)?;
+        let fees = self.fee_breakdown(fees, nft, &offer.owner_id, offer.amount);
+        if offer.from_owner {
+            debit(self, &offer.buyer_id, offer.currency, offer.amount)?;
+        }
+
+        // Mark the offer accepted first so the transfer does not refund it
+        let offer = {
+            let stored = self
+                .state
+                .offers
+                .get_mut(offer_id)
+                .ok_or_else(|| MarketError::OfferNotFound(offer_id.to_string()))?;
+            stored.status = OfferStatus::Accepted;
+            stored.clone()
+        };
+        self.pay_out(&offer.owner_id, &fees, offer.currency);
+
+        let (sold_at, tx_hash) = match offer.currency {
+            Currency::Ntc => {
+                let tx_hash = random_hash(&mut rand::thread_rng());
+                let marketplace = self.contracts.marketplace.clone();
+                let sold_at = self
+                    .append_event(
+                        marketplace,
+                        tx_hash.clone(),
+                        now,
+                        EventPayload::NftMarketSold {
+                            nft_id: offer.nft_id.clone(),
+                            player_id: offer.buyer_id.clone(),
+                            seller_id: offer.owner_id.clone(),
+                            price_ntc_wei: offer.amount,
+                            marketplace_fee_wei: fees.marketplace_fee,
+                            royalty_wei: fees.royalty,
+                        },
+                    )
+                    .block_timestamp;
+                self.insert_sale(Sale {
+                    listing_id: offer.offer_id.clone(),
+                    nft_id: offer.nft_id.clone(),
+                    seller_id: offer.owner_id.clone(),
+                    buyer_id: offer.buyer_id.clone(),
+                    price_ntc_wei: offer.amount,
+                    marketplace_fee_wei: fees.marketplace_fee,
+                    seller_proceeds_wei: fees.seller_proceeds,
+                    fees: fees.clone(),
+                    sold_at,
+                    tx_hash: tx_hash.clone(),
+                });
+                (sold_at, Some(tx_hash))
+            }
+            Currency::Credits => (now, None),
+        };
+        self.transfer_nft(&offer.nft_id, &offer.buyer_id, sold_at);
+
+        Ok(OfferTrade {
+            offer,
+            fees,
+            tx_hash,
+        })
+    }
+
+    fn reject_offer(
+        &mut self,
+        player_id: &str,
+        offer_id: &str,
+        now: i64,
+    ) -> Result<Offer, MarketError> {
+        open_offer_for(self, player_id, offer_id, now, Party::Responder)?;
+        self.close_offer(offer_id, OfferStatus::Rejected)
+            .ok_or_else(|| MarketError::OfferNotFound(offer_id.to_string()))
+    }
+
+    fn counter_offer(
+        &mut self,
+        player_id: &str,
+        offer_id: &str,
+        amount: u64,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<&Offer, MarketError> {
+        let offer = open_offer_for(self, player_id, offer_id, now, Party::Responder)?;
+        if amount == 0 {
+            return Err(MarketError::InvalidOfferAmount);
+        }
+        let expires_at = offer_expiry(expires_at, now)?;
+
+        // Only the buyer counters an owner's offer, which holds no escrow
+        let from_owner = !offer.from_owner;
+        if !from_owner {
+            debit(self, &offer.buyer_id, offer.currency, amount)?;
+        }
+        self.close_offer(offer_id, OfferStatus::Countered)
+            .ok_or_else(|| MarketError::OfferNotFound(offer_id.to_string()))?;
+
+        Ok(self.state.offers.insert(Offer {
+            offer_id: uuid::Uuid::new_v4().to_string(),
+            nft_id: offer.nft_id,
+            buyer_id: offer.buyer_id,
+            owner_id: offer.owner_id,
+            amount,
+            currency: offer.currency,
+            from_owner,
+            counter_of: Some(offer.offer_id),
+            created_at: now,
+            expires_at,
+            status: OfferStatus::Open,
+        }))
+    }
+
+    fn cancel_offer(
+        &mut self,
+        player_id: &str,
+        offer_id: &str,
+        now: i64,
+    ) -> Result<Offer, MarketError> {
+        open_offer_for(self, player_id, offer_id, now, Party::Proposer)?;
+        self.close_offer(offer_id, OfferStatus::Cancelled)
+            .ok_or_else(|| MarketError::OfferNotFound(offer_id.to_string()))
+    }
+
+    fn expire_offers(&mut self, now: i64) -> usize {
+        let lapsed: Vec<String> = self
+            .state
+            .offers
+            .iter()
+            .filter(|offer| offer.status == OfferStatus::Open && offer.expires_at <= now)
+            .map(|offer| offer.offer_id.clone())
+            .collect();
+        for offer_id in &lapsed {
+            self.close_offer(offer_id, OfferStatus::Expired);
+        }
+        lapsed.len()
+    }
+}
+
+/// Open offer `offer_id` if `player_id` is its `party`; a lapsed offer
+/// is expired on the way
+fn open_offer_for(
+    market: &mut Market<'_>,
+    player_id: &str,
+    offer_id: &str,
+    now: i64,
+    party: Party,
+) -> Result<Offer, MarketError> {
+    let offer = market
+        .state
+        .offers
+        .get(offer_id)
+        .ok_or_else(|| MarketError::OfferNotFound(offer_id.to_string()))?;
+    let (expected, err) = match party {
+        Party::Proposer => (
+            offer.proposer_id(),
+            MarketError::NotOfferProposer {
+                player_id: player_id.to_string(),
+                offer_id: offer_id.to_string(),
+            },
+        ),
+        Party::Responder => (
+            offer.responder_id(),
+            MarketError::NotOfferResponder {
+                player_id: player_id.to_string(),
+                offer_id: offer_id.to_string(),
+            },
+        ),
+    };
+    if expected != player_id {
+        return Err(err);
+    }
+    match offer.status_at(now) {
+        OfferStatus::Open => Ok(offer.clone()),
+        OfferStatus::Expired if offer.status == OfferStatus::Open => {
+            market.close_offer(offer_id, OfferStatus::Expired);
+            Err(MarketError::OfferExpired(offer_id.to_string()))
+        }
+        _ => Err(MarketError::OfferNotOpen(offer_id.to_string())),
+    }
+}
+
+fn debit(
+    world: &mut World,
+    player_id: &str,
+    currency: Currency,
+    amount: u64,
+) -> Result<(), MarketError> {
+    let player = world
+        .player_mut(player_id)
+        .ok_or_else(|| MarketError::PlayerNotFound(player_id.to_string()))?;
+    let (balance, shortfall) = match currency {
+        Currency::Ntc => (
+            &mut player.ntc_balance_wei,
+            MarketError::InsufficientFunds {
+                player_id: player_id.to_string(),
+                required: amount,
+            },
+        ),
+        Currency::Credits => (
+            &mut player.credits_balance,
+            MarketError::InsufficientCredits {
+                player_id: player_id.to_string(),
+                required: amount,
+            },
+        ),
+    };
+    *balance = balance.checked_sub(amount).ok_or(shortfall)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::{NewListing, OrderBook};
+    use crate::state::{Market, MarketState};
+    use smart_stubs::stub::WorldState;
+    use smart_stubs::world::{MarketStatus, NTC_WEI};
+    use smart_stubs::Dataset;
+
+    const NOW: i64 = 1_800_000_000;
+
+    /// World and market state with a tradable unlisted NFT, its owner and a funded buyer
+    fn setup() -> (World, MarketState, String, String, String) {
+        let mut world = World::generate(Dataset::Minimal, 37);
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let nft = market
+            .nfts
+            .iter()
+            .find(|nft| market.is_marketable(nft))
+            .unwrap()
+            .clone();
+        let buyer = market
+            .players
+            .iter()
+            .find(|player| player.player_id != nft.owner_id)
+            .unwrap()
+            .player_id
+            .clone();
+        let player = market.player_mut(&buyer).unwrap();
+        player.ntc_balance_wei = 10 * NTC_WEI;
+        player.credits_balance = 1_000;
+        (world, state, nft.nft_id, nft.owner_id, buyer)
+    }
+
+    fn offer(
+        market: &mut Market<'_>,
+        buyer: &str,
+        nft_id: &str,
+        amount: u64,
+        currency: Currency,
+    ) -> String {
+        market
+            .make_offer(
+                NewOffer {
+                    buyer_id: buyer.to_string(),
+                    nft_id: nft_id.to_string(),
+                    amount,
+                    currency,
+                    expires_at: 0,
+                },
+                NOW,
+            )
+            .unwrap()
+            .offer_id
+            .clone()
+    }
+
+    #[test]
+    fn test_accepted_offer_trades_escrowed_funds() {
+        let (mut world, mut state, nft_id, owner, buyer) = setup();
+        let mut market = Market::new(&mut world, &mut state);
+        let owner_balance = market.player(&owner).unwrap().ntc_balance_wei;
+        let offer_id = offer(&mut market, &buyer, &nft_id, 2 * NTC_WEI, Currency::Ntc);
+        assert_eq!(market.player(&buyer).unwrap().ntc_balance_wei, 8 * NTC_WEI);
+
+        assert!(matches!(
+            market.accept_offer(&buyer, &offer_id, &FeeSchedule::default(), NOW),
+            Err(MarketError::NotOfferResponder { .. })
+        ));
+        let trade = market
+            .accept_offer(&owner, &offer_id, &FeeSchedule::default(), NOW + 10)
+            .unwrap();
+
+        assert_eq!(market.nft(&nft_id).unwrap().owner_id, buyer);
+        assert_eq!(market.player(&buyer).unwrap().ntc_balance_wei, 8 * NTC_WEI);
+        assert_eq!(
+            market.player(&owner).unwrap().ntc_balance_wei,
+            owner_balance + trade.fees.seller_proceeds
+        );
+        assert_eq!(trade.offer.status, OfferStatus::Accepted);
+        assert!(trade.tx_hash.is_some());
+        assert_eq!(market.sales.last().unwrap().listing_id, offer_id);
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_counter_offers_move_escrow_between_rounds() {
+        let (mut world, mut state, nft_id, owner, buyer) = setup();
+        let mut market = Market::new(&mut world, &mut state);
+        let opening = offer(&mut market, &buyer, &nft_id, 100, Currency::Credits);
+        assert_eq!(market.player(&buyer).unwrap().credits_balance, 900);
+
+        // The owner's counter releases the buyer's escrow
+        let counter = market
+            .counter_offer(&owner, &opening, 400, 0, NOW + 1)
+            .unwrap()
+            .offer_id
+            .clone();
+        assert_eq!(market.player(&buyer).unwrap().credits_balance, 1_000);
+        assert_eq!(
+            market.state.offers.get(&opening).unwrap().status,
+            OfferStatus::Countered
+        );
+
+        // The buyer meets in the middle, which holds the new amount
+        let middle = market
+            .counter_offer(&buyer, &counter, 250, 0, NOW + 2)
+            .unwrap()
+            .offer_id
+            .clone();
+        assert_eq!(market.player(&buyer).unwrap().credits_balance, 750);
+
+        let trade = market
+            .accept_offer(&owner, &middle, &FeeSchedule::default(), NOW + 3)
+            .unwrap();
+        assert_eq!(trade.offer.amount, 250);
+        assert!(trade.tx_hash.is_none());
+        assert_eq!(market.nft(&nft_id).unwrap().owner_id, buyer);
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_offers_are_voided_when_nft_is_listed() {
+        let (mut world, mut state, nft_id, owner, buyer) = setup();
+        let mut market = Market::new(&mut world, &mut state);
+        let offer_id = offer(&mut market, &buyer, &nft_id, NTC_WEI, Currency::Ntc);
+
+        market
+            .create_listing(
+                NewListing {
+                    seller_id: owner,
+                    nft_id,
+                    price: 3 * NTC_WEI,
+                    currency: Currency::Ntc,
+                    listing_type: MarketStatus::ListedForSale,
+                    reserve_price_ntc_wei: 0,
+                    expires_at: 0,
+                },
+                NOW,
+            )
+            .unwrap();
+
+        assert_eq!(
+            market.state.offers.get(&offer_id).unwrap().status,
+            OfferStatus::Cancelled
+        );
+        assert_eq!(market.player(&buyer).unwrap().ntc_balance_wei, 10 * NTC_WEI);
+        market.validate().unwrap();
+    }
+
+    #[test]
+    fn test_lapsed_offers_expire_and_refund() {
+        let (mut world, mut state, nft_id, owner, buyer) = setup();
+        let mut market = Market::new(&mut world, &mut state);
+        let offer_id = offer(&mut market, &buyer, &nft_id, NTC_WEI, Currency::Ntc);
+        let later = NOW + DEFAULT_OFFER_DURATION_SECS;
+
+        assert_eq!(
+            market.accept_offer(&owner, &offer_id, &FeeSchedule::default(), later),
+            Err(MarketError::OfferExpired(offer_id.clone()))
+        );
+        assert_eq!(
+            market.state.offers.get(&offer_id).unwrap().status,
+            OfferStatus::Expired
+        );
+        assert_eq!(market.player(&buyer).unwrap().ntc_balance_wei, 10 * NTC_WEI);
+        assert_eq!(market.expire_offers(later), 0);
+    }
+
+    #[test]
+    fn test_unknown_offers_are_not_found() {
+        let (mut world, mut state, _, owner, _) = setup();
+        let mut market = Market::new(&mut world, &mut state);
+
+        assert_eq!(market.close_offer("missing", OfferStatus::Cancelled), None);
+        assert_eq!(
+            market.reject_offer(&owner, "missing", NOW),
+            Err(MarketError::OfferNotFound("missing".to_string()))
+        );
+    }
+}
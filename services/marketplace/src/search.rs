@@ -0,0 +1,501 @@
+//! Faceted full-text search over open listings
+//! Queries the marketplace's inverted index of name and origin terms and facet
+//! postings, then filters, counts facets and sorts the matches
+
+use smart_stubs::world::{
+    BunkerClass, ClassAffiliation, ItemCondition, ItemRarity, ItemType, Listing, Nft, World,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Suggestions returned for the last term of a query
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Category of `CoreStats` averaged for a stat range filter; mirrors `StatCategoryProto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatCategory {
+    Combat,
+    Mobility,
+    Survivability,
+    Sensors,
+}
+
+impl StatCategory {
+    #[must_use]
+    pub fn from_proto(value: i32) -> Option<Self> {
+        match value {
+            1 => Some(Self::Combat),
+            2 => Some(Self::Mobility),
+            3 => Some(Self::Survivability),
+            4 => Some(Self::Sensors),
+            _ => None,
+        }
+    }
+
+    fn value(self, nft: &Nft) -> u32 {
+        let stats = &nft.stat_boosts;
+        match self {
+            Self::Combat => stats.combat_average(),
+            Self::Mobility => stats.mobility_average(),
+            Self::Survivability => stats.survivability_average(),
+            Self::Sensors => stats.sensors_average(),
+        }
+    }
+}
+
+/// Inclusive bounds on a stat category average of the listed NFT's boosts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatRange {
+    pub category: StatCategory,
+    pub min: u32,
+    /// 0 for no upper bound
+    pub max: u32,
+}
+
+/// Result ordering; mirrors `MarketplaceSortProto.SortField`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchSort {
+    /// Oldest listing first, in order book order when listed together
+    #[default]
+    Listed,
+    PriceAsc,
+    PriceDesc,
+    CreatedAsc,
+    CreatedDesc,
+    RarityAsc,
+    RarityDesc,
+    /// Most viewed and favorited first
+    Popularity,
+}
+
+impl SearchSort {
+    #[must_use]
+    pub fn from_proto(value: i32) -> Self {
+        match value {
+            1 => Self::PriceAsc,
+            2 => Self::PriceDesc,
+            3 => Self::CreatedAsc,
+            4 => Self::CreatedDesc,
+            5 => Self::RarityAsc,
+            6 => Self::RarityDesc,
+            7 => Self::Popularity,
+            _ => Self::Listed,
+        }
+    }
+}
+
+/// Text and filters of a marketplace search; empty fields do not filter
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    /// Every word must prefix a term of the NFT's name or construct origin
+    pub text: String,
+    pub item_types: Vec<ItemType>,
+    pub rarities: Vec<ItemRarity>,
+    pub conditions: Vec<ItemCondition>,
+    pub class_affinities: Vec<BunkerClass>,
+    pub affiliations: Vec<ClassAffiliation>,
+    pub min_price_ntc_wei: u64,
+    /// 0 for no upper bound
+    pub max_price_ntc_wei: u64,
+    pub seller_ids: Vec<String>,
+    pub exclude_seller_id: Option<String>,
+    pub stat_ranges: Vec<StatRange>,
+    pub sort: SearchSort,
+}
+
+/// Matching listings with facet counts over the whole match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResults<'a> {
+    pub listings: Vec<&'a Listing>,
+    /// Keyed `<facet>:<value>`, e.g. `rarity:supreme` or `item_type:Head`
+    pub facet_counts: BTreeMap<String, u32>,
+    /// Indexed terms completing the last word of the query
+    pub suggestions: Vec<String>,
+}
+
+/// Inverted index from name and origin terms and facet values to open listing IDs
+///
+/// Kept in step with the order book as listings open and close through
+/// [`Market`](crate::state::Market).
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    terms: BTreeMap<String, BTreeSet<String>>,
+    facets: HashMap<String, BTreeSet<String>>,
+}
+
+/// Lowercased words of `text`
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Key of a facet value, e.g. `rarity:supreme` or `item_type:Head`
+pub fn facet_key(facet: &str, value: &str) -> String {
+    format!("{facet}:{value}")
+}
+
+/// Every facet value an NFT is filed under
+pub fn facet_keys(nft: &Nft) -> Vec<String> {
+    let mut keys = vec![
+        facet_key("item_type", nft.item_type.as_str()),
+        facet_key("rarity", nft.rarity.as_str()),
+        facet_key("condition", nft.condition.as_str()),
+        facet_key("affiliation", nft.affiliation.as_str()),
+    ];
+    keys.extend(
+        nft.class_affinities
+            .iter()
+            .map(|class| facet_key("class", class.as_str())),
+    );
+    keys
+}
+
+impl SearchIndex {
+    /// Index every open listing of `world`
+    #[must_use]
+    pub fn build(world: &World) -> Self {
+        let mut index = Self::default();
+        for listing in &world.listings {
+            if let Some(nft) = world.nft(&listing.nft_id) {
+                index.insert(listing, nft);
+            }
+        }
+        index
+    }
+
+    pub fn insert(&mut self, listing: &Listing, nft: &Nft) {
+        for term in tokenize(&nft.name()).chain(tokenize(&nft.construct_origin)) {
+            self.terms
+                .entry(term)
+                .or_default()
+                .insert(listing.listing_id.clone());
+        }
+        for key in facet_keys(nft) {
+            self.facets
+                .entry(key)
+                .or_default()
+                .insert(listing.listing_id.clone());
+        }
+    }
+
+    pub fn remove(&mut self, listing: &Listing, nft: &Nft) {
+        for term in tokenize(&nft.name()).chain(tokenize(&nft.construct_origin)) {
+            if let Some(postings) = self.terms.get_mut(&term) {
+                postings.remove(&listing.listing_id);
+                if postings.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+        for key in facet_keys(nft) {
+            if let Some(postings) = self.facets.get_mut(&key) {
+                postings.remove(&listing.listing_id);
+            }
+        }
+    }
+
+    /// Listings with a term starting with `prefix`
+    #[must_use]
+    pub fn prefixed(&self, prefix: &str) -> BTreeSet<String> {
+        self.terms
+            .range(prefix.to_string()..)
+            .take_while(|(term, _)| term.starts_with(prefix))
+            .flat_map(|(_, postings)| postings.iter().cloned())
+            .collect()
+    }
+
+    /// Listings filed under any of `values` of `facet`
+    pub fn any_of<'a>(
+        &self,
+        facet: &str,
+        values: impl Iterator<Item = &'a str>,
+    ) -> BTreeSet<String> {
+        values
+            .filter_map(|value| self.facets.get(&facet_key(facet, value)))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Indexed terms starting with `prefix`, in order
+    pub fn completions<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.terms
+            .range(prefix.to_string()..)
+            .map(|(term, _)| term.as_str())
+            .take_while(move |term| term.starts_with(prefix))
+    }
+}
+
+/// Listing IDs matching the text and facet filters, `None` when unfiltered
+fn candidates(index: &SearchIndex, query: &SearchQuery) -> Option<BTreeSet<String>> {
+    let mut sets: Vec<BTreeSet<String>> = tokenize(&query.text)
+        .map(|token| index.prefixed(&token))
+        .collect();
+    let facet_filters: [(&str, Vec<&str>); 5] = [
+        (
+            "item_type",
+            query.item_types.iter().map(ItemType::as_str).collect(),
+        ),
+        (
+            "rarity",
+            query.rarities.iter().map(ItemRarity::as_str).collect(),
+        ),
+        (
+            "condition",
+            query.conditions.iter().map(ItemCondition::as_str).collect(),
+        ),
+        (
+            "class",
+            query
+                .class_affinities
+                .iter()
+                .map(BunkerClass::as_str)
+                .collect(),
+        ),
+        (
+            "affiliation",
+            query
+                .affiliations
+                .iter()
+                .map(ClassAffiliation::as_str)
+                .collect(),
+        ),
+    ];
+    for (facet, values) in facet_filters {
+        if !values.is_empty() {
+            sets.push(index.any_of(facet, values.into_iter()));
+        }
+    }
+    sets.into_iter()
+        .reduce(|matched, set| matched.intersection(&set).cloned().collect())
+}
+
+/// Indexed terms completing the last word of `text`
+fn suggestions(index: &SearchIndex, text: &str) -> Vec<String> {
+    let Some(last) = tokenize(text).last() else {
+        return Vec::new();
+    };
+    index
+        .completions(&last)
+        .filter(|term| *term != last)
+        .take(MAX_SUGGESTIONS)
+        .map(str::to_string)
+        .collect()
+}
+
+impl SearchIndex {
+    /// Open listings of `world` matching `query`, sorted as it asks
+    #[must_use]
+    pub fn search<'w>(&self, world: &'w World, query: &SearchQuery) -> SearchResults<'w> {
+        let candidates: Vec<&Listing> = match candidates(self, query) {
+            Some(ids) => world
+                .listings
+                .iter()
+                .filter(|listing| ids.contains(&listing.listing_id))
+                .collect(),
+            None => world.listings.iter().collect(),
+        };
+
+        let mut matched: Vec<(&Listing, &Nft)> = candidates
+            .into_iter()
+            .filter_map(|listing| Some((listing, world.nft(&listing.nft_id)?)))
+            .filter(|(listing, nft)| {
+                listing.price_ntc_wei >= query.min_price_ntc_wei
+                    && (query.max_price_ntc_wei == 0
+                        || listing.price_ntc_wei <= query.max_price_ntc_wei)
+                    && (query.seller_ids.is_empty()
+                        || query.seller_ids.contains(&listing.seller_id))
+                    && query.exclude_seller_id.as_ref() != Some(&listing.seller_id)
+                    && query.stat_ranges.iter().all(|range| {
+                        let value = range.category.value(nft);
+                        value >= range.min && (range.max == 0 || value <= range.max)
+                    })
+            })
+            .collect();
+
+        let mut facet_counts = BTreeMap::new();
+        for (_, nft) in &matched {
+            for key in facet_keys(nft) {
+                *facet_counts.entry(key).or_insert(0) += 1;
+            }
+        }
+
+        match query.sort {
+            SearchSort::Listed | SearchSort::CreatedAsc => {
+                matched.sort_by_key(|(listing, _)| listing.created_at);
+            }
+            SearchSort::PriceAsc => matched.sort_by_key(|(listing, _)| listing.price_ntc_wei),
+            SearchSort::PriceDesc => {
+                matched.sort_by_key(|(listing, _)| std::cmp::Reverse(listing.price_ntc_wei));
+            }
+            SearchSort::CreatedDesc => {
+                matched.sort_by_key(|(listing, _)| std::cmp::Reverse(listing.created_at));
+            }
+            SearchSort::RarityAsc => matched.sort_by_key(|(_, nft)| nft.rarity),
+            SearchSort::RarityDesc => matched.sort_by_key(|(_, nft)| std::cmp::Reverse(nft.rarity)),
+            SearchSort::Popularity => matched.sort_by_key(|(listing, _)| {
+                std::cmp::Reverse(
+                    u64::from(listing.view_count) + 3 * u64::from(listing.favorite_count),
+                )
+            }),
+        }
+
+        SearchResults {
+            listings: matched.into_iter().map(|(listing, _)| listing).collect(),
+            facet_counts,
+            suggestions: suggestions(self, &query.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::{NewListing, OrderBook};
+    use crate::state::{Market, MarketState};
+    use smart_stubs::stub::WorldState;
+    use smart_stubs::world::{Currency, MarketStatus};
+    use smart_stubs::Dataset;
+
+    fn world() -> World {
+        World::generate(Dataset::Development, 41)
+    }
+
+    #[test]
+    fn test_text_and_facets_narrow_results() {
+        let world = world();
+        let index = SearchIndex::build(&world);
+        let listing = &world.listings[0];
+        let nft = world.nft(&listing.nft_id).unwrap();
+        let origin_term = tokenize(&nft.construct_origin).next().unwrap();
+        let prefix: String = origin_term.chars().take(3).collect();
+
+        let results = index.search(
+            &world,
+            &SearchQuery {
+                text: prefix.clone(),
+                rarities: vec![nft.rarity],
+                ..SearchQuery::default()
+            },
+        );
+
+        assert!(results
+            .listings
+            .iter()
+            .any(|found| found.listing_id == listing.listing_id));
+        for found in &results.listings {
+            let found_nft = world.nft(&found.nft_id).unwrap();
+            assert_eq!(found_nft.rarity, nft.rarity);
+            assert!(tokenize(&found_nft.name())
+                .chain(tokenize(&found_nft.construct_origin))
+                .any(|term| term.starts_with(&prefix)));
+        }
+        assert_eq!(
+            results.facet_counts[&format!("rarity:{}", nft.rarity.as_str())] as usize,
+            results.listings.len()
+        );
+    }
+
+    #[test]
+    fn test_facet_counts_cover_all_matches() {
+        let world = world();
+        let results = SearchIndex::build(&world).search(&world, &SearchQuery::default());
+
+        assert_eq!(results.listings.len(), world.listings.len());
+        let by_rarity: u32 = results
+            .facet_counts
+            .iter()
+            .filter(|(key, _)| key.starts_with("rarity:"))
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(by_rarity as usize, world.listings.len());
+    }
+
+    #[test]
+    fn test_sorts_and_price_bounds() {
+        let world = world();
+        let mut prices: Vec<u64> = world
+            .listings
+            .iter()
+            .map(|listing| listing.price_ntc_wei)
+            .collect();
+        prices.sort_unstable();
+        let median = prices[prices.len() / 2];
+
+        let results = SearchIndex::build(&world).search(
+            &world,
+            &SearchQuery {
+                max_price_ntc_wei: median,
+                sort: SearchSort::PriceDesc,
+                ..SearchQuery::default()
+            },
+        );
+
+        assert!(!results.listings.is_empty());
+        assert!(results
+            .listings
+            .iter()
+            .all(|listing| listing.price_ntc_wei <= median));
+        assert!(results
+            .listings
+            .windows(2)
+            .all(|pair| pair[0].price_ntc_wei >= pair[1].price_ntc_wei));
+    }
+
+    #[test]
+    fn test_index_follows_order_book_changes() {
+        let mut world = world();
+        let mut state = MarketState::from_world(&world);
+        let mut market = Market::new(&mut world, &mut state);
+        let listing = market.listings[0].clone();
+        let query = SearchQuery {
+            text: market.nft(&listing.nft_id).unwrap().name(),
+            ..SearchQuery::default()
+        };
+        assert!(market
+            .state
+            .search
+            .search(&market, &query)
+            .listings
+            .iter()
+            .any(|found| found.listing_id == listing.listing_id));
+
+        market
+            .cancel_listing(&listing.seller_id, &listing.listing_id, 1_800_000_000)
+            .unwrap();
+        assert!(market
+            .state
+            .search
+            .search(&market, &query)
+            .listings
+            .iter()
+            .all(|found| found.nft_id != listing.nft_id));
+
+        let relisted = market
+            .create_listing(
+                NewListing {
+                    seller_id: listing.seller_id.clone(),
+                    nft_id: listing.nft_id.clone(),
+                    price: listing.price_ntc_wei,
+                    currency: Currency::Ntc,
+                    listing_type: MarketStatus::ListedForSale,
+                    reserve_price_ntc_wei: 0,
+                    expires_at: 0,
+                },
+                1_800_000_000,
+            )
+            .unwrap()
+            .listing_id
+            .clone();
+        let found: Vec<&str> = market
+            .state
+            .search
+            .search(&market, &query)
+            .listings
+            .iter()
+            .map(|found| found.listing_id.as_str())
+            .collect();
+        assert!(found.contains(&relisted.as_str()));
+        assert!(!found.contains(&listing.listing_id.as_str()));
+    }
+}
@@ -0,0 +1,145 @@
+//! Marketplace state kept beside the shared fixture world
+//! Indexes only the marketplace uses, rebuilt whenever the world is replaced and
+//! kept in step by the order book changes made through [`Market`]
+
+use crate::escrow::EscrowBook;
+use crate::expiry::ExpiryQueue;
+use crate::feed::MarketFeed;
+use crate::offer::OfferBook;
+use crate::search::SearchIndex;
+use smart_stubs::stub::{ServiceStub, WorldState, WorldStateGuard};
+use smart_stubs::world::{Listing, MarketStatus, World};
+use std::ops::{Deref, DerefMut};
+
+/// Read access to the fixture world and the marketplace state beside it
+pub type MarketGuard = WorldStateGuard<MarketState>;
+
+/// Marketplace state derived from the fixture world
+#[derive(Debug, Clone, Default)]
+pub struct MarketState {
+    /// Inverted index over the open listings
+    pub search: SearchIndex,
+    /// Recent order book changes for market subscribers
+    pub feed: MarketFeed,
+    /// Offers made outside the order book
+    pub offers: OfferBook,
+    /// Purchases waiting for their transaction to settle
+    pub escrows: EscrowBook,
+    /// Open listings by deadline, for the expiry sweeper
+    pub expiries: ExpiryQueue,
+}
+
+impl WorldState for MarketState {
+    fn from_world(world: &World) -> Self {
+        Self {
+            search: SearchIndex::build(world),
+            feed: MarketFeed::default(),
+            offers: OfferBook::default(),
+            escrows: EscrowBook::default(),
+            expiries: ExpiryQueue::build(world),
+        }
+    }
+}
+
+impl MarketState {
+    /// Market status of `nft_id` in `world`, `LOCKED` while a purchase of its
+    /// listing is pending
+    #[must_use]
+    pub fn market_status(&self, world: &World, nft_id: &str) -> MarketStatus {
+        match world.listing_for_nft(nft_id) {
+            Some(listing) if self.escrows.pending(&listing.listing_id).is_some() => {
+                MarketStatus::Locked
+            }
+            _ => world.market_status(nft_id),
+        }
+    }
+}
+
+/// Fixture world seen by the order book, changed together with the market state
+///
+/// Dereferences to the world. The listing changes it shadows also keep the
+/// market state current, so order book code goes through them rather than
+/// the world's own.
+#[derive(Debug)]
+pub struct Market<'a> {
+    world: &'a mut World,
+    pub state: &'a mut MarketState,
+}
+
+impl<'a> Market<'a> {
+    pub fn new(world: &'a mut World, state: &'a mut MarketState) -> Self {
+        Self { world, state }
+    }
+
+    /// Copy of the world and market state to [`Market::restore`] later
+    #[must_use]
+    pub fn snapshot(&self) -> (World, MarketState) {
+        (self.world.clone(), self.state.clone())
+    }
+
+    /// Roll the world and market state back to a [`Market::snapshot`]
+    pub fn restore(&mut self, (world, state): (World, MarketState)) {
+        *self.world = world;
+        *self.state = state;
+    }
+
+    /// Open a listing, indexing it for search and expiry
+    ///
+    /// Open offers on the NFT are voided; buyers go through the listing instead.
+    pub fn insert_listing(&mut self, listing: Listing) {
+        self.void_offers(&listing.nft_id);
+        if let Some(nft) = self.world.nft(&listing.nft_id) {
+            self.state.search.insert(&listing, nft);
+        }
+        self.state.expiries.insert(&listing);
+        self.world.insert_listing(listing);
+    }
+
+    /// Close a listing and drop it from search and expiry
+    pub fn remove_listing(&mut self, listing_id: &str) -> Option<Listing> {
+        let listing = self.world.remove_listing(listing_id)?;
+        self.state.expiries.remove(&listing);
+        if let Some(nft) = self.world.nft(&listing.nft_id) {
+            self.state.search.remove(&listing, nft);
+        }
+        Some(listing)
+    }
+
+    /// Hand an NFT to `new_owner_id` at `timestamp`, voiding open offers made
+    /// to the previous owner
+    ///
+    /// Returns `false` if the NFT does not exist.
+    pub fn transfer_nft(&mut self, nft_id: &str, new_owner_id: &str, timestamp: i64) -> bool {
+        if self.world.nft(nft_id).is_none() {
+            return false;
+        }
+        self.void_offers(nft_id);
+        self.world.transfer_nft(nft_id, new_owner_id, timestamp)
+    }
+}
+
+impl Deref for Market<'_> {
+    type Target = World;
+
+    fn deref(&self) -> &World {
+        self.world
+    }
+}
+
+impl DerefMut for Market<'_> {
+    fn deref_mut(&mut self) -> &mut World {
+        self.world
+    }
+}
+
+/// Order book changes through the shared stub
+pub trait UpdateMarket {
+    /// Change the fixture world and the market state together
+    fn update_market<R>(&self, update: impl FnOnce(&mut Market<'_>) -> R) -> R;
+}
+
+impl UpdateMarket for ServiceStub {
+    fn update_market<R>(&self, update: impl FnOnce(&mut Market<'_>) -> R) -> R {
+        self.update_world_with(|world, state| update(&mut Market::new(world, state)))
+    }
+}